@@ -1,31 +1,195 @@
-use expr::{Expr};
-use types::{SortKind,Value};
+use expr::{Expr,Function,NVar};
+use types::{SortKind,Value,Datatype,FieldSort,ObjectiveValue};
 use embed::{Embed};
-use std::io::{Read,Write,Error,stderr};
+use model::Model;
+use profile::SolverProfile;
+use options::{Logic,OptionValue};
+use std::io::{Read,Write,Error,ErrorKind,stderr};
 use std::collections::HashMap;
 use std::collections::hash_map::Entry;
-use std::process::{Command, Stdio, ChildStdin, ChildStdout };
+use std::process::{Command, Stdio, Child, ChildStdin, ChildStdout };
+use std::sync::{Arc,Mutex};
+use std::sync::mpsc::{channel,RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
 use parser::*;
+use reader::SExprReader;
 use unique::*;
 use std::str;
+use std::mem;
 use std::str::FromStr;
 use std::fmt;
+use num_bigint::BigUint;
+#[cfg(test)]
+use num_bigint::BigInt;
+#[cfg(test)]
+use num_rational::Ratio;
+#[cfg(test)]
+use model::Interpretation;
+#[cfg(test)]
+use profile;
 
 pub trait Backend : Embed {
+    /// The error reported for a command the backend does not support. The
+    /// optional commands default to it.
+    fn unsupported(what: &str) -> Self::Error;
     fn enable_models(&mut self) -> Result<(),Self::Error>;
     fn enable_proofs(&mut self) -> Result<(),Self::Error>;
     fn enable_unsat_cores(&mut self) -> Result<(),Self::Error>;
     fn enable_interpolants(&mut self) -> Result<(),Self::Error>;
+    /// Set the logic. Has to be called before any declarations.
+    fn set_logic(&mut self,_: Logic) -> Result<(),Self::Error> {
+        Err(Self::unsupported("set-logic"))
+    }
+    /// Set a solver option, given as keyword (like `:random-seed`).
+    fn set_option(&mut self,_: &str,_: OptionValue) -> Result<(),Self::Error> {
+        Err(Self::unsupported("set-option"))
+    }
+    fn get_option(&mut self,_: &str) -> Result<OptionValue,Self::Error> {
+        Err(Self::unsupported("get-option"))
+    }
+    /// Set an info attribute (like `:status`).
+    fn set_info(&mut self,_: &str,_: OptionValue) -> Result<(),Self::Error> {
+        Err(Self::unsupported("set-info"))
+    }
     fn solver_name(&mut self) -> Result<String,Self::Error>;
     fn solver_version(&mut self) -> Result<String,Self::Error>;
+    /// Why the last `check_sat` returned `Unknown`.
+    fn reason_unknown(&mut self) -> Result<String,Self::Error> {
+        Err(Self::unsupported("get-info :reason-unknown"))
+    }
+    /// The statistics of the solver, like the number of conflicts or the
+    /// time spent.
+    fn statistics(&mut self) -> Result<HashMap<String,StatValue>,Self::Error> {
+        Err(Self::unsupported("get-info :all-statistics"))
+    }
     fn comment(&mut self,&str) -> Result<(),Self::Error>;
     fn push(&mut self) -> Result<(),Self::Error>;
     fn pop(&mut self) -> Result<(),Self::Error>;
     fn declare_var(&mut self,Self::Sort,Option<String>) -> Result<Self::Var,Self::Error>;
     fn define_var(&mut self,Self::Expr) -> Result<Self::Var,Self::Error>;
     fn assert(&mut self,Self::Expr) -> Result<(),Self::Error>;
+    /// Assert an expression and give it a name, so that it can show up in
+    /// unsat cores.
+    fn assert_named(&mut self,_: Self::Expr) -> Result<AssertionId,Self::Error> {
+        Err(Self::unsupported(":named"))
+    }
+    /// Retrieve the named assertions which make the last `check_sat` unsatisfiable.
+    fn get_unsat_core(&mut self) -> Result<Vec<AssertionId>,Self::Error> {
+        Err(Self::unsupported("get-unsat-core"))
+    }
     fn check_sat(&mut self) -> Result<CheckSatResult,Self::Error>;
+    /// Check satisfiability of the assertions together with the given
+    /// boolean literals, without adding them to the assertion stack.
+    fn check_sat_assuming(&mut self,_: &[Self::Expr]) -> Result<CheckSatResult,Self::Error> {
+        Err(Self::unsupported("check-sat-assuming"))
+    }
+    /// Retrieve the subset of assumptions of the last `check_sat_assuming`
+    /// which made it unsatisfiable.
+    fn get_unsat_assumptions(&mut self) -> Result<Vec<Self::Expr>,Self::Error> {
+        Err(Self::unsupported("get-unsat-assumptions"))
+    }
+    /// Retrieve the proof of the last unsatisfiable `check_sat`. Requires
+    /// proofs to be enabled using `enable_proofs`.
+    fn get_proof(&mut self) -> Result<SExpr,Self::Error> {
+        Err(Self::unsupported("get-proof"))
+    }
+    /// Compute a Craig interpolant for the partitions `a` and `b`, whose
+    /// conjunction has to be unsatisfiable. The partitions are only asserted
+    /// temporarily. Returns `None` if the solver could not find an
+    /// interpolant.
+    fn get_interpolant(&mut self,_a: &[Self::Expr],_b: &[Self::Expr])
+                       -> Result<Option<Self::Expr>,Self::Error> {
+        Err(Self::unsupported("get-interpolants"))
+    }
+    /// Let the solver compute a quantifier-free formula equivalent to the
+    /// given one.
+    fn eliminate_quantifiers(&mut self,_: Self::Expr) -> Result<Self::Expr,Self::Error> {
+        Err(Self::unsupported("qe"))
+    }
+    /// Let the solver simplify an expression.
+    fn solver_simplify(&mut self,_: Self::Expr) -> Result<Self::Expr,Self::Error> {
+        Err(Self::unsupported("simplify"))
+    }
     fn get_value(&mut self,Self::Expr) -> Result<Value,Self::Error>;
+    /// Get the values of multiple expressions at once.
+    fn get_values(&mut self,exprs: Vec<Self::Expr>) -> Result<Vec<Value>,Self::Error> {
+        let mut res = Vec::with_capacity(exprs.len());
+        for e in exprs.into_iter() {
+            res.push(self.get_value(e)?);
+        }
+        Ok(res)
+    }
+    fn get_model(&mut self)
+                 -> Result<Model<Self::Sort,Self::Var,Self::Expr,Self::Fun>,Self::Error> {
+        Err(Self::unsupported("get-model"))
+    }
+    /// Declare a group of (possibly mutually recursive) datatypes. The
+    /// datatypes are numbered consecutively, starting with the returned id.
+    fn declare_datatypes(&mut self,_: Vec<Datatype<FieldSort<Self::Sort>>>)
+                         -> Result<usize,Self::Error> {
+        Err(Self::unsupported("declare-datatypes"))
+    }
+    fn declare_datatype(&mut self,dt: Datatype<FieldSort<Self::Sort>>)
+                        -> Result<usize,Self::Error> {
+        self.declare_datatypes(vec![dt])
+    }
+    /// Declare an uninterpreted sort with the given number of arguments.
+    fn declare_sort(&mut self,_arity: usize) -> Result<usize,Self::Error> {
+        Err(Self::unsupported("declare-sort"))
+    }
+    /// Define a sort alias with the given number of parameters. The
+    /// parameters are referenced via `SortKind::Param` in the body.
+    fn define_sort(&mut self,_params: usize,_body: Self::Sort)
+                   -> Result<usize,Self::Error> {
+        Err(Self::unsupported("define-sort"))
+    }
+    /// Instantiate a sort alias created by `define_sort`.
+    fn defined_sort(&mut self,_id: usize,_args: Vec<Self::Sort>)
+                    -> Result<Self::Sort,Self::Error> {
+        Err(Self::unsupported("define-sort"))
+    }
+    fn declare_fun(&mut self,_: Vec<Self::Sort>,_: Self::Sort,_: Option<String>)
+                   -> Result<Self::Fun,Self::Error> {
+        Err(Self::unsupported("declare-fun"))
+    }
+    /// Define a function. The parameters are referenced in the body as
+    /// quantified variables (`Expr::QVar`).
+    fn define_fun(&mut self,_: Vec<NVar<Self::Sort>>,_: Self::Expr)
+                  -> Result<Self::Fun,Self::Error> {
+        Err(Self::unsupported("define-fun"))
+    }
+    /// Allocate a group of mutually recursive functions with the given
+    /// signatures without defining them yet.
+    fn declare_funs_rec(&mut self,_: &[(Vec<NVar<Self::Sort>>,Self::Sort)])
+                        -> Result<Vec<Self::Fun>,Self::Error> {
+        Err(Self::unsupported("define-funs-rec"))
+    }
+    /// Emit the definitions of functions allocated by `declare_funs_rec`.
+    fn emit_funs_rec(&mut self,_: &[Self::Fun],_: &[(Vec<NVar<Self::Sort>>,Self::Sort)],
+                     _: Vec<Self::Expr>) -> Result<(),Self::Error> {
+        Err(Self::unsupported("define-funs-rec"))
+    }
+    /// Define a group of mutually recursive functions. The bodies are
+    /// created by `bodies`, which receives the functions being defined.
+    fn define_funs_rec<F>(&mut self,sigs: Vec<(Vec<NVar<Self::Sort>>,Self::Sort)>,bodies: F)
+                          -> Result<Vec<Self::Fun>,Self::Error>
+        where F : FnOnce(&mut Self,&[Self::Fun]) -> Result<Vec<Self::Expr>,Self::Error> {
+        let funs = self.declare_funs_rec(&sigs)?;
+        let exprs = bodies(self,&funs)?;
+        self.emit_funs_rec(&funs,&sigs,exprs)?;
+        Ok(funs)
+    }
+    fn define_fun_rec<F>(&mut self,params: Vec<NVar<Self::Sort>>,ret: Self::Sort,body: F)
+                         -> Result<Self::Fun,Self::Error>
+        where F : FnOnce(&mut Self,&Self::Fun) -> Result<Self::Expr,Self::Error> {
+        let mut funs = self.define_funs_rec(vec![(params,ret)],
+                                            |b,funs| {
+                                                let e = body(b,&funs[0])?;
+                                                Ok(vec![e])
+                                            })?;
+        Ok(funs.remove(0))
+    }
     fn declare(&mut self,srt: Self::Sort) -> Result<Self::Expr,Self::Error> {
         let var = self.declare_var(srt,None)?;
         self.embed(Expr::Var(var))
@@ -36,18 +200,156 @@ pub trait Backend : Embed {
     }
 }
 
+/// Backends which can optimize objectives (optimization modulo theories).
+/// The values of the objectives are available after a `check_sat`.
+pub trait Optimizer : Backend {
+    fn minimize(&mut self,Self::Expr) -> Result<ObjectiveId,Self::Error>;
+    fn maximize(&mut self,Self::Expr) -> Result<ObjectiveId,Self::Error>;
+    /// Assert a soft constraint with a weight. Soft constraints with the
+    /// same group form a single objective, which minimizes the weight of
+    /// the violated constraints.
+    fn assert_soft(&mut self,Self::Expr,BigUint,Option<&str>) -> Result<ObjectiveId,Self::Error>;
+    /// The values of all objectives, indexed by their `ObjectiveId`.
+    fn get_objectives(&mut self) -> Result<Vec<ObjectiveValue>,Self::Error>;
+}
+
+#[derive(PartialEq,Eq,PartialOrd,Ord,Hash,Clone,Copy,Debug)]
+pub struct AssertionId(pub usize);
+
+#[derive(PartialEq,Eq,PartialOrd,Ord,Hash,Clone,Copy,Debug)]
+pub struct ObjectiveId(pub usize);
+
+/// The command used to retrieve interpolants from a solver.
+#[derive(PartialEq,Eq,Hash,Clone,Copy,Debug)]
+pub enum InterpolationDialect {
+    /// `(get-interpolants A B)` over named assertions (SMTInterpol, MathSAT)
+    GetInterpolants,
+    /// `(get-interpol name B)` with the assertions as the A partition (CVC5)
+    GetInterpol
+}
+
+/// The command used to eliminate quantifiers using a solver.
+#[derive(PartialEq,Eq,Hash,Clone,Copy,Debug)]
+pub enum QeDialect {
+    /// Assert the formula and run `(apply qe)` (Z3)
+    ApplyQe,
+    /// `(get-qe formula)` (CVC5)
+    GetQe
+}
+
 pub struct Pipe<R : Read, W : Write> {
     reader: R,
-    writer: W,
+    /// Output of the solver which hasn't been consumed yet
+    response: SExprReader,
+    pub(crate) writer: W,
     sorts: Uniquer<SortKind<PipeSort>>,
     vars: Vec<(PipeSort,Option<String>)>,
     named_vars: HashMap<String,usize>,
     exprs: Uniquer<Expr<PipeSort,PipeVar,PipeExpr,PipeFun>>,
-    funs: HashMap<usize,(Vec<PipeSort>,PipeSort)>
+    funs: HashMap<usize,(Vec<PipeSort>,PipeSort,Option<String>)>,
+    named_funs: HashMap<String,usize>,
+    datatypes: Vec<Datatype<PipeSort>>,
+    declared_sorts: Vec<usize>,
+    defined_sorts: Vec<(usize,PipeSort)>,
+    named_assertions: usize,
+    interpolation: InterpolationDialect,
+    interpolants: usize,
+    qe: QeDialect,
+    objectives: Vec<PipeSort>,
+    soft_groups: HashMap<String,usize>,
+    profile: Option<SolverProfile>,
+    print_success: bool,
+    child: Option<Arc<Mutex<Child>>>,
+    unknown_reason: Option<String>,
+    logic: Option<Logic>,
+    declared: bool
 }
 
 const PIPE_VAR_NAME: &'static str = "v";
 const PIPE_FUN_NAME: &'static str = "f";
+const PIPE_ASSERTION_NAME: &'static str = "a";
+
+/// Whether a name has the form of the names generated for unnamed symbols,
+/// sorts, datatypes and bound variables, like `v3`, `d0c1s2` or `qv0`.
+fn is_generated_name(name: &str) -> bool {
+    fn numbered<'a>(name: &'a str,prefix: &str) -> Option<&'a str> {
+        if !name.starts_with(prefix) {
+            return None
+        }
+        let rest = &name[prefix.len()..];
+        let digits = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+        if digits==0 {
+            None
+        } else {
+            Some(&rest[digits..])
+        }
+    }
+    for prefix in [PIPE_VAR_NAME,PIPE_FUN_NAME,PIPE_ASSERTION_NAME,"u","p","qv","lv"].iter() {
+        if numbered(name,prefix)==Some("") {
+            return true
+        }
+    }
+    // Datatypes, constructors and selectors
+    match numbered(name,"d") {
+        None => false,
+        Some("") => true,
+        Some(rest) => match numbered(rest,"c") {
+            None => false,
+            Some("") => true,
+            Some(rest) => numbered(rest,"s")==Some("")
+        }
+    }
+}
+
+/// Check that a name can be written as a (possibly quoted) symbol.
+fn check_symbol_name(name: &str) -> Result<(),PipeError> {
+    if name.contains(|c| c=='|' || c=='\\') {
+        return Err(PipeError::InvalidName(String::from(name)))
+    }
+    Ok(())
+}
+
+/// Symbols of the theories which user-declared symbols would shadow.
+/// Quoting doesn't help, since `|and|` is the same symbol as `and`.
+const PREDEFINED_SYMBOLS: &'static [&'static str]
+    = &["true","false","not","=>","and","or","xor","=","distinct","ite",
+        "+","-","*","/","div","mod","abs","<=","<",">=",">",
+        "to_real","to_int","is_int","select","store"];
+
+/// Check that a name can be given to a declared symbol without clashing
+/// with the generated names or predefined symbols.
+fn check_declared_name(name: &str) -> Result<(),PipeError> {
+    check_symbol_name(name)?;
+    if is_generated_name(name) || PREDEFINED_SYMBOLS.contains(&name) {
+        return Err(PipeError::InvalidName(String::from(name)))
+    }
+    Ok(())
+}
+
+/// How long to wait for the solver to honor its own timeout before
+/// interrupting it
+const PIPE_TIMEOUT_GRACE: u64 = 1000;
+
+/// The state of a check guarded by `check_sat_with_timeout`.
+#[derive(PartialEq,Eq,Clone,Copy,Debug)]
+enum Watchdog {
+    Running,
+    Finished,
+    Interrupted
+}
+
+/// Ask a solver process to abort its current command.
+#[cfg(unix)]
+fn interrupt(child: &mut Child) {
+    unsafe {
+        libc::kill(child.id() as libc::pid_t,libc::SIGINT);
+    }
+}
+
+#[cfg(not(unix))]
+fn interrupt(child: &mut Child) {
+    let _ = child.kill();
+}
 
 #[derive(PartialEq,Eq,Hash,Clone,Debug)]
 pub struct PipeSort(UniqueRef<SortKind<PipeSort>>);
@@ -60,55 +362,342 @@ pub type PipeFun = usize;
 impl<Inp : Read,Outp : Write> Pipe<Inp,Outp> {
     pub fn new(inp: Inp,outp: Outp) -> Self {
         Pipe { reader: inp,
+               response: SExprReader::new(),
                writer: outp,
                sorts: Uniquer::new(),
                vars: Vec::new(),
                named_vars: HashMap::new(),
                exprs: Uniquer::new(),
-               funs: HashMap::new() }
+               funs: HashMap::new(),
+               named_funs: HashMap::new(),
+               datatypes: Vec::new(),
+               declared_sorts: Vec::new(),
+               defined_sorts: Vec::new(),
+               named_assertions: 0,
+               interpolation: InterpolationDialect::GetInterpolants,
+               interpolants: 0,
+               qe: QeDialect::ApplyQe,
+               objectives: Vec::new(),
+               soft_groups: HashMap::new(),
+               profile: None,
+               print_success: false,
+               child: None,
+               unknown_reason: None,
+               logic: None,
+               declared: false }
+    }
+    /// Adapt the pipe to the dialect of a specific solver.
+    pub fn set_profile(&mut self,profile: &SolverProfile) {
+        if let Some(dialect) = profile.interpolation {
+            self.interpolation = dialect;
+        }
+        if let Some(dialect) = profile.qe {
+            self.qe = dialect;
+        }
+        self.profile = Some(profile.clone());
+    }
+    pub fn profile(&self) -> Option<&SolverProfile> {
+        self.profile.as_ref()
+    }
+    fn enable_option(&mut self,opt: &'static str) -> Result<(),PipeError> {
+        if let Some(ref profile) = self.profile {
+            if !profile.supports_option(opt) {
+                return Err(PipeError::Unsupported(String::from(opt)))
+            }
+        }
+        write!(self.writer,"(set-option {} true)\n",opt)?;
+        self.ack()
+    }
+    /// Limit the resources (as counted by the solver) of the following
+    /// checks. Only supported by some solvers.
+    pub fn set_resource_limit(&mut self,limit: u64) -> Result<(),PipeError> {
+        if let Some(ref profile) = self.profile {
+            if !profile.supports_option(":rlimit") {
+                return Err(PipeError::Unsupported(String::from(":rlimit")))
+            }
+        }
+        write!(self.writer,"(set-option :rlimit {})\n",limit)?;
+        self.ack()
+    }
+    /// Check satisfiability with a time limit. If the solver supports it,
+    /// the limit is also passed on as the `:timeout` option for the
+    /// duration of this check. A solver which does not answer in time is
+    /// interrupted (with `SIGINT` on Unix), which makes most solvers give up
+    /// the check and answer `unknown`, so the pipe can still be used
+    /// afterwards. The reason for such an `Unknown` result is `timeout`.
+    /// Only pipes to a solver process can enforce the limit.
+    pub fn check_sat_with_timeout(&mut self,timeout: Duration)
+                                  -> Result<CheckSatResult,PipeError> {
+        let child = match self.child {
+            Some(ref child) => child.clone(),
+            None => return Err(PipeError::Unsupported(String::from("timeout")))
+        };
+        let solver_side = match self.profile {
+            Some(ref profile) => profile.supports_option(":timeout"),
+            None => false
+        };
+        let ms = timeout.as_secs()*1000+(timeout.subsec_nanos()/1000000) as u64;
+        let previous = if solver_side {
+            let old = self.get_option(":timeout")?;
+            self.set_option(":timeout",OptionValue::Numeral(ms))?;
+            Some(old)
+        } else {
+            None
+        };
+        let res = self.check_sat_watched(child,timeout,solver_side);
+        // The previous limit is restored even if the check failed
+        if let Some(old) = previous {
+            let restored = self.set_option(":timeout",old);
+            if res.is_ok() {
+                restored?;
+            }
+        }
+        res
+    }
+    /// Run a check which is interrupted if the solver does not answer
+    /// within the time limit (plus a grace period if the solver enforces
+    /// the limit itself).
+    fn check_sat_watched(&mut self,child: Arc<Mutex<Child>>,timeout: Duration,solver_side: bool)
+                         -> Result<CheckSatResult,PipeError> {
+        self.unknown_reason = None;
+        write!(self.writer,"(check-sat)\n")?;
+        self.writer.flush()?;
+        let limit = if solver_side {
+            timeout+Duration::from_millis(PIPE_TIMEOUT_GRACE)
+        } else {
+            timeout
+        };
+        let state = Arc::new(Mutex::new(Watchdog::Running));
+        let wstate = state.clone();
+        let (tx,rx) = channel::<()>();
+        thread::spawn(move || {
+            if let Err(RecvTimeoutError::Timeout) = rx.recv_timeout(limit) {
+                // Holding the lock makes sure that no finished check is
+                // interrupted
+                if let Ok(mut state) = wstate.lock() {
+                    if *state==Watchdog::Running {
+                        if let Ok(mut child) = child.lock() {
+                            interrupt(&mut child);
+                        }
+                        *state = Watchdog::Interrupted;
+                    }
+                }
+            }
+        });
+        let res = smt_response(self,parse_checksat_result);
+        let interrupted = match state.lock() {
+            Ok(mut state) => {
+                let interrupted = *state==Watchdog::Interrupted;
+                *state = Watchdog::Finished;
+                interrupted
+            },
+            Err(_) => false
+        };
+        let _ = tx.send(());
+        let res = match res {
+            Ok(res) => res,
+            // The solver did not survive the interruption
+            Err(_) if interrupted => {
+                self.unknown_reason = Some(String::from("timeout"));
+                return Ok(CheckSatResult::Unknown)
+            },
+            Err(e) => return Err(e)
+        };
+        if interrupted && res==CheckSatResult::Unknown {
+            self.unknown_reason = Some(String::from("timeout"));
+        }
+        Ok(res)
+    }
+    /// Let the solver acknowledge every command with `success`, so that
+    /// errors are reported by the command that caused them.
+    pub fn enable_print_success(&mut self) -> Result<(),PipeError> {
+        self.print_success = true;
+        self.enable_option(":print-success")
+    }
+    /// Must be called before declaring a symbol or asserting, to check
+    /// that the logic is set if needed.
+    fn declaration(&mut self) -> Result<(),PipeError> {
+        if self.logic.is_none() {
+            if let Some(ref profile) = self.profile {
+                if profile.quirks.requires_logic {
+                    return Err(PipeError::LogicRequired)
+                }
+            }
+        }
+        self.declared = true;
+        Ok(())
+    }
+    /// Check that a name can be given to a new variable or function.
+    /// Variables and functions share a namespace in SMT-LIB.
+    fn check_new_name(&self,name: &str) -> Result<(),PipeError> {
+        check_declared_name(name)?;
+        if self.named_vars.contains_key(name) || self.named_funs.contains_key(name) {
+            return Err(PipeError::InvalidName(String::from(name)))
+        }
+        Ok(())
+    }
+    /// Wait for the acknowledgement of a command, if enabled.
+    fn ack(&mut self) -> Result<(),PipeError> {
+        if self.print_success {
+            self.writer.flush()?;
+            smt_ack(self)
+        } else {
+            Ok(())
+        }
+    }
+    pub fn set_interpolation_dialect(&mut self,dialect: InterpolationDialect) {
+        self.interpolation = dialect;
+    }
+    pub fn set_qe_dialect(&mut self,dialect: QeDialect) {
+        self.qe = dialect;
+    }
+    /// Assert the two parts of an interpolation query and ask for the
+    /// interpolant. Must be called inside a fresh assertion level.
+    fn interpolant_query(&mut self,a: &[PipeExpr],b: &[PipeExpr])
+                         -> Result<Option<PipeExpr>,PipeError> {
+        let res = match self.interpolation {
+            InterpolationDialect::GetInterpolants => {
+                let mut parts = Vec::with_capacity(2);
+                for part in [a,b].iter() {
+                    let mut names = Vec::with_capacity(part.len());
+                    for e in part.iter() {
+                        names.push(self.assert_named(e.clone())?);
+                    }
+                    parts.push(names);
+                }
+                if self.check_sat()?==CheckSatResult::Unsat {
+                    write!(self.writer,"(get-interpolants")?;
+                    for names in parts.iter() {
+                        match names.len() {
+                            0 => write!(self.writer," true")?,
+                            1 => write!(self.writer," {}{}",PIPE_ASSERTION_NAME,names[0].0)?,
+                            _ => {
+                                write!(self.writer," (and")?;
+                                for name in names.iter() {
+                                    write!(self.writer," {}{}",PIPE_ASSERTION_NAME,name.0)?;
+                                }
+                                write!(self.writer,")")?;
+                            }
+                        }
+                    }
+                    write!(self.writer,")\n")?;
+                    self.writer.flush()?;
+                    let mut itps = smt_response(self,parse_interpolants)?;
+                    itps.pop()
+                } else {
+                    None
+                }
+            },
+            InterpolationDialect::GetInterpol => {
+                for e in a.iter() {
+                    self.assert(e.clone())?;
+                }
+                let goal = match b.len() {
+                    0 => self.const_bool(false)?,
+                    1 => self.not(b[0].clone())?,
+                    _ => {
+                        let conj = self.and(b.to_vec())?;
+                        self.not(conj)?
+                    }
+                };
+                let id = self.interpolants;
+                self.interpolants+=1;
+                write!(self.writer,"(get-interpol i{} ",id)?;
+                self.fmt_expr(&goal)?;
+                write!(self.writer,")\n")?;
+                self.writer.flush()?;
+                smt_response(self,parse_interpol)?
+            }
+        };
+        Ok(res)
+    }
+    /// Assert a formula and apply the `qe` tactic to it. Must be called
+    /// inside a fresh assertion level.
+    fn apply_qe(&mut self,expr: PipeExpr) -> Result<Vec<Vec<PipeExpr>>,PipeError> {
+        self.assert(expr)?;
+        write!(self.writer,"(apply qe)\n")?;
+        self.writer.flush()?;
+        smt_response(self,parse_goals)
     }
     fn fmt_var(&mut self,var: &PipeVar) -> Result<(),PipeError> {
         match self.vars[var.0].1 {
             None => write!(self.writer,"v{}",var.0)?,
-            Some(ref name) => write!(self.writer,"{}",name)?
+            Some(ref name) => write!(self.writer,"{}",SymbolName(name))?
+        }
+        Ok(())
+    }
+    fn fmt_fun(&mut self,fun: &Function<PipeSort,PipeFun>) -> Result<(),PipeError> {
+        match *fun {
+            Function::Fun(ref f) => match self.funs.get(f) {
+                Some(&(_,_,Some(ref name))) => write!(self.writer,"{}",SymbolName(name))?,
+                _ => write!(self.writer,"{}{}",PIPE_FUN_NAME,f)?
+            },
+            Function::Map(ref g,_) => {
+                write!(self.writer,"(_ map ")?;
+                self.fmt_fun(g)?;
+                write!(self.writer,")")?;
+            },
+            _ => write!(self.writer,"{}",fun)?
+        }
+        Ok(())
+    }
+    fn fmt_params(&mut self,params: &[NVar<PipeSort>]) -> Result<(),PipeError> {
+        write!(self.writer,"(")?;
+        for (i,par) in params.iter().enumerate() {
+            if i>0 {
+                write!(self.writer," ")?;
+            }
+            write!(self.writer,"(qv{} {})",par.id,par.sort)?;
         }
+        write!(self.writer,")")?;
         Ok(())
     }
-    fn fmt_expr(&mut self,e: &PipeExpr) -> Result<(),PipeError> {
+    pub(crate) fn fmt_expr(&mut self,e: &PipeExpr) -> Result<(),PipeError> {
         match e.0.get() {
             &Expr::Var(ref v) => self.fmt_var(v)?,
             &Expr::QVar(ref v) => write!(self.writer,"qv{}",v.id)?,
             &Expr::LVar(ref v) => write!(self.writer,"lv{}",v.id)?,
             &Expr::Const(ref c) => write!(self.writer,"{}",c)?,
-            &Expr::App(ref fun,ref args) => {
-                write!(self.writer,"({}",fun)?;
+            &Expr::App(ref fun,ref args) => if args.len()==0 {
+                self.fmt_fun(fun)?;
+            } else {
+                write!(self.writer,"(")?;
+                self.fmt_fun(fun)?;
                 for arg in args.iter() {
                     write!(self.writer," ")?;
                     self.fmt_expr(&arg)?;
                 }
                 write!(self.writer,")")?;
             },
-            &Expr::AsArray(ref fun) => write!(self.writer,"(_ as-array {})",fun)?,
+            &Expr::AsArray(ref fun) => {
+                write!(self.writer,"(_ as-array ")?;
+                self.fmt_fun(fun)?;
+                write!(self.writer,")")?;
+            },
             &Expr::Exists(ref vars,ref body) => {
-                write!(self.writer,"(exists (")?;
-                for var in vars.iter() {
-                    write!(self.writer,"(qv{} {}) ",var.id,var.sort)?;
-                }
-                write!(self.writer,") {})",body)?;
+                write!(self.writer,"(exists ")?;
+                self.fmt_params(vars)?;
+                write!(self.writer," ")?;
+                self.fmt_expr(body)?;
+                write!(self.writer,")")?;
             },
             &Expr::Forall(ref vars,ref body) => {
-                write!(self.writer,"(forall (")?;
-                for var in vars.iter() {
-                    write!(self.writer,"(qv{} {}) ",var.id,var.sort)?;
-                }
-                write!(self.writer,") {})",body)?;
+                write!(self.writer,"(forall ")?;
+                self.fmt_params(vars)?;
+                write!(self.writer," ")?;
+                self.fmt_expr(body)?;
+                write!(self.writer,")")?;
             },
             &Expr::Let(ref vars,ref body) => {
                 write!(self.writer,"(let (")?;
                 for &(ref var,ref bind) in vars.iter() {
-                    write!(self.writer,"(lv{} {}) ",var.id,bind)?;
+                    write!(self.writer,"(lv{} ",var.id)?;
+                    self.fmt_expr(bind)?;
+                    write!(self.writer,") ")?;
                 }
-                write!(self.writer,") {})",body)?;
+                write!(self.writer,") ")?;
+                self.fmt_expr(body)?;
+                write!(self.writer,")")?;
             }
         }
         Ok(())
@@ -118,22 +707,45 @@ impl<Inp : Read,Outp : Write> Pipe<Inp,Outp> {
 impl Pipe<ChildStdout,ChildStdin> {
     pub fn new_process(bin: &str,args: &[&str])
                        -> Result<Pipe<ChildStdout,ChildStdin>,Error> {
-        let child = Command::new(bin)
+        let mut child = Command::new(bin)
             .args(args)
             .stdin(Stdio::piped())
             .stdout(Stdio::piped())
             .stderr(Stdio::inherit())
             .spawn()?;
-        Ok(Pipe::new(child.stdout.expect("Process API misbehaving"),
-                     child.stdin.expect("Process API misbehaving")))
+        let mut pipe = Pipe::new(child.stdout.take().expect("Process API misbehaving"),
+                                 child.stdin.take().expect("Process API misbehaving"));
+        pipe.child = Some(Arc::new(Mutex::new(child)));
+        Ok(pipe)
+    }
+    /// Start the solver described by a profile.
+    pub fn with_profile(profile: &SolverProfile)
+                        -> Result<Pipe<ChildStdout,ChildStdin>,Error> {
+        let mut pipe = Pipe::new_process(profile.binary,profile.args)?;
+        pipe.set_profile(profile);
+        Ok(pipe)
     }
 }
 
 #[derive(Debug)]
 pub enum PipeError {
     Parse(ParseError<PipeSort>),
+    /// A malformed response of the solver
+    Syntax(SyntaxError<PipeSort>),
     IO(Error),
-    Format(fmt::Error)
+    Format(fmt::Error),
+    /// An option or command which is not supported by the solver. The name
+    /// is empty if the solver replied `unsupported` to an unknown command.
+    Unsupported(String),
+    /// An `(error "...")` response of the solver
+    Solver(String),
+    /// The solver needs a logic to be set before declarations
+    LogicRequired,
+    /// The logic was set twice or after declarations
+    MisplacedSetLogic,
+    /// A symbol name which cannot be quoted, is already declared or clashes
+    /// with generated names or predefined symbols
+    InvalidName(String)
 }
 
 pub struct DebugWrite<W : Write>(W);
@@ -156,12 +768,29 @@ impl<W : Write> Write for DebugWrite<W> {
 impl<R : Read, W : Write> Pipe<R,W> {
     pub fn debug_write(self) -> Pipe<R,DebugWrite<W>> {
         Pipe { reader: self.reader,
+               response: self.response,
                writer: DebugWrite(self.writer),
                sorts: self.sorts,
                vars: self.vars,
                named_vars: self.named_vars,
                exprs: self.exprs,
-               funs: self.funs }
+               funs: self.funs,
+               named_funs: self.named_funs,
+               datatypes: self.datatypes,
+               declared_sorts: self.declared_sorts,
+               defined_sorts: self.defined_sorts,
+               named_assertions: self.named_assertions,
+               interpolation: self.interpolation,
+               interpolants: self.interpolants,
+               qe: self.qe,
+               objectives: self.objectives,
+               soft_groups: self.soft_groups,
+               profile: self.profile,
+               print_success: self.print_success,
+               child: self.child,
+               unknown_reason: self.unknown_reason,
+               logic: self.logic,
+               declared: self.declared }
     }
 }
 
@@ -199,20 +828,20 @@ impl<R : Read, W : Write> Embed for Pipe<R,W> {
     fn type_of_fun(&mut self,f: &PipeFun)
                    -> Result<PipeSort,PipeError> {
         match self.funs.get(f) {
-            Some(&(_,ref srt)) => Ok(srt.clone()),
+            Some(&(_,ref srt,_)) => Ok(srt.clone()),
             None => panic!("Getting type of undeclared function")
         }
     }
     fn type_of_arg(&mut self,f: &PipeFun,arg: usize)
                    -> Result<PipeSort,PipeError> {
         match self.funs.get(f) {
-            Some(&(ref args,_)) => Ok(args[arg].clone()),
+            Some(&(ref args,_,_)) => Ok(args[arg].clone()),
             None => panic!("Getting argument type of undeclared function")
         }
     }
     fn arity(&mut self,f: &PipeFun) -> Result<usize,PipeError> {
         match self.funs.get(f) {
-            Some(&(ref args,_)) => Ok(args.len()),
+            Some(&(ref args,_,_)) => Ok(args.len()),
             None => panic!("Getting arity of undeclared function")
         }
     }
@@ -220,6 +849,10 @@ impl<R : Read, W : Write> Embed for Pipe<R,W> {
                    -> Result<PipeSort,PipeError> {
         Ok(self.vars[v.0].0.clone())
     }
+    fn datatype(&mut self,dt: usize)
+                -> Result<Datatype<PipeSort>,PipeError> {
+        Ok(self.datatypes[dt].clone())
+    }
 }
 
 impl fmt::Display for PipeVar {
@@ -253,43 +886,160 @@ impl From<fmt::Error> for PipeError {
 
 fn smt_response<R : Read,W : Write,T,F>(p: &mut Pipe<R,W>,parse: F) -> Result<T,PipeError>
     where F : for<'inp> Fn(&'inp[u8],&mut Pos,&mut Pipe<R,W>) -> PResult<'inp,T,Pipe<R,W>> {
+    // The parser needs access to the pipe, so the buffered output is taken
+    // out of it while parsing.
+    let mut rd = mem::replace(&mut p.response,SExprReader::new());
+    let res = smt_response_buffered(p,&mut rd,parse);
+    p.response = rd;
+    res
+}
 
-    let mut buf = Vec::with_capacity(1024);
-    let mut pos = 0;
-    buf.resize(1024,0);
+fn smt_response_buffered<R : Read,W : Write,T,F>(p: &mut Pipe<R,W>,rd: &mut SExprReader,parse: F)
+                                                 -> Result<T,PipeError>
+    where F : for<'inp> Fn(&'inp[u8],&mut Pos,&mut Pipe<R,W>) -> PResult<'inp,T,Pipe<R,W>> {
     loop {
-        if pos==buf.len() {
-            buf.resize(pos+1024,0);
+        // Only parse once another complete expression is available
+        if !rd.read_item(&mut p.reader)? {
+            return Err(PipeError::IO(Error::new(ErrorKind::UnexpectedEof,
+                                                "Solver closed its output")))
         }
-        let sz = p.reader.read(&mut buf[pos..])?;
-        pos+=sz;
-
+        rd.skip_space();
+        let inp = rd.pending();
         let mut syn_pos = Pos { line: 0, col: 0 };
-        match parse(&buf[0..pos],&mut syn_pos,p) {
-            PResult::Done(res,_) => return Ok(res),
+        // Skip acknowledgements and report errors
+        let rest = match parse_general_response(inp,&mut syn_pos,p) {
+            PResult::Done(Some(GeneralResponse::Success),ninp) => {
+                let n = inp.len()-ninp.len();
+                rd.consume(n);
+                continue
+            },
+            PResult::Done(Some(GeneralResponse::Unsupported),ninp) => {
+                let n = inp.len()-ninp.len();
+                rd.consume(n);
+                return Err(PipeError::Unsupported(String::new()))
+            },
+            PResult::Done(Some(GeneralResponse::Error(msg)),ninp) => {
+                let n = inp.len()-ninp.len();
+                rd.consume(n);
+                return Err(PipeError::Solver(msg))
+            },
+            PResult::Done(None,ninp) => ninp,
             PResult::Incomplete => continue,
-            PResult::SyntaxError(err) => return Err(PipeError::Parse(err)),
-            PResult::EmbedError(err) => return Err(err)
+            PResult::SyntaxError(err,epos) => {
+                let err = SyntaxError::new(err,epos,inp);
+                rd.clear();
+                return Err(PipeError::Syntax(err))
+            },
+            PResult::EmbedError(err) => {
+                rd.consume_complete();
+                return Err(err)
+            }
+        };
+        match parse(rest,&mut syn_pos,p) {
+            PResult::Done(res,ninp) => {
+                let n = inp.len()-ninp.len();
+                rd.consume(n);
+                return Ok(res)
+            },
+            // The response consists of more than one expression
+            PResult::Incomplete => {},
+            PResult::SyntaxError(err,epos) => {
+                let err = SyntaxError::new(err,epos,inp);
+                rd.clear();
+                return Err(PipeError::Syntax(err))
+            },
+            PResult::EmbedError(err) => {
+                rd.consume_complete();
+                return Err(err)
+            }
         }
     }
 }
 
+/// Read the `success` response to a command.
+fn smt_ack<R : Read,W : Write>(p: &mut Pipe<R,W>) -> Result<(),PipeError> {
+    let mut rd = mem::replace(&mut p.response,SExprReader::new());
+    let res = smt_ack_buffered(p,&mut rd);
+    p.response = rd;
+    res
+}
+
+fn smt_ack_buffered<R : Read,W : Write>(p: &mut Pipe<R,W>,rd: &mut SExprReader)
+                                        -> Result<(),PipeError> {
+    loop {
+        if !rd.read_item(&mut p.reader)? {
+            return Err(PipeError::IO(Error::new(ErrorKind::UnexpectedEof,
+                                                "Solver closed its output")))
+        }
+        rd.skip_space();
+        let inp = rd.pending();
+        let mut syn_pos = Pos { line: 0, col: 0 };
+        let (res,ninp) = match parse_general_response(inp,&mut syn_pos,p) {
+            PResult::Done(Some(GeneralResponse::Success),ninp) => (Ok(()),ninp),
+            PResult::Done(Some(GeneralResponse::Unsupported),ninp)
+                => (Err(PipeError::Unsupported(String::new())),ninp),
+            PResult::Done(Some(GeneralResponse::Error(msg)),ninp)
+                => (Err(PipeError::Solver(msg)),ninp),
+            PResult::Done(None,_) => {
+                rd.consume_complete();
+                return Err(PipeError::Parse(ParseError::ExpectedLiteral(b"success")))
+            },
+            PResult::Incomplete => continue,
+            PResult::SyntaxError(err,epos) => {
+                let err = SyntaxError::new(err,epos,inp);
+                rd.clear();
+                return Err(PipeError::Syntax(err))
+            },
+            PResult::EmbedError(err) => {
+                rd.consume_complete();
+                return Err(err)
+            }
+        };
+        let n = inp.len()-ninp.len();
+        rd.consume(n);
+        return res
+    }
+}
+
 impl<R : Read,W : Write> Backend for Pipe<R,W> {
+    fn unsupported(what: &str) -> PipeError {
+        PipeError::Unsupported(String::from(what))
+    }
     fn enable_models(&mut self) -> Result<(),PipeError> {
-        write!(self.writer,"(set-option :produce-models true)\n")
-            .map_err(PipeError::IO)
+        self.enable_option(":produce-models")
     }
     fn enable_proofs(&mut self) -> Result<(),PipeError> {
-        write!(self.writer,"(set-option :produce-proofs true)\n")
-            .map_err(PipeError::IO)
+        self.enable_option(":produce-proofs")
     }
     fn enable_unsat_cores(&mut self) -> Result<(),PipeError> {
-        write!(self.writer,"(set-option :produce-unsat-cores true)\n")
-            .map_err(PipeError::IO)
+        self.enable_option(":produce-unsat-cores")
     }
     fn enable_interpolants(&mut self) -> Result<(),PipeError> {
-        write!(self.writer,"(set-option :produce-interpolants true)\n")
-            .map_err(PipeError::IO)
+        self.enable_option(":produce-interpolants")
+    }
+    fn set_logic(&mut self,logic: Logic) -> Result<(),PipeError> {
+        if self.declared || self.logic.is_some() {
+            return Err(PipeError::MisplacedSetLogic)
+        }
+        write!(self.writer,"(set-logic {})\n",logic)?;
+        self.logic = Some(logic);
+        self.ack()
+    }
+    fn set_option(&mut self,name: &str,value: OptionValue) -> Result<(),PipeError> {
+        write!(self.writer,"(set-option {} {})\n",name,value)?;
+        if name==":print-success" {
+            self.print_success = value==OptionValue::Bool(true);
+        }
+        self.ack()
+    }
+    fn get_option(&mut self,name: &str) -> Result<OptionValue,PipeError> {
+        write!(self.writer,"(get-option {})\n",name)?;
+        self.writer.flush()?;
+        smt_response(self,parse_option_value)
+    }
+    fn set_info(&mut self,name: &str,value: OptionValue) -> Result<(),PipeError> {
+        write!(self.writer,"(set-info {} {})\n",name,value)?;
+        self.ack()
     }
     fn solver_name(&mut self) -> Result<String,PipeError> {
         write!(self.writer,"(get-info :name)\n")?;
@@ -301,51 +1051,100 @@ impl<R : Read,W : Write> Backend for Pipe<R,W> {
         self.writer.flush()?;
         smt_response(self,parse_info_response_version)
     }
+    fn reason_unknown(&mut self) -> Result<String,PipeError> {
+        if let Some(ref reason) = self.unknown_reason {
+            return Ok(reason.clone())
+        }
+        write!(self.writer,"(get-info :reason-unknown)\n")?;
+        self.writer.flush()?;
+        smt_response(self,parse_info_response_reason_unknown)
+    }
+    fn statistics(&mut self) -> Result<HashMap<String,StatValue>,PipeError> {
+        write!(self.writer,"(get-info :all-statistics)\n")?;
+        self.writer.flush()?;
+        smt_response(self,parse_statistics)
+    }
     fn comment(&mut self,comment: &str) -> Result<(),PipeError> {
         write!(self.writer,"; {}\n",comment).map_err(PipeError::IO)
     }
     fn push(&mut self) -> Result<(),PipeError> {
-        write!(self.writer,"(push 1)\n").map_err(PipeError::IO)
+        write!(self.writer,"(push 1)\n")?;
+        self.ack()
     }
     fn pop(&mut self) -> Result<(),PipeError> {
-        write!(self.writer,"(pop 1)\n").map_err(PipeError::IO)
+        write!(self.writer,"(pop 1)\n")?;
+        self.ack()
     }
     fn declare_var(&mut self,tp: PipeSort,name: Option<String>) -> Result<PipeVar,PipeError> {
+        if let Some(ref name) = name {
+            self.check_new_name(name)?;
+        }
+        self.declaration()?;
         match name {
             None => {
                 let vid = self.vars.len();
                 write!(self.writer,"(declare-fun {}{} () {})\n",PIPE_VAR_NAME,vid,tp)?;
                 self.vars.push((tp,None));
+                self.ack()?;
                 Ok(PipeVar(vid))
             },
             Some(name) => {
                 let vid = self.vars.len();
-                write!(self.writer,"(declare-fun {} () {})\n",name,tp)?;
+                write!(self.writer,"(declare-fun {} () {})\n",SymbolName(&name),tp)?;
                 self.vars.push((tp,Some(name.clone())));
-                match self.named_vars.entry(name) {
-                    Entry::Occupied(_) => panic!("Cannot declare two variables with the same name"),
-                    Entry::Vacant(v) => { v.insert(vid); }
-                }
+                self.named_vars.insert(name,vid);
+                self.ack()?;
                 Ok(PipeVar(vid))
             }
         }
     }
     fn define_var(&mut self,e: PipeExpr) -> Result<PipeVar,PipeError> {
+        self.declaration()?;
         let vid = self.vars.len();
         let tp = self.type_of(&e)?;
         write!(self.writer,"(define-fun {}{} () {} ",PIPE_VAR_NAME,vid,tp)?;
         self.fmt_expr(&e)?;
         write!(self.writer,")\n")?;
         self.vars.push((tp,None));
+        self.ack()?;
         Ok(PipeVar(vid))
     }
     fn assert(&mut self,expr: PipeExpr) -> Result<(),PipeError> {
+        self.declaration()?;
         write!(self.writer,"(assert ")?;
         self.fmt_expr(&expr)?;
         write!(self.writer,")\n")?;
-        Ok(())
+        self.ack()
+    }
+    fn assert_named(&mut self,expr: PipeExpr) -> Result<AssertionId,PipeError> {
+        self.declaration()?;
+        let aid = self.named_assertions;
+        write!(self.writer,"(assert (! ")?;
+        self.fmt_expr(&expr)?;
+        write!(self.writer," :named {}{}))\n",PIPE_ASSERTION_NAME,aid)?;
+        self.named_assertions+=1;
+        self.ack()?;
+        Ok(AssertionId(aid))
+    }
+    fn get_unsat_core(&mut self) -> Result<Vec<AssertionId>,PipeError> {
+        write!(self.writer,"(get-unsat-core)\n")?;
+        self.writer.flush()?;
+        let names = smt_response(self,parse_unsat_core)?;
+        let pref = PIPE_ASSERTION_NAME.len();
+        let mut res = Vec::with_capacity(names.len());
+        for name in names.iter() {
+            if name.len() <= pref || &name[0..pref] != PIPE_ASSERTION_NAME {
+                return Err(PipeError::Parse(ParseError::UnknownAssertion))
+            }
+            match FromStr::from_str(&name[pref..]) {
+                Err(_) => return Err(PipeError::Parse(ParseError::UnknownAssertion)),
+                Ok(n) => res.push(AssertionId(n))
+            }
+        }
+        Ok(res)
     }
     fn check_sat(&mut self) -> Result<CheckSatResult,PipeError> {
+        self.unknown_reason = None;
         write!(self.writer,"(check-sat)\n")?;
         self.writer.flush()?;
         smt_response(self,parse_checksat_result)
@@ -358,39 +1157,369 @@ impl<R : Read,W : Write> Backend for Pipe<R,W> {
         let hint = self.type_of(&expr)?;
         smt_response(self,|inp,pos,p| parse_get_value_result(inp,pos,p,&hint))
     }
+    fn check_sat_assuming(&mut self,assumptions: &[PipeExpr]) -> Result<CheckSatResult,PipeError> {
+        self.unknown_reason = None;
+        write!(self.writer,"(check-sat-assuming (")?;
+        for (i,e) in assumptions.iter().enumerate() {
+            if i>0 {
+                write!(self.writer," ")?;
+            }
+            self.fmt_expr(e)?;
+        }
+        write!(self.writer,"))\n")?;
+        self.writer.flush()?;
+        smt_response(self,parse_checksat_result)
+    }
+    fn get_unsat_assumptions(&mut self) -> Result<Vec<PipeExpr>,PipeError> {
+        write!(self.writer,"(get-unsat-assumptions)\n")?;
+        self.writer.flush()?;
+        smt_response(self,parse_unsat_assumptions)
+    }
+    fn get_proof(&mut self) -> Result<SExpr,PipeError> {
+        write!(self.writer,"(get-proof)\n")?;
+        self.writer.flush()?;
+        smt_response(self,parse_sexpr)
+    }
+    fn get_interpolant(&mut self,a: &[PipeExpr],b: &[PipeExpr])
+                       -> Result<Option<PipeExpr>,PipeError> {
+        if let Some(ref profile) = self.profile {
+            if profile.interpolation.is_none() {
+                return Err(PipeError::Unsupported(String::from("get-interpolants")))
+            }
+        }
+        self.push()?;
+        let res = self.interpolant_query(a,b);
+        // Always remove the temporary assertions, even if the query failed
+        let popped = self.pop();
+        let res = res?;
+        popped?;
+        Ok(res)
+    }
+    fn eliminate_quantifiers(&mut self,expr: PipeExpr) -> Result<PipeExpr,PipeError> {
+        if let Some(ref profile) = self.profile {
+            if profile.qe.is_none() {
+                return Err(PipeError::Unsupported(String::from("qe")))
+            }
+        }
+        match self.qe {
+            QeDialect::ApplyQe => {
+                self.push()?;
+                let goals = self.apply_qe(expr);
+                // Always remove the asserted formula, even if the tactic failed
+                let popped = self.pop();
+                let goals = goals?;
+                popped?;
+                // The result is the disjunction of the resulting goals
+                let mut disj = Vec::with_capacity(goals.len());
+                for goal in goals.into_iter() {
+                    let conj = match goal.len() {
+                        0 => self.const_bool(true)?,
+                        1 => goal.into_iter().next().unwrap(),
+                        _ => self.and(goal)?
+                    };
+                    disj.push(conj);
+                }
+                match disj.len() {
+                    0 => self.const_bool(false),
+                    1 => Ok(disj.into_iter().next().unwrap()),
+                    _ => self.or(disj)
+                }
+            },
+            QeDialect::GetQe => {
+                write!(self.writer,"(get-qe ")?;
+                self.fmt_expr(&expr)?;
+                write!(self.writer,")\n")?;
+                self.writer.flush()?;
+                let tbool = self.tp_bool()?;
+                smt_response(self,|inp,pos,p| parse_expr(inp,pos,p,Some(&tbool)))
+            }
+        }
+    }
+    fn solver_simplify(&mut self,expr: PipeExpr) -> Result<PipeExpr,PipeError> {
+        if let Some(ref profile) = self.profile {
+            if !profile.simplify {
+                return Err(PipeError::Unsupported(String::from("simplify")))
+            }
+        }
+        write!(self.writer,"(simplify ")?;
+        self.fmt_expr(&expr)?;
+        write!(self.writer,")\n")?;
+        self.writer.flush()?;
+        let hint = self.type_of(&expr)?;
+        smt_response(self,|inp,pos,p| parse_expr(inp,pos,p,Some(&hint)))
+    }
+    fn get_values(&mut self,exprs: Vec<PipeExpr>) -> Result<Vec<Value>,PipeError> {
+        if exprs.len()==0 {
+            return Ok(vec![])
+        }
+        let mut hints = Vec::with_capacity(exprs.len());
+        write!(self.writer,"(get-value (")?;
+        for (i,e) in exprs.iter().enumerate() {
+            if i>0 {
+                write!(self.writer," ")?;
+            }
+            self.fmt_expr(e)?;
+            hints.push(self.type_of(e)?);
+        }
+        write!(self.writer,"))\n")?;
+        self.writer.flush()?;
+        smt_response(self,|inp,pos,p| parse_get_values_result(inp,pos,p,&hints))
+    }
+    fn get_model(&mut self)
+                 -> Result<Model<PipeSort,PipeVar,PipeExpr,PipeFun>,PipeError> {
+        write!(self.writer,"(get-model)\n")?;
+        self.writer.flush()?;
+        smt_response(self,parse_model)
+    }
+    fn declare_datatypes(&mut self,dts: Vec<Datatype<FieldSort<PipeSort>>>)
+                         -> Result<usize,PipeError> {
+        self.declaration()?;
+        let base = self.datatypes.len();
+        let mut ndts = Vec::with_capacity(dts.len());
+        for dt in dts.iter() {
+            let mut cons = Vec::with_capacity(dt.constructors.len());
+            for con in dt.constructors.iter() {
+                let mut fields = Vec::with_capacity(con.len());
+                for field in con.iter() {
+                    fields.push(field.resolve(base,self)?);
+                }
+                cons.push(fields);
+            }
+            ndts.push(Datatype { params: dt.params,
+                                 constructors: cons });
+        }
+        write!(self.writer,"(declare-datatypes (")?;
+        for (i,dt) in ndts.iter().enumerate() {
+            write!(self.writer,"(d{} {})",base+i,dt.params)?;
+        }
+        write!(self.writer,") (")?;
+        for (i,dt) in ndts.iter().enumerate() {
+            if dt.params>0 {
+                write!(self.writer,"(par (")?;
+                for p in 0..dt.params {
+                    if p>0 {
+                        write!(self.writer," ")?;
+                    }
+                    write!(self.writer,"p{}",p)?;
+                }
+                write!(self.writer,") ")?;
+            }
+            write!(self.writer,"(")?;
+            for (c,con) in dt.constructors.iter().enumerate() {
+                write!(self.writer,"(d{}c{}",base+i,c)?;
+                for (f,field) in con.iter().enumerate() {
+                    write!(self.writer," (d{}c{}s{} {})",base+i,c,f,field)?;
+                }
+                write!(self.writer,")")?;
+            }
+            write!(self.writer,")")?;
+            if dt.params>0 {
+                write!(self.writer,")")?;
+            }
+        }
+        write!(self.writer,"))\n")?;
+        self.datatypes.extend(ndts);
+        self.ack()?;
+        Ok(base)
+    }
+    fn declare_fun(&mut self,args: Vec<PipeSort>,ret: PipeSort,name: Option<String>)
+                   -> Result<PipeFun,PipeError> {
+        if let Some(ref name) = name {
+            self.check_new_name(name)?;
+        }
+        self.declaration()?;
+        let fid = self.funs.len();
+        match name {
+            None => write!(self.writer,"(declare-fun {}{} (",PIPE_FUN_NAME,fid)?,
+            Some(ref name) => {
+                write!(self.writer,"(declare-fun {} (",SymbolName(name))?;
+                self.named_funs.insert(name.clone(),fid);
+            }
+        }
+        for (i,arg) in args.iter().enumerate() {
+            if i>0 {
+                write!(self.writer," ")?;
+            }
+            write!(self.writer,"{}",arg)?;
+        }
+        write!(self.writer,") {})\n",ret)?;
+        self.funs.insert(fid,(args,ret,name));
+        self.ack()?;
+        Ok(fid)
+    }
+    fn define_fun(&mut self,params: Vec<NVar<PipeSort>>,body: PipeExpr)
+                  -> Result<PipeFun,PipeError> {
+        self.declaration()?;
+        let fid = self.funs.len();
+        let ret = self.type_of(&body)?;
+        write!(self.writer,"(define-fun {}{} ",PIPE_FUN_NAME,fid)?;
+        self.fmt_params(&params)?;
+        write!(self.writer," {} ",ret)?;
+        self.fmt_expr(&body)?;
+        write!(self.writer,")\n")?;
+        self.funs.insert(fid,(params.into_iter().map(|p| p.sort).collect(),ret,None));
+        self.ack()?;
+        Ok(fid)
+    }
+    fn declare_funs_rec(&mut self,sigs: &[(Vec<NVar<PipeSort>>,PipeSort)])
+                        -> Result<Vec<PipeFun>,PipeError> {
+        let base = self.funs.len();
+        let funs : Vec<PipeFun> = (base..base+sigs.len()).collect();
+        for (&fid,&(ref params,ref ret)) in funs.iter().zip(sigs.iter()) {
+            let args = params.iter().map(|p| p.sort.clone()).collect();
+            self.funs.insert(fid,(args,ret.clone(),None));
+        }
+        Ok(funs)
+    }
+    fn emit_funs_rec(&mut self,funs: &[PipeFun],sigs: &[(Vec<NVar<PipeSort>>,PipeSort)],
+                     exprs: Vec<PipeExpr>) -> Result<(),PipeError> {
+        debug_assert_eq!(exprs.len(),funs.len());
+        self.declaration()?;
+        if funs.len()==1 {
+            write!(self.writer,"(define-fun-rec {}{} ",PIPE_FUN_NAME,funs[0])?;
+            self.fmt_params(&sigs[0].0)?;
+            write!(self.writer," {} ",sigs[0].1)?;
+            self.fmt_expr(&exprs[0])?;
+            write!(self.writer,")\n")?;
+        } else {
+            write!(self.writer,"(define-funs-rec (")?;
+            for (&fid,&(ref params,ref ret)) in funs.iter().zip(sigs.iter()) {
+                write!(self.writer,"({}{} ",PIPE_FUN_NAME,fid)?;
+                self.fmt_params(params)?;
+                write!(self.writer," {})",ret)?;
+            }
+            write!(self.writer,") (")?;
+            for (i,e) in exprs.iter().enumerate() {
+                if i>0 {
+                    write!(self.writer," ")?;
+                }
+                self.fmt_expr(e)?;
+            }
+            write!(self.writer,"))\n")?;
+        }
+        self.ack()
+    }
+    fn declare_sort(&mut self,arity: usize) -> Result<usize,PipeError> {
+        self.declaration()?;
+        let id = self.declared_sorts.len();
+        write!(self.writer,"(declare-sort u{} {})\n",id,arity)?;
+        self.declared_sorts.push(arity);
+        self.ack()?;
+        Ok(id)
+    }
+    fn define_sort(&mut self,params: usize,body: PipeSort)
+                   -> Result<usize,PipeError> {
+        // Aliases are expanded before anything is sent to the solver, so
+        // there is no need to tell it about them.
+        let id = self.defined_sorts.len();
+        self.defined_sorts.push((params,body));
+        Ok(id)
+    }
+    fn defined_sort(&mut self,id: usize,args: Vec<PipeSort>)
+                    -> Result<PipeSort,PipeError> {
+        let (params,body) = match self.defined_sorts.get(id) {
+            Some(def) => def.clone(),
+            None => return Err(PipeError::Parse(ParseError::UnknownSort))
+        };
+        debug_assert_eq!(params,args.len());
+        self.instantiate_sort(&body,&args)
+    }
+}
+
+impl<R : Read,W : Write> Optimizer for Pipe<R,W> {
+    fn minimize(&mut self,expr: PipeExpr) -> Result<ObjectiveId,PipeError> {
+        write!(self.writer,"(minimize ")?;
+        self.fmt_expr(&expr)?;
+        write!(self.writer,")\n")?;
+        let srt = self.type_of(&expr)?;
+        self.objectives.push(srt);
+        self.ack()?;
+        Ok(ObjectiveId(self.objectives.len()-1))
+    }
+    fn maximize(&mut self,expr: PipeExpr) -> Result<ObjectiveId,PipeError> {
+        write!(self.writer,"(maximize ")?;
+        self.fmt_expr(&expr)?;
+        write!(self.writer,")\n")?;
+        let srt = self.type_of(&expr)?;
+        self.objectives.push(srt);
+        self.ack()?;
+        Ok(ObjectiveId(self.objectives.len()-1))
+    }
+    fn assert_soft(&mut self,expr: PipeExpr,weight: BigUint,group: Option<&str>)
+                   -> Result<ObjectiveId,PipeError> {
+        if let Some(name) = group {
+            check_symbol_name(name)?;
+        }
+        write!(self.writer,"(assert-soft ")?;
+        self.fmt_expr(&expr)?;
+        write!(self.writer," :weight {}",weight)?;
+        if let Some(name) = group {
+            write!(self.writer," :id {}",SymbolName(name))?;
+        }
+        write!(self.writer,")\n")?;
+        self.ack()?;
+        let key = match group {
+            None => String::new(),
+            Some(name) => String::from(name)
+        };
+        match self.soft_groups.entry(key) {
+            Entry::Occupied(occ) => Ok(ObjectiveId(*occ.get())),
+            Entry::Vacant(vac) => {
+                let srt = self.sorts.get(SortKind::Int);
+                self.objectives.push(PipeSort(srt));
+                vac.insert(self.objectives.len()-1);
+                Ok(ObjectiveId(self.objectives.len()-1))
+            }
+        }
+    }
+    fn get_objectives(&mut self) -> Result<Vec<ObjectiveValue>,PipeError> {
+        write!(self.writer,"(get-objectives)\n")?;
+        self.writer.flush()?;
+        let hints = self.objectives.clone();
+        smt_response(self,|inp,pos,p| parse_objectives(inp,pos,p,&hints))
+    }
 }
 
 impl<R : Read,W : Write> Parser for Pipe<R,W> {
     fn parse_var(&mut self,inp: &[u8]) -> Result<PipeVar,PipeError> {
         let pref = PIPE_VAR_NAME.len();
-        match str::from_utf8(inp) {
-            Err(_) => Err(PipeError::Parse(ParseError::UnknownVar)),
-            Ok(nstr) => if nstr.len() < pref || &nstr[0..pref] != PIPE_VAR_NAME {
-                let name = nstr.to_string();
-                match self.named_vars.get(&name) {
-                    None => Err(PipeError::Parse(ParseError::UnknownVar)),
-                    Some(n) => Ok(PipeVar(*n))
-                }
-            } else {
-                match FromStr::from_str(&nstr[pref..]) {
-                    Err(_) => Err(PipeError::Parse(ParseError::UnknownVar)),
-                    Ok(n) => Ok(PipeVar(n))
-                }
-            }
+        let nstr = match str::from_utf8(inp) {
+            Err(_) => return Err(PipeError::Parse(ParseError::UnknownVar)),
+            Ok(nstr) => nstr
+        };
+        if let Some(n) = self.named_vars.get(nstr) {
+            return Ok(PipeVar(*n))
+        }
+        if nstr.len() <= pref || &nstr[0..pref] != PIPE_VAR_NAME {
+            return Err(PipeError::Parse(ParseError::UnknownVar))
+        }
+        match FromStr::from_str(&nstr[pref..]) {
+            // Named variables are never printed with their generated name
+            Ok(n) => match self.vars.get(n) {
+                Some(&(_,None)) => Ok(PipeVar(n)),
+                _ => Err(PipeError::Parse(ParseError::UnknownVar))
+            },
+            Err(_) => Err(PipeError::Parse(ParseError::UnknownVar))
         }
     }
     fn parse_fun(&mut self,inp: &[u8]) -> Result<PipeFun,PipeError> {
         let pref = PIPE_FUN_NAME.len();
-        match str::from_utf8(&inp[pref..]) {
-            Err(_) => Err(PipeError::Parse(ParseError::UnknownFun)),
-            Ok(nstr) => if nstr.len() < pref {
-                Err(PipeError::Parse(ParseError::UnknownFun))
-            } else {
-                match FromStr::from_str(&nstr[pref..]) {
-                    Err(_) => Err(PipeError::Parse(ParseError::UnknownFun)),
-                    Ok(n) => Ok(n)
-                }
-            }
+        let nstr = match str::from_utf8(inp) {
+            Err(_) => return Err(PipeError::Parse(ParseError::UnknownFun)),
+            Ok(nstr) => nstr
+        };
+        if let Some(n) = self.named_funs.get(nstr) {
+            return Ok(*n)
+        }
+        if nstr.len() <= pref || &nstr[0..pref] != PIPE_FUN_NAME {
+            return Err(PipeError::Parse(ParseError::UnknownFun))
+        }
+        match FromStr::from_str(&nstr[pref..]) {
+            Ok(n) => match self.funs.get(&n) {
+                Some(&(_,_,None)) => Ok(n),
+                _ => Err(PipeError::Parse(ParseError::UnknownFun))
+            },
+            Err(_) => Err(PipeError::Parse(ParseError::UnknownFun))
         }
     }
 
@@ -399,13 +1528,13 @@ impl<R : Read,W : Write> Parser for Pipe<R,W> {
 #[cfg(feature="test-z3")]
 #[test]
 fn test_z3() {
-    let mut solver = Pipe::new_process("z3",&["-smt2","-in"])
+    let mut solver = Pipe::with_profile(&profile::Z3)
         .expect("Cannot create Z3 solver").debug_write();
     solver.enable_models().expect("Cannot enable models");
     let name = solver.solver_name().expect("Cannot get solver name");
     assert_eq!(name,"Z3".to_string());
     let vers = solver.solver_version().expect("Cannot get solver version");
-    assert_eq!(vers,"4.4.1".to_string());
+    assert!(vers.len()>0);
     let tint = solver.tp_int().expect("Cannot create inttype");
     let v1 = solver.declare(tint.clone()).expect("Cannot declare var");
     let v2 = solver.declare(tint).expect("Cannot declare var");
@@ -421,3 +1550,591 @@ fn test_z3() {
     let res2 = solver.check_sat().expect("Cannot checksat");
     assert_eq!(res2,CheckSatResult::Unsat);
 }
+
+#[test]
+fn test_pipe_funs() {
+    let mut pipe = Pipe::new(&b""[..],Vec::new());
+    let tint = pipe.tp_int().unwrap();
+    let tbool = pipe.tp_bool().unwrap();
+    let p = pipe.declare_fun(vec![tint.clone()],tbool,Some("p".to_string())).unwrap();
+    let x = NVar { id: 0, sort: tint.clone() };
+    let qx = pipe.embed(Expr::QVar(x.clone())).unwrap();
+    let one = pipe.const_int(BigInt::from(1)).unwrap();
+    let body = pipe.add_int(vec![qx.clone(),one.clone()]).unwrap();
+    let succ = pipe.define_fun(vec![x.clone()],body).unwrap();
+    let sum = pipe.define_fun_rec(vec![x.clone()],tint.clone(),|b,sum| {
+        let zero = b.const_int(BigInt::from(0))?;
+        let cond = b.eq(qx.clone(),zero.clone())?;
+        let prev = b.sub_int(vec![qx.clone(),one.clone()])?;
+        let rec = b.app(*sum,vec![prev])?;
+        let res = b.add_int(vec![qx.clone(),rec])?;
+        b.ite(cond,zero,res)
+    }).unwrap();
+    let c = pipe.app(succ,vec![one.clone()]).unwrap();
+    let c = pipe.app(sum,vec![c]).unwrap();
+    let c = pipe.app(p,vec![c]).unwrap();
+    pipe.assert(c).unwrap();
+    assert_eq!(str::from_utf8(&pipe.writer).unwrap(),
+               "(declare-fun p (Int) Bool)\n\
+                (define-fun f1 ((qv0 Int)) Int (+ qv0 1))\n\
+                (define-fun-rec f2 ((qv0 Int)) Int (ite (= qv0 0) 0 (+ qv0 (f2 (- qv0 1)))))\n\
+                (assert (p (f2 (f1 1))))\n");
+    assert_eq!(pipe.parse_fun(b"f2").unwrap(),sum);
+    assert_eq!(pipe.parse_fun(b"p").unwrap(),p);
+    assert!(pipe.parse_fun(b"f7").is_err());
+}
+
+#[test]
+fn test_pipe_define_sort() {
+    let mut pipe = Pipe::new(&b""[..],Vec::new());
+    let tint = pipe.tp_int().unwrap();
+    let tbool = pipe.tp_bool().unwrap();
+    let par = pipe.embed_sort(SortKind::Param(0)).unwrap();
+    let arr = pipe.tp_array(vec![tint.clone()],par).unwrap();
+    let alias = pipe.define_sort(1,arr).unwrap();
+    let inst = pipe.defined_sort(alias,vec![tbool.clone()]).unwrap();
+    assert_eq!(inst,pipe.tp_array(vec![tint],tbool.clone()).unwrap());
+    pipe.declare(inst).unwrap();
+    assert_eq!(str::from_utf8(&pipe.writer).unwrap(),
+               "(declare-fun v0 () (Array Int Bool))\n");
+    assert!(pipe.defined_sort(alias+1,vec![tbool]).is_err());
+}
+
+#[test]
+fn test_pipe_unsat_core() {
+    let mut pipe = Pipe::new(&b"(a1 a0)\n"[..],Vec::new());
+    let t = pipe.const_bool(true).unwrap();
+    let f = pipe.const_bool(false).unwrap();
+    let a0 = pipe.assert_named(t).unwrap();
+    let a1 = pipe.assert_named(f).unwrap();
+    let core = pipe.get_unsat_core().unwrap();
+    assert_eq!(core,vec![a1,a0]);
+    assert_eq!(str::from_utf8(&pipe.writer).unwrap(),
+               "(assert (! true :named a0))\n\
+                (assert (! false :named a1))\n\
+                (get-unsat-core)\n");
+}
+
+#[test]
+fn test_pipe_model() {
+    let resp = b"(\n  (define-fun v1 () Int\n    (- 3))\n  \
+                 (define-fun k!0 ((x!0 Int)) Int\n    0)\n  \
+                 (define-fun f0 ((x!0 Int)) Int\n    (let ((a!1 (= x!0 1))) (ite a!1 2\n      3)))\n  \
+                 ; a comment\n  \
+                 (define-fun v0 () Real\n    (/ 1.0 3.0))\n)\n";
+    let mut pipe = Pipe::new(&resp[..],Vec::new());
+    let treal = pipe.tp_real().unwrap();
+    let tint = pipe.tp_int().unwrap();
+    let v0 = pipe.declare_var(treal,None).unwrap();
+    let v1 = pipe.declare_var(tint.clone(),None).unwrap();
+    let f0 = pipe.declare_fun(vec![tint.clone()],tint.clone(),None).unwrap();
+    let model = pipe.get_model().unwrap();
+    assert_eq!(model.value(&v0),
+               Some(&Value::Real(Ratio::new(BigInt::from(1),BigInt::from(3)))));
+    assert_eq!(model.value(&v1),Some(&Value::Int(BigInt::from(-3))));
+    assert_eq!(model.funs.len(),1);
+    match model.fun(&f0) {
+        Some(&Interpretation::Fun(ref params,ref body)) => {
+            assert_eq!(params,&vec![NVar { id: 0, sort: tint }]);
+            assert_eq!(format!("{}",body),"(let ((lv1 (= qv0 1)) ) (ite lv1 2 3))");
+        },
+        _ => panic!("No interpretation for f0")
+    }
+}
+
+#[test]
+fn test_pipe_quoted_names() {
+    let resp = b"(\n  (define-fun |x y| () Int\n    2)\n  \
+                 (define-fun w7 () Int\n    (- 1))\n  \
+                 (define-fun |my\nfun| ((|a b| Int)) Int\n    (+ |a b| |x y|))\n  \
+                 (define-fun v1 () Int\n    4)\n)\n";
+    let mut pipe = Pipe::new(&resp[..],Vec::new());
+    let tint = pipe.tp_int().unwrap();
+    let v0 = pipe.declare_var(tint.clone(),Some("x y".to_string())).unwrap();
+    let v1 = pipe.declare_var(tint.clone(),None).unwrap();
+    let v2 = pipe.declare_var(tint.clone(),Some("w7".to_string())).unwrap();
+    let f0 = pipe.declare_fun(vec![tint.clone()],tint.clone(),Some("my\nfun".to_string())).unwrap();
+    let model = pipe.get_model().unwrap();
+    assert_eq!(str::from_utf8(&pipe.writer[..]).unwrap(),
+               "(declare-fun |x y| () Int)\n\
+                (declare-fun v1 () Int)\n\
+                (declare-fun w7 () Int)\n\
+                (declare-fun |my\nfun| (Int) Int)\n\
+                (get-model)\n");
+    assert_eq!(model.value(&v0),Some(&Value::Int(BigInt::from(2))));
+    assert_eq!(model.value(&v1),Some(&Value::Int(BigInt::from(4))));
+    assert_eq!(model.value(&v2),Some(&Value::Int(BigInt::from(-1))));
+    assert!(model.fun(&f0).is_some());
+    // Generated names of named symbols are not valid
+    assert!(pipe.parse_var(b"v0").is_err());
+    assert!(pipe.parse_fun(b"f0").is_err());
+}
+
+#[test]
+fn test_pipe_reserved_names() {
+    let resp = b"(\n  (define-fun v9 () Int\n    9)\n  (define-fun v10 () Int\n    10)\n  \
+                 (define-fun v1x () Int\n    (- 1))\n)\n";
+    let mut pipe = Pipe::new(&resp[..],Vec::new());
+    let tint = pipe.tp_int().unwrap();
+    let mut vars = Vec::new();
+    for _ in 0..11 {
+        vars.push(pipe.declare_var(tint.clone(),None).unwrap());
+    }
+    // Names of the generated form would be confused with other symbols
+    for name in ["v9","v10","f0","a3","u1","p0","qv2","lv0","d1","d1c0","d1c0s12"].iter() {
+        match pipe.declare_var(tint.clone(),Some(name.to_string())) {
+            Err(PipeError::InvalidName(ref n)) => assert_eq!(n,name),
+            r => panic!("Unexpected result {:?}",r)
+        }
+    }
+    match pipe.declare_fun(vec![tint.clone()],tint.clone(),Some("f12".to_string())) {
+        Err(PipeError::InvalidName(ref n)) => assert_eq!(n,"f12"),
+        r => panic!("Unexpected result {:?}",r)
+    }
+    // Names which cannot be quoted
+    for name in ["a|b","a\\b"].iter() {
+        match pipe.declare_var(tint.clone(),Some(name.to_string())) {
+            Err(PipeError::InvalidName(ref n)) => assert_eq!(n,name),
+            r => panic!("Unexpected result {:?}",r)
+        }
+    }
+    let named = pipe.declare_var(tint.clone(),Some("v1x".to_string())).unwrap();
+    let model = pipe.get_model().unwrap();
+    assert_eq!(model.value(&vars[9]),Some(&Value::Int(BigInt::from(9))));
+    assert_eq!(model.value(&vars[10]),Some(&Value::Int(BigInt::from(10))));
+    assert_eq!(model.value(&named),Some(&Value::Int(BigInt::from(-1))));
+    assert!(str::from_utf8(&pipe.writer[..]).unwrap()
+            .ends_with("(declare-fun v10 () Int)\n(declare-fun v1x () Int)\n(get-model)\n"));
+}
+
+#[test]
+fn test_pipe_reserved_words() {
+    let resp = b"(\n  (define-fun |let| () Int\n    3)\n  \
+                 (define-fun |par| ((x!0 Int)) Int\n    (+ x!0 |let|))\n)\n\
+                 ((|let| 3) ((|par| |let|) 6))\n";
+    let mut pipe = Pipe::new(&resp[..],Vec::new());
+    let tint = pipe.tp_int().unwrap();
+    // Reserved words are quoted
+    let v = pipe.declare_var(tint.clone(),Some("let".to_string())).unwrap();
+    let f = pipe.declare_fun(vec![tint.clone()],tint.clone(),Some("par".to_string())).unwrap();
+    // Predefined symbols would be shadowed even if quoted
+    for name in ["and","not","true","false"].iter() {
+        match pipe.declare_var(tint.clone(),Some(name.to_string())) {
+            Err(PipeError::InvalidName(ref n)) => assert_eq!(n,name),
+            r => panic!("Unexpected result {:?}",r)
+        }
+    }
+    // Variables and functions share their names
+    match pipe.declare_var(tint.clone(),Some("par".to_string())) {
+        Err(PipeError::InvalidName(ref n)) => assert_eq!(n,"par"),
+        r => panic!("Unexpected result {:?}",r)
+    }
+    match pipe.declare_fun(vec![tint.clone()],tint.clone(),Some("let".to_string())) {
+        Err(PipeError::InvalidName(ref n)) => assert_eq!(n,"let"),
+        r => panic!("Unexpected result {:?}",r)
+    }
+    let model = pipe.get_model().unwrap();
+    assert_eq!(model.value(&v),Some(&Value::Int(BigInt::from(3))));
+    assert!(model.fun(&f).is_some());
+    let ve = pipe.embed(Expr::Var(v)).unwrap();
+    let app = pipe.embed(Expr::App(Function::Fun(f),vec![ve.clone()])).unwrap();
+    assert_eq!(pipe.get_values(vec![ve,app]).unwrap(),
+               vec![Value::Int(BigInt::from(3)),Value::Int(BigInt::from(6))]);
+    assert_eq!(str::from_utf8(&pipe.writer[..]).unwrap(),
+               "(declare-fun |let| () Int)\n\
+                (declare-fun |par| (Int) Int)\n\
+                (get-model)\n\
+                (get-value (|let| (|par| |let|)))\n");
+}
+
+#[test]
+fn test_pipe_get_values() {
+    let mut pipe = Pipe::new(&b"((v0 (- 5))\n (v1 #b101)\n ((bvadd v1 v1) #b010))\n"[..],Vec::new());
+    let tint = pipe.tp_int().unwrap();
+    let tbv = pipe.tp_bitvec(3).unwrap();
+    let v0 = pipe.declare(tint).unwrap();
+    let v1 = pipe.declare(tbv).unwrap();
+    let sum = pipe.bvadd(v1.clone(),v1.clone()).unwrap();
+    let vals = pipe.get_values(vec![v0,v1,sum]).unwrap();
+    assert_eq!(vals,vec![Value::Int(BigInt::from(-5)),
+                         Value::BitVec(3,BigUint::from(5u8)),
+                         Value::BitVec(3,BigUint::from(2u8))]);
+}
+
+#[test]
+fn test_pipe_check_sat_assuming() {
+    let mut pipe = Pipe::new(&b"unsat\n"[..],Vec::new());
+    let tbool = pipe.tp_bool().unwrap();
+    let a = pipe.declare(tbool.clone()).unwrap();
+    let b = pipe.declare(tbool).unwrap();
+    let nb = pipe.not(b.clone()).unwrap();
+    pipe.unknown_reason = Some(String::from("timeout"));
+    let res = pipe.check_sat_assuming(&[a.clone(),nb.clone()]).unwrap();
+    assert_eq!(res,CheckSatResult::Unsat);
+    assert_eq!(pipe.unknown_reason,None);
+    pipe.reader = &b"(v0 (not v1))\n"[..];
+    let core = pipe.get_unsat_assumptions().unwrap();
+    assert_eq!(core,vec![a,nb]);
+    assert_eq!(str::from_utf8(&pipe.writer).unwrap(),
+               "(declare-fun v0 () Bool)\n\
+                (declare-fun v1 () Bool)\n\
+                (check-sat-assuming (v0 (not v1)))\n\
+                (get-unsat-assumptions)\n");
+}
+
+#[test]
+fn test_pipe_interpol() {
+    let mut pipe = Pipe::new(&b"(define-fun i0 () Bool (= v0 0))\n"[..],Vec::new());
+    pipe.set_interpolation_dialect(InterpolationDialect::GetInterpol);
+    let tint = pipe.tp_int().unwrap();
+    let x = pipe.declare(tint.clone()).unwrap();
+    let y = pipe.declare(tint).unwrap();
+    let zero = pipe.const_int(BigInt::from(0)).unwrap();
+    let a = pipe.eq(x.clone(),zero).unwrap();
+    let one = pipe.const_int(BigInt::from(1)).unwrap();
+    let b1 = pipe.eq(y.clone(),x).unwrap();
+    let b2 = pipe.eq(y,one).unwrap();
+    let itp = pipe.get_interpolant(&[a.clone()],&[b1,b2]).unwrap();
+    assert_eq!(itp,Some(a.clone()));
+    assert_eq!(str::from_utf8(&pipe.writer).unwrap(),
+               "(declare-fun v0 () Int)\n\
+                (declare-fun v1 () Int)\n\
+                (push 1)\n\
+                (assert (= v0 0))\n\
+                (get-interpol i0 (not (and (= v1 v0) (= v1 1))))\n\
+                (pop 1)\n");
+    // A failed query still removes the temporary assertions
+    pipe.writer.clear();
+    pipe.reader = &b"(error \"no interpolant\")\n"[..];
+    match pipe.get_interpolant(&[a],&[]) {
+        Err(PipeError::Solver(ref msg)) => assert_eq!(msg,"no interpolant"),
+        _ => panic!("expected a solver error")
+    }
+    assert_eq!(str::from_utf8(&pipe.writer).unwrap(),
+               "(push 1)\n\
+                (assert (= v0 0))\n\
+                (get-interpol i1 false)\n\
+                (pop 1)\n");
+}
+
+#[test]
+fn test_pipe_qe() {
+    let mut pipe = Pipe::new(&b"(goals\n(goal\n  v0\n  (not v1)\n  :precision precise :depth 1)\n)\n"[..],
+                             Vec::new());
+    let tbool = pipe.tp_bool().unwrap();
+    let a = pipe.declare(tbool.clone()).unwrap();
+    let b = pipe.declare(tbool).unwrap();
+    let nb = pipe.not(b.clone()).unwrap();
+    let conj = pipe.and(vec![a.clone(),nb.clone()]).unwrap();
+    let res1 = pipe.eliminate_quantifiers(conj.clone()).unwrap();
+    assert_eq!(res1,conj);
+    pipe.reader = &b"(not v1)\n"[..];
+    pipe.set_qe_dialect(QeDialect::GetQe);
+    let res2 = pipe.eliminate_quantifiers(nb.clone()).unwrap();
+    assert_eq!(res2,nb);
+    pipe.reader = &b"v0\n"[..];
+    let ctrue = pipe.const_bool(true).unwrap();
+    let disj = pipe.and(vec![a.clone(),ctrue]).unwrap();
+    let res3 = pipe.solver_simplify(disj).unwrap();
+    assert_eq!(res3,a);
+    assert_eq!(str::from_utf8(&pipe.writer).unwrap(),
+               "(declare-fun v0 () Bool)\n\
+                (declare-fun v1 () Bool)\n\
+                (push 1)\n\
+                (assert (and v0 (not v1)))\n\
+                (apply qe)\n\
+                (pop 1)\n\
+                (get-qe (not v1))\n\
+                (simplify (and v0 true))\n");
+    // A failing tactic still removes the asserted formula
+    pipe.writer.clear();
+    pipe.reader = &b"(error \"tactic failed\")\n"[..];
+    pipe.set_qe_dialect(QeDialect::ApplyQe);
+    match pipe.eliminate_quantifiers(nb) {
+        Err(PipeError::Solver(ref msg)) => assert_eq!(msg,"tactic failed"),
+        _ => panic!("expected a solver error")
+    }
+    assert_eq!(str::from_utf8(&pipe.writer).unwrap(),
+               "(push 1)\n\
+                (assert (not v1))\n\
+                (apply qe)\n\
+                (pop 1)\n");
+}
+
+#[test]
+fn test_pipe_optimize() {
+    let mut pipe = Pipe::new(&b"(objectives\n (v0 10)\n (v1 (* (- 1) oo))\n (v2 (+ (- 3) (* (- 1) epsilon)))\n (|soft goal| 2)\n)\n"[..],
+                             Vec::new());
+    let tint = pipe.tp_int().unwrap();
+    let treal = pipe.tp_real().unwrap();
+    let tbool = pipe.tp_bool().unwrap();
+    let x = pipe.declare(tint.clone()).unwrap();
+    let y = pipe.declare(tint).unwrap();
+    let z = pipe.declare(treal).unwrap();
+    let b = pipe.declare(tbool).unwrap();
+    let nb = pipe.not(b.clone()).unwrap();
+    assert_eq!(pipe.maximize(x).unwrap(),ObjectiveId(0));
+    assert_eq!(pipe.minimize(y).unwrap(),ObjectiveId(1));
+    assert_eq!(pipe.maximize(z).unwrap(),ObjectiveId(2));
+    assert_eq!(pipe.assert_soft(b,BigUint::from(2u8),Some("soft goal")).unwrap(),ObjectiveId(3));
+    assert_eq!(pipe.assert_soft(nb.clone(),BigUint::from(3u8),Some("soft goal")).unwrap(),ObjectiveId(3));
+    match pipe.assert_soft(nb,BigUint::from(1u8),Some("bad|goal")) {
+        Err(PipeError::InvalidName(ref n)) => assert_eq!(n,"bad|goal"),
+        r => panic!("Unexpected result {:?}",r)
+    }
+    let objs = pipe.get_objectives().unwrap();
+    assert_eq!(objs,vec![ObjectiveValue::Finite(Value::Int(BigInt::from(10))),
+                         ObjectiveValue::Infinite(false),
+                         ObjectiveValue::Epsilon(Value::Real(Ratio::from_integer(BigInt::from(-3))),false),
+                         ObjectiveValue::Finite(Value::Int(BigInt::from(2)))]);
+    assert_eq!(str::from_utf8(&pipe.writer).unwrap(),
+               "(declare-fun v0 () Int)\n\
+                (declare-fun v1 () Int)\n\
+                (declare-fun v2 () Real)\n\
+                (declare-fun v3 () Bool)\n\
+                (maximize v0)\n\
+                (minimize v1)\n\
+                (maximize v2)\n\
+                (assert-soft v3 :weight 2 :id |soft goal|)\n\
+                (assert-soft (not v3) :weight 3 :id |soft goal|)\n\
+                (get-objectives)\n");
+}
+
+#[test]
+fn test_pipe_profile() {
+    let mut pipe = Pipe::new(&b""[..],Vec::new());
+    pipe.set_profile(SolverProfile::by_name("cvc5").unwrap());
+    pipe.enable_models().unwrap();
+    pipe.enable_interpolants().unwrap();
+    pipe.set_profile(&profile::BOOLECTOR);
+    match pipe.enable_proofs() {
+        Err(PipeError::Unsupported(ref opt)) => assert_eq!(opt,":produce-proofs"),
+        _ => panic!("Proofs should be unsupported")
+    }
+    let tbool = pipe.tp_bool().unwrap();
+    let a = pipe.declare(tbool).unwrap();
+    match pipe.eliminate_quantifiers(a.clone()) {
+        Err(PipeError::Unsupported(ref cmd)) => assert_eq!(cmd,"qe"),
+        _ => panic!("Quantifier elimination should be unsupported")
+    }
+    match pipe.solver_simplify(a) {
+        Err(PipeError::Unsupported(ref cmd)) => assert_eq!(cmd,"simplify"),
+        _ => panic!("Simplification should be unsupported")
+    }
+    assert_eq!(pipe.interpolation,InterpolationDialect::GetInterpol);
+    assert_eq!(str::from_utf8(&pipe.writer).unwrap(),
+               "(set-option :produce-models true)\n\
+                (set-option :produce-interpolants true)\n\
+                (declare-fun v0 () Bool)\n");
+}
+
+#[test]
+fn test_pipe_errors() {
+    let mut pipe = Pipe::new(&b"(error \"line 1 column 10: \"\"x\"\" undeclared\")\n"[..],Vec::new());
+    match pipe.check_sat() {
+        Err(PipeError::Solver(ref msg)) => assert_eq!(msg,"line 1 column 10: \"x\" undeclared"),
+        _ => panic!("Expected solver error")
+    }
+    pipe.reader = &b"unsupported\n"[..];
+    match pipe.get_proof() {
+        Err(PipeError::Unsupported(_)) => {},
+        _ => panic!("Expected unsupported")
+    }
+    pipe.reader = &b"success\n"[..];
+    pipe.enable_print_success().unwrap();
+    let tbool = pipe.tp_bool().unwrap();
+    pipe.reader = &b"success\n"[..];
+    let a = pipe.declare(tbool).unwrap();
+    pipe.reader = &b"(error \"assertion failed\")\n"[..];
+    match pipe.assert(a) {
+        Err(PipeError::Solver(ref msg)) => assert_eq!(msg,"assertion failed"),
+        _ => panic!("Expected solver error")
+    }
+    pipe.reader = &b""[..];
+    match pipe.check_sat() {
+        Err(PipeError::IO(_)) => {},
+        _ => panic!("Expected end of input")
+    }
+}
+
+/// Delivers its input one byte at a time.
+#[cfg(test)]
+struct Trickle<'a>(&'a [u8]);
+
+#[cfg(test)]
+impl<'a> Read for Trickle<'a> {
+    fn read(&mut self,buf: &mut [u8]) -> Result<usize,Error> {
+        if self.0.len()==0 || buf.len()==0 {
+            return Ok(0)
+        }
+        buf[0] = self.0[0];
+        self.0 = &self.0[1..];
+        Ok(1)
+    }
+}
+
+#[test]
+fn test_pipe_buffered() {
+    let mut pipe = Pipe::new(Trickle(b"sat ; done\nunsat (error \"e\") (\n  (v0 true)\n)\n"),
+                             Vec::new());
+    let tbool = pipe.tp_bool().unwrap();
+    let v0 = pipe.declare(tbool).unwrap();
+    assert_eq!(pipe.check_sat().unwrap(),CheckSatResult::Sat);
+    assert_eq!(pipe.check_sat().unwrap(),CheckSatResult::Unsat);
+    match pipe.check_sat() {
+        Err(PipeError::Solver(ref msg)) => assert_eq!(msg,"e"),
+        _ => panic!("Expected solver error")
+    }
+    assert_eq!(pipe.get_values(vec![v0]).unwrap(),vec![Value::Bool(true)]);
+}
+
+#[test]
+fn test_pipe_skip_failed() {
+    let mut pipe = Pipe::new(Trickle(b"((v5 true))\nsat\nunsat\nsuccess\nunknown\n"),
+                             Vec::new());
+    let tbool = pipe.tp_bool().unwrap();
+    let v0 = pipe.declare(tbool.clone()).unwrap();
+    // Responses which cannot be parsed don't confuse the following commands
+    match pipe.get_values(vec![v0]) {
+        Err(PipeError::Parse(ParseError::UnknownVar)) => {},
+        r => panic!("Unexpected result {:?}",r)
+    }
+    assert_eq!(pipe.check_sat().unwrap(),CheckSatResult::Sat);
+    match pipe.enable_print_success() {
+        Err(PipeError::Parse(ParseError::ExpectedLiteral(lit))) => assert_eq!(lit,b"success"),
+        r => panic!("Unexpected result {:?}",r)
+    }
+    pipe.declare(tbool).unwrap();
+    assert_eq!(pipe.check_sat().unwrap(),CheckSatResult::Unknown);
+}
+
+#[test]
+fn test_pipe_timeout() {
+    let mut pipe = Pipe::new(&b""[..],Vec::new());
+    pipe.set_profile(&profile::Z3);
+    pipe.set_resource_limit(5000).unwrap();
+    // Without a process, the time limit cannot be enforced
+    match pipe.check_sat_with_timeout(Duration::from_millis(1500)) {
+        Err(PipeError::Unsupported(ref what)) => assert_eq!(what,"timeout"),
+        _ => panic!("Timeouts should be unsupported")
+    }
+    assert_eq!(str::from_utf8(&pipe.writer).unwrap(),
+               "(set-option :rlimit 5000)\n");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_process_timeout() {
+    // A process which never answers and dies when interrupted
+    let mut pipe = Pipe::new_process("sleep",&["10"]).expect("Cannot start sleep");
+    let res = pipe.check_sat_with_timeout(Duration::from_millis(100)).unwrap();
+    assert_eq!(res,CheckSatResult::Unknown);
+    assert_eq!(pipe.reason_unknown().unwrap(),"timeout");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_pipe_timeout_restore() {
+    let mut pipe = Pipe::new(&b"1000\ninvalid-answer\n"[..],Vec::new());
+    pipe.set_profile(&profile::Z3);
+    let child = Command::new("sleep").arg("10").spawn().expect("Cannot start sleep");
+    pipe.child = Some(Arc::new(Mutex::new(child)));
+    match pipe.check_sat_with_timeout(Duration::from_millis(5000)) {
+        Err(PipeError::Syntax(_)) => {},
+        r => panic!("Unexpected result {:?}",r)
+    }
+    // The limit of the solver is reset although the response was invalid
+    assert_eq!(str::from_utf8(&pipe.writer).unwrap(),
+               "(get-option :timeout)\n\
+                (set-option :timeout 5000)\n\
+                (check-sat)\n\
+                (set-option :timeout 1000)\n");
+    if let Some(ref child) = pipe.child {
+        let _ = child.lock().unwrap().kill();
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn test_process_interrupt() {
+    // A fake solver whose first check runs until it is interrupted
+    let script = "to=4294967295; n=0; trap 'echo unknown' INT; \
+                  while read -r l; do case \"$l\" in \
+                  \"(set-option :timeout \"*) to=${l#\"(set-option :timeout \"}; to=${to%\")\"};; \
+                  \"(get-option :timeout)\") echo $to;; \
+                  \"(check-sat)\") n=$((n+1)); \
+                  if [ $n = 1 ]; then sleep 10 >/dev/null & wait $!; else echo sat; fi;; \
+                  esac; done";
+    let mut pipe = Pipe::new_process("sh",&["-c",script]).expect("Cannot start sh");
+    pipe.set_profile(&profile::Z3);
+    let res = pipe.check_sat_with_timeout(Duration::from_millis(100)).unwrap();
+    assert_eq!(res,CheckSatResult::Unknown);
+    assert_eq!(pipe.reason_unknown().unwrap(),"timeout");
+    // The solver-side limit is reset and the pipe is still usable
+    assert_eq!(pipe.get_option(":timeout").unwrap(),OptionValue::Numeral(4294967295));
+    assert_eq!(pipe.check_sat().unwrap(),CheckSatResult::Sat);
+}
+
+#[test]
+fn test_pipe_statistics() {
+    let mut pipe = Pipe::new(&b"(:reason-unknown incomplete)\n"[..],Vec::new());
+    assert_eq!(pipe.reason_unknown().unwrap(),"incomplete");
+    pipe.reader = &b"(:reason-unknown \"canceled\")\n"[..];
+    assert_eq!(pipe.reason_unknown().unwrap(),"canceled");
+    pipe.reader = &b"(:added-eqs           2\n :memory              16.85\n :mode \"fast\")\n"[..];
+    let stats = pipe.statistics().unwrap();
+    assert_eq!(stats.len(),3);
+    assert_eq!(stats["added-eqs"],StatValue::Number(2.0));
+    assert_eq!(stats["memory"],StatValue::Number(16.85));
+    assert_eq!(stats["mode"],StatValue::String(String::from("fast")));
+    pipe.reader = &b"(:all-statistics (:time 0.5))\n"[..];
+    let stats2 = pipe.statistics().unwrap();
+    assert_eq!(stats2["time"],StatValue::Number(0.5));
+    assert_eq!(str::from_utf8(&pipe.writer).unwrap(),
+               "(get-info :reason-unknown)\n\
+                (get-info :reason-unknown)\n\
+                (get-info :all-statistics)\n\
+                (get-info :all-statistics)\n");
+}
+
+#[test]
+fn test_pipe_options() {
+    let mut pipe = Pipe::new(&b"42\n"[..],Vec::new());
+    pipe.set_profile(&profile::YICES2);
+    let tbool = pipe.tp_bool().unwrap();
+    match pipe.declare(tbool.clone()) {
+        Err(PipeError::LogicRequired) => {},
+        _ => panic!("Declaration without logic should fail")
+    }
+    pipe.set_logic(Logic::QF_UF).unwrap();
+    pipe.set_option(":random-seed",OptionValue::Numeral(42)).unwrap();
+    pipe.set_info(":status",OptionValue::Symbol(String::from("sat"))).unwrap();
+    assert_eq!(pipe.get_option(":random-seed").unwrap(),OptionValue::Numeral(42));
+    pipe.declare(tbool).unwrap();
+    match pipe.set_logic(Logic::QF_LIA) {
+        Err(PipeError::MisplacedSetLogic) => {},
+        _ => panic!("Logic set after declarations")
+    }
+    assert_eq!(str::from_utf8(&pipe.writer).unwrap(),
+               "(set-logic QF_UF)\n\
+                (set-option :random-seed 42)\n\
+                (set-info :status sat)\n\
+                (get-option :random-seed)\n\
+                (declare-fun v0 () Bool)\n");
+}
+
+#[test]
+fn test_pipe_proof() {
+    let mut pipe = Pipe::new(&b"((proof (asserted false) \"x \"\"y\"\"\" |a b|))\n"[..],Vec::new());
+    let proof = pipe.get_proof().unwrap();
+    assert_eq!(proof,
+               SExpr::List(vec![SExpr::List(vec![SExpr::Atom(String::from("proof")),
+                                                 SExpr::List(vec![SExpr::Atom(String::from("asserted")),
+                                                                  SExpr::Atom(String::from("false"))]),
+                                                 SExpr::Str(String::from("x \"y\"")),
+                                                 SExpr::Atom(String::from("|a b|"))])]));
+    assert_eq!(format!("{}",proof),
+               "((proof (asserted false) \"x \"\"y\"\"\" |a b|))");
+    assert_eq!(str::from_utf8(&pipe.writer).unwrap(),
+               "(get-proof)\n");
+}
@@ -0,0 +1,1138 @@
+use expr::{Expr,Function,NVar,ArithOp,OrdOp,BVOp};
+use types::{SortKind,Value,Datatype,FieldSort};
+use embed::Embed;
+use backend::{Backend,AssertionId};
+use model::{Model,Interpretation};
+use options::{Logic,OptionValue};
+use parser::{CheckSatResult,StatValue,SExpr};
+use sat::{Solver,Lit};
+use std::collections::HashMap;
+use num_bigint::BigUint;
+#[cfg(test)]
+use num_bigint::BigInt;
+
+/// A backend for quantifier-free bitvector and boolean problems which needs
+/// no external solver. Expressions are translated into a circuit, which is
+/// turned into clauses and solved by the embedded SAT solver. Assertions
+/// which use other theories make `check_sat` answer `Unknown`.
+pub struct BitBlaster {
+    sat: Solver,
+    /// A literal which is always true
+    tru: Lit,
+    sorts: Vec<SortKind<BlastSort>>,
+    sort_map: HashMap<SortKind<BlastSort>,BlastSort>,
+    exprs: Vec<BlastTerm>,
+    expr_map: HashMap<BlastTerm,BlastExpr>,
+    /// Declared variables and the definitions of defined ones
+    vars: Vec<(BlastSort,Option<BlastExpr>)>,
+    funs: Vec<(Vec<BlastSort>,BlastSort,Option<(Vec<NVar<BlastSort>>,BlastExpr)>)>,
+    /// The bits of declared variables and constants, least significant first
+    var_bits: HashMap<usize,Vec<Lit>>,
+    fun_bits: HashMap<usize,Vec<Lit>>,
+    /// The bits of all closed expressions translated so far
+    blasted: HashMap<BlastExpr,Vec<Lit>>,
+    gates: HashMap<Gate,Lit>,
+    /// Maps the output variable of every gate to the gate, so that values of
+    /// gates created after the last check can still be computed
+    gate_defs: HashMap<usize,Gate>,
+    levels: Vec<Level>,
+    /// The literals guarding named assertions
+    named: Vec<Lit>,
+    /// The assumptions of the last `check_sat_assuming`
+    assumed: Vec<(Lit,BlastExpr)>,
+    defined_sorts: Vec<(usize,BlastSort)>,
+    options: HashMap<String,OptionValue>,
+    logic: Option<Logic>,
+    declared: bool,
+    last_result: Option<CheckSatResult>,
+    unknown_reason: Option<String>
+}
+
+/// An assertion level created by `push`.
+struct Level {
+    /// The literal which enables the assertions of the level, or `None` for
+    /// the base level
+    act: Option<Lit>,
+    /// The first theory which was asserted but cannot be handled
+    unsupported: Option<String>,
+    named: Vec<usize>
+}
+
+#[derive(PartialEq,Eq,Hash,Clone,Copy,Debug)]
+enum Gate {
+    And(Lit,Lit),
+    Xor(Lit,Lit),
+    Ite(Lit,Lit,Lit)
+}
+
+impl Gate {
+    fn inputs(&self) -> Vec<Lit> {
+        match *self {
+            Gate::And(a,b) | Gate::Xor(a,b) => vec![a,b],
+            Gate::Ite(c,t,e) => vec![c,t,e]
+        }
+    }
+    fn eval<F : Fn(Lit) -> bool>(&self,val: F) -> bool {
+        match *self {
+            Gate::And(a,b) => val(a) && val(b),
+            Gate::Xor(a,b) => val(a)!=val(b),
+            Gate::Ite(c,t,e) => if val(c) { val(t) } else { val(e) }
+        }
+    }
+}
+
+#[derive(PartialEq,Eq,PartialOrd,Ord,Hash,Clone,Copy,Debug)]
+pub struct BlastSort(usize);
+#[derive(PartialEq,Eq,PartialOrd,Ord,Hash,Clone,Copy,Debug)]
+pub struct BlastVar(usize);
+#[derive(PartialEq,Eq,PartialOrd,Ord,Hash,Clone,Copy,Debug)]
+pub struct BlastExpr(usize);
+pub type BlastFun = usize;
+
+type BlastTerm = Expr<BlastSort,BlastVar,BlastExpr,BlastFun>;
+
+/// The bits of bound variables, indexed by whether they are let-bound and
+/// their id.
+type Env = HashMap<(bool,usize),Vec<Lit>>;
+
+#[derive(Debug)]
+pub enum BlastError {
+    /// A theory or command which cannot be handled by bit-blasting
+    Unsupported(String),
+    /// A model, core or reason was requested without a matching result of
+    /// the last check
+    NoResult,
+    /// The logic was set twice or after declarations
+    MisplacedSetLogic,
+    /// A let variable outside of the `let` expression binding it
+    UnboundVariable,
+    /// A `pop` without a matching `push`
+    EmptyStack
+}
+
+fn unsupported<T>(what: &str) -> Result<T,BlastError> {
+    Err(BlastError::Unsupported(String::from(what)))
+}
+
+fn sort_theory<S>(srt: &SortKind<S>) -> &'static str {
+    match *srt {
+        SortKind::Bool | SortKind::BitVec(_) => "bitvectors",
+        SortKind::Int => "integers",
+        SortKind::Real => "reals",
+        SortKind::Array(_,_) => "arrays",
+        SortKind::FloatingPoint(_,_) | SortKind::RoundingMode => "floating points",
+        SortKind::Datatype(_,_) => "datatypes",
+        SortKind::Uninterpreted(_,_) => "uninterpreted sorts",
+        SortKind::Param(_) => "sort parameters"
+    }
+}
+
+fn fun_theory<S,F>(fun: &Function<S,F>) -> &'static str {
+    match *fun {
+        Function::Fun(_) => "uninterpreted functions",
+        Function::OrdInt(_) | Function::ArithInt(_,_) | Function::Div | Function::Mod |
+        Function::Rem | Function::Exp | Function::AbsInt | Function::ToInt => "integer arithmetic",
+        Function::OrdReal(_) | Function::ArithReal(_,_) | Function::Divide |
+        Function::AbsReal | Function::ToReal => "real arithmetic",
+        Function::FP(_,_,_) => "floating points",
+        Function::Map(_,_) | Function::Select(_,_) | Function::Store(_,_) |
+        Function::ConstArray(_,_) => "arrays",
+        Function::Constructor(_,_,_) | Function::Selector(_,_,_,_) |
+        Function::Tester(_,_,_) => "datatypes",
+        _ => "bitvectors"
+    }
+}
+
+fn bits_of(val: &BigUint,width: usize) -> Vec<bool> {
+    let bytes = val.to_bytes_le();
+    (0..width).map(|i| i/8<bytes.len() && (bytes[i/8] >> (i%8)) & 1==1).collect()
+}
+
+fn from_bits(bits: &[bool]) -> BigUint {
+    let mut bytes = vec![0u8; (bits.len()+7)/8];
+    for (i,bit) in bits.iter().enumerate() {
+        if *bit {
+            bytes[i/8] |= 1 << (i%8);
+        }
+    }
+    BigUint::from_bytes_le(&bytes)
+}
+
+impl BitBlaster {
+    pub fn new() -> BitBlaster {
+        let mut sat = Solver::new();
+        let tru = Lit::new(sat.new_var(),true);
+        sat.add_clause(&[tru]);
+        BitBlaster { sat: sat,
+                     tru: tru,
+                     sorts: Vec::new(),
+                     sort_map: HashMap::new(),
+                     exprs: Vec::new(),
+                     expr_map: HashMap::new(),
+                     vars: Vec::new(),
+                     funs: Vec::new(),
+                     var_bits: HashMap::new(),
+                     fun_bits: HashMap::new(),
+                     blasted: HashMap::new(),
+                     gates: HashMap::new(),
+                     gate_defs: HashMap::new(),
+                     levels: vec![Level { act: None,
+                                          unsupported: None,
+                                          named: Vec::new() }],
+                     named: Vec::new(),
+                     assumed: Vec::new(),
+                     defined_sorts: Vec::new(),
+                     options: HashMap::new(),
+                     logic: None,
+                     declared: false,
+                     last_result: None,
+                     unknown_reason: None }
+    }
+    fn fresh(&mut self) -> Lit {
+        Lit::new(self.sat.new_var(),true)
+    }
+    fn constant(&self,val: bool) -> Lit {
+        if val { self.tru } else { !self.tru }
+    }
+    fn width(&self,srt: &BlastSort) -> Result<usize,BlastError> {
+        match self.sorts[srt.0] {
+            SortKind::Bool => Ok(1),
+            SortKind::BitVec(bw) => Ok(bw),
+            ref kind => unsupported(sort_theory(kind))
+        }
+    }
+    fn fresh_bits(&mut self,srt: &BlastSort) -> Result<Vec<Lit>,BlastError> {
+        let width = self.width(srt)?;
+        Ok((0..width).map(|_| self.fresh()).collect())
+    }
+    fn gate(&mut self,gate: Gate) -> Lit {
+        if let Some(out) = self.gates.get(&gate) {
+            return *out
+        }
+        let o = self.fresh();
+        match gate {
+            Gate::And(a,b) => {
+                self.sat.add_clause(&[!o,a]);
+                self.sat.add_clause(&[!o,b]);
+                self.sat.add_clause(&[o,!a,!b]);
+            },
+            Gate::Xor(a,b) => {
+                self.sat.add_clause(&[!o,a,b]);
+                self.sat.add_clause(&[!o,!a,!b]);
+                self.sat.add_clause(&[o,!a,b]);
+                self.sat.add_clause(&[o,a,!b]);
+            },
+            Gate::Ite(c,t,e) => {
+                self.sat.add_clause(&[!c,!t,o]);
+                self.sat.add_clause(&[!c,t,!o]);
+                self.sat.add_clause(&[c,!e,o]);
+                self.sat.add_clause(&[c,e,!o]);
+                // Redundant, but helps propagation
+                self.sat.add_clause(&[!t,!e,o]);
+                self.sat.add_clause(&[t,e,!o]);
+            }
+        }
+        self.gates.insert(gate,o);
+        self.gate_defs.insert(o.var(),gate);
+        o
+    }
+    fn and2(&mut self,a: Lit,b: Lit) -> Lit {
+        let fls = !self.tru;
+        if a==fls || b==fls || a==!b {
+            fls
+        } else if a==self.tru || a==b {
+            b
+        } else if b==self.tru {
+            a
+        } else if a<b {
+            self.gate(Gate::And(a,b))
+        } else {
+            self.gate(Gate::And(b,a))
+        }
+    }
+    fn or2(&mut self,a: Lit,b: Lit) -> Lit {
+        !self.and2(!a,!b)
+    }
+    fn xor2(&mut self,a: Lit,b: Lit) -> Lit {
+        let fls = !self.tru;
+        if a==fls {
+            b
+        } else if a==self.tru {
+            !b
+        } else if b==fls {
+            a
+        } else if b==self.tru {
+            !a
+        } else if a==b {
+            fls
+        } else if a==!b {
+            self.tru
+        } else {
+            // Only gates over positive inputs are built
+            let neg = a.is_positive()!=b.is_positive();
+            let pa = Lit::new(a.var(),true);
+            let pb = Lit::new(b.var(),true);
+            let out = if pa<pb { self.gate(Gate::Xor(pa,pb)) } else { self.gate(Gate::Xor(pb,pa)) };
+            if neg { !out } else { out }
+        }
+    }
+    fn ite(&mut self,c: Lit,t: Lit,e: Lit) -> Lit {
+        let fls = !self.tru;
+        if c==self.tru || t==e {
+            t
+        } else if c==fls {
+            e
+        } else if t==self.tru {
+            self.or2(c,e)
+        } else if t==fls {
+            self.and2(!c,e)
+        } else if e==self.tru {
+            self.or2(!c,t)
+        } else if e==fls {
+            self.and2(c,t)
+        } else if t==!e {
+            !self.xor2(c,t)
+        } else if c.is_positive() {
+            self.gate(Gate::Ite(c,t,e))
+        } else {
+            self.gate(Gate::Ite(!c,e,t))
+        }
+    }
+    fn and_all(&mut self,lits: &[Lit]) -> Lit {
+        lits.iter().fold(self.tru,|acc,l| self.and2(acc,*l))
+    }
+    fn or_all(&mut self,lits: &[Lit]) -> Lit {
+        lits.iter().fold(!self.tru,|acc,l| self.or2(acc,*l))
+    }
+    fn eq_bits(&mut self,a: &[Lit],b: &[Lit]) -> Lit {
+        let eqs : Vec<Lit> = a.iter().zip(b.iter()).map(|(x,y)| !self.xor2(*x,*y)).collect();
+        self.and_all(&eqs)
+    }
+    fn mux(&mut self,c: Lit,t: &[Lit],e: &[Lit]) -> Vec<Lit> {
+        t.iter().zip(e.iter()).map(|(x,y)| self.ite(c,*x,*y)).collect()
+    }
+    fn const_bits(&self,val: &BigUint,width: usize) -> Vec<Lit> {
+        bits_of(val,width).into_iter().map(|b| self.constant(b)).collect()
+    }
+    fn add(&mut self,a: &[Lit],b: &[Lit],mut carry: Lit) -> Vec<Lit> {
+        let mut res = Vec::with_capacity(a.len());
+        for (x,y) in a.iter().zip(b.iter()) {
+            let t = self.xor2(*x,*y);
+            res.push(self.xor2(t,carry));
+            let c1 = self.and2(*x,*y);
+            let c2 = self.and2(t,carry);
+            carry = self.or2(c1,c2);
+        }
+        res
+    }
+    fn neg(&mut self,a: &[Lit]) -> Vec<Lit> {
+        let inv : Vec<Lit> = a.iter().map(|l| !*l).collect();
+        let zero = vec![!self.tru; a.len()];
+        let one = self.tru;
+        self.add(&inv,&zero,one)
+    }
+    fn sub(&mut self,a: &[Lit],b: &[Lit]) -> Vec<Lit> {
+        let inv : Vec<Lit> = b.iter().map(|l| !*l).collect();
+        let one = self.tru;
+        self.add(a,&inv,one)
+    }
+    fn mul(&mut self,a: &[Lit],b: &[Lit]) -> Vec<Lit> {
+        let n = a.len();
+        let mut acc = vec![!self.tru; n];
+        for i in 0..n {
+            let mut partial = vec![!self.tru; n];
+            for j in 0..n-i {
+                partial[i+j] = self.and2(a[j],b[i]);
+            }
+            let fls = !self.tru;
+            acc = self.add(&acc,&partial,fls);
+        }
+        acc
+    }
+    /// Unsigned less-than. Higher bits are visited later and override the
+    /// decision of lower ones.
+    fn ult(&mut self,a: &[Lit],b: &[Lit]) -> Lit {
+        let mut lt = !self.tru;
+        for (x,y) in a.iter().zip(b.iter()) {
+            let diff = self.xor2(*x,*y);
+            lt = self.ite(diff,*y,lt);
+        }
+        lt
+    }
+    fn slt(&mut self,a: &[Lit],b: &[Lit]) -> Lit {
+        let mut a = a.to_vec();
+        let mut b = b.to_vec();
+        let msb = a.len()-1;
+        a[msb] = !a[msb];
+        b[msb] = !b[msb];
+        self.ult(&a,&b)
+    }
+    /// Unsigned division and remainder by restoring division. Division by
+    /// zero yields all ones and the dividend, as required by SMT-LIB.
+    fn udivrem(&mut self,a: &[Lit],b: &[Lit]) -> (Vec<Lit>,Vec<Lit>) {
+        let n = a.len();
+        let mut quot = vec![!self.tru; n];
+        let mut rem = vec![!self.tru; n];
+        let mut divisor = b.to_vec();
+        divisor.push(!self.tru);
+        for i in (0..n).rev() {
+            let mut shifted = Vec::with_capacity(n+1);
+            shifted.push(a[i]);
+            shifted.extend_from_slice(&rem);
+            let ge = !self.ult(&shifted,&divisor);
+            let diff = self.sub(&shifted,&divisor);
+            rem = self.mux(ge,&diff[0..n],&shifted[0..n]);
+            quot[i] = ge;
+        }
+        (quot,rem)
+    }
+    fn sdivrem(&mut self,a: &[Lit],b: &[Lit],div: bool) -> Vec<Lit> {
+        let msb_a = a[a.len()-1];
+        let msb_b = b[b.len()-1];
+        let neg_a = self.neg(a);
+        let abs_a = self.mux(msb_a,&neg_a,a);
+        let neg_b = self.neg(b);
+        let abs_b = self.mux(msb_b,&neg_b,b);
+        let (quot,rem) = self.udivrem(&abs_a,&abs_b);
+        if div {
+            let sign = self.xor2(msb_a,msb_b);
+            let neg_quot = self.neg(&quot);
+            self.mux(sign,&neg_quot,&quot)
+        } else {
+            // The sign of the remainder follows the dividend
+            let neg_rem = self.neg(&rem);
+            self.mux(msb_a,&neg_rem,&rem)
+        }
+    }
+    /// A barrel shifter. Shifting by the width or more fills the result.
+    fn shift(&mut self,a: &[Lit],b: &[Lit],op: &BVOp) -> Vec<Lit> {
+        let n = a.len();
+        let fill = match *op {
+            BVOp::ASHR => a[n-1],
+            _ => !self.tru
+        };
+        let mut res = a.to_vec();
+        let mut overflow = !self.tru;
+        for (k,bit) in b.iter().enumerate() {
+            if k<usize::max_value().count_ones() as usize && (1usize << k)<n {
+                let dist = 1usize << k;
+                let shifted : Vec<Lit> = match *op {
+                    BVOp::SHL => (0..n).map(|i| if i>=dist { res[i-dist] } else { !self.tru })
+                        .collect(),
+                    _ => (0..n).map(|i| if i+dist<n { res[i+dist] } else { fill }).collect()
+                };
+                res = self.mux(*bit,&shifted,&res);
+            } else {
+                overflow = self.or2(overflow,*bit);
+            }
+        }
+        let filled = vec![fill; n];
+        self.mux(overflow,&filled,&res)
+    }
+    /// Compare the number of true literals with a constant.
+    fn cardinality(&mut self,lits: &[Lit],k: usize,at_least: bool) -> Lit {
+        let mut width = 1;
+        while (1usize << width)<=lits.len().max(k) {
+            width+=1;
+        }
+        let mut sum = vec![!self.tru; width];
+        let zero = sum.clone();
+        for lit in lits.iter() {
+            sum = self.add(&sum,&zero,*lit);
+        }
+        let bound = self.const_bits(&BigUint::from(k),width);
+        if at_least {
+            !self.ult(&sum,&bound)
+        } else {
+            !self.ult(&bound,&sum)
+        }
+    }
+    fn blast(&mut self,e: &BlastExpr,env: &Env) -> Result<Vec<Lit>,BlastError> {
+        // Only closed expressions can be cached
+        if env.len()==0 {
+            if let Some(bits) = self.blasted.get(e) {
+                return Ok(bits.clone())
+            }
+        }
+        let bits = match self.exprs[e.0].clone() {
+            Expr::Var(BlastVar(v)) => match self.vars[v] {
+                (_,Some(def)) => self.blast(&def,&HashMap::new())?,
+                (srt,None) => match self.var_bits.get(&v) {
+                    Some(bits) => bits.clone(),
+                    None => {
+                        let bits = self.fresh_bits(&srt)?;
+                        self.var_bits.insert(v,bits.clone());
+                        bits
+                    }
+                }
+            },
+            Expr::QVar(ref v) => match env.get(&(false,v.id)) {
+                Some(bits) => bits.clone(),
+                None => return unsupported("quantifiers")
+            },
+            Expr::LVar(ref v) => match env.get(&(true,v.id)) {
+                Some(bits) => bits.clone(),
+                None => return Err(BlastError::UnboundVariable)
+            },
+            Expr::Const(Value::Bool(b)) => vec![self.constant(b)],
+            Expr::Const(Value::BitVec(bw,ref val)) => self.const_bits(val,bw),
+            Expr::Const(_) => return unsupported("constants of other sorts than bitvectors"),
+            Expr::App(fun,args) => {
+                let mut bargs = Vec::with_capacity(args.len());
+                for arg in args.iter() {
+                    bargs.push(self.blast(arg,env)?);
+                }
+                self.blast_app(&fun,bargs)?
+            },
+            Expr::Let(binds,body) => {
+                let mut nenv = env.clone();
+                for &(ref v,ref def) in binds.iter() {
+                    let bits = self.blast(def,env)?;
+                    nenv.insert((true,v.id),bits);
+                }
+                self.blast(&body,&nenv)?
+            },
+            Expr::AsArray(_) => return unsupported("arrays"),
+            Expr::Exists(_,_) | Expr::Forall(_,_) => return unsupported("quantifiers")
+        };
+        if env.len()==0 {
+            self.blasted.insert(*e,bits.clone());
+        }
+        Ok(bits)
+    }
+    fn blast_app(&mut self,fun: &Function<BlastSort,BlastFun>,args: Vec<Vec<Lit>>)
+                 -> Result<Vec<Lit>,BlastError> {
+        let bit = match *fun {
+            Function::Fun(f) => return match self.funs[f] {
+                (_,_,Some((ref params,body))) => {
+                    let env = params.iter().zip(args.into_iter())
+                        .map(|(p,bits)| ((false,p.id),bits)).collect();
+                    self.blast(&body,&env)
+                },
+                (ref fargs,srt,None) => if fargs.len()==0 {
+                    if let Some(bits) = self.fun_bits.get(&f) {
+                        return Ok(bits.clone())
+                    }
+                    let bits = self.fresh_bits(&srt)?;
+                    self.fun_bits.insert(f,bits.clone());
+                    Ok(bits)
+                } else {
+                    unsupported(fun_theory(fun))
+                }
+            },
+            Function::Eq(_,_) => {
+                let mut eqs = Vec::with_capacity(args.len());
+                for i in 1..args.len() {
+                    eqs.push(self.eq_bits(&args[i-1],&args[i]));
+                }
+                self.and_all(&eqs)
+            },
+            Function::Distinct(_,_) => {
+                let mut neqs = Vec::new();
+                for i in 0..args.len() {
+                    for j in i+1..args.len() {
+                        neqs.push(!self.eq_bits(&args[i],&args[j]));
+                    }
+                }
+                self.and_all(&neqs)
+            },
+            Function::Not => !args[0][0],
+            Function::And(_) => {
+                let lits : Vec<Lit> = args.iter().map(|a| a[0]).collect();
+                self.and_all(&lits)
+            },
+            Function::Or(_) => {
+                let lits : Vec<Lit> = args.iter().map(|a| a[0]).collect();
+                self.or_all(&lits)
+            },
+            Function::XOr(_) => {
+                let fls = !self.tru;
+                args.iter().fold(fls,|acc,a| self.xor2(acc,a[0]))
+            },
+            Function::Implies(_) => {
+                // Implication associates to the right
+                let mut iter = args.iter().rev();
+                let last = iter.next().map(|a| a[0]).unwrap_or(self.tru);
+                iter.fold(last,|acc,a| self.or2(!a[0],acc))
+            },
+            Function::AtLeast(k,_) | Function::AtMost(k,_) => {
+                let lits : Vec<Lit> = args.iter().map(|a| a[0]).collect();
+                let at_least = match *fun {
+                    Function::AtLeast(_,_) => true,
+                    _ => false
+                };
+                self.cardinality(&lits,k,at_least)
+            },
+            Function::ITE(_) => return Ok(self.mux(args[0][0],&args[1],&args[2])),
+            Function::BV(_,ref op) => match *op {
+                BVOp::Ord(signed,op) => {
+                    let (a,b) = match op {
+                        OrdOp::Lt | OrdOp::Ge => (&args[0],&args[1]),
+                        OrdOp::Gt | OrdOp::Le => (&args[1],&args[0])
+                    };
+                    let lt = if signed { self.slt(a,b) } else { self.ult(a,b) };
+                    match op {
+                        OrdOp::Lt | OrdOp::Gt => lt,
+                        OrdOp::Ge | OrdOp::Le => !lt
+                    }
+                },
+                BVOp::Arith(ArithOp::Add) => {
+                    let fls = !self.tru;
+                    let mut iter = args.into_iter();
+                    let first = iter.next().unwrap();
+                    return Ok(iter.fold(first,|acc,a| self.add(&acc,&a,fls)))
+                },
+                BVOp::Arith(ArithOp::Sub) => return Ok(if args.len()==1 {
+                    self.neg(&args[0])
+                } else {
+                    let mut iter = args.into_iter();
+                    let first = iter.next().unwrap();
+                    iter.fold(first,|acc,a| self.sub(&acc,&a))
+                }),
+                BVOp::Arith(ArithOp::Mult) => {
+                    let mut iter = args.into_iter();
+                    let first = iter.next().unwrap();
+                    return Ok(iter.fold(first,|acc,a| self.mul(&acc,&a)))
+                },
+                BVOp::Div(false) => return Ok(self.udivrem(&args[0],&args[1]).0),
+                BVOp::Rem(false) => return Ok(self.udivrem(&args[0],&args[1]).1),
+                BVOp::Div(true) => return Ok(self.sdivrem(&args[0],&args[1],true)),
+                BVOp::Rem(true) => return Ok(self.sdivrem(&args[0],&args[1],false)),
+                BVOp::SHL | BVOp::LSHR | BVOp::ASHR
+                    => return Ok(self.shift(&args[0],&args[1],op)),
+                BVOp::XOr | BVOp::And | BVOp::Or => {
+                    let mut iter = args.into_iter();
+                    let mut res = iter.next().unwrap();
+                    for arg in iter {
+                        for i in 0..res.len() {
+                            res[i] = match *op {
+                                BVOp::XOr => self.xor2(res[i],arg[i]),
+                                BVOp::And => self.and2(res[i],arg[i]),
+                                _ => self.or2(res[i],arg[i])
+                            };
+                        }
+                    }
+                    return Ok(res)
+                },
+                BVOp::Not => return Ok(args[0].iter().map(|l| !*l).collect()),
+                BVOp::Neg => return Ok(self.neg(&args[0])),
+                BVOp::Extract(start,len) => return Ok(args[0][start..start+len].to_vec()),
+                BVOp::Concat => {
+                    // The first argument forms the most significant bits
+                    let mut res = Vec::new();
+                    for arg in args.iter().rev() {
+                        res.extend_from_slice(arg);
+                    }
+                    return Ok(res)
+                }
+            },
+            _ => return unsupported(fun_theory(fun))
+        };
+        Ok(vec![bit])
+    }
+    /// Blast a boolean expression, recording it as unsupported at the
+    /// current level if it cannot be handled.
+    fn blast_assertion(&mut self,expr: &BlastExpr) -> Result<Option<Lit>,BlastError> {
+        match self.blast(expr,&HashMap::new()) {
+            Ok(bits) => Ok(Some(bits[0])),
+            Err(BlastError::Unsupported(what)) => {
+                let level = self.levels.last_mut().unwrap();
+                if level.unsupported.is_none() {
+                    level.unsupported = Some(what);
+                }
+                Ok(None)
+            },
+            Err(err) => Err(err)
+        }
+    }
+    fn solve(&mut self,extra: &[Lit]) -> CheckSatResult {
+        self.unknown_reason = None;
+        for level in self.levels.iter() {
+            if let Some(ref what) = level.unsupported {
+                self.unknown_reason = Some(format!("incomplete ({} not supported)",what));
+                self.last_result = Some(CheckSatResult::Unknown);
+                return CheckSatResult::Unknown
+            }
+        }
+        let mut assumptions = Vec::new();
+        for level in self.levels.iter() {
+            if let Some(act) = level.act {
+                assumptions.push(act);
+            }
+            for aid in level.named.iter() {
+                assumptions.push(self.named[*aid]);
+            }
+        }
+        assumptions.extend_from_slice(extra);
+        if self.sat.solve(&assumptions) {
+            self.last_result = Some(CheckSatResult::Sat);
+            CheckSatResult::Sat
+        } else {
+            self.last_result = Some(CheckSatResult::Unsat);
+            CheckSatResult::Unsat
+        }
+    }
+    /// Compute the values of literals in the last model. Literals created
+    /// afterwards are evaluated through their gates, fresh inputs are false.
+    fn eval(&self,lits: &[Lit]) -> Vec<bool> {
+        let mut memo : HashMap<usize,bool> = HashMap::new();
+        let mut res = Vec::with_capacity(lits.len());
+        for lit in lits.iter() {
+            let mut stack = vec![lit.var()];
+            while let Some(&var) = stack.last() {
+                if memo.contains_key(&var) {
+                    stack.pop();
+                    continue
+                }
+                if let Some(val) = self.sat.model_value(Lit::new(var,true)) {
+                    memo.insert(var,val);
+                    stack.pop();
+                    continue
+                }
+                match self.gate_defs.get(&var) {
+                    None => {
+                        memo.insert(var,false);
+                        stack.pop();
+                    },
+                    Some(gate) => {
+                        let missing : Vec<usize> = gate.inputs().iter().map(|l| l.var())
+                            .filter(|v| !memo.contains_key(v)).collect();
+                        if missing.len()==0 {
+                            let val = gate.eval(|l| memo[&l.var()]==l.is_positive());
+                            memo.insert(var,val);
+                            stack.pop();
+                        } else {
+                            stack.extend(missing);
+                        }
+                    }
+                }
+            }
+            res.push(memo[&lit.var()]==lit.is_positive());
+        }
+        res
+    }
+    fn value_of(&mut self,srt: &BlastSort,bits: &[Lit]) -> Result<Value,BlastError> {
+        let vals = self.eval(bits);
+        match self.sorts[srt.0] {
+            SortKind::Bool => Ok(Value::Bool(vals[0])),
+            SortKind::BitVec(bw) => Ok(Value::BitVec(bw,from_bits(&vals))),
+            ref kind => unsupported(sort_theory(kind))
+        }
+    }
+}
+
+impl Embed for BitBlaster {
+    type Sort = BlastSort;
+    type Var = BlastVar;
+    type Expr = BlastExpr;
+    type Fun = BlastFun;
+    type Error = BlastError;
+    fn embed_sort(&mut self,tp: SortKind<BlastSort>) -> Result<BlastSort,BlastError> {
+        if let Some(srt) = self.sort_map.get(&tp) {
+            return Ok(*srt)
+        }
+        let srt = BlastSort(self.sorts.len());
+        self.sorts.push(tp.clone());
+        self.sort_map.insert(tp,srt);
+        Ok(srt)
+    }
+    fn unbed_sort(&mut self,tp: &BlastSort) -> Result<SortKind<BlastSort>,BlastError> {
+        Ok(self.sorts[tp.0].clone())
+    }
+    fn embed(&mut self,e: BlastTerm) -> Result<BlastExpr,BlastError> {
+        if let Some(re) = self.expr_map.get(&e) {
+            return Ok(*re)
+        }
+        let re = BlastExpr(self.exprs.len());
+        self.exprs.push(e.clone());
+        self.expr_map.insert(e,re);
+        Ok(re)
+    }
+    fn unbed(&mut self,e: &BlastExpr) -> Result<BlastTerm,BlastError> {
+        Ok(self.exprs[e.0].clone())
+    }
+    fn type_of_var(&mut self,v: &BlastVar) -> Result<BlastSort,BlastError> {
+        Ok(self.vars[v.0].0)
+    }
+    fn type_of_fun(&mut self,f: &BlastFun) -> Result<BlastSort,BlastError> {
+        Ok(self.funs[*f].1)
+    }
+    fn arity(&mut self,f: &BlastFun) -> Result<usize,BlastError> {
+        Ok(self.funs[*f].0.len())
+    }
+    fn type_of_arg(&mut self,f: &BlastFun,arg: usize) -> Result<BlastSort,BlastError> {
+        Ok(self.funs[*f].0[arg])
+    }
+    fn datatype(&mut self,_: usize) -> Result<Datatype<BlastSort>,BlastError> {
+        unsupported("datatypes")
+    }
+}
+
+impl Backend for BitBlaster {
+    fn unsupported(what: &str) -> BlastError {
+        BlastError::Unsupported(String::from(what))
+    }
+    fn enable_models(&mut self) -> Result<(),BlastError> {
+        Ok(())
+    }
+    fn enable_proofs(&mut self) -> Result<(),BlastError> {
+        unsupported(":produce-proofs")
+    }
+    fn enable_unsat_cores(&mut self) -> Result<(),BlastError> {
+        Ok(())
+    }
+    fn enable_interpolants(&mut self) -> Result<(),BlastError> {
+        unsupported(":produce-interpolants")
+    }
+    fn set_logic(&mut self,logic: Logic) -> Result<(),BlastError> {
+        if self.declared || self.logic.is_some() {
+            return Err(BlastError::MisplacedSetLogic)
+        }
+        self.logic = Some(logic);
+        Ok(())
+    }
+    fn set_option(&mut self,name: &str,value: OptionValue) -> Result<(),BlastError> {
+        match name {
+            ":produce-proofs" if value==OptionValue::Bool(true) => self.enable_proofs()?,
+            ":produce-interpolants" if value==OptionValue::Bool(true) => self.enable_interpolants()?,
+            _ => {}
+        }
+        self.options.insert(String::from(name),value);
+        Ok(())
+    }
+    fn get_option(&mut self,name: &str) -> Result<OptionValue,BlastError> {
+        match self.options.get(name) {
+            Some(value) => Ok(value.clone()),
+            None => Err(BlastError::Unsupported(String::from(name)))
+        }
+    }
+    fn set_info(&mut self,_: &str,_: OptionValue) -> Result<(),BlastError> {
+        Ok(())
+    }
+    fn solver_name(&mut self) -> Result<String,BlastError> {
+        Ok(String::from("smtrs-bitblast"))
+    }
+    fn solver_version(&mut self) -> Result<String,BlastError> {
+        Ok(String::from(env!("CARGO_PKG_VERSION")))
+    }
+    fn reason_unknown(&mut self) -> Result<String,BlastError> {
+        match self.unknown_reason {
+            Some(ref reason) => Ok(reason.clone()),
+            None => Err(BlastError::NoResult)
+        }
+    }
+    fn statistics(&mut self) -> Result<HashMap<String,StatValue>,BlastError> {
+        let mut res = HashMap::new();
+        res.insert(String::from("decisions"),StatValue::Number(self.sat.decisions() as f64));
+        res.insert(String::from("conflicts"),StatValue::Number(self.sat.conflicts() as f64));
+        res.insert(String::from("propagations"),
+                   StatValue::Number(self.sat.propagations() as f64));
+        res.insert(String::from("sat-vars"),StatValue::Number(self.sat.num_vars() as f64));
+        res.insert(String::from("sat-clauses"),
+                   StatValue::Number(self.sat.num_clauses() as f64));
+        Ok(res)
+    }
+    fn comment(&mut self,_: &str) -> Result<(),BlastError> {
+        Ok(())
+    }
+    fn push(&mut self) -> Result<(),BlastError> {
+        let act = self.fresh();
+        self.levels.push(Level { act: Some(act),
+                                 unsupported: None,
+                                 named: Vec::new() });
+        Ok(())
+    }
+    fn pop(&mut self) -> Result<(),BlastError> {
+        // The first level holds the assertions outside of any push
+        if self.levels.len()<=1 {
+            return Err(BlastError::EmptyStack)
+        }
+        if let Some(act) = self.levels.pop().unwrap().act {
+            // Disable the assertions of the level for good
+            self.sat.add_clause(&[!act]);
+        }
+        Ok(())
+    }
+    fn declare_var(&mut self,tp: BlastSort,_: Option<String>) -> Result<BlastVar,BlastError> {
+        self.declared = true;
+        let vid = self.vars.len();
+        self.vars.push((tp,None));
+        Ok(BlastVar(vid))
+    }
+    fn define_var(&mut self,e: BlastExpr) -> Result<BlastVar,BlastError> {
+        self.declared = true;
+        let vid = self.vars.len();
+        let tp = self.type_of(&e)?;
+        self.vars.push((tp,Some(e)));
+        Ok(BlastVar(vid))
+    }
+    fn assert(&mut self,expr: BlastExpr) -> Result<(),BlastError> {
+        self.declared = true;
+        if let Some(lit) = self.blast_assertion(&expr)? {
+            match self.levels.last().unwrap().act {
+                None => self.sat.add_clause(&[lit]),
+                Some(act) => self.sat.add_clause(&[!act,lit])
+            };
+        }
+        Ok(())
+    }
+    fn assert_named(&mut self,expr: BlastExpr) -> Result<AssertionId,BlastError> {
+        self.declared = true;
+        let aid = self.named.len();
+        let guard = self.fresh();
+        self.named.push(guard);
+        self.levels.last_mut().unwrap().named.push(aid);
+        if let Some(lit) = self.blast_assertion(&expr)? {
+            self.sat.add_clause(&[!guard,lit]);
+        }
+        Ok(AssertionId(aid))
+    }
+    fn get_unsat_core(&mut self) -> Result<Vec<AssertionId>,BlastError> {
+        if self.last_result!=Some(CheckSatResult::Unsat) {
+            return Err(BlastError::NoResult)
+        }
+        let failed = self.sat.failed_assumptions();
+        Ok(self.named.iter().enumerate()
+           .filter(|&(_,guard)| failed.contains(guard))
+           .map(|(aid,_)| AssertionId(aid)).collect())
+    }
+    fn check_sat(&mut self) -> Result<CheckSatResult,BlastError> {
+        self.assumed.clear();
+        Ok(self.solve(&[]))
+    }
+    fn check_sat_assuming(&mut self,assumptions: &[BlastExpr])
+                          -> Result<CheckSatResult,BlastError> {
+        self.assumed.clear();
+        for e in assumptions.iter() {
+            match self.blast(e,&HashMap::new()) {
+                Ok(bits) => self.assumed.push((bits[0],*e)),
+                Err(BlastError::Unsupported(what)) => {
+                    self.unknown_reason = Some(format!("incomplete ({} not supported)",what));
+                    self.last_result = Some(CheckSatResult::Unknown);
+                    return Ok(CheckSatResult::Unknown)
+                },
+                Err(err) => return Err(err)
+            }
+        }
+        let lits : Vec<Lit> = self.assumed.iter().map(|&(l,_)| l).collect();
+        Ok(self.solve(&lits))
+    }
+    fn get_unsat_assumptions(&mut self) -> Result<Vec<BlastExpr>,BlastError> {
+        if self.last_result!=Some(CheckSatResult::Unsat) {
+            return Err(BlastError::NoResult)
+        }
+        let failed = self.sat.failed_assumptions();
+        Ok(self.assumed.iter()
+           .filter(|&&(ref lit,_)| failed.contains(lit))
+           .map(|&(_,e)| e).collect())
+    }
+    fn get_proof(&mut self) -> Result<SExpr,BlastError> {
+        unsupported("get-proof")
+    }
+    fn get_interpolant(&mut self,_: &[BlastExpr],_: &[BlastExpr])
+                       -> Result<Option<BlastExpr>,BlastError> {
+        unsupported("get-interpolants")
+    }
+    fn eliminate_quantifiers(&mut self,_: BlastExpr) -> Result<BlastExpr,BlastError> {
+        unsupported("quantifiers")
+    }
+    fn solver_simplify(&mut self,_: BlastExpr) -> Result<BlastExpr,BlastError> {
+        unsupported("simplify")
+    }
+    fn get_value(&mut self,expr: BlastExpr) -> Result<Value,BlastError> {
+        if self.last_result!=Some(CheckSatResult::Sat) {
+            return Err(BlastError::NoResult)
+        }
+        let srt = self.type_of(&expr)?;
+        let bits = self.blast(&expr,&HashMap::new())?;
+        self.value_of(&srt,&bits)
+    }
+    fn get_model(&mut self) -> Result<Model<BlastSort,BlastVar,BlastExpr,BlastFun>,BlastError> {
+        if self.last_result!=Some(CheckSatResult::Sat) {
+            return Err(BlastError::NoResult)
+        }
+        let mut model = Model::new();
+        for vid in 0..self.vars.len() {
+            if let (srt,None) = self.vars[vid] {
+                let bits = match self.var_bits.get(&vid) {
+                    Some(bits) => bits.clone(),
+                    // Unconstrained, so any value will do
+                    None => vec![!self.tru; self.width(&srt)?]
+                };
+                let val = self.value_of(&srt,&bits)?;
+                model.vars.push((BlastVar(vid),Interpretation::Value(val)));
+            }
+        }
+        for fid in 0..self.funs.len() {
+            if let (ref args,srt,None) = self.funs[fid] {
+                if args.len()>0 {
+                    continue
+                }
+                let bits = match self.fun_bits.get(&fid) {
+                    Some(bits) => bits.clone(),
+                    // Unconstrained, so any value will do
+                    None => vec![!self.tru; self.width(&srt)?]
+                };
+                let val = self.value_of(&srt,&bits)?;
+                model.funs.push((fid,Interpretation::Value(val)));
+            }
+        }
+        Ok(model)
+    }
+    fn declare_datatypes(&mut self,_: Vec<Datatype<FieldSort<BlastSort>>>)
+                         -> Result<usize,BlastError> {
+        unsupported("datatypes")
+    }
+    fn declare_sort(&mut self,_: usize) -> Result<usize,BlastError> {
+        unsupported("uninterpreted sorts")
+    }
+    fn define_sort(&mut self,params: usize,body: BlastSort) -> Result<usize,BlastError> {
+        let id = self.defined_sorts.len();
+        self.defined_sorts.push((params,body));
+        Ok(id)
+    }
+    fn defined_sort(&mut self,id: usize,args: Vec<BlastSort>) -> Result<BlastSort,BlastError> {
+        let (params,body) = self.defined_sorts[id];
+        debug_assert_eq!(params,args.len());
+        self.instantiate_sort(&body,&args)
+    }
+    fn declare_fun(&mut self,args: Vec<BlastSort>,ret: BlastSort,_: Option<String>)
+                   -> Result<BlastFun,BlastError> {
+        self.declared = true;
+        let fid = self.funs.len();
+        self.funs.push((args,ret,None));
+        Ok(fid)
+    }
+    fn define_fun(&mut self,params: Vec<NVar<BlastSort>>,body: BlastExpr)
+                  -> Result<BlastFun,BlastError> {
+        self.declared = true;
+        let fid = self.funs.len();
+        let ret = self.type_of(&body)?;
+        self.funs.push((params.iter().map(|p| p.sort).collect(),ret,Some((params,body))));
+        Ok(fid)
+    }
+    fn declare_funs_rec(&mut self,_: &[(Vec<NVar<BlastSort>>,BlastSort)])
+                        -> Result<Vec<BlastFun>,BlastError> {
+        unsupported("recursive functions")
+    }
+    fn emit_funs_rec(&mut self,_: &[BlastFun],_: &[(Vec<NVar<BlastSort>>,BlastSort)],
+                     _: Vec<BlastExpr>) -> Result<(),BlastError> {
+        unsupported("recursive functions")
+    }
+}
+
+#[test]
+fn test_bitblast_arith() {
+    let mut b = BitBlaster::new();
+    let bv8 = b.tp_bitvec(8).unwrap();
+    let x = b.declare(bv8).unwrap();
+    let y = b.declare(bv8).unwrap();
+    let c3 = b.const_bitvec(8,BigUint::from(3u32)).unwrap();
+    // x*3 = x+x+x holds for all x
+    let lhs = b.embed(Expr::App(Function::BV(8,BVOp::Arith(ArithOp::Mult)),vec![x,c3])).unwrap();
+    let xx = b.bvadd(x,x).unwrap();
+    let rhs = b.bvadd(xx,x).unwrap();
+    let eq = b.eq(lhs,rhs).unwrap();
+    let neq = b.not(eq).unwrap();
+    b.push().unwrap();
+    b.assert(neq).unwrap();
+    assert_eq!(b.check_sat().unwrap(),CheckSatResult::Unsat);
+    b.pop().unwrap();
+    // Find x and y with x+y = 200 and x <u y
+    let c200 = b.const_bitvec(8,BigUint::from(200u32)).unwrap();
+    let sum = b.bvadd(x,y).unwrap();
+    let sum_eq = b.eq(sum,c200).unwrap();
+    let lt = b.bvult(x,y).unwrap();
+    b.assert(sum_eq).unwrap();
+    b.assert(lt).unwrap();
+    assert_eq!(b.check_sat().unwrap(),CheckSatResult::Sat);
+    match (b.get_value(x).unwrap(),b.get_value(y).unwrap()) {
+        (Value::BitVec(8,vx),Value::BitVec(8,vy)) => {
+            assert_eq!((vx.clone()+vy.clone()) % BigUint::from(256u32),BigUint::from(200u32));
+            assert!(vx<vy);
+        },
+        vals => panic!("Unexpected values {:?}",vals)
+    }
+}
+
+#[test]
+fn test_bitblast_division() {
+    // Division follows the SMT-LIB semantics, including division by zero
+    let cases = [(BVOp::Div(false),7u32,2u32,3u32),
+                 (BVOp::Rem(false),7,2,1),
+                 (BVOp::Div(false),7,0,15),
+                 (BVOp::Rem(false),7,0,7),
+                 (BVOp::Div(true),0b1001,2,0b1101), // -7 / 2 = -3
+                 (BVOp::Rem(true),0b1001,2,0b1111), // -7 % 2 = -1
+                 (BVOp::Div(true),0b1001,0,1),
+                 (BVOp::ASHR,0b1001,1,0b1100),
+                 (BVOp::LSHR,0b1001,5,0),
+                 (BVOp::SHL,0b1001,2,0b0100)];
+    for &(ref op,a,b,res) in cases.iter() {
+        let mut bb = BitBlaster::new();
+        let bv4 = bb.tp_bitvec(4).unwrap();
+        let x = bb.declare(bv4).unwrap();
+        let ca = bb.const_bitvec(4,BigUint::from(a)).unwrap();
+        let cb = bb.const_bitvec(4,BigUint::from(b)).unwrap();
+        let app = bb.embed(Expr::App(Function::BV(4,op.clone()),vec![ca,cb])).unwrap();
+        let def = bb.eq(x,app).unwrap();
+        bb.assert(def).unwrap();
+        assert_eq!(bb.check_sat().unwrap(),CheckSatResult::Sat);
+        assert_eq!(bb.get_value(x).unwrap(),Value::BitVec(4,BigUint::from(res)),
+                   "{:?} {} {}",op,a,b);
+    }
+}
+
+#[test]
+fn test_bitblast_cores() {
+    let mut b = BitBlaster::new();
+    let tbool = b.tp_bool().unwrap();
+    let p = b.declare(tbool).unwrap();
+    let q = b.declare(tbool).unwrap();
+    let np = b.not(p).unwrap();
+    let p_or_q = b.or(vec![p,q]).unwrap();
+    let a1 = b.assert_named(p_or_q).unwrap();
+    let a2 = b.assert_named(np).unwrap();
+    assert_eq!(b.check_sat().unwrap(),CheckSatResult::Sat);
+    assert_eq!(b.get_value(q).unwrap(),Value::Bool(true));
+    let nq = b.not(q).unwrap();
+    assert_eq!(b.check_sat_assuming(&[nq]).unwrap(),CheckSatResult::Unsat);
+    assert_eq!(b.get_unsat_assumptions().unwrap(),vec![nq]);
+    let mut core = b.get_unsat_core().unwrap();
+    core.sort();
+    assert_eq!(core,vec![a1,a2]);
+}
+
+#[test]
+fn test_bitblast_unknown() {
+    let mut b = BitBlaster::new();
+    let tint = b.tp_int().unwrap();
+    let x = b.declare(tint).unwrap();
+    let c = b.const_int(BigInt::from(1)).unwrap();
+    let eq = b.eq(x,c).unwrap();
+    b.push().unwrap();
+    b.assert(eq).unwrap();
+    assert_eq!(b.check_sat().unwrap(),CheckSatResult::Unknown);
+    assert!(b.reason_unknown().unwrap().contains("integers"));
+    b.pop().unwrap();
+    assert_eq!(b.check_sat().unwrap(),CheckSatResult::Sat);
+}
+
+#[test]
+fn test_bitblast_errors() {
+    let mut b = BitBlaster::new();
+    match b.pop() {
+        Err(BlastError::EmptyStack) => {},
+        r => panic!("Unexpected result {:?}",r)
+    }
+    let tbool = b.tp_bool().unwrap();
+    let v = b.embed(Expr::LVar(NVar { id: 0, sort: tbool })).unwrap();
+    match b.assert(v) {
+        Err(BlastError::UnboundVariable) => {},
+        r => panic!("Unexpected result {:?}",r)
+    }
+    // The blaster is still usable
+    b.push().unwrap();
+    b.pop().unwrap();
+    assert_eq!(b.check_sat().unwrap(),CheckSatResult::Sat);
+}
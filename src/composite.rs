@@ -1880,7 +1880,7 @@ pub fn get_vec_elem_dyn<'a,T,Em
             Some(rx) => Ok(Some(get_vec_elem(rx,vec,inp_vec)?)),
             None => panic!("Index overflow")
         },
-        Some(Value::Real(_)) => panic!("Cannot index vector with Real"),
+        Some(v) => panic!("Cannot index vector with {}",v),
         None => {
             let srt = em.type_of(&idx)?;
             //let rvec = OptRef::Ref(vec.as_ref());
@@ -1986,7 +1986,7 @@ pub fn set_vec_elem_dyn<'a,'b,T,Em
             Some(rx) => Ok(Some(set_vec_elem(rx,vec,el,inp_vec,inp_el)?)),
             None => panic!("Index overflow")
         },
-        Some(Value::Real(_)) => panic!("Cannot index vector with Real"),
+        Some(v) => panic!("Cannot index vector with {}",v),
         None => {
             let srt = em.type_of(&idx)?;
             let mut nvec = OptRef::Owned(vec.as_ref().clone());
@@ -1,5 +1,5 @@
 use types::*;
-use expr::{Expr,Function,BVOp,ArithOp,OrdOp};
+use expr::{Expr,Function,BVOp,FPOp,ArithOp,OrdOp};
 use num_bigint::{BigInt,BigUint};
 use num_rational::Ratio;
 use std::fmt::Debug;
@@ -68,9 +68,93 @@ pub trait Embed : Sized {
                 -> Result<Self::Sort,Self::Error> {
         self.embed_sort(SortKind::Array(idx,el))
     }
+    fn tp_float(&mut self,eb: usize,sb: usize) -> Result<Self::Sort,Self::Error> {
+        self.embed_sort(SortKind::FloatingPoint(eb,sb))
+    }
+    fn is_float(&mut self,srt: &Self::Sort) -> Result<Option<(usize,usize)>,Self::Error> {
+        match self.unbed_sort(srt)? {
+            SortKind::FloatingPoint(eb,sb) => Ok(Some((eb,sb))),
+            _ => Ok(None)
+        }
+    }
+    fn tp_rounding_mode(&mut self) -> Result<Self::Sort,Self::Error> {
+        self.embed_sort(SortKind::RoundingMode)
+    }
+    /// Look up the definition of a declared datatype.
+    fn datatype(&mut self,usize)
+                -> Result<Datatype<Self::Sort>,Self::Error> {
+        panic!("Datatypes are not supported by this embedding")
+    }
+    fn tp_datatype(&mut self,dt: usize,pars: Vec<Self::Sort>)
+                   -> Result<Self::Sort,Self::Error> {
+        self.embed_sort(SortKind::Datatype(dt,pars))
+    }
+    fn is_datatype(&mut self,srt: &Self::Sort)
+                   -> Result<Option<(usize,Vec<Self::Sort>)>,Self::Error> {
+        match self.unbed_sort(srt)? {
+            SortKind::Datatype(dt,pars) => Ok(Some((dt,pars))),
+            _ => Ok(None)
+        }
+    }
+    fn tp_uninterpreted(&mut self,id: usize,args: Vec<Self::Sort>)
+                        -> Result<Self::Sort,Self::Error> {
+        self.embed_sort(SortKind::Uninterpreted(id,args))
+    }
+    /// Replace the sort parameters occuring in a sort by the given sorts.
+    fn instantiate_sort(&mut self,srt: &Self::Sort,pars: &[Self::Sort])
+                        -> Result<Self::Sort,Self::Error> {
+        if pars.len()==0 {
+            return Ok(srt.clone())
+        }
+        match self.unbed_sort(srt)? {
+            SortKind::Param(n) => Ok(pars[n].clone()),
+            SortKind::Array(idx,el) => {
+                let mut nidx = Vec::with_capacity(idx.len());
+                for i in idx.iter() {
+                    nidx.push(self.instantiate_sort(i,pars)?);
+                }
+                let nel = self.instantiate_sort(&el,pars)?;
+                self.tp_array(nidx,nel)
+            },
+            SortKind::Datatype(dt,args) => {
+                let mut nargs = Vec::with_capacity(args.len());
+                for arg in args.iter() {
+                    nargs.push(self.instantiate_sort(arg,pars)?);
+                }
+                self.tp_datatype(dt,nargs)
+            },
+            SortKind::Uninterpreted(id,args) => {
+                let mut nargs = Vec::with_capacity(args.len());
+                for arg in args.iter() {
+                    nargs.push(self.instantiate_sort(arg,pars)?);
+                }
+                self.tp_uninterpreted(id,nargs)
+            },
+            _ => Ok(srt.clone())
+        }
+    }
     fn var(&mut self,var: Self::Var) -> Result<Self::Expr,Self::Error> {
         self.embed(Expr::Var(var))
     }
+    fn app(&mut self,fun: Self::Fun,args: Vec<Self::Expr>)
+           -> Result<Self::Expr,Self::Error> {
+        debug_assert!({
+            let ar = self.arity(&fun)?;
+            let mut correct = ar==args.len();
+            for (i,arg) in args.iter().enumerate() {
+                if !correct { break }
+                let srt = self.type_of(arg)?;
+                let exp = self.type_of_arg(&fun,i)?;
+                correct = srt==exp;
+            }
+            correct
+        });
+        self.embed(Expr::App(Function::Fun(fun),args))
+    }
+    fn as_array(&mut self,fun: Self::Fun)
+                -> Result<Self::Expr,Self::Error> {
+        self.embed(Expr::AsArray(Function::Fun(fun)))
+    }
     fn const_bool(&mut self,val: bool)
                   -> Result<Self::Expr,Self::Error> {
         self.embed(Expr::Const(Value::Bool(val)))
@@ -87,6 +171,43 @@ pub trait Embed : Sized {
                     -> Result<Self::Expr,Self::Error> {
         self.embed(Expr::Const(Value::BitVec(bw,val)))
     }
+    fn const_float(&mut self,eb: usize,sb: usize,bits: BigUint)
+                   -> Result<Self::Expr,Self::Error> {
+        self.embed(Expr::Const(Value::Float(eb,sb,bits)))
+    }
+    fn const_rounding_mode(&mut self,rm: RoundingMode)
+                           -> Result<Self::Expr,Self::Error> {
+        self.embed(Expr::Const(Value::RoundingMode(rm)))
+    }
+    fn construct(&mut self,dt: usize,pars: Vec<Self::Sort>,con: usize,
+                 args: Vec<Self::Expr>)
+                 -> Result<Self::Expr,Self::Error> {
+        debug_assert!({
+            let def = self.datatype(dt)?;
+            def.params==pars.len() && def.constructors[con].len()==args.len()
+        });
+        self.embed(Expr::App(Function::Constructor(dt,pars,con),args))
+    }
+    fn select_field(&mut self,con: usize,field: usize,e: Self::Expr)
+                    -> Result<Self::Expr,Self::Error> {
+        let srt = self.type_of(&e)?;
+        match self.is_datatype(&srt)? {
+            Some((dt,pars))
+                => self.embed(Expr::App(Function::Selector(dt,pars,con,field),
+                                        vec![e])),
+            None => panic!("Cannot select field of non-datatype expression")
+        }
+    }
+    fn is_constructor(&mut self,con: usize,e: Self::Expr)
+                      -> Result<Self::Expr,Self::Error> {
+        let srt = self.type_of(&e)?;
+        match self.is_datatype(&srt)? {
+            Some((dt,pars))
+                => self.embed(Expr::App(Function::Tester(dt,pars,con),
+                                        vec![e])),
+            None => panic!("Cannot test constructor of non-datatype expression")
+        }
+    }
     fn eq(&mut self,e1: Self::Expr,e2: Self::Expr)
           -> Result<Self::Expr,Self::Error> {
         let tp1 = self.type_of(&e1)?;
@@ -478,7 +599,172 @@ pub trait Embed : Sized {
             }
             }));
 
-        self.embed(Expr::App(Function::Select(idx_tp,el_tp),idx))
+        let mut args = Vec::with_capacity(idx.len()+1);
+        args.push(arr);
+        args.extend(idx);
+        self.embed(Expr::App(Function::Select(idx_tp,el_tp),args))
+    }
+    /// Apply a floating point operation. The format is taken from the
+    /// first floating point argument.
+    fn fpop(&mut self,op: FPOp,args: Vec<Self::Expr>)
+            -> Result<Self::Expr,Self::Error> {
+        let mut format = None;
+        for arg in args.iter() {
+            let srt = self.type_of(arg)?;
+            if let Some(fmt) = self.is_float(&srt)? {
+                format = Some(fmt);
+                break
+            }
+        }
+        let (eb,sb) = match format {
+            Some(r) => r,
+            None => panic!("No floating point argument to {}",op)
+        };
+        debug_assert!(args.iter().skip(if op.has_rounding_mode() { 1 } else { 0 }).all(|arg| match self.type_of(arg) {
+            Ok(tp) => match self.is_float(&tp) {
+                Ok(Some(fmt)) => fmt==(eb,sb),
+                _ => false
+            },
+            Err(_) => false
+        }));
+        self.embed(Expr::App(Function::FP(eb,sb,op),args))
+    }
+    fn fp_abs(&mut self,e: Self::Expr) -> Result<Self::Expr,Self::Error> {
+        self.fpop(FPOp::Abs,vec![e])
+    }
+    fn fp_neg(&mut self,e: Self::Expr) -> Result<Self::Expr,Self::Error> {
+        self.fpop(FPOp::Neg,vec![e])
+    }
+    fn fp_add(&mut self,rm: Self::Expr,lhs: Self::Expr,rhs: Self::Expr)
+              -> Result<Self::Expr,Self::Error> {
+        self.fpop(FPOp::Arith(ArithOp::Add),vec![rm,lhs,rhs])
+    }
+    fn fp_sub(&mut self,rm: Self::Expr,lhs: Self::Expr,rhs: Self::Expr)
+              -> Result<Self::Expr,Self::Error> {
+        self.fpop(FPOp::Arith(ArithOp::Sub),vec![rm,lhs,rhs])
+    }
+    fn fp_mul(&mut self,rm: Self::Expr,lhs: Self::Expr,rhs: Self::Expr)
+              -> Result<Self::Expr,Self::Error> {
+        self.fpop(FPOp::Arith(ArithOp::Mult),vec![rm,lhs,rhs])
+    }
+    fn fp_div(&mut self,rm: Self::Expr,lhs: Self::Expr,rhs: Self::Expr)
+              -> Result<Self::Expr,Self::Error> {
+        self.fpop(FPOp::Div,vec![rm,lhs,rhs])
+    }
+    fn fp_fma(&mut self,rm: Self::Expr,x: Self::Expr,y: Self::Expr,z: Self::Expr)
+              -> Result<Self::Expr,Self::Error> {
+        self.fpop(FPOp::FMA,vec![rm,x,y,z])
+    }
+    fn fp_sqrt(&mut self,rm: Self::Expr,e: Self::Expr)
+               -> Result<Self::Expr,Self::Error> {
+        self.fpop(FPOp::Sqrt,vec![rm,e])
+    }
+    fn fp_rem(&mut self,lhs: Self::Expr,rhs: Self::Expr)
+              -> Result<Self::Expr,Self::Error> {
+        self.fpop(FPOp::Rem,vec![lhs,rhs])
+    }
+    fn fp_round_to_integral(&mut self,rm: Self::Expr,e: Self::Expr)
+                            -> Result<Self::Expr,Self::Error> {
+        self.fpop(FPOp::RoundToIntegral,vec![rm,e])
+    }
+    fn fp_min(&mut self,lhs: Self::Expr,rhs: Self::Expr)
+              -> Result<Self::Expr,Self::Error> {
+        self.fpop(FPOp::Min,vec![lhs,rhs])
+    }
+    fn fp_max(&mut self,lhs: Self::Expr,rhs: Self::Expr)
+              -> Result<Self::Expr,Self::Error> {
+        self.fpop(FPOp::Max,vec![lhs,rhs])
+    }
+    fn fp_cmp(&mut self,op: OrdOp,lhs: Self::Expr,rhs: Self::Expr)
+              -> Result<Self::Expr,Self::Error> {
+        self.fpop(FPOp::Ord(op),vec![lhs,rhs])
+    }
+    fn fp_leq(&mut self,lhs: Self::Expr,rhs: Self::Expr)
+              -> Result<Self::Expr,Self::Error> {
+        self.fp_cmp(OrdOp::Le,lhs,rhs)
+    }
+    fn fp_lt(&mut self,lhs: Self::Expr,rhs: Self::Expr)
+             -> Result<Self::Expr,Self::Error> {
+        self.fp_cmp(OrdOp::Lt,lhs,rhs)
+    }
+    fn fp_geq(&mut self,lhs: Self::Expr,rhs: Self::Expr)
+              -> Result<Self::Expr,Self::Error> {
+        self.fp_cmp(OrdOp::Ge,lhs,rhs)
+    }
+    fn fp_gt(&mut self,lhs: Self::Expr,rhs: Self::Expr)
+             -> Result<Self::Expr,Self::Error> {
+        self.fp_cmp(OrdOp::Gt,lhs,rhs)
+    }
+    fn fp_eq(&mut self,lhs: Self::Expr,rhs: Self::Expr)
+             -> Result<Self::Expr,Self::Error> {
+        self.fpop(FPOp::Eq,vec![lhs,rhs])
+    }
+    fn fp_is_normal(&mut self,e: Self::Expr) -> Result<Self::Expr,Self::Error> {
+        self.fpop(FPOp::IsNormal,vec![e])
+    }
+    fn fp_is_subnormal(&mut self,e: Self::Expr) -> Result<Self::Expr,Self::Error> {
+        self.fpop(FPOp::IsSubnormal,vec![e])
+    }
+    fn fp_is_zero(&mut self,e: Self::Expr) -> Result<Self::Expr,Self::Error> {
+        self.fpop(FPOp::IsZero,vec![e])
+    }
+    fn fp_is_infinite(&mut self,e: Self::Expr) -> Result<Self::Expr,Self::Error> {
+        self.fpop(FPOp::IsInfinite,vec![e])
+    }
+    fn fp_is_nan(&mut self,e: Self::Expr) -> Result<Self::Expr,Self::Error> {
+        self.fpop(FPOp::IsNaN,vec![e])
+    }
+    fn fp_is_negative(&mut self,e: Self::Expr) -> Result<Self::Expr,Self::Error> {
+        self.fpop(FPOp::IsNegative,vec![e])
+    }
+    fn fp_is_positive(&mut self,e: Self::Expr) -> Result<Self::Expr,Self::Error> {
+        self.fpop(FPOp::IsPositive,vec![e])
+    }
+    fn fp_to_ubv(&mut self,rm: Self::Expr,bw: usize,e: Self::Expr)
+                 -> Result<Self::Expr,Self::Error> {
+        self.fpop(FPOp::ToUBV(bw),vec![rm,e])
+    }
+    fn fp_to_sbv(&mut self,rm: Self::Expr,bw: usize,e: Self::Expr)
+                 -> Result<Self::Expr,Self::Error> {
+        self.fpop(FPOp::ToSBV(bw),vec![rm,e])
+    }
+    fn fp_to_real(&mut self,e: Self::Expr) -> Result<Self::Expr,Self::Error> {
+        self.fpop(FPOp::ToReal,vec![e])
+    }
+    /// Convert to a floating point number of the given format. The source
+    /// can be a bitvector holding the IEEE bit pattern (without rounding
+    /// mode), or another float, a real or a signed bitvector (with rounding
+    /// mode).
+    fn to_fp(&mut self,eb: usize,sb: usize,rm: Option<Self::Expr>,e: Self::Expr)
+             -> Result<Self::Expr,Self::Error> {
+        let srt = self.type_of(&e)?;
+        match rm {
+            None => {
+                debug_assert!(match self.is_bitvec(&srt) {
+                    Ok(Some(bw)) => bw==eb+sb,
+                    _ => false
+                });
+                self.embed(Expr::App(Function::FP(eb,sb,FPOp::FromBV),vec![e]))
+            },
+            Some(rm) => {
+                let op = match self.unbed_sort(&srt)? {
+                    SortKind::FloatingPoint(eb2,sb2) => FPOp::FromFP(eb2,sb2),
+                    SortKind::Real => FPOp::FromReal,
+                    SortKind::BitVec(bw) => FPOp::FromSBV(bw),
+                    _ => panic!("Argument to to_fp has unsupported sort")
+                };
+                self.embed(Expr::App(Function::FP(eb,sb,op),vec![rm,e]))
+            }
+        }
+    }
+    fn to_fp_unsigned(&mut self,eb: usize,sb: usize,rm: Self::Expr,e: Self::Expr)
+                      -> Result<Self::Expr,Self::Error> {
+        let srt = self.type_of(&e)?;
+        let bw = match self.is_bitvec(&srt)? {
+            Some(r) => r,
+            None => panic!("Argument to to_fp_unsigned not a bitvector")
+        };
+        self.embed(Expr::App(Function::FP(eb,sb,FPOp::FromUBV(bw)),vec![rm,e]))
     }
 }
 
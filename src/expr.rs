@@ -50,7 +50,11 @@ pub enum Function<S,F> {
     ToReal,ToInt,
     ITE(S),
     BV(usize,BVOp),
-    Select(Vec<S>,S),Store(Vec<S>,S),ConstArray(Vec<S>,S)
+    FP(usize,usize,FPOp),
+    Select(Vec<S>,S),Store(Vec<S>,S),ConstArray(Vec<S>,S),
+    Constructor(usize,Vec<S>,usize), // datatype, parameters, constructor
+    Selector(usize,Vec<S>,usize,usize), // datatype, parameters, constructor, field
+    Tester(usize,Vec<S>,usize) // datatype, parameters, constructor
 }
 
 #[derive(Debug,PartialEq,Eq,Hash,Clone,Copy)]
@@ -76,6 +80,44 @@ pub enum BVOp {
     Concat
 }
 
+/// Floating point operations. The format of a `Function::FP` is the format
+/// of the floating point arguments, except for the conversions to floating
+/// point, where it is the format of the result.
+#[derive(Debug,PartialEq,Eq,Hash,Clone,Copy)]
+pub enum FPOp {
+    Abs,Neg,
+    Arith(ArithOp), // rounding mode, lhs, rhs
+    Div,            // rounding mode, lhs, rhs
+    FMA,            // rounding mode, x, y, z
+    Sqrt,           // rounding mode, x
+    Rem,
+    RoundToIntegral, // rounding mode, x
+    Min,Max,
+    Ord(OrdOp),
+    Eq,
+    IsNormal,IsSubnormal,IsZero,IsInfinite,IsNaN,IsNegative,IsPositive,
+    ToUBV(usize),ToSBV(usize), // rounding mode, x
+    ToReal,
+    FromBV,              // bitvector of the same width
+    FromFP(usize,usize), // rounding mode, float of the given format
+    FromReal,            // rounding mode, real
+    FromSBV(usize),      // rounding mode, signed bitvector of the given width
+    FromUBV(usize)       // rounding mode, unsigned bitvector of the given width
+}
+
+impl FPOp {
+    /// Does the operation take a rounding mode as first argument?
+    pub fn has_rounding_mode(&self) -> bool {
+        match *self {
+            FPOp::Arith(_) | FPOp::Div | FPOp::FMA | FPOp::Sqrt |
+            FPOp::RoundToIntegral | FPOp::ToUBV(_) | FPOp::ToSBV(_) |
+            FPOp::FromFP(_,_) | FPOp::FromReal | FPOp::FromSBV(_) |
+            FPOp::FromUBV(_) => true,
+            _ => false
+        }
+    }
+}
+
 impl<S : Clone + Eq + Debug,
      V : Clone + Eq + Debug,
      E : Clone + Eq + Debug,
@@ -170,9 +212,24 @@ impl<S : Clone + Eq + Debug,F : Clone + Eq + Debug> Function<S,F> {
                 BVOp::Extract(_,len) => em.tp_bitvec(len),
                 _ => em.tp_bitvec(sz)
             },
+            Function::FP(eb,sb,ref op) => match *op {
+                FPOp::Ord(_) | FPOp::Eq |
+                FPOp::IsNormal | FPOp::IsSubnormal | FPOp::IsZero |
+                FPOp::IsInfinite | FPOp::IsNaN |
+                FPOp::IsNegative | FPOp::IsPositive => em.tp_bool(),
+                FPOp::ToUBV(bw) | FPOp::ToSBV(bw) => em.tp_bitvec(bw),
+                FPOp::ToReal => em.tp_real(),
+                _ => em.tp_float(eb,sb)
+            },
             Function::Select(_,ref r) => Ok((*r).clone()),
             Function::Store(ref idx,ref r) => em.tp_array((*idx).clone(),(*r).clone()),
-            Function::ConstArray(ref idx,ref r) => em.tp_array((*idx).clone(),(*r).clone())
+            Function::ConstArray(ref idx,ref r) => em.tp_array((*idx).clone(),(*r).clone()),
+            Function::Constructor(dt,ref pars,_) => em.tp_datatype(dt,pars.clone()),
+            Function::Selector(dt,ref pars,con,field) => {
+                let def = em.datatype(dt)?;
+                em.instantiate_sort(&def.constructors[con][field],pars)
+            },
+            Function::Tester(_,_,_) => em.tp_bool()
         }
     }
     pub fn arity<Em : Embed<Sort=S,Fun=F>>(&self,em: &mut Em) -> Result<usize,Em::Error> {
@@ -206,9 +263,25 @@ impl<S : Clone + Eq + Debug,F : Clone + Eq + Debug> Function<S,F> {
                 BVOp::Not | BVOp::Neg | BVOp::Extract(_,_) => Ok(1),
                 _ => Ok(2)
             },
+            Function::FP(_,_,ref op) => match *op {
+                FPOp::FMA => Ok(4),
+                FPOp::Arith(_) | FPOp::Div => Ok(3),
+                FPOp::Abs | FPOp::Neg |
+                FPOp::IsNormal | FPOp::IsSubnormal | FPOp::IsZero |
+                FPOp::IsInfinite | FPOp::IsNaN |
+                FPOp::IsNegative | FPOp::IsPositive |
+                FPOp::ToReal | FPOp::FromBV => Ok(1),
+                _ => Ok(2)
+            },
             Function::Select(ref idx,_) => Ok(idx.len()+1),
             Function::Store(ref idx,_) => Ok(idx.len()+2),
-            Function::ConstArray(_,_) => Ok(1)
+            Function::ConstArray(_,_) => Ok(1),
+            Function::Constructor(dt,_,con) => {
+                let def = em.datatype(dt)?;
+                Ok(def.constructors[con].len())
+            },
+            Function::Selector(_,_,_,_) => Ok(1),
+            Function::Tester(_,_,_) => Ok(1)
         }
     }
     pub fn arg_sort<Em : Embed<Sort=S,Fun=F>>(&self,em: &mut Em, arg: usize)
@@ -247,6 +320,17 @@ impl<S : Clone + Eq + Debug,F : Clone + Eq + Debug> Function<S,F> {
                 Ok((*s).clone())
             },
             Function::BV(sz,_) => em.tp_bitvec(sz),
+            Function::FP(eb,sb,ref op) => if arg==0 && op.has_rounding_mode() {
+                em.tp_rounding_mode()
+            } else {
+                match *op {
+                    FPOp::FromBV => em.tp_bitvec(eb+sb),
+                    FPOp::FromFP(eb2,sb2) => em.tp_float(eb2,sb2),
+                    FPOp::FromReal => em.tp_real(),
+                    FPOp::FromSBV(bw) | FPOp::FromUBV(bw) => em.tp_bitvec(bw),
+                    _ => em.tp_float(eb,sb)
+                }
+            },
             Function::Select(ref idx,ref srt)
                 => if arg==0 {
                     let arr_idx = (*idx).clone();
@@ -266,7 +350,13 @@ impl<S : Clone + Eq + Debug,F : Clone + Eq + Debug> Function<S,F> {
                     Ok((*srt).clone())
                 },
             Function::ConstArray(_,ref srt)
-                => Ok((*srt).clone())
+                => Ok((*srt).clone()),
+            Function::Constructor(dt,ref pars,con) => {
+                let def = em.datatype(dt)?;
+                em.instantiate_sort(&def.constructors[con][arg],pars)
+            },
+            Function::Selector(dt,ref pars,_,_) => em.tp_datatype(dt,pars.clone()),
+            Function::Tester(dt,ref pars,_) => em.tp_datatype(dt,pars.clone())
         }
     }
 }
@@ -287,6 +377,7 @@ impl<S,F> Function<S,F> {
             Function::AtMost(_,_) => true,
             Function::ITE(_) => true,
             Function::BV(_,_) => true,
+            Function::FP(_,_,_) => true,
             Function::Select(_,_) => true,
             Function::Store(_,_) => true,
             _ => false
@@ -340,6 +431,7 @@ impl<S,F> Function<S,F> {
                 Ok(Function::ITE(nsrt))
             },
             &Function::BV(bw,op) => Ok(Function::BV(bw,op)),
+            &Function::FP(eb,sb,op) => Ok(Function::FP(eb,sb,op)),
             &Function::Select(ref idx,ref el) => {
                 let mut nidx = Vec::with_capacity(idx.len());
                 for i in idx.iter() {
@@ -363,6 +455,27 @@ impl<S,F> Function<S,F> {
                 }
                 let nel = trs(el)?;
                 Ok(Function::ConstArray(nidx,nel))
+            },
+            &Function::Constructor(dt,ref pars,con) => {
+                let mut npars = Vec::with_capacity(pars.len());
+                for par in pars.iter() {
+                    npars.push(trs(par)?)
+                }
+                Ok(Function::Constructor(dt,npars,con))
+            },
+            &Function::Selector(dt,ref pars,con,field) => {
+                let mut npars = Vec::with_capacity(pars.len());
+                for par in pars.iter() {
+                    npars.push(trs(par)?)
+                }
+                Ok(Function::Selector(dt,npars,con,field))
+            },
+            &Function::Tester(dt,ref pars,con) => {
+                let mut npars = Vec::with_capacity(pars.len());
+                for par in pars.iter() {
+                    npars.push(trs(par)?)
+                }
+                Ok(Function::Tester(dt,npars,con))
             }
         }
     }
@@ -431,6 +544,47 @@ impl Display for BVOp {
     }
 }
 
+impl Display for FPOp {
+    fn fmt(&self,f: &mut Formatter) -> Result<(),Error> {
+        match *self {
+            FPOp::Abs => write!(f,"fp.abs"),
+            FPOp::Neg => write!(f,"fp.neg"),
+            FPOp::Arith(op) => match op {
+                ArithOp::Add => write!(f,"fp.add"),
+                ArithOp::Sub => write!(f,"fp.sub"),
+                ArithOp::Mult => write!(f,"fp.mul")
+            },
+            FPOp::Div => write!(f,"fp.div"),
+            FPOp::FMA => write!(f,"fp.fma"),
+            FPOp::Sqrt => write!(f,"fp.sqrt"),
+            FPOp::Rem => write!(f,"fp.rem"),
+            FPOp::RoundToIntegral => write!(f,"fp.roundToIntegral"),
+            FPOp::Min => write!(f,"fp.min"),
+            FPOp::Max => write!(f,"fp.max"),
+            FPOp::Ord(op) => match op {
+                OrdOp::Ge => write!(f,"fp.geq"),
+                OrdOp::Gt => write!(f,"fp.gt"),
+                OrdOp::Le => write!(f,"fp.leq"),
+                OrdOp::Lt => write!(f,"fp.lt")
+            },
+            FPOp::Eq => write!(f,"fp.eq"),
+            FPOp::IsNormal => write!(f,"fp.isNormal"),
+            FPOp::IsSubnormal => write!(f,"fp.isSubnormal"),
+            FPOp::IsZero => write!(f,"fp.isZero"),
+            FPOp::IsInfinite => write!(f,"fp.isInfinite"),
+            FPOp::IsNaN => write!(f,"fp.isNaN"),
+            FPOp::IsNegative => write!(f,"fp.isNegative"),
+            FPOp::IsPositive => write!(f,"fp.isPositive"),
+            FPOp::ToUBV(bw) => write!(f,"(_ fp.to_ubv {})",bw),
+            FPOp::ToSBV(bw) => write!(f,"(_ fp.to_sbv {})",bw),
+            FPOp::ToReal => write!(f,"fp.to_real"),
+            FPOp::FromBV | FPOp::FromFP(_,_) |
+            FPOp::FromReal | FPOp::FromSBV(_) => write!(f,"to_fp"),
+            FPOp::FromUBV(_) => write!(f,"to_fp_unsigned")
+        }
+    }
+}
+
 impl<S : Display,F : Display> Display for Function<S,F> {
     fn fmt(&self,f: &mut Formatter) -> Result<(),Error> {
         match *self {
@@ -460,6 +614,12 @@ impl<S : Display,F : Display> Display for Function<S,F> {
             Function::ToInt => write!(f,"to-int"),
             Function::ITE(_) => write!(f,"ite"),
             Function::BV(_,ref op) => Display::fmt(&op,f),
+            Function::FP(eb,sb,ref op) => match *op {
+                FPOp::FromBV | FPOp::FromFP(_,_) |
+                FPOp::FromReal | FPOp::FromSBV(_) |
+                FPOp::FromUBV(_) => write!(f,"(_ {} {} {})",op,eb,sb),
+                _ => Display::fmt(&op,f)
+            },
             Function::Select(_,_) => write!(f,"select"),
             Function::Store(_,_) => write!(f,"store"),
             Function::ConstArray(ref idx,ref el) => {
@@ -470,7 +630,18 @@ impl<S : Display,F : Display> Display for Function<S,F> {
                 }
                 Display::fmt(&el,f)?;
                 write!(f,")")
-            }
+            },
+            Function::Constructor(dt,ref pars,con) => if pars.len()==0 {
+                write!(f,"d{}c{}",dt,con)
+            } else {
+                write!(f,"(as d{}c{} (d{}",dt,con,dt)?;
+                for par in pars.iter() {
+                    write!(f," {}",par)?;
+                }
+                write!(f,"))")
+            },
+            Function::Selector(dt,_,con,field) => write!(f,"d{}c{}s{}",dt,con,field),
+            Function::Tester(dt,_,con) => write!(f,"(_ is d{}c{})",dt,con)
         }
     }
 }
@@ -487,6 +658,9 @@ impl<S : Display,
             Expr::LVar(ref v) => write!(f,"lv{}",v.id),
             Expr::Const(ref c) => Display::fmt(&c,f),
             Expr::App(ref fun,ref args) => {
+                if args.len()==0 {
+                    return Display::fmt(&fun,f)
+                }
                 write!(f,"({}",fun)?;
                 for arg in args.iter() {
                     write!(f," ")?;
@@ -1,9 +1,12 @@
 extern crate num_bigint;
 extern crate num_rational;
 extern crate num_traits;
+#[cfg(unix)]
+extern crate libc;
 
 pub mod embed;
 pub mod parser;
+pub mod reader;
 pub mod composite;
 pub mod domain;
 pub mod unique;
@@ -12,5 +15,13 @@ pub mod expr;
 pub mod backend;
 pub mod lazy;
 pub mod simplify;
+pub mod model;
+pub mod profile;
+pub mod options;
+pub mod sat;
+pub mod bitblast;
+pub mod script;
+#[cfg(feature="z3")]
+pub mod z3;
 #[cfg(test)]
 mod test;
@@ -0,0 +1,49 @@
+use types::Value;
+use expr::NVar;
+
+/// The interpretation of a variable or function in a model.
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub enum Interpretation<S,E> {
+    Value(Value),
+    /// A function given by its parameters and its body, in which the
+    /// parameters are referenced as quantified variables (`Expr::QVar`).
+    Fun(Vec<NVar<S>>,E)
+}
+
+/// A model as returned by `get-model`, containing interpretations for the
+/// declared variables and functions.
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub struct Model<S,V,E,F> {
+    pub vars: Vec<(V,Interpretation<S,E>)>,
+    pub funs: Vec<(F,Interpretation<S,E>)>
+}
+
+impl<S,V : Eq,E,F : Eq> Model<S,V,E,F> {
+    pub fn new() -> Self {
+        Model { vars: Vec::new(),
+                funs: Vec::new() }
+    }
+    pub fn var(&self,var: &V) -> Option<&Interpretation<S,E>> {
+        for &(ref v,ref interp) in self.vars.iter() {
+            if v==var {
+                return Some(interp)
+            }
+        }
+        None
+    }
+    /// The value of a variable, if the model assigns a constant to it.
+    pub fn value(&self,var: &V) -> Option<&Value> {
+        match self.var(var) {
+            Some(&Interpretation::Value(ref v)) => Some(v),
+            _ => None
+        }
+    }
+    pub fn fun(&self,fun: &F) -> Option<&Interpretation<S,E>> {
+        for &(ref f,ref interp) in self.funs.iter() {
+            if f==fun {
+                return Some(interp)
+            }
+        }
+        None
+    }
+}
@@ -0,0 +1,86 @@
+use std::fmt::{Display,Formatter,Error};
+use std::str::FromStr;
+
+/// The standard SMT-LIB logics.
+#[allow(non_camel_case_types)]
+#[derive(Clone,Debug,PartialEq,Eq,PartialOrd,Ord,Hash)]
+pub enum Logic {
+    QF_UF, QF_BV, QF_IDL, QF_RDL, QF_LIA, QF_LRA, QF_NIA, QF_NRA,
+    QF_LIRA, QF_UFBV, QF_UFIDL, QF_UFLIA, QF_UFLRA, QF_UFNIA, QF_UFNRA,
+    QF_AX, QF_ABV, QF_ALIA, QF_AUFBV, QF_AUFLIA, QF_FP, QF_BVFP, QF_ABVFP,
+    QF_DT, QF_UFDT, QF_S,
+    UF, BV, LIA, LRA, NIA, NRA, UFBV, UFLIA, UFLRA, UFNIA, UFDT,
+    ABV, ALIA, AUFLIA, AUFLIRA, AUFNIRA, AUFBV, FP,
+    ALL,
+    /// A solver specific logic
+    Other(String)
+}
+
+static LOGICS: [(&'static str,Logic);45] =
+    [("QF_UF",Logic::QF_UF),("QF_BV",Logic::QF_BV),("QF_IDL",Logic::QF_IDL),
+     ("QF_RDL",Logic::QF_RDL),("QF_LIA",Logic::QF_LIA),("QF_LRA",Logic::QF_LRA),
+     ("QF_NIA",Logic::QF_NIA),("QF_NRA",Logic::QF_NRA),("QF_LIRA",Logic::QF_LIRA),
+     ("QF_UFBV",Logic::QF_UFBV),("QF_UFIDL",Logic::QF_UFIDL),("QF_UFLIA",Logic::QF_UFLIA),
+     ("QF_UFLRA",Logic::QF_UFLRA),("QF_UFNIA",Logic::QF_UFNIA),("QF_UFNRA",Logic::QF_UFNRA),
+     ("QF_AX",Logic::QF_AX),("QF_ABV",Logic::QF_ABV),("QF_ALIA",Logic::QF_ALIA),
+     ("QF_AUFBV",Logic::QF_AUFBV),("QF_AUFLIA",Logic::QF_AUFLIA),("QF_FP",Logic::QF_FP),
+     ("QF_BVFP",Logic::QF_BVFP),("QF_ABVFP",Logic::QF_ABVFP),("QF_DT",Logic::QF_DT),
+     ("QF_UFDT",Logic::QF_UFDT),("QF_S",Logic::QF_S),
+     ("UF",Logic::UF),("BV",Logic::BV),("LIA",Logic::LIA),("LRA",Logic::LRA),
+     ("NIA",Logic::NIA),("NRA",Logic::NRA),("UFBV",Logic::UFBV),("UFLIA",Logic::UFLIA),
+     ("UFLRA",Logic::UFLRA),("UFNIA",Logic::UFNIA),("UFDT",Logic::UFDT),
+     ("ABV",Logic::ABV),("ALIA",Logic::ALIA),("AUFLIA",Logic::AUFLIA),
+     ("AUFLIRA",Logic::AUFLIRA),("AUFNIRA",Logic::AUFNIRA),("AUFBV",Logic::AUFBV),
+     ("FP",Logic::FP),("ALL",Logic::ALL)];
+
+impl Display for Logic {
+    fn fmt(&self, f: &mut Formatter) -> Result<(),Error> {
+        if let Logic::Other(ref name) = *self {
+            return f.write_str(name)
+        }
+        for &(name,ref l) in LOGICS.iter() {
+            if l==self {
+                return f.write_str(name)
+            }
+        }
+        unreachable!()
+    }
+}
+
+impl FromStr for Logic {
+    type Err = ();
+    fn from_str(s: &str) -> Result<Logic,()> {
+        for &(name,ref l) in LOGICS.iter() {
+            if name==s {
+                return Ok(l.clone())
+            }
+        }
+        Ok(Logic::Other(String::from(s)))
+    }
+}
+
+/// The value of an option or info attribute.
+#[derive(Clone,Debug,PartialEq)]
+pub enum OptionValue {
+    Bool(bool),
+    Numeral(u64),
+    Decimal(f64),
+    String(String),
+    Symbol(String)
+}
+
+impl Display for OptionValue {
+    fn fmt(&self, f: &mut Formatter) -> Result<(),Error> {
+        match *self {
+            OptionValue::Bool(b) => write!(f,"{}",b),
+            OptionValue::Numeral(n) => write!(f,"{}",n),
+            OptionValue::Decimal(d) => if d.fract()==0.0 {
+                write!(f,"{:.1}",d)
+            } else {
+                write!(f,"{}",d)
+            },
+            OptionValue::String(ref s) => write!(f,"\"{}\"",s.replace("\"","\"\"")),
+            OptionValue::Symbol(ref s) => f.write_str(s)
+        }
+    }
+}
@@ -3,12 +3,17 @@ extern crate num_rational;
 
 use self::num_bigint::{BigInt,BigUint};
 use self::num_rational::Ratio;
-use types::{SortKind};
-use expr::{Expr,Function,OrdOp,ArithOp,BVOp};
-use types::Value;
+use types::{Sort,SortKind};
+use expr::{Expr,Function,NVar,OrdOp,ArithOp,BVOp,FPOp};
+use types::{Value,RoundingMode,ObjectiveValue,Datatype,FieldSort};
 use embed::Embed;
+use model::{Model,Interpretation};
+use options::{Logic,OptionValue};
 use std::str;
 use std::str::FromStr;
+use std::collections::HashMap;
+use std::fmt;
+use std::fmt::{Display,Formatter};
 #[cfg(test)]
 use test::{Simple};
 
@@ -20,6 +25,40 @@ pub enum CheckSatResult {
 pub trait Parser : Embed {
     fn parse_var(&mut self,&[u8]) -> Result<Self::Var,Self::Error>;
     fn parse_fun(&mut self,&[u8]) -> Result<Self::Fun,Self::Error>;
+    /// Resolve the name of a datatype or declared sort to its (argument-less)
+    /// sort kind. By default, the `d<N>` and `u<N>` names are recognized.
+    fn parse_sort_name(&mut self,name: &[u8]) -> Option<SortKind<Self::Sort>> {
+        if name.len()==0 {
+            return None
+        }
+        match parse_dt_number(&name[1..]) {
+            Some((dt,rest)) if name[0]==b'd' && rest.len()==0
+                => Some(SortKind::Datatype(dt,vec![])),
+            Some((id,rest)) if name[0]==b'u' && rest.len()==0
+                => Some(SortKind::Uninterpreted(id,vec![])),
+            _ => None
+        }
+    }
+    /// Resolve the name of a sort alias (introduced by `define-sort`) to its
+    /// number of parameters and its definition, in which the parameters are
+    /// `SortKind::Param`. By default, there are no aliases.
+    fn parse_sort_alias(&mut self,_: &[u8]) -> Option<(usize,Self::Sort)> {
+        None
+    }
+    /// Make the names and signatures of recursive functions known before
+    /// their definitions (`define-fun-rec` and `define-funs-rec`) are
+    /// parsed, so that the bodies can refer to them. By default, nothing
+    /// is done.
+    fn declare_rec_symbols(&mut self,_: &[(String,Vec<Self::Sort>,Self::Sort)])
+                           -> Result<(),Self::Error> {
+        Ok(())
+    }
+    /// Resolve the name of a datatype constructor or selector to the
+    /// datatype, the constructor and (for selectors) the field. By default,
+    /// the `d<N>c<M>` and `d<N>c<M>s<K>` names are recognized.
+    fn parse_dt_symbol(&mut self,name: &[u8]) -> Option<(usize,usize,Option<usize>)> {
+        parse_dt_name(name)
+    }
 }
 
 #[derive(Debug,Clone,PartialEq,Eq)]
@@ -35,6 +74,9 @@ pub enum ParseError<Sort> {
     WrongNumberOfArgs(usize,usize,bool),
     ExpectedNumeric(Sort),
     ExpectedBitVec(Sort),
+    ExpectedFloat(Sort),
+    ExpectedDatatype(Sort),
+    ExpectedNumeral,
     ExpectedId,
     ExpectedParFun,
     ExpectedArray,
@@ -45,32 +87,141 @@ pub enum ParseError<Sort> {
     ExpectedArraySort(Sort),
     UnknownVar,
     UnknownFun,
+    UnknownSort,
+    UnknownAssertion,
     ExpectedLiteral(&'static[u8]),
     ExpectedQuote,
     InvalidUTF8,
     ExpectedCheckSatResult,
-    ExpectedExpr
+    ExpectedExpr,
+    UnknownCommand(String),
+    UnsupportedCommand(String)
+}
+
+/// A generic S-expression, used for solver responses which have no
+/// structured representation (like proofs).
+#[derive(Debug,Clone,PartialEq,Eq,Hash)]
+pub enum SExpr {
+    Atom(String), // symbol, keyword or literal
+    Str(String), // string literal without quotes
+    List(Vec<SExpr>)
+}
+
+/// A command of an SMT-LIB script.
+#[derive(Debug,Clone,PartialEq)]
+pub enum Command<S,E> {
+    SetLogic(Logic),
+    SetOption(String,OptionValue),
+    GetOption(String),
+    SetInfo(String,OptionValue),
+    GetInfo(String),
+    DeclareSort(String,usize), // name, arity
+    /// Declaration of a function (a constant if there are no arguments)
+    DeclareFun(String,Vec<S>,S),
+    /// Definition of a function, whose parameters are referenced as
+    /// quantified variables (`Expr::QVar`) in the body
+    DefineFun(String,Vec<NVar<S>>,S,E),
+    /// Definition of a group of (possibly mutually) recursive functions,
+    /// from `define-fun-rec` or `define-funs-rec`
+    DefineFunsRec(Vec<(String,Vec<NVar<S>>,S)>,Vec<E>),
+    /// Definition of a sort alias, whose parameters are referenced as
+    /// `SortKind::Param` in the body
+    DefineSort(String,Vec<String>,S),
+    DeclareDatatypes(Vec<DatatypeDecl<S>>),
+    Push(usize),
+    Pop(usize),
+    Assert(E),
+    AssertNamed(E,String),
+    CheckSat,
+    CheckSatAssuming(Vec<E>),
+    GetValue(Vec<E>),
+    GetModel,
+    GetProof,
+    GetUnsatCore,
+    GetUnsatAssumptions,
+    GetAssertions,
+    GetAssignment,
+    Reset,
+    ResetAssertions,
+    Echo(String),
+    Exit
+}
+
+/// A datatype of a `declare-datatypes` command together with the names of
+/// its sort parameters, constructors and fields.
+#[derive(Debug,Clone,PartialEq)]
+pub struct DatatypeDecl<S> {
+    pub name: String,
+    pub params: Vec<String>,
+    pub constructors: Vec<(String,Vec<(String,FieldSort<S>)>)>
+}
+
+impl<S : Clone> DatatypeDecl<S> {
+    /// The declared datatype without the names.
+    pub fn datatype(&self) -> Datatype<FieldSort<S>> {
+        Datatype { params: self.params.len(),
+                   constructors: self.constructors.iter()
+                   .map(|&(_,ref fields)| fields.iter()
+                        .map(|&(_,ref srt)| srt.clone()).collect())
+                   .collect() }
+    }
 }
 
 #[derive(Debug,PartialEq,Eq)]
 pub enum PResult<'inp,R,P : Parser> {
     Done(R,&'inp[u8]),
-    SyntaxError(ParseError<P::Sort>),
+    SyntaxError(ParseError<P::Sort>,Pos),
     EmbedError(P::Error),
     Incomplete
 }
 
+/// A position in the input, counting lines and columns (in bytes) from
+/// zero.
 #[derive(Debug,Clone,PartialEq,Eq)]
 pub struct Pos { pub line: usize,
                  pub col: usize }
 
+/// A syntax error located in the input it was found in. The offending
+/// token starts at `pos`; it is empty if the error is at the end of a
+/// line or at whitespace.
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub struct SyntaxError<S> {
+    pub error: ParseError<S>,
+    pub pos: Pos,
+    pub token: String,
+    /// The complete line containing the error
+    pub line: String
+}
+
+impl<S> SyntaxError<S> {
+    /// Locate an error reported at `pos` in the complete input that was
+    /// parsed (starting at line and column zero).
+    pub fn new(error: ParseError<S>,pos: Pos,input: &[u8]) -> Self {
+        let mut line = input.split(|c| *c==b'\n').nth(pos.line).unwrap_or(&[]);
+        if line.len()>0 && line[line.len()-1]==b'\r' {
+            line = &line[0..line.len()-1];
+        }
+        let col = if pos.col<line.len() { pos.col } else { line.len() };
+        let rest = &line[col..];
+        let len = if rest.len()>0 && (rest[0]==b'(' || rest[0]==b')') {
+            1
+        } else {
+            rest.iter().take_while(|c| is_sym_char(**c)).count()
+        };
+        SyntaxError { error: error,
+                      pos: pos,
+                      token: String::from_utf8_lossy(&rest[0..len]).into_owned(),
+                      line: String::from_utf8_lossy(line).into_owned() }
+    }
+}
+
 #[inline]
 fn eat_ws<'inp>(inp: &'inp[u8],pos: &mut Pos) -> &'inp[u8] {
     let mut off = 0;
     loop {
         if off >= inp.len() { return &inp[off..] }
         match inp[off] {
-            b' ' | b'\t' => { pos.col+=1; }
+            b' ' | b'\t' | b'\r' => { pos.col+=1; }
             b'\n' => {
                 pos.line+=1;
                 pos.col=0;
@@ -81,31 +232,395 @@ fn eat_ws<'inp>(inp: &'inp[u8],pos: &mut Pos) -> &'inp[u8] {
     }
 }
 
+/// Update the position after consuming input which may span several lines
+/// (like string literals and quoted symbols).
+fn advance(consumed: &[u8],pos: &mut Pos) {
+    for c in consumed.iter() {
+        if *c==b'\n' {
+            pos.line+=1;
+            pos.col=0;
+        } else {
+            pos.col+=1;
+        }
+    }
+}
+
 #[inline]
 fn is_sym_char(c: u8) -> bool {
     c!=b' ' &&
         c!=b'\t' &&
+        c!=b'\r' &&
         c!=b'\n' &&
         c!=b')' &&
         c!=b'('
 }
 
-pub fn parse_var<'inp,P : Parser>(input: &'inp[u8],pos: &mut Pos,p: &mut P) -> PResult<'inp,P::Var,P> {
-    if input.len()==0 {
-        return PResult::Incomplete
+/// Determine the extent of the symbol at the start of the input, which is
+/// either a simple symbol or a `|`-quoted one. Returns the name of the
+/// symbol without the quotes together with the number of bytes it occupies,
+/// or `None` if a quoted symbol isn't terminated yet.
+fn symbol_extent(input: &[u8]) -> Option<(&[u8],usize)> {
+    if input.len()>0 && input[0]==b'|' {
+        input[1..].iter().position(|c| *c==b'|')
+            .map(|end| (&input[1..end+1],end+2))
+    } else {
+        let off = input.iter().take_while(|c| is_sym_char(**c)).count();
+        Some((&input[0..off],off))
+    }
+}
+
+/// The reserved words of SMT-LIB, which are only symbols when quoted.
+const RESERVED_WORDS: &'static [&'static str]
+    = &["!","_","as","BINARY","DECIMAL","exists","HEXADECIMAL","forall","let",
+        "match","NUMERAL","par","STRING",
+        "assert","check-sat","check-sat-assuming","declare-const","declare-datatype",
+        "declare-datatypes","declare-fun","declare-sort","define-fun","define-fun-rec",
+        "define-funs-rec","define-sort","echo","exit","get-assertions","get-assignment",
+        "get-info","get-model","get-option","get-proof","get-unsat-assumptions",
+        "get-unsat-core","get-value","pop","push","reset","reset-assertions",
+        "set-info","set-logic","set-option"];
+
+/// Whether a name can be printed as a simple symbol or needs to be quoted.
+fn is_simple_symbol(name: &str) -> bool {
+    if RESERVED_WORDS.contains(&name) {
+        return false
+    }
+    match name.chars().next() {
+        None => false,
+        Some(c) => !c.is_ascii_digit() && name.chars().all(|c| {
+            c.is_ascii_alphanumeric() || "~!@$%^&*_-+=<>.?/".contains(c)
+        })
+    }
+}
+
+/// Formats a symbol name, quoting it with `|...|` if it isn't a simple
+/// symbol.
+pub struct SymbolName<'a>(pub &'a str);
+
+impl<'a> Display for SymbolName<'a> {
+    fn fmt(&self,f: &mut Formatter) -> fmt::Result {
+        if is_simple_symbol(self.0) {
+            write!(f,"{}",self.0)
+        } else {
+            write!(f,"|{}|",self.0)
+        }
     }
+}
+
+fn parse_usize<'inp,P : Parser>(input: &'inp[u8],pos: &mut Pos,_: &mut P) -> PResult<'inp,usize,P> {
     let mut off = 0;
     loop {
-        match input[off] {
-            b' ' | b'\t' | b'\n' | b'(' | b')' => break,
-            _ => {}
+        if off>=input.len() {
+            return PResult::Incomplete
+        }
+        if !(input[off] as char).is_digit(10) {
+            break
+        }
+        off+=1;
+    }
+    if off==0 {
+        return PResult::SyntaxError(ParseError::ExpectedNumeral,pos.clone())
+    }
+    match str::from_utf8(&input[0..off]) {
+        Err(_) => panic!("Internal error: Cannot parse {:?} to &str",&input[0..off]),
+        Ok(sz) => match FromStr::from_str(sz) {
+            Err(_) => PResult::SyntaxError(ParseError::ExpectedNumeral,pos.clone()),
+            Ok(rsz) => {
+                pos.col+=off;
+                PResult::Done(rsz,&input[off..])
+            }
+        }
+    }
+}
+
+/// Parse the remaining arguments of a function application up to (and
+/// including) the closing parenthesis. `hints` gives the expected sorts of
+/// the first arguments.
+fn parse_args<'inp,P : Parser,F,Sub>(input: &'inp[u8],
+                                     pos: &mut Pos,
+                                     p: &mut P,
+                                     indir: usize,
+                                     hints: &[P::Sort],
+                                     rec: &F)
+                                     -> PResult<'inp,(Vec<P::Sort>,Vec<Sub>,Vec<Vec<Vec<P::Sort>>>),P>
+    where F : Fn(&'inp[u8],
+                 &mut Pos,
+                 &mut P,
+                 usize,
+                 usize,
+                 Option<&P::Sort>) -> PResult<'inp,Option<(P::Sort,Sub,Vec<Vec<P::Sort>>)>,P> {
+    let mut sorts = Vec::new();
+    let mut args = Vec::new();
+    let mut indices = Vec::new();
+    let mut input1 = input;
+    loop {
+        let hint = hints.get(args.len());
+        match rec(input1,pos,p,args.len(),indir,hint) {
+            PResult::Done(None,ninp) => return PResult::Done((sorts,args,indices),ninp),
+            PResult::Done(Some((srt,sub,idx)),ninp) => {
+                sorts.push(srt);
+                args.push(sub);
+                indices.push(idx);
+                input1 = ninp;
+            },
+            PResult::Incomplete => return PResult::Incomplete,
+            PResult::EmbedError(e) => return PResult::EmbedError(e),
+            PResult::SyntaxError(e,epos) => return PResult::SyntaxError(e,epos)
+        }
+    }
+}
+
+/// Parse the arguments of a floating point operation and determine the
+/// format from the first floating point argument.
+fn parse_fp_app<'inp,P : Parser,F,Sub>(op: FPOp,
+                                       input: &'inp[u8],
+                                       pos: &mut Pos,
+                                       p: &mut P,
+                                       indir: usize,
+                                       rec: &F)
+                                       -> PResult<'inp,(Function<P::Sort,P::Fun>,
+                                                        Vec<Sub>,Vec<Vec<Vec<P::Sort>>>,bool),P>
+    where F : Fn(&'inp[u8],
+                 &mut Pos,
+                 &mut P,
+                 usize,
+                 usize,
+                 Option<&P::Sort>) -> PResult<'inp,Option<(P::Sort,Sub,Vec<Vec<P::Sort>>)>,P> {
+    let hints = if op.has_rounding_mode() {
+        match p.embed_sort(SortKind::RoundingMode) {
+            Err(e) => return PResult::EmbedError(e),
+            Ok(srt) => vec![srt]
+        }
+    } else {
+        vec![]
+    };
+    match parse_args(input,pos,p,indir,&hints,rec) {
+        PResult::Done((sorts,args,indices),ninp) => {
+            let mut format = None;
+            for srt in sorts.iter() {
+                match p.unbed_sort(srt) {
+                    Err(e) => return PResult::EmbedError(e),
+                    Ok(SortKind::FloatingPoint(eb,sb)) => {
+                        format = Some((eb,sb));
+                        break
+                    },
+                    Ok(_) => {}
+                }
+            }
+            let (eb,sb) = match format {
+                Some(r) => r,
+                None => return match sorts.into_iter().last() {
+                    None => PResult::SyntaxError(ParseError::WrongNumberOfArgs(0,1,true),pos.clone()),
+                    Some(srt) => PResult::SyntaxError(ParseError::ExpectedFloat(srt),pos.clone())
+                }
+            };
+            let fun = Function::FP(eb,sb,op);
+            match fun.arity(p) {
+                Err(e) => PResult::EmbedError(e),
+                Ok(ar) => if ar!=args.len() {
+                    PResult::SyntaxError(ParseError::WrongNumberOfArgs(args.len(),ar,false),pos.clone())
+                } else {
+                    PResult::Done((fun,args,indices,true),ninp)
+                }
+            }
+        },
+        PResult::Incomplete => PResult::Incomplete,
+        PResult::EmbedError(e) => PResult::EmbedError(e),
+        PResult::SyntaxError(e,epos) => PResult::SyntaxError(e,epos)
+    }
+}
+
+/// Parse the arguments of an indexed conversion function
+/// (`to_fp`, `to_fp_unsigned`, `fp.to_ubv` or `fp.to_sbv`).
+fn parse_fp_conversion<'inp,P : Parser,F,Sub>(name: &[u8],
+                                              idx: (usize,usize),
+                                              input: &'inp[u8],
+                                              pos: &mut Pos,
+                                              p: &mut P,
+                                              indir: usize,
+                                              rec: &F)
+                                              -> PResult<'inp,(Function<P::Sort,P::Fun>,
+                                                               Vec<Sub>,Vec<Vec<Vec<P::Sort>>>,bool),P>
+    where F : Fn(&'inp[u8],
+                 &mut Pos,
+                 &mut P,
+                 usize,
+                 usize,
+                 Option<&P::Sort>) -> PResult<'inp,Option<(P::Sort,Sub,Vec<Vec<P::Sort>>)>,P> {
+    let (eb,sb) = idx;
+    match name {
+        b"fp.to_ubv" | b"fp.to_sbv" => {
+            let op = if name==b"fp.to_ubv" { FPOp::ToUBV(eb) } else { FPOp::ToSBV(eb) };
+            parse_fp_app(op,input,pos,p,indir,rec)
+        },
+        _ => {
+            // No hints: The first argument is either a rounding mode or the
+            // bitvector to reinterpret.
+            let (sorts,args,indices,ninp) = match parse_args(input,pos,p,indir,&[],rec) {
+                PResult::Done((sorts,args,indices),ninp) => (sorts,args,indices,ninp),
+                PResult::Incomplete => return PResult::Incomplete,
+                PResult::EmbedError(e) => return PResult::EmbedError(e),
+                PResult::SyntaxError(e,epos) => return PResult::SyntaxError(e,epos)
+            };
+            let src = match sorts.len() {
+                1 | 2 => match p.unbed_sort(&sorts[sorts.len()-1]) {
+                    Err(e) => return PResult::EmbedError(e),
+                    Ok(k) => k
+                },
+                n => return PResult::SyntaxError(ParseError::WrongNumberOfArgs(n,2,false),pos.clone())
+            };
+            let op = match (name==b"to_fp",sorts.len(),src) {
+                (true,1,SortKind::BitVec(_)) => FPOp::FromBV,
+                (true,2,SortKind::FloatingPoint(eb2,sb2)) => FPOp::FromFP(eb2,sb2),
+                (true,2,SortKind::Real) => FPOp::FromReal,
+                (true,2,SortKind::BitVec(bw)) => FPOp::FromSBV(bw),
+                (false,2,SortKind::BitVec(bw)) => FPOp::FromUBV(bw),
+                _ => return PResult::SyntaxError(ParseError::ExpectedFloat(sorts[sorts.len()-1].clone()),pos.clone())
+            };
+            PResult::Done((Function::FP(eb,sb,op),args,indices,true),ninp)
+        }
+    }
+}
+
+/// Parse a decimal number at the start of a symbol.
+fn parse_dt_number(inp: &[u8]) -> Option<(usize,&[u8])> {
+    let mut off = 0;
+    while off<inp.len() && (inp[off] as char).is_digit(10) {
+        off+=1;
+    }
+    if off==0 {
+        return None
+    }
+    match str::from_utf8(&inp[0..off]) {
+        Err(_) => None,
+        Ok(n) => match FromStr::from_str(n) {
+            Err(_) => None,
+            Ok(rn) => Some((rn,&inp[off..]))
+        }
+    }
+}
+
+/// Decompose a datatype symbol of the form `d<N>c<M>` (a constructor) or
+/// `d<N>c<M>s<K>` (a selector).
+fn parse_dt_name(name: &[u8]) -> Option<(usize,usize,Option<usize>)> {
+    if name.len()==0 || name[0]!=b'd' {
+        return None
+    }
+    let (dt,rest) = parse_dt_number(&name[1..])?;
+    if rest.len()==0 || rest[0]!=b'c' {
+        return None
+    }
+    let (con,rest) = parse_dt_number(&rest[1..])?;
+    if rest.len()==0 {
+        return Some((dt,con,None))
+    }
+    if rest[0]!=b's' {
+        return None
+    }
+    let (field,rest) = parse_dt_number(&rest[1..])?;
+    if rest.len()==0 {
+        Some((dt,con,Some(field)))
+    } else {
+        None
+    }
+}
+
+/// Determine the parameters of a datatype constructor from the expected
+/// sort. Returns `None` if the parameters cannot be determined.
+fn constructor_params<P : Parser>(dt: usize,p: &mut P,hint: Option<&P::Sort>)
+                                  -> Result<Option<Vec<P::Sort>>,P::Error> {
+    if let Some(srt) = hint {
+        if let Some((hdt,pars)) = p.is_datatype(srt)? {
+            if hdt==dt {
+                return Ok(Some(pars))
+            }
         }
+    }
+    let def = p.datatype(dt)?;
+    if def.params==0 {
+        Ok(Some(vec![]))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Parse the constructor and sort of a qualified constructor
+/// `(as d<N>c<M> sort)`, starting after the `as`.
+fn parse_as_constructor<'inp,P : Parser>(input: &'inp[u8],pos: &mut Pos,p: &mut P)
+                                         -> PResult<'inp,(usize,Vec<P::Sort>,usize),P> {
+    let mut off = 0;
+    while off<input.len() && is_sym_char(input[off]) {
         off+=1;
-        if off >= input.len() { break }
     }
-    match p.parse_var(&input[0..off]) {
+    if off==input.len() {
+        return PResult::Incomplete
+    }
+    let (dt,con) = match p.parse_dt_symbol(&input[0..off]) {
+        Some((dt,con,None)) => (dt,con),
+        _ => return PResult::SyntaxError(ParseError::ExpectedConst,pos.clone())
+    };
+    pos.col+=off;
+    let input1 = eat_ws(&input[off..],pos);
+    match parse_sort(input1,pos,p) {
+        PResult::Done(srt,input2) => match p.is_datatype(&srt) {
+            Err(e) => PResult::EmbedError(e),
+            Ok(Some((sdt,pars))) => if sdt==dt {
+                PResult::Done((dt,pars,con),input2)
+            } else {
+                PResult::SyntaxError(ParseError::ExpectedDatatype(srt),pos.clone())
+            },
+            Ok(None) => PResult::SyntaxError(ParseError::ExpectedDatatype(srt),pos.clone())
+        },
+        PResult::Incomplete => PResult::Incomplete,
+        PResult::EmbedError(e) => PResult::EmbedError(e),
+        PResult::SyntaxError(e,epos) => PResult::SyntaxError(e,epos)
+    }
+}
+
+/// Parse the argument of a selector or tester and determine the parameters
+/// of the datatype from it.
+fn parse_dt_arg<'inp,P : Parser,F,Sub>(dt: usize,
+                                       input: &'inp[u8],
+                                       pos: &mut Pos,
+                                       p: &mut P,
+                                       indir: usize,
+                                       rec: &F)
+                                       -> PResult<'inp,(Vec<P::Sort>,Sub,Vec<Vec<P::Sort>>),P>
+    where F : Fn(&'inp[u8],
+                 &mut Pos,
+                 &mut P,
+                 usize,
+                 usize,
+                 Option<&P::Sort>) -> PResult<'inp,Option<(P::Sort,Sub,Vec<Vec<P::Sort>>)>,P> {
+    match rec(input,pos,p,0,indir,None) {
+        PResult::Done(None,_)
+            => PResult::SyntaxError(ParseError::WrongNumberOfArgs(0,1,false),pos.clone()),
+        PResult::Done(Some((srt,sub,idx)),ninp) => match p.is_datatype(&srt) {
+            Err(e) => PResult::EmbedError(e),
+            Ok(Some((sdt,pars))) => if sdt==dt {
+                PResult::Done((pars,sub,idx),ninp)
+            } else {
+                PResult::SyntaxError(ParseError::ExpectedDatatype(srt),pos.clone())
+            },
+            Ok(None) => PResult::SyntaxError(ParseError::ExpectedDatatype(srt),pos.clone())
+        },
+        PResult::Incomplete => PResult::Incomplete,
+        PResult::EmbedError(e) => PResult::EmbedError(e),
+        PResult::SyntaxError(e,epos) => PResult::SyntaxError(e,epos)
+    }
+}
+
+pub fn parse_var<'inp,P : Parser>(input: &'inp[u8],pos: &mut Pos,p: &mut P) -> PResult<'inp,P::Var,P> {
+    if input.len()==0 {
+        return PResult::Incomplete
+    }
+    let (name,off) = match symbol_extent(input) {
+        None => return PResult::Incomplete,
+        Some(r) => r
+    };
+    match p.parse_var(name) {
         Ok(v) => {
-            pos.col+=off;
+            advance(&input[0..off],pos);
             PResult::Done(v,&input[off..])
         },
         Err(e) => PResult::EmbedError(e)
@@ -116,28 +631,64 @@ pub fn parse_fun<'inp,P : Parser>(input: &'inp[u8],pos: &mut Pos,p: &mut P) -> P
     if input.len()==0 {
         return PResult::Incomplete
     }
-    let mut off = 0;
-    loop {
-        match input[off] {
-            b' ' | b'\t' | b'\n' | b'(' | b')' => break,
-            _ => {}
-        }
-        off+=1;
-        if off >= input.len() { break }
-    }
-    match p.parse_fun(&input[0..off]) {
+    let (name,off) = match symbol_extent(input) {
+        None => return PResult::Incomplete,
+        Some(r) => r
+    };
+    match p.parse_fun(name) {
         Ok(v) => {
-            pos.col+=off;
+            advance(&input[0..off],pos);
             PResult::Done(v,&input[off..])
         },
         Err(e) => PResult::EmbedError(e)
     }
 }
 
-fn parse_expr<'inp,P : Parser>(input: &'inp[u8],pos: &mut Pos,p: &mut P, hint: Option<&P::Sort>)
-                               -> PResult<'inp,P::Expr,P> {
+/// Variables bound by function parameters or `let` expressions which are
+/// visible while parsing an expression.
+#[derive(Clone)]
+struct Scope<S> {
+    bound: Vec<(Vec<u8>,bool,NVar<S>)> // name, let-bound, variable
+}
+
+impl<S : Clone> Scope<S> {
+    fn new() -> Self {
+        Scope { bound: Vec::new() }
+    }
+    fn bind(&mut self,name: &[u8],is_let: bool,srt: S) -> NVar<S> {
+        let var = NVar { id: self.bound.len(),
+                         sort: srt };
+        self.bound.push((name.to_vec(),is_let,var.clone()));
+        var
+    }
+    fn lookup<V,E,F>(&self,name: &[u8]) -> Option<Expr<S,V,E,F>> {
+        for &(ref bname,is_let,ref var) in self.bound.iter().rev() {
+            if &bname[..]==name {
+                return Some(if is_let {
+                    Expr::LVar(var.clone())
+                } else {
+                    Expr::QVar(var.clone())
+                })
+            }
+        }
+        None
+    }
+}
+
+/// Parse an expression. The optional sort hint is used to resolve the sort
+/// of ambiguous constants.
+pub fn parse_expr<'inp,P : Parser>(input: &'inp[u8],pos: &mut Pos,p: &mut P, hint: Option<&P::Sort>)
+                                   -> PResult<'inp,P::Expr,P> {
+    parse_expr_scoped(input,pos,p,&Scope::new(),hint)
+}
+
+fn parse_expr_scoped<'inp,P : Parser>(input: &'inp[u8],pos: &mut Pos,p: &mut P,
+                                      scope: &Scope<P::Sort>,hint: Option<&P::Sort>)
+                                      -> PResult<'inp,P::Expr,P> {
     let old_pos = pos.clone();
-    match parse_value(input,pos,p,hint) {
+    // Constant arrays are left to the expression parser, which turns them
+    // into applications of `ConstArray`
+    match parse_scalar_value(input,pos,p,hint) {
         PResult::Done(v,ninp) => match p.embed(Expr::Const(v)) {
             Ok(e) => PResult::Done(e,ninp),
             Err(e) => PResult::EmbedError(e)
@@ -147,6 +698,10 @@ fn parse_expr<'inp,P : Parser>(input: &'inp[u8],pos: &mut Pos,p: &mut P, hint: O
             *pos = old_pos;
             pos.col+=1;
             let input1=eat_ws(&input[1..],pos);
+            if input1.len()>3 && &input1[0..3]==b"let" && !is_sym_char(input1[3]) {
+                pos.col+=3;
+                return parse_let(&input1[3..],pos,p,scope,hint)
+            }
             match parse_function(input1,pos,p,hint,0,
                                  &|inp,pos,p,n,indir,hint| {
                                      let rhint = if indir==0 { hint } else { None };
@@ -158,7 +713,7 @@ fn parse_expr<'inp,P : Parser>(input: &'inp[u8],pos: &mut Pos,p: &mut P, hint: O
                                              }
                                              if ninp[0]!=b')' {
                                                  return PResult::SyntaxError
-                                                     (ParseError::ExpectedClosePar)
+                                                     (ParseError::ExpectedClosePar,pos.clone())
                                              }
                                              pos.col+=1;
                                              ninp = eat_ws(&ninp[1..],pos);
@@ -171,7 +726,7 @@ fn parse_expr<'inp,P : Parser>(input: &'inp[u8],pos: &mut Pos,p: &mut P, hint: O
                                          pos.col+=1;
                                          return PResult::Done(None,&ninp[1..])
                                      }
-                                     match parse_expr(ninp,pos,p,rhint) {
+                                     match parse_expr_scoped(ninp,pos,p,scope,rhint) {
                                          PResult::Done(e,ninp2)
                                              => match p.type_of(&e) {
                                                  Err(e) => PResult::EmbedError(e),
@@ -186,7 +741,7 @@ fn parse_expr<'inp,P : Parser>(input: &'inp[u8],pos: &mut Pos,p: &mut P, hint: O
                                                                  indices.push(idx);
                                                              },
                                                              Ok(_) => return PResult::SyntaxError
-                                                                 (ParseError::ExpectedArray)
+                                                                 (ParseError::ExpectedArray,pos.clone())
                                                          }
                                                      }
                                                      PResult::Done(Some((ctp,e,indices)),ninp2)
@@ -194,8 +749,8 @@ fn parse_expr<'inp,P : Parser>(input: &'inp[u8],pos: &mut Pos,p: &mut P, hint: O
                                              },
                                          PResult::Incomplete
                                              => PResult::Incomplete,
-                                         PResult::SyntaxError(e)
-                                             => PResult::SyntaxError(e),
+                                         PResult::SyntaxError(e,epos)
+                                             => PResult::SyntaxError(e,epos),
                                          PResult::EmbedError(e)
                                              => PResult::EmbedError(e)
                                      }
@@ -216,7 +771,7 @@ fn parse_expr<'inp,P : Parser>(input: &'inp[u8],pos: &mut Pos,p: &mut P, hint: O
                             }
                             match fun.arg_sort(p,narg) {
                                 Err(e) => return PResult::EmbedError(e),
-                                Ok(srt) => match parse_expr(input1,pos,p,Some(&srt)) {
+                                Ok(srt) => match parse_expr_scoped(input1,pos,p,scope,Some(&srt)) {
                                     PResult::Done(e,ninp) => {
                                         args.push(e);
                                         narg+=1;
@@ -226,8 +781,8 @@ fn parse_expr<'inp,P : Parser>(input: &'inp[u8],pos: &mut Pos,p: &mut P, hint: O
                                         => return PResult::Incomplete,
                                     PResult::EmbedError(e)
                                         => return PResult::EmbedError(e),
-                                    PResult::SyntaxError(e)
-                                        => return PResult::SyntaxError(e)
+                                    PResult::SyntaxError(e,epos)
+                                        => return PResult::SyntaxError(e,epos)
                                 }
                             }
                         }
@@ -235,7 +790,7 @@ fn parse_expr<'inp,P : Parser>(input: &'inp[u8],pos: &mut Pos,p: &mut P, hint: O
                             Err(e) => return PResult::EmbedError(e),
                             Ok(ar) => if ar!=narg {
                                 return PResult::SyntaxError
-                                    (ParseError::WrongNumberOfArgs(narg,ar,false))
+                                    (ParseError::WrongNumberOfArgs(narg,ar,false),pos.clone())
                             }
                         }
                     }
@@ -246,27 +801,145 @@ fn parse_expr<'inp,P : Parser>(input: &'inp[u8],pos: &mut Pos,p: &mut P, hint: O
                 },
                 PResult::Incomplete => PResult::Incomplete,
                 PResult::EmbedError(e) => PResult::EmbedError(e),
-                PResult::SyntaxError(e) => PResult::SyntaxError(e)
+                PResult::SyntaxError(e,epos) => PResult::SyntaxError(e,epos)
             }
         } else if is_sym_char(input[0]) {
-            let mut off=1;
-            while off<input.len() {
-                if !is_sym_char(input[off]) { break }
-                off+=1
-            }
-            match p.parse_var(&input[0..off]) {
-                Ok(v) => match p.embed(Expr::Var(v)) {
+            *pos = old_pos;
+            let (name,off) = match symbol_extent(input) {
+                None => return PResult::Incomplete,
+                Some(r) => r
+            };
+            if let Some(bound) = scope.lookup(name) {
+                return match p.embed(bound) {
                     Ok(rv) => {
-                        pos.col+=off;
+                        advance(&input[0..off],pos);
                         PResult::Done(rv,&input[off..])
                     },
                     Err(e) => PResult::EmbedError(e)
+                }
+            }
+            if let Some((dt,con,None)) = p.parse_dt_symbol(name) {
+                // A nullary constructor
+                return match constructor_params(dt,p,hint) {
+                    Err(e) => PResult::EmbedError(e),
+                    Ok(None) => PResult::SyntaxError(ParseError::ExpectedAs,pos.clone()),
+                    Ok(Some(pars)) => match p.embed(Expr::App(Function::Constructor(dt,pars,con),
+                                                              vec![])) {
+                        Ok(rv) => {
+                            advance(&input[0..off],pos);
+                            PResult::Done(rv,&input[off..])
+                        },
+                        Err(e) => PResult::EmbedError(e)
+                    }
+                }
+            }
+            let rv = match p.parse_var(name) {
+                Ok(v) => p.embed(Expr::Var(v)),
+                // A nullary function, like a recursive constant
+                Err(e) => match p.parse_fun(name) {
+                    Ok(f) => match p.arity(&f) {
+                        Ok(0) => p.embed(Expr::App(Function::Fun(f),vec![])),
+                        _ => Err(e)
+                    },
+                    Err(_) => Err(e)
+                }
+            };
+            match rv {
+                Ok(rv) => {
+                    advance(&input[0..off],pos);
+                    PResult::Done(rv,&input[off..])
                 },
                 Err(e) => PResult::EmbedError(e)
             }
         } else {
-            PResult::SyntaxError(ParseError::ExpectedExpr)
+            PResult::SyntaxError(ParseError::ExpectedExpr,pos.clone())
+        }
+    }
+}
+
+/// Parse the rest of a `let` expression, starting after the `let` keyword.
+fn parse_let<'inp,P : Parser>(input: &'inp[u8],pos: &mut Pos,p: &mut P,
+                              scope: &Scope<P::Sort>,hint: Option<&P::Sort>)
+                              -> PResult<'inp,P::Expr,P> {
+    let input1 = eat_ws(input,pos);
+    if input1.len()==0 {
+        return PResult::Incomplete
+    }
+    if input1[0]!=b'(' {
+        return PResult::SyntaxError(ParseError::ExpectedOpenPar,pos.clone())
+    }
+    pos.col+=1;
+    let mut nscope = scope.clone();
+    let mut binds = Vec::new();
+    let mut input2 = &input1[1..];
+    loop {
+        input2 = eat_ws(input2,pos);
+        if input2.len()==0 {
+            return PResult::Incomplete
+        }
+        if input2[0]==b')' {
+            pos.col+=1;
+            input2 = &input2[1..];
+            break
+        }
+        if input2[0]!=b'(' {
+            return PResult::SyntaxError(ParseError::ExpectedOpenPar,pos.clone())
+        }
+        pos.col+=1;
+        let input3 = eat_ws(&input2[1..],pos);
+        let (name,off) = match symbol_extent(input3) {
+            None => return PResult::Incomplete,
+            Some(r) => r
+        };
+        if off==input3.len() {
+            return PResult::Incomplete
+        }
+        if off==0 {
+            return PResult::SyntaxError(ParseError::ExpectedId,pos.clone())
+        }
+        advance(&input3[0..off],pos);
+        let input4 = eat_ws(&input3[off..],pos);
+        // The bindings are parallel, so they are parsed in the outer scope
+        let (e,input5) = match parse_expr_scoped(input4,pos,p,scope,None) {
+            PResult::Done(e,ninp) => (e,ninp),
+            PResult::Incomplete => return PResult::Incomplete,
+            PResult::EmbedError(e) => return PResult::EmbedError(e),
+            PResult::SyntaxError(e,epos) => return PResult::SyntaxError(e,epos)
+        };
+        let srt = match p.type_of(&e) {
+            Err(e) => return PResult::EmbedError(e),
+            Ok(srt) => srt
+        };
+        let var = nscope.bind(name,true,srt);
+        binds.push((var,e));
+        let input6 = eat_ws(input5,pos);
+        if input6.len()==0 {
+            return PResult::Incomplete
         }
+        if input6[0]!=b')' {
+            return PResult::SyntaxError(ParseError::ExpectedClosePar,pos.clone())
+        }
+        pos.col+=1;
+        input2 = &input6[1..];
+    }
+    let input7 = eat_ws(input2,pos);
+    let (body,input8) = match parse_expr_scoped(input7,pos,p,&nscope,hint) {
+        PResult::Done(e,ninp) => (e,ninp),
+        PResult::Incomplete => return PResult::Incomplete,
+        PResult::EmbedError(e) => return PResult::EmbedError(e),
+        PResult::SyntaxError(e,epos) => return PResult::SyntaxError(e,epos)
+    };
+    let input9 = eat_ws(input8,pos);
+    if input9.len()==0 {
+        return PResult::Incomplete
+    }
+    if input9[0]!=b')' {
+        return PResult::SyntaxError(ParseError::ExpectedClosePar,pos.clone())
+    }
+    pos.col+=1;
+    match p.embed(Expr::Let(binds,body)) {
+        Err(e) => PResult::EmbedError(e),
+        Ok(res) => PResult::Done(res,&input9[1..])
     }
 }
 
@@ -287,6 +960,15 @@ fn parse_function<'inp,P : Parser,F,Sub>(input: &'inp[u8],
     if input.len()==0 {
         return PResult::Incomplete
     }
+    if input[0]==b'|' {
+        // Quoted symbols can only refer to user functions
+        return match parse_fun(input,pos,p) {
+            PResult::Done(fun,ninp) => PResult::Done((Function::Fun(fun),vec![],vec![],false),ninp),
+            PResult::Incomplete => PResult::Incomplete,
+            PResult::EmbedError(e) => PResult::EmbedError(e),
+            PResult::SyntaxError(e,epos) => PResult::SyntaxError(e,epos)
+        }
+    }
     if is_sym_char(input[0]) {
         let mut off=1;
         while off<input.len() {
@@ -310,7 +992,7 @@ fn parse_function<'inp,P : Parser,F,Sub>(input: &'inp[u8],
                                     if srt!=srt1 {
                                         return PResult::SyntaxError
                                             (ParseError::TypeMismatch
-                                             (srt,srt1))
+                                             (srt,srt1),pos.clone())
                                     }
                                     narg+=1;
                                     args.push(sub1);
@@ -322,8 +1004,8 @@ fn parse_function<'inp,P : Parser,F,Sub>(input: &'inp[u8],
                                          break },
                                 PResult::Incomplete
                                     => return PResult::Incomplete,
-                                PResult::SyntaxError(e)
-                                    => return PResult::SyntaxError(e),
+                                PResult::SyntaxError(e,epos)
+                                    => return PResult::SyntaxError(e,epos),
                                 PResult::EmbedError(e)
                                     => return PResult::EmbedError(e)
                             }
@@ -337,11 +1019,11 @@ fn parse_function<'inp,P : Parser,F,Sub>(input: &'inp[u8],
                     },
                     PResult::Done(None,_)
                         => PResult::SyntaxError
-                        (ParseError::WrongNumberOfArgs(0,1,true)),
+                        (ParseError::WrongNumberOfArgs(0,1,true),pos.clone()),
                     PResult::Incomplete
                         => PResult::Incomplete,
-                    PResult::SyntaxError(e)
-                        => PResult::SyntaxError(e),
+                    PResult::SyntaxError(e,epos)
+                        => PResult::SyntaxError(e,epos),
                     PResult::EmbedError(e)
                         => PResult::EmbedError(e)
                 }
@@ -358,7 +1040,7 @@ fn parse_function<'inp,P : Parser,F,Sub>(input: &'inp[u8],
                 match rec(&input[off..],pos,p,0,indir,None) {
                     PResult::Done(None,_)
                         => PResult::SyntaxError
-                        (ParseError::WrongNumberOfArgs(0,2,false)),
+                        (ParseError::WrongNumberOfArgs(0,2,false),pos.clone()),
                     PResult::Done(Some((tp,sub,_)),ninp) => match p.unbed_sort(&tp) {
                         Err(e) => PResult::EmbedError(e),
                         Ok(SortKind::Int)
@@ -368,11 +1050,11 @@ fn parse_function<'inp,P : Parser,F,Sub>(input: &'inp[u8],
                             => PResult::Done((Function::OrdReal(op),
                                               vec![sub],vec![vec![]],false),ninp),
                         Ok(_) => PResult::SyntaxError
-                            (ParseError::ExpectedNumeric(tp))
+                            (ParseError::ExpectedNumeric(tp),pos.clone())
                     },
                     PResult::Incomplete => PResult::Incomplete,
                     PResult::EmbedError(e) => PResult::EmbedError(e),
-                    PResult::SyntaxError(e) => PResult::SyntaxError(e)
+                    PResult::SyntaxError(e,epos) => PResult::SyntaxError(e,epos)
                 }
             },
             b"+" | b"-" | b"*" => {
@@ -395,12 +1077,12 @@ fn parse_function<'inp,P : Parser,F,Sub>(input: &'inp[u8],
                                               vec![],vec![],false),
                                              &input[off..]),
                         Ok(_) => PResult::SyntaxError
-                            (ParseError::ExpectedNumeric(srt.clone()))
+                            (ParseError::ExpectedNumeric(srt.clone()),pos.clone())
                     },
                     None => match rec(&input[1..],pos,p,0,indir,None) {
                         PResult::Done(None,_)
                             => PResult::SyntaxError
-                            (ParseError::WrongNumberOfArgs(0,2,false)),
+                            (ParseError::WrongNumberOfArgs(0,2,false),pos.clone()),
                         PResult::Done(Some((tp,sub,idx)),ninp) => {
                             let mut args = vec![sub];
                             let mut indices = vec![idx];
@@ -415,7 +1097,7 @@ fn parse_function<'inp,P : Parser,F,Sub>(input: &'inp[u8],
                                     PResult::Done(Some((tp2,sub,idx)),ninp) => {
                                         if tp!=tp2 {
                                             return PResult::SyntaxError
-                                                (ParseError::TypeMismatch(tp2,tp))
+                                                (ParseError::TypeMismatch(tp2,tp),pos.clone())
                                         }
                                         input1 = ninp;
                                         args.push(sub);
@@ -424,7 +1106,7 @@ fn parse_function<'inp,P : Parser,F,Sub>(input: &'inp[u8],
                                     },
                                     PResult::Incomplete => return PResult::Incomplete,
                                     PResult::EmbedError(e) => return PResult::EmbedError(e),
-                                    PResult::SyntaxError(e) => return PResult::SyntaxError(e)
+                                    PResult::SyntaxError(e,epos) => return PResult::SyntaxError(e,epos)
                                 }
                             }
                             match p.unbed_sort(&tp) {
@@ -438,12 +1120,12 @@ fn parse_function<'inp,P : Parser,F,Sub>(input: &'inp[u8],
                                         (Function::ArithReal(op,args.len()),
                                          args,indices,true),ninp),
                                 Ok(_) => PResult::SyntaxError
-                                    (ParseError::ExpectedNumeric(tp))
+                                    (ParseError::ExpectedNumeric(tp),pos.clone())
                             }
                         },
                         PResult::Incomplete => PResult::Incomplete,
                         PResult::EmbedError(e) => PResult::EmbedError(e),
-                        PResult::SyntaxError(e) => PResult::SyntaxError(e)
+                        PResult::SyntaxError(e,epos) => PResult::SyntaxError(e,epos)
                     }
                 }
             },
@@ -484,12 +1166,12 @@ fn parse_function<'inp,P : Parser,F,Sub>(input: &'inp[u8],
                             => PResult::Done((Function::AbsReal,vec![],vec![],false),
                                              &input[off..]),
                         Ok(_) => PResult::SyntaxError
-                            (ParseError::ExpectedNumeric(srt.clone()))
+                            (ParseError::ExpectedNumeric(srt.clone()),pos.clone())
                     },
                     None => match rec(&input[off..],pos,p,0,indir,None) {
                         PResult::Done(None,_)
                             => PResult::SyntaxError
-                            (ParseError::WrongNumberOfArgs(0,1,false)),
+                            (ParseError::WrongNumberOfArgs(0,1,false),pos.clone()),
                         PResult::Done(Some((srt,sub,idx)),ninp)
                             => match p.unbed_sort(&srt) {
                                 Err(e) => PResult::EmbedError(e),
@@ -502,11 +1184,11 @@ fn parse_function<'inp,P : Parser,F,Sub>(input: &'inp[u8],
                                                       vec![sub],vec![idx],false),
                                                      ninp),
                                 Ok(_) => PResult::SyntaxError
-                                    (ParseError::ExpectedNumeric(srt))
+                                    (ParseError::ExpectedNumeric(srt),pos.clone())
                             },
                         PResult::Incomplete => PResult::Incomplete,
                         PResult::EmbedError(e) => PResult::EmbedError(e),
-                        PResult::SyntaxError(e) => PResult::SyntaxError(e)
+                        PResult::SyntaxError(e,epos) => PResult::SyntaxError(e,epos)
                     }
                 }
             },
@@ -538,8 +1220,8 @@ fn parse_function<'inp,P : Parser,F,Sub>(input: &'inp[u8],
                             PResult::Incomplete => return PResult::Incomplete,
                             PResult::EmbedError(e)
                                 => return PResult::EmbedError(e),
-                            PResult::SyntaxError(e)
-                                => return PResult::SyntaxError(e)
+                            PResult::SyntaxError(e,epos)
+                                => return PResult::SyntaxError(e,epos)
                         }
                     }
                     let op = match &input[0..off] {
@@ -573,18 +1255,18 @@ fn parse_function<'inp,P : Parser,F,Sub>(input: &'inp[u8],
                         Ok(tp_b) => match rec(&input[off..],pos,p,0,indir,Some(&tp_b)) {
                             PResult::Done(None,_)
                                 => PResult::SyntaxError
-                                (ParseError::WrongNumberOfArgs(0,3,false)),
+                                (ParseError::WrongNumberOfArgs(0,3,false),pos.clone()),
                             PResult::Incomplete => PResult::Incomplete,
                             PResult::EmbedError(e) => PResult::EmbedError(e),
-                            PResult::SyntaxError(e) => PResult::SyntaxError(e),
+                            PResult::SyntaxError(e,epos) => PResult::SyntaxError(e,epos),
                             PResult::Done(Some((_,cond,cidx)),input1)
                                 => match rec(input1,pos,p,1,indir,None) {
                                     PResult::Done(None,_)
                                         => PResult::SyntaxError
-                                        (ParseError::WrongNumberOfArgs(0,3,false)),
+                                        (ParseError::WrongNumberOfArgs(0,3,false),pos.clone()),
                                     PResult::Incomplete => PResult::Incomplete,
                                     PResult::EmbedError(e) => PResult::EmbedError(e),
-                                    PResult::SyntaxError(e) => PResult::SyntaxError(e),
+                                    PResult::SyntaxError(e,epos) => PResult::SyntaxError(e,epos),
                                     PResult::Done(Some((srt,lhs,lidx)),input2)
                                         => PResult::Done
                                         ((Function::ITE(srt),vec![cond,lhs],vec![cidx,lidx],false),
@@ -602,7 +1284,7 @@ fn parse_function<'inp,P : Parser,F,Sub>(input: &'inp[u8],
             b"bvxor" | b"bvand" | b"bvor" |
             b"bvnot" | b"bvneg" => {
                 pos.col+=off;
-                let op = match &input[3..off] {
+                let op = match &input[2..off] {
                     b"uge" => BVOp::Ord(false,OrdOp::Ge),
                     b"ugt" => BVOp::Ord(false,OrdOp::Gt),
                     b"ule" => BVOp::Ord(false,OrdOp::Le),
@@ -636,14 +1318,16 @@ fn parse_function<'inp,P : Parser,F,Sub>(input: &'inp[u8],
                     BVOp::Ord(_,_) => true,
                     _ => false
                 };
-                let sz = if is_ord {
+                // The hint is the sort of the result, which is only the
+                // sort of the operands if the operation is no comparison
+                let sz = if !is_ord {
                     match hint {
                         None => None,
                         Some(srt) => match p.unbed_sort(srt) {
                             Err(e) => return PResult::EmbedError(e),
                             Ok(SortKind::BitVec(sz)) => Some(sz),
                             Ok(_) => return PResult::SyntaxError
-                                (ParseError::ExpectedBitVec(srt.clone()))
+                                (ParseError::ExpectedBitVec(srt.clone()),pos.clone())
                         }
                     }
                 } else { None };
@@ -653,22 +1337,95 @@ fn parse_function<'inp,P : Parser,F,Sub>(input: &'inp[u8],
                     None => match rec(&input[off..],pos,p,0,indir,None) {
                         PResult::Done(None,_)
                             => return PResult::SyntaxError
-                            (ParseError::WrongNumberOfArgs(0,arity,false)),
+                            (ParseError::WrongNumberOfArgs(0,arity,false),pos.clone()),
                         PResult::Done(Some((srt,sub,idx)),ninp)
                             => match p.unbed_sort(&srt) {
                                 Err(e) => return PResult::EmbedError(e),
                                 Ok(SortKind::BitVec(sz))
                                     => (sz,vec![sub],vec![idx],ninp),
                                 Ok(_) => return PResult::SyntaxError
-                                    (ParseError::ExpectedBitVec(srt))
+                                    (ParseError::ExpectedBitVec(srt),pos.clone())
                             },
                         PResult::Incomplete => return PResult::Incomplete,
                         PResult::EmbedError(e) => return PResult::EmbedError(e),
-                        PResult::SyntaxError(e) => return PResult::SyntaxError(e)
+                        PResult::SyntaxError(e,epos) => return PResult::SyntaxError(e,epos)
                     }
                 };
                 PResult::Done((Function::BV(nsz,op),args,indices,false),input1)
             },
+            b"fp.abs" | b"fp.neg" | b"fp.add" | b"fp.sub" | b"fp.mul" |
+            b"fp.div" | b"fp.fma" | b"fp.sqrt" | b"fp.rem" |
+            b"fp.roundToIntegral" | b"fp.min" | b"fp.max" |
+            b"fp.leq" | b"fp.lt" | b"fp.geq" | b"fp.gt" | b"fp.eq" |
+            b"fp.isNormal" | b"fp.isSubnormal" | b"fp.isZero" |
+            b"fp.isInfinite" | b"fp.isNaN" | b"fp.isNegative" |
+            b"fp.isPositive" | b"fp.to_real" => {
+                let op = match &input[3..off] {
+                    b"abs" => FPOp::Abs,
+                    b"neg" => FPOp::Neg,
+                    b"add" => FPOp::Arith(ArithOp::Add),
+                    b"sub" => FPOp::Arith(ArithOp::Sub),
+                    b"mul" => FPOp::Arith(ArithOp::Mult),
+                    b"div" => FPOp::Div,
+                    b"fma" => FPOp::FMA,
+                    b"sqrt" => FPOp::Sqrt,
+                    b"rem" => FPOp::Rem,
+                    b"roundToIntegral" => FPOp::RoundToIntegral,
+                    b"min" => FPOp::Min,
+                    b"max" => FPOp::Max,
+                    b"leq" => FPOp::Ord(OrdOp::Le),
+                    b"lt" => FPOp::Ord(OrdOp::Lt),
+                    b"geq" => FPOp::Ord(OrdOp::Ge),
+                    b"gt" => FPOp::Ord(OrdOp::Gt),
+                    b"eq" => FPOp::Eq,
+                    b"isNormal" => FPOp::IsNormal,
+                    b"isSubnormal" => FPOp::IsSubnormal,
+                    b"isZero" => FPOp::IsZero,
+                    b"isInfinite" => FPOp::IsInfinite,
+                    b"isNaN" => FPOp::IsNaN,
+                    b"isNegative" => FPOp::IsNegative,
+                    b"isPositive" => FPOp::IsPositive,
+                    b"to_real" => FPOp::ToReal,
+                    _ => unreachable!()
+                };
+                pos.col+=off;
+                parse_fp_app(op,&input[off..],pos,p,indir,rec)
+            },
+            b"as" => {
+                // A nullary constructor qualified with its sort
+                pos.col+=off;
+                let input1 = eat_ws(&input[off..],pos);
+                match parse_as_constructor(input1,pos,p) {
+                    PResult::Done((dt,pars,con),ninp)
+                        => PResult::Done((Function::Constructor(dt,pars,con),
+                                          vec![],vec![],false),ninp),
+                    PResult::Incomplete => PResult::Incomplete,
+                    PResult::EmbedError(e) => PResult::EmbedError(e),
+                    PResult::SyntaxError(e,epos) => PResult::SyntaxError(e,epos)
+                }
+            },
+            name if p.parse_dt_symbol(name).is_some() => {
+                pos.col+=off;
+                match p.parse_dt_symbol(name) {
+                    Some((dt,con,None)) => match constructor_params(dt,p,hint) {
+                        Err(e) => PResult::EmbedError(e),
+                        Ok(None) => PResult::SyntaxError(ParseError::ExpectedAs,pos.clone()),
+                        Ok(Some(pars))
+                            => PResult::Done((Function::Constructor(dt,pars,con),
+                                              vec![],vec![],false),&input[off..])
+                    },
+                    Some((dt,con,Some(field)))
+                        => match parse_dt_arg(dt,&input[off..],pos,p,indir,rec) {
+                            PResult::Done((pars,sub,idx),ninp)
+                                => PResult::Done((Function::Selector(dt,pars,con,field),
+                                                  vec![sub],vec![idx],false),ninp),
+                            PResult::Incomplete => PResult::Incomplete,
+                            PResult::EmbedError(e) => PResult::EmbedError(e),
+                            PResult::SyntaxError(e,epos) => PResult::SyntaxError(e,epos)
+                        },
+                    None => unreachable!()
+                }
+            },
             _ => match p.parse_fun(&input[0..off]) {
                 Ok(fun) => {
                     pos.col+=off;
@@ -692,7 +1449,7 @@ fn parse_function<'inp,P : Parser,F,Sub>(input: &'inp[u8],
                 return PResult::Incomplete
             }
             if !is_sym_char(input2[0]) {
-                return PResult::SyntaxError(ParseError::ExpectedId)
+                return PResult::SyntaxError(ParseError::ExpectedId,pos.clone())
             }
             let mut off=1;
             while off<input2.len() {
@@ -715,7 +1472,7 @@ fn parse_function<'inp,P : Parser,F,Sub>(input: &'inp[u8],
                                             Err(e) => return PResult::EmbedError(e),
                                             Ok(n) => n
                                         };
-                                        return PResult::SyntaxError(ParseError::WrongNumberOfArgs(0,arity,false))
+                                        return PResult::SyntaxError(ParseError::WrongNumberOfArgs(0,arity,false),pos.clone())
                                     },
                                     PResult::Done(Some((_,sub,ind)),input6) => {
                                         subs.push(sub);
@@ -724,7 +1481,7 @@ fn parse_function<'inp,P : Parser,F,Sub>(input: &'inp[u8],
                                     },
                                     PResult::Incomplete => return PResult::Incomplete,
                                     PResult::EmbedError(e) => return PResult::EmbedError(e),
-                                    PResult::SyntaxError(e) => return PResult::SyntaxError(e)
+                                    PResult::SyntaxError(e,epos) => return PResult::SyntaxError(e,epos)
                                 }
                             }
                             let idx = indices[0][0].clone();
@@ -732,32 +1489,115 @@ fn parse_function<'inp,P : Parser,F,Sub>(input: &'inp[u8],
                         },
                         PResult::Incomplete => PResult::Incomplete,
                         PResult::EmbedError(e) => PResult::EmbedError(e),
-                        PResult::SyntaxError(e) => PResult::SyntaxError(e)
+                        PResult::SyntaxError(e,epos) => PResult::SyntaxError(e,epos)
                     }
                 },
-                _ => PResult::SyntaxError(ParseError::ExpectedParFun)
-            }
-        } else if input1[0]==b'a' {
-            if input1.len() < 3 {
-                return PResult::Incomplete
-            }
-            if input1[1]!=b's' || (input1[2]!=b' ' && input1[2]!=b'\t' && input1[2]!=b'\n') {
-                return PResult::SyntaxError(ParseError::ExpectedAs)
-            }
-            pos.col+=2;
-            let input2 = eat_ws(&input1[2..],pos);
-            if input2.len() < 6 {
-                return PResult::Incomplete
+                b"to_fp" | b"to_fp_unsigned" | b"fp.to_ubv" | b"fp.to_sbv" => {
+                    let name = &input2[0..off];
+                    let two_idx = name[0]==b't';
+                    pos.col+=off;
+                    let input3 = eat_ws(&input2[off..],pos);
+                    let (i1,input4) = match parse_usize(input3,pos,p) {
+                        PResult::Done(n,ninp) => (n,ninp),
+                        PResult::Incomplete => return PResult::Incomplete,
+                        PResult::EmbedError(e) => return PResult::EmbedError(e),
+                        PResult::SyntaxError(e,epos) => return PResult::SyntaxError(e,epos)
+                    };
+                    let mut input5 = eat_ws(input4,pos);
+                    let i2 = if two_idx {
+                        match parse_usize(input5,pos,p) {
+                            PResult::Done(n,ninp) => {
+                                input5 = eat_ws(ninp,pos);
+                                n
+                            },
+                            PResult::Incomplete => return PResult::Incomplete,
+                            PResult::EmbedError(e) => return PResult::EmbedError(e),
+                            PResult::SyntaxError(e,epos) => return PResult::SyntaxError(e,epos)
+                        }
+                    } else { 0 };
+                    if input5.len()==0 {
+                        return PResult::Incomplete
+                    }
+                    if input5[0]!=b')' {
+                        return PResult::SyntaxError(ParseError::ExpectedClosePar,pos.clone())
+                    }
+                    pos.col+=1;
+                    parse_fp_conversion(name,(i1,i2),&input5[1..],pos,p,indir,rec)
+                },
+                b"is" => {
+                    pos.col+=off;
+                    let input3 = eat_ws(&input2[off..],pos);
+                    let mut off3 = 0;
+                    while off3<input3.len() && is_sym_char(input3[off3]) {
+                        off3+=1;
+                    }
+                    if off3==input3.len() {
+                        return PResult::Incomplete
+                    }
+                    let (dt,con) = match p.parse_dt_symbol(&input3[0..off3]) {
+                        Some((dt,con,None)) => (dt,con),
+                        _ => return PResult::SyntaxError(ParseError::ExpectedId,pos.clone())
+                    };
+                    pos.col+=off3;
+                    let input4 = eat_ws(&input3[off3..],pos);
+                    if input4.len()==0 {
+                        return PResult::Incomplete
+                    }
+                    if input4[0]!=b')' {
+                        return PResult::SyntaxError(ParseError::ExpectedClosePar,pos.clone())
+                    }
+                    pos.col+=1;
+                    match parse_dt_arg(dt,&input4[1..],pos,p,indir,rec) {
+                        PResult::Done((pars,sub,idx),ninp)
+                            => PResult::Done((Function::Tester(dt,pars,con),
+                                              vec![sub],vec![idx],false),ninp),
+                        PResult::Incomplete => PResult::Incomplete,
+                        PResult::EmbedError(e) => PResult::EmbedError(e),
+                        PResult::SyntaxError(e,epos) => PResult::SyntaxError(e,epos)
+                    }
+                },
+                _ => PResult::SyntaxError(ParseError::ExpectedParFun,pos.clone())
+            }
+        } else if input1[0]==b'a' {
+            if input1.len() < 3 {
+                return PResult::Incomplete
+            }
+            if input1[1]!=b's' || (input1[2]!=b' ' && input1[2]!=b'\t' && input1[2]!=b'\n') {
+                return PResult::SyntaxError(ParseError::ExpectedAs,pos.clone())
+            }
+            pos.col+=2;
+            let input2 = eat_ws(&input1[2..],pos);
+            if input2.len() < 6 {
+                return PResult::Incomplete
+            }
+            if input2[0]==b'd' {
+                return match parse_as_constructor(input2,pos,p) {
+                    PResult::Done((dt,pars,con),input3) => {
+                        let input4 = eat_ws(input3,pos);
+                        if input4.len()==0 {
+                            return PResult::Incomplete
+                        }
+                        if input4[0]!=b')' {
+                            return PResult::SyntaxError(ParseError::ExpectedClosePar,pos.clone())
+                        }
+                        pos.col+=1;
+                        PResult::Done((Function::Constructor(dt,pars,con),vec![],vec![],false),
+                                      &input4[1..])
+                    },
+                    PResult::Incomplete => PResult::Incomplete,
+                    PResult::EmbedError(e) => PResult::EmbedError(e),
+                    PResult::SyntaxError(e,epos) => PResult::SyntaxError(e,epos)
+                }
             }
             if input2[0..5]!=b"const"[..] || (input2[5]!=b' ' && input2[5]!=b'\t' && input2[5]!=b'\n') {
-                return PResult::SyntaxError(ParseError::ExpectedConst)
+                return PResult::SyntaxError(ParseError::ExpectedConst,pos.clone())
             }
             pos.col+=5;
             let input3 = eat_ws(&input2[5..],pos);
             match parse_sort(input3,pos,p) {
                 PResult::Incomplete => PResult::Incomplete,
                 PResult::EmbedError(e) => PResult::EmbedError(e),
-                PResult::SyntaxError(e) => PResult::SyntaxError(e),
+                PResult::SyntaxError(e,epos) => PResult::SyntaxError(e,epos),
                 PResult::Done(srt,input4) => {
                     match p.unbed_sort(&srt) {
                         Err(e) => PResult::EmbedError(e),
@@ -767,11 +1607,11 @@ fn parse_function<'inp,P : Parser,F,Sub>(input: &'inp[u8],
                                 return PResult::Incomplete
                             }
                             if input5[0]!=b')' {
-                                return PResult::SyntaxError(ParseError::ExpectedClosePar)
+                                return PResult::SyntaxError(ParseError::ExpectedClosePar,pos.clone())
                             }
                             PResult::Done((Function::ConstArray(idx,el),vec![],vec![],false),&input5[1..])
                         },
-                        Ok(_) => PResult::SyntaxError(ParseError::ExpectedArraySort(srt))
+                        Ok(_) => PResult::SyntaxError(ParseError::ExpectedArraySort(srt),pos.clone())
                     }
                 }
             }
@@ -784,33 +1624,76 @@ fn parse_function<'inp,P : Parser,F,Sub>(input: &'inp[u8],
 }
 
 fn parse_sort<'inp,P : Parser>(input: &'inp[u8],pos: &mut Pos,p: &mut P) -> PResult<'inp,P::Sort,P> {
+    parse_sort_scoped(input,pos,p,&[])
+}
+
+/// Parse a sort in which the given names refer to sort parameters (as in
+/// the body of a `define-sort` or a parametric datatype).
+fn parse_sort_scoped<'inp,P : Parser>(input: &'inp[u8],pos: &mut Pos,p: &mut P,params: &[String])
+                                      -> PResult<'inp,P::Sort,P> {
     if input.len()<3 {
         return PResult::Incomplete
     }
-    if &input[0..3]==b"Int" {
+    if &input[0..3]==b"Int" && (input.len()==3 || !is_sym_char(input[3])) {
         pos.col+=3;
         match p.embed_sort(SortKind::Int) {
             Err(e) => return PResult::EmbedError(e),
             Ok(tp) => return PResult::Done(tp,&input[3..])
         }
     }
-    if input.len()<4 {
+    if input.len()<4 && (input[0]==b'B' || input[0]==b'R') {
         return PResult::Incomplete
     }
-    if &input[0..4]==b"Bool" {
+    if input.len()>=4 && &input[0..4]==b"Bool" && (input.len()==4 || !is_sym_char(input[4])) {
         pos.col+=4;
         match p.embed_sort(SortKind::Bool) {
             Err(e) => return PResult::EmbedError(e),
             Ok(tp) => return PResult::Done(tp,&input[4..])
         }
     }
-    if &input[0..4]==b"Real" {
+    if input.len()>=4 && &input[0..4]==b"Real" && (input.len()==4 || !is_sym_char(input[4])) {
         pos.col+=4;
         match p.embed_sort(SortKind::Real) {
             Err(e) => return PResult::EmbedError(e),
             Ok(tp) => return PResult::Done(tp,&input[4..])
         }
     }
+    if is_sym_char(input[0]) {
+        let mut off = 1;
+        while off<input.len() && is_sym_char(input[off]) {
+            off+=1;
+        }
+        if off==input.len() {
+            return PResult::Incomplete
+        }
+        let kind = match &input[0..off] {
+            b"RoundingMode" => SortKind::RoundingMode,
+            b"Float16" => SortKind::FloatingPoint(5,11),
+            b"Float32" => SortKind::FloatingPoint(8,24),
+            b"Float64" => SortKind::FloatingPoint(11,53),
+            b"Float128" => SortKind::FloatingPoint(15,113),
+            name => if let Some(n) = params.iter().position(|par| par.as_bytes()==name) {
+                SortKind::Param(n)
+            } else if let Some((npars,body)) = p.parse_sort_alias(name) {
+                if npars!=0 {
+                    return PResult::SyntaxError(ParseError::WrongNumberOfArgs(0,npars,false),
+                                                pos.clone())
+                }
+                pos.col+=off;
+                return PResult::Done(body,&input[off..])
+            } else {
+                match p.parse_sort_name(name) {
+                    Some(kind) => kind,
+                    None => return PResult::SyntaxError(ParseError::ExpectedSort,pos.clone())
+                }
+            }
+        };
+        pos.col+=off;
+        match p.embed_sort(kind) {
+            Err(e) => return PResult::EmbedError(e),
+            Ok(tp) => return PResult::Done(tp,&input[off..])
+        }
+    }
     if input[0]==b'(' {
         pos.col+=1;
         let input1 = eat_ws(&input[1..],pos);
@@ -822,8 +1705,43 @@ fn parse_sort<'inp,P : Parser>(input: &'inp[u8],pos: &mut Pos,p: &mut P) -> PRes
             if input2.len() < 6 {
                 return PResult::Incomplete
             }
+            if input2[0]==b'F' {
+                if input2.len() < 13 {
+                    return PResult::Incomplete
+                }
+                if &input2[0..13] != b"FloatingPoint" {
+                    return PResult::SyntaxError(ParseError::ExpectedSort,pos.clone())
+                }
+                pos.col+=13;
+                let input3 = eat_ws(&input2[13..],pos);
+                let (eb,input4) = match parse_usize(input3,pos,p) {
+                    PResult::Done(n,ninp) => (n,ninp),
+                    PResult::Incomplete => return PResult::Incomplete,
+                    PResult::EmbedError(e) => return PResult::EmbedError(e),
+                    PResult::SyntaxError(e,epos) => return PResult::SyntaxError(e,epos)
+                };
+                let input5 = eat_ws(input4,pos);
+                let (sb,input6) = match parse_usize(input5,pos,p) {
+                    PResult::Done(n,ninp) => (n,ninp),
+                    PResult::Incomplete => return PResult::Incomplete,
+                    PResult::EmbedError(e) => return PResult::EmbedError(e),
+                    PResult::SyntaxError(e,epos) => return PResult::SyntaxError(e,epos)
+                };
+                let input7 = eat_ws(input6,pos);
+                if input7.len()==0 {
+                    return PResult::Incomplete
+                }
+                if input7[0]!=b')' {
+                    return PResult::SyntaxError(ParseError::ExpectedClosePar,pos.clone())
+                }
+                pos.col+=1;
+                match p.embed_sort(SortKind::FloatingPoint(eb,sb)) {
+                    Err(e) => return PResult::EmbedError(e),
+                    Ok(tp) => return PResult::Done(tp,&input7[1..])
+                }
+            }
             if &input2[0..6] != b"BitVec" {
-                return PResult::SyntaxError(ParseError::ExpectedLitBitVec)
+                return PResult::SyntaxError(ParseError::ExpectedLitBitVec,pos.clone())
             }
             pos.col+=6;
             let input3 = eat_ws(&input2[6..],pos);
@@ -850,7 +1768,7 @@ fn parse_sort<'inp,P : Parser>(input: &'inp[u8],pos: &mut Pos,p: &mut P) -> PRes
                 return PResult::Incomplete
             }
             if input4[0]!=b')' {
-                return PResult::SyntaxError(ParseError::ExpectedClosePar)
+                return PResult::SyntaxError(ParseError::ExpectedClosePar,pos.clone())
             }
             pos.col+=1;
             match p.embed_sort(SortKind::BitVec(bw)) {
@@ -858,16 +1776,20 @@ fn parse_sort<'inp,P : Parser>(input: &'inp[u8],pos: &mut Pos,p: &mut P) -> PRes
                 Ok(tp) => return PResult::Done(tp,&input4[1..])
             }
         }
-        if input1.len() < 5 {
+        let mut off = 0;
+        while off<input1.len() && is_sym_char(input1[off]) {
+            off+=1;
+        }
+        if off==input1.len() {
             return PResult::Incomplete
         }
-        if input1[0..5]==b"Array"[..] {
+        if &input1[0..off]==b"Array" {
             pos.col+=5;
             let input2 = eat_ws(&input1[5..],pos);
-            match parse_sort(input2,pos,p) {
+            match parse_sort_scoped(input2,pos,p,params) {
                 PResult::Incomplete => return PResult::Incomplete,
                 PResult::EmbedError(e) => return PResult::EmbedError(e),
-                PResult::SyntaxError(e) => return PResult::SyntaxError(e),
+                PResult::SyntaxError(e,epos) => return PResult::SyntaxError(e,epos),
                 PResult::Done(tp,input3) => {
                     let mut indices = Vec::new();
                     let mut last = tp;
@@ -881,7 +1803,7 @@ fn parse_sort<'inp,P : Parser>(input: &'inp[u8],pos: &mut Pos,p: &mut P) -> PRes
                             input4 = &input4[1..];
                             break;
                         }
-                        match parse_sort(input4,pos,p) {
+                        match parse_sort_scoped(input4,pos,p,params) {
                             PResult::Done(tp,ninp) => {
                                 indices.push(last);
                                 last = tp;
@@ -889,7 +1811,7 @@ fn parse_sort<'inp,P : Parser>(input: &'inp[u8],pos: &mut Pos,p: &mut P) -> PRes
                             },
                             PResult::Incomplete => return PResult::Incomplete,
                             PResult::EmbedError(e) => return PResult::EmbedError(e),
-                            PResult::SyntaxError(e) => return PResult::SyntaxError(e)
+                            PResult::SyntaxError(e,epos) => return PResult::SyntaxError(e,epos)
                         }
                     }
                     match p.embed_sort(SortKind::Array(indices,last)) {
@@ -899,18 +1821,369 @@ fn parse_sort<'inp,P : Parser>(input: &'inp[u8],pos: &mut Pos,p: &mut P) -> PRes
                 }
             }
         }
+        let alias = p.parse_sort_alias(&input1[0..off]);
+        let kind = match alias {
+            Some(_) => None,
+            None => match p.parse_sort_name(&input1[0..off]) {
+                Some(kind) => Some(kind),
+                None => return PResult::SyntaxError(ParseError::ExpectedSort,pos.clone())
+            }
+        };
+        pos.col+=off;
+        let mut pars = Vec::new();
+        let mut input2 = eat_ws(&input1[off..],pos);
+        loop {
+            if input2.len()==0 {
+                return PResult::Incomplete
+            }
+            if input2[0]==b')' {
+                pos.col+=1;
+                input2 = &input2[1..];
+                break
+            }
+            match parse_sort_scoped(input2,pos,p,params) {
+                PResult::Done(tp,ninp) => {
+                    pars.push(tp);
+                    input2 = eat_ws(ninp,pos);
+                },
+                PResult::Incomplete => return PResult::Incomplete,
+                PResult::EmbedError(e) => return PResult::EmbedError(e),
+                PResult::SyntaxError(e,epos) => return PResult::SyntaxError(e,epos)
+            }
+        }
+        if let Some((npars,body)) = alias {
+            if npars!=pars.len() {
+                return PResult::SyntaxError(ParseError::WrongNumberOfArgs(pars.len(),npars,false),
+                                            pos.clone())
+            }
+            return match p.instantiate_sort(&body,&pars) {
+                Err(e) => PResult::EmbedError(e),
+                Ok(tp) => PResult::Done(tp,input2)
+            }
+        }
+        let nkind = match kind {
+            Some(SortKind::Datatype(id,_)) => SortKind::Datatype(id,pars),
+            Some(SortKind::Uninterpreted(id,_)) => SortKind::Uninterpreted(id,pars),
+            _ => return PResult::SyntaxError(ParseError::ExpectedSort,pos.clone())
+        };
+        match p.embed_sort(nkind) {
+            Err(e) => return PResult::EmbedError(e),
+            Ok(tp) => return PResult::Done(tp,input2)
+        }
+    }
+    return PResult::SyntaxError(ParseError::ExpectedSort,pos.clone())
+}
+
+/// Parse a value, using the sort hint to disambiguate numerals.
+pub fn parse_value<'inp,P : Parser>(input: &'inp[u8],pos: &mut Pos,p: &mut P, hint: Option<&P::Sort>)
+                                    -> PResult<'inp,Value,P> {
+    if input.len()>0 && input[0]==b'(' {
+        let mut tmp_pos = pos.clone();
+        let input1 = eat_ws(&input[1..],&mut tmp_pos);
+        let form = if input1.len()>0 && input1[0]==b'(' {
+            let input2 = eat_ws(&input1[1..],&mut tmp_pos);
+            match starts_with_keyword(input2,b"as") {
+                None => return PResult::Incomplete,
+                Some(false) => None,
+                Some(true) => {
+                    let input3 = eat_ws(&input2[2..],&mut tmp_pos);
+                    match starts_with_keyword(input3,b"const") {
+                        None => return PResult::Incomplete,
+                        Some(is_const) => if is_const { Some(ArrayForm::Const) } else { None }
+                    }
+                }
+            }
+        } else {
+            match (starts_with_keyword(input1,b"store"),starts_with_keyword(input1,b"lambda")) {
+                (Some(true),_) => Some(ArrayForm::Store),
+                (_,Some(true)) => Some(ArrayForm::Lambda),
+                (None,_) | (_,None) => return PResult::Incomplete,
+                _ => None
+            }
+        };
+        if let Some(form) = form {
+            return parse_array_value(form,input,pos,p,hint)
+        }
+    }
+    parse_scalar_value(input,pos,p,hint)
+}
+
+/// Check whether the input starts with the given keyword. Returns `None` if
+/// the input is too short to decide.
+fn starts_with_keyword(input: &[u8],kw: &[u8]) -> Option<bool> {
+    if input.len()<=kw.len() {
+        if kw.starts_with(input) {
+            None
+        } else {
+            Some(false)
+        }
+    } else {
+        Some(input.starts_with(kw) && !is_sym_char(input[kw.len()]))
+    }
+}
+
+/// The ways in which solvers print array values.
+#[derive(Clone,Copy,PartialEq,Eq,Debug)]
+enum ArrayForm {
+    /// `((as const (Array I E)) v)`
+    Const,
+    /// `(store a i v)`
+    Store,
+    /// `(lambda ((x I)) body)`
+    Lambda
+}
+
+/// Parse an array value of the given form, starting at the opening
+/// parenthesis.
+fn parse_array_value<'inp,P : Parser>(form: ArrayForm,input: &'inp[u8],pos: &mut Pos,p: &mut P,
+                                      hint: Option<&P::Sort>) -> PResult<'inp,Value,P> {
+    pos.col+=1;
+    let input1 = eat_ws(&input[1..],pos);
+    let (res,input2) = match form {
+        ArrayForm::Const => {
+            pos.col+=1;
+            let input2 = eat_ws(&input1[1..],pos);
+            pos.col+=2;
+            let input3 = eat_ws(&input2[2..],pos);
+            pos.col+=5;
+            let input4 = eat_ws(&input3[5..],pos);
+            let (srt,input5) = match parse_sort(input4,pos,p) {
+                PResult::Done(srt,ninp) => (srt,ninp),
+                PResult::Incomplete => return PResult::Incomplete,
+                PResult::EmbedError(e) => return PResult::EmbedError(e),
+                PResult::SyntaxError(e,epos) => return PResult::SyntaxError(e,epos)
+            };
+            let (idx,el) = match p.unbed_sort(&srt) {
+                Err(e) => return PResult::EmbedError(e),
+                Ok(SortKind::Array(idx,el)) => (idx,el),
+                Ok(_) => return PResult::SyntaxError(ParseError::ExpectedArraySort(srt),pos.clone())
+            };
+            let input6 = eat_ws(input5,pos);
+            if input6.len()==0 {
+                return PResult::Incomplete
+            }
+            if input6[0]!=b')' {
+                return PResult::SyntaxError(ParseError::ExpectedClosePar,pos.clone())
+            }
+            pos.col+=1;
+            let input7 = eat_ws(&input6[1..],pos);
+            let (def,input8) = match parse_value(input7,pos,p,Some(&el)) {
+                PResult::Done(v,ninp) => (v,ninp),
+                err => return err
+            };
+            let mut index = Vec::with_capacity(idx.len());
+            for i in idx.iter() {
+                match Sort::from_embed(i,p) {
+                    Err(e) => return PResult::EmbedError(e),
+                    Ok(ri) => index.push(ri)
+                }
+            }
+            match Sort::from_embed(&el,p) {
+                Err(e) => return PResult::EmbedError(e),
+                Ok(rel) => (Value::array(index,rel,def,Vec::new()),input8)
+            }
+        },
+        ArrayForm::Store => {
+            pos.col+=5;
+            let input2 = eat_ws(&input1[5..],pos);
+            let (mut arr,mut input3) = match parse_value(input2,pos,p,hint) {
+                PResult::Done(v,ninp) => (v,ninp),
+                err => return err
+            };
+            let (index,element) = match arr {
+                Value::Array { ref index, ref element, .. } => (index.clone(),element.clone()),
+                _ => return PResult::SyntaxError(ParseError::ExpectedValue,pos.clone())
+            };
+            let mut keys = Vec::with_capacity(index.len());
+            for srt in index.iter().chain(Some(&element)) {
+                let rsrt = match srt.embed(p) {
+                    Err(e) => return PResult::EmbedError(e),
+                    Ok(rsrt) => rsrt
+                };
+                input3 = eat_ws(input3,pos);
+                match parse_value(input3,pos,p,Some(&rsrt)) {
+                    PResult::Done(v,ninp) => {
+                        keys.push(v);
+                        input3 = ninp;
+                    },
+                    err => return err
+                }
+            }
+            let val = keys.pop().unwrap();
+            arr.store(&keys,val);
+            (arr,input3)
+        },
+        ArrayForm::Lambda => {
+            pos.col+=6;
+            let input2 = eat_ws(&input1[6..],pos);
+            let mut scope = Scope::new();
+            let (params,input3) = match parse_params(input2,pos,p,&mut scope) {
+                PResult::Done(params,ninp) => (params,ninp),
+                PResult::Incomplete => return PResult::Incomplete,
+                PResult::EmbedError(e) => return PResult::EmbedError(e),
+                PResult::SyntaxError(e,epos) => return PResult::SyntaxError(e,epos)
+            };
+            let el = match hint {
+                None => None,
+                Some(srt) => match p.unbed_sort(srt) {
+                    Err(e) => return PResult::EmbedError(e),
+                    Ok(SortKind::Array(_,el)) => Some(el),
+                    Ok(_) => return PResult::SyntaxError(ParseError::ExpectedArraySort((*srt).clone()),
+                                                         pos.clone())
+                }
+            };
+            let input4 = eat_ws(input3,pos);
+            let (body,input5) = match parse_expr_scoped(input4,pos,p,&scope,el.as_ref()) {
+                PResult::Done(e,ninp) => (e,ninp),
+                PResult::Incomplete => return PResult::Incomplete,
+                PResult::EmbedError(e) => return PResult::EmbedError(e),
+                PResult::SyntaxError(e,epos) => return PResult::SyntaxError(e,epos)
+            };
+            match array_from_fun(p,&params,&body) {
+                Err(e) => return PResult::EmbedError(e),
+                Ok(Some(v)) => (v,input5),
+                Ok(None) => return PResult::SyntaxError(ParseError::ExpectedValue,pos.clone())
+            }
+        }
+    };
+    let input3 = eat_ws(input2,pos);
+    if input3.len()==0 {
+        return PResult::Incomplete
+    }
+    if input3[0]!=b')' {
+        return PResult::SyntaxError(ParseError::ExpectedClosePar,pos.clone())
+    }
+    pos.col+=1;
+    PResult::Done(res,&input3[1..])
+}
+
+/// Convert the interpretation of a function (or the body of a `lambda`) into
+/// an array value. Only bodies consisting of `ite` chains whose conditions
+/// compare the parameters with values are supported, otherwise `None` is
+/// returned.
+fn array_from_fun<P : Parser>(p: &mut P,params: &[NVar<P::Sort>],body: &P::Expr)
+                              -> Result<Option<Value>,P::Error> {
+    let mut index = Vec::with_capacity(params.len());
+    for par in params.iter() {
+        index.push(Sort::from_embed(&par.sort,p)?);
+    }
+    let el = p.type_of(body)?;
+    let element = Sort::from_embed(&el,p)?;
+    let mut entries = Vec::new();
+    let mut cur = body.clone();
+    loop {
+        match p.unbed(&cur)? {
+            Expr::Const(def) => {
+                // The first matching condition determines the value
+                entries.reverse();
+                return Ok(Some(Value::array(index,element,def,entries)))
+            },
+            Expr::App(Function::ITE(_),args) => {
+                let key = match condition_indices(p,params,&args[0])? {
+                    None => return Ok(None),
+                    Some(key) => key
+                };
+                match p.unbed(&args[1])? {
+                    Expr::Const(val) => entries.push((key,val)),
+                    _ => return Ok(None)
+                }
+                cur = args[2].clone();
+            },
+            _ => return Ok(None)
+        }
+    }
+}
+
+/// Extract the indices from a condition like `(and (= x 1) (= y 2))`, which
+/// has to compare every parameter with a value.
+fn condition_indices<P : Parser>(p: &mut P,params: &[NVar<P::Sort>],cond: &P::Expr)
+                                 -> Result<Option<Vec<Value>>,P::Error> {
+    let conds = match p.unbed(cond)? {
+        Expr::App(Function::And(_),args) => args,
+        _ => vec![cond.clone()]
+    };
+    let mut key = vec![None; params.len()];
+    for c in conds.iter() {
+        let args = match p.unbed(c)? {
+            Expr::App(Function::Eq(_,_),ref args) if args.len()==2 => args.clone(),
+            _ => return Ok(None)
+        };
+        let (var,val) = match (p.unbed(&args[0])?,p.unbed(&args[1])?) {
+            (Expr::QVar(var),Expr::Const(val)) |
+            (Expr::Const(val),Expr::QVar(var)) => (var,val),
+            _ => return Ok(None)
+        };
+        match params.iter().position(|par| *par==var) {
+            Some(i) => key[i] = Some(val),
+            None => return Ok(None)
+        }
+    }
+    Ok(key.into_iter().collect())
+}
+
+/// Parse a list of sorted variables like `((x Int) (y Bool))` and bind them
+/// in the scope.
+fn parse_params<'inp,P : Parser>(input: &'inp[u8],pos: &mut Pos,p: &mut P,scope: &mut Scope<P::Sort>)
+                                 -> PResult<'inp,Vec<NVar<P::Sort>>,P> {
+    if input.len()==0 {
+        return PResult::Incomplete
+    }
+    if input[0]!=b'(' {
+        return PResult::SyntaxError(ParseError::ExpectedOpenPar,pos.clone())
+    }
+    pos.col+=1;
+    let mut params = Vec::new();
+    let mut input1 = &input[1..];
+    loop {
+        input1 = eat_ws(input1,pos);
+        if input1.len()==0 {
+            return PResult::Incomplete
+        }
+        if input1[0]==b')' {
+            pos.col+=1;
+            return PResult::Done(params,&input1[1..])
+        }
+        if input1[0]!=b'(' {
+            return PResult::SyntaxError(ParseError::ExpectedOpenPar,pos.clone())
+        }
+        pos.col+=1;
+        let input2 = eat_ws(&input1[1..],pos);
+        let (pname,poff) = match symbol_extent(input2) {
+            None => return PResult::Incomplete,
+            Some(r) => r
+        };
+        if poff==input2.len() {
+            return PResult::Incomplete
+        }
+        advance(&input2[0..poff],pos);
+        let input3 = eat_ws(&input2[poff..],pos);
+        let (srt,input4) = match parse_sort(input3,pos,p) {
+            PResult::Done(srt,ninp) => (srt,ninp),
+            PResult::Incomplete => return PResult::Incomplete,
+            PResult::EmbedError(e) => return PResult::EmbedError(e),
+            PResult::SyntaxError(e,epos) => return PResult::SyntaxError(e,epos)
+        };
+        params.push(scope.bind(pname,false,srt));
+        let input5 = eat_ws(input4,pos);
+        if input5.len()==0 {
+            return PResult::Incomplete
+        }
+        if input5[0]!=b')' {
+            return PResult::SyntaxError(ParseError::ExpectedClosePar,pos.clone())
+        }
+        pos.col+=1;
+        input1 = &input5[1..];
     }
-    return PResult::SyntaxError(ParseError::ExpectedSort)
 }
 
-fn parse_value<'inp,P : Parser>(input: &'inp[u8],pos: &mut Pos,p: &mut P, hint: Option<&P::Sort>)
-                                -> PResult<'inp,Value,P> {
+/// Parse a value which isn't an array.
+fn parse_scalar_value<'inp,P : Parser>(input: &'inp[u8],pos: &mut Pos,p: &mut P, hint: Option<&P::Sort>)
+                                       -> PResult<'inp,Value,P> {
     if input.len()==0 {
         return PResult::Incomplete
     }
     if (input[0] as char).is_digit(10) {
         // Num constant parsing
-        pos.col+=1;
         let mut off = 1;
         loop {
             if !(input[off] as char).is_digit(10) {
@@ -919,6 +2192,25 @@ fn parse_value<'inp,P : Parser>(input: &'inp[u8],pos: &mut Pos,p: &mut P, hint:
             off+=1;
             if off >= input.len() { break }
         }
+        if off+1<input.len() && input[off]==b'.' && (input[off+1] as char).is_digit(10) {
+            // Decimal constant
+            let mut end = off+1;
+            while end<input.len() && (input[end] as char).is_digit(10) {
+                end+=1;
+            }
+            let mut digits = input[0..off].to_vec();
+            digits.extend_from_slice(&input[off+1..end]);
+            let numer = match BigInt::parse_bytes(&digits,10) {
+                None => panic!("Internal error: Cannot parse {:?} to BigInt",digits),
+                Some(v) => v
+            };
+            let mut denom = BigInt::from(1);
+            for _ in off+1..end {
+                denom = denom*BigInt::from(10);
+            }
+            pos.col+=end;
+            return PResult::Done(Value::Real(Ratio::new(numer,denom)),&input[end..])
+        }
         match BigInt::parse_bytes(&input[0..off],10) {
             None => panic!("Internal error: Cannot parse {:?} to BigInt",&input[0..off]),
             Some(v) => match hint {
@@ -939,10 +2231,10 @@ fn parse_value<'inp,P : Parser>(input: &'inp[u8],pos: &mut Pos,p: &mut P, hint:
                         },
                         None => {
                             pos.col+=off;
-                            PResult::SyntaxError(ParseError::NumberNotOfSort(v,(*srt).clone()))
+                            PResult::SyntaxError(ParseError::NumberNotOfSort(v,(*srt).clone()),pos.clone())
                         }
                     },
-                    Ok(_) => PResult::SyntaxError(ParseError::NumberNotOfSort(v,(*srt).clone()))
+                    Ok(_) => PResult::SyntaxError(ParseError::NumberNotOfSort(v,(*srt).clone()),pos.clone())
                 },
                 None => {
                     pos.col+=off;
@@ -951,11 +2243,10 @@ fn parse_value<'inp,P : Parser>(input: &'inp[u8],pos: &mut Pos,p: &mut P, hint:
             }
         }
     } else if input[0]==b'#' {
-        pos.col+=1;
         if input.len() < 2 { return PResult::Incomplete }
         match input[1] {
             b'x' => {
-                pos.col+=1;
+                pos.col+=2;
                 let mut off = 2;
                 while off < input.len() {
                     if !(input[off] as char).is_digit(16) { break; }
@@ -970,7 +2261,7 @@ fn parse_value<'inp,P : Parser>(input: &'inp[u8],pos: &mut Pos,p: &mut P, hint:
                 }
             },
             b'b' => {
-                pos.col+=1;
+                pos.col+=2;
                 let mut off = 2;
                 while off < input.len() {
                     if input[off]!=b'0' &&
@@ -985,7 +2276,7 @@ fn parse_value<'inp,P : Parser>(input: &'inp[u8],pos: &mut Pos,p: &mut P, hint:
                     }
                 }
             },
-            c => PResult::SyntaxError(ParseError::InvalidNumberFormat(c))
+            c => PResult::SyntaxError(ParseError::InvalidNumberFormat(c),pos.clone())
         }
     } else if (input.len()==4 ||
                (input.len()>4 && !is_sym_char(input[4]))) && &input[0..4]==&b"true"[..] {
@@ -993,72 +2284,447 @@ fn parse_value<'inp,P : Parser>(input: &'inp[u8],pos: &mut Pos,p: &mut P, hint:
         PResult::Done(Value::Bool(true),&input[4..])
     } else if (input.len()==5 ||
                (input.len()>5 && !is_sym_char(input[5]))) && &input[0..5]==&b"false"[..] {
-        pos.col+=4;
+        pos.col+=5;
         PResult::Done(Value::Bool(false),&input[5..])
+    } else if input[0]==b'R' || input[0]==b'r' {
+        let mut off = 1;
+        while off<input.len() && is_sym_char(input[off]) {
+            off+=1;
+        }
+        let rm = match &input[0..off] {
+            b"RNE" | b"roundNearestTiesToEven" => RoundingMode::RNE,
+            b"RNA" | b"roundNearestTiesToAway" => RoundingMode::RNA,
+            b"RTP" | b"roundTowardPositive" => RoundingMode::RTP,
+            b"RTN" | b"roundTowardNegative" => RoundingMode::RTN,
+            b"RTZ" | b"roundTowardZero" => RoundingMode::RTZ,
+            _ => return PResult::SyntaxError(ParseError::ExpectedValue,pos.clone())
+        };
+        pos.col+=off;
+        PResult::Done(Value::RoundingMode(rm),&input[off..])
+    } else if input[0]==b'd' {
+        let mut off = 1;
+        while off<input.len() && is_sym_char(input[off]) {
+            off+=1;
+        }
+        let (dt,con) = match p.parse_dt_symbol(&input[0..off]) {
+            Some((dt,con,None)) => (dt,con),
+            _ => return PResult::SyntaxError(ParseError::ExpectedValue,pos.clone())
+        };
+        let pars = match constructor_params(dt,p,hint) {
+            Err(e) => return PResult::EmbedError(e),
+            Ok(None) => return PResult::SyntaxError(ParseError::ExpectedAs,pos.clone()),
+            Ok(Some(pars)) => pars
+        };
+        pos.col+=off;
+        parse_dt_value(dt,pars,con,false,&input[off..],pos,p)
     } else if input[0]==b'(' {
         pos.col+=1;
         let input1 = eat_ws(&input[1..],pos);
         if input1.len()<1 {
             return PResult::Incomplete
         }
-        if input1[0]!=b'_' {
-            return PResult::SyntaxError(ParseError::ExpectedUnderscore)
-        }
-        pos.col+=1;
-        let input2 = eat_ws(&input1[1..],pos);
-        if input2.len()<3 {
-            return PResult::Incomplete
-        }
-        if input2[0]!=b'b' || input2[1]!=b'v' {
-            return PResult::SyntaxError(ParseError::ExpectedBV)
-        }
-        pos.col+=2;
-        let mut off = 2;
-        while off < input2.len() {
-            if !(input2[off] as char).is_digit(10) { break }
-            off+=1;
-        }
-        if off==2 {
-            return PResult::Incomplete
-        }
-        match BigUint::parse_bytes(&input2[2..off],10) {
-            None => panic!("Internal error: Cannot parse {:?} to BigUint",&input2[2..off]),
-            Some(v) => {
-                pos.col+=off;
-                let input3 = eat_ws(&input2[off..],pos);
-                off=0;
-                while off<input3.len() {
-                    if !(input3[off] as char).is_digit(10) { break }
+        if input1[0]==b'd' || input1[0]==b'a' || input1[0]==b'(' {
+            // Datatype value
+            let (dt,pars,con,input2) = if input1[0]==b'd' {
+                let mut off = 1;
+                while off<input1.len() && is_sym_char(input1[off]) {
                     off+=1;
                 }
-                if off==0 {
+                let (dt,con) = match p.parse_dt_symbol(&input1[0..off]) {
+                    Some((dt,con,None)) => (dt,con),
+                    _ => return PResult::SyntaxError(ParseError::ExpectedValue,pos.clone())
+                };
+                let pars = match constructor_params(dt,p,hint) {
+                    Err(e) => return PResult::EmbedError(e),
+                    Ok(None) => return PResult::SyntaxError(ParseError::ExpectedAs,pos.clone()),
+                    Ok(Some(pars)) => pars
+                };
+                pos.col+=off;
+                (dt,pars,con,&input1[off..])
+            } else {
+                let applied = input1[0]==b'(';
+                let input2 = if applied {
+                    pos.col+=1;
+                    eat_ws(&input1[1..],pos)
+                } else {
+                    input1
+                };
+                if input2.len()<3 {
                     return PResult::Incomplete
                 }
-                match str::from_utf8(&input3[0..off]) {
-                    Err(_) => panic!("Internal error: Cannot parse {:?} into &str",&input3[0..off]),
-                    Ok(sz) => match FromStr::from_str(sz) {
-                        Err(_) => panic!("Internal error: Cannot parse {:?} into usize",sz),
-                        Ok(rsz) => {
-                            pos.col+=off;
-                            let input4 = eat_ws(&input3[off..],pos);
-                            if input4.len()==0 {
-                                return PResult::Incomplete
-                            }
+                if input2[0]!=b'a' || input2[1]!=b's' || is_sym_char(input2[2]) {
+                    return PResult::SyntaxError(ParseError::ExpectedAs,pos.clone())
+                }
+                pos.col+=2;
+                let input3 = eat_ws(&input2[2..],pos);
+                if !applied && input3.len()>0 && input3[0]!=b'd' {
+                    return parse_abstract_value(input3,pos,p)
+                }
+                let (dt,pars,con,input4) = match parse_as_constructor(input3,pos,p) {
+                    PResult::Done((dt,pars,con),ninp) => (dt,pars,con,ninp),
+                    PResult::Incomplete => return PResult::Incomplete,
+                    PResult::EmbedError(e) => return PResult::EmbedError(e),
+                    PResult::SyntaxError(e,epos) => return PResult::SyntaxError(e,epos)
+                };
+                if applied {
+                    let input5 = eat_ws(input4,pos);
+                    if input5.len()==0 {
+                        return PResult::Incomplete
+                    }
+                    if input5[0]!=b')' {
+                        return PResult::SyntaxError(ParseError::ExpectedClosePar,pos.clone())
+                    }
+                    pos.col+=1;
+                    (dt,pars,con,&input5[1..])
+                } else {
+                    (dt,pars,con,input4)
+                }
+            };
+            return parse_dt_value(dt,pars,con,true,input2,pos,p)
+        }
+        if input1.len()<2 {
+            return PResult::Incomplete
+        }
+        if input1[0]==b'-' && !is_sym_char(input1[1]) {
+            // Negative number
+            pos.col+=1;
+            let input2 = eat_ws(&input1[1..],pos);
+            let (v,input3) = match parse_value(input2,pos,p,hint) {
+                PResult::Done(Value::Int(v),ninp) => (Value::Int(-v),ninp),
+                PResult::Done(Value::Real(v),ninp) => (Value::Real(-v),ninp),
+                PResult::Done(_,_) => return PResult::SyntaxError(ParseError::ExpectedValue,pos.clone()),
+                err => return err
+            };
+            let input4 = eat_ws(input3,pos);
+            if input4.len()==0 {
+                return PResult::Incomplete
+            }
+            if input4[0]!=b')' {
+                return PResult::SyntaxError(ParseError::ExpectedClosePar,pos.clone())
+            }
+            pos.col+=1;
+            return PResult::Done(v,&input4[1..])
+        }
+        if input1[0]==b'/' && !is_sym_char(input1[1]) {
+            // Rational number
+            pos.col+=1;
+            let mut parts = Vec::with_capacity(2);
+            let mut input2 = &input1[1..];
+            for _ in 0..2 {
+                input2 = eat_ws(input2,pos);
+                match parse_value(input2,pos,p,None) {
+                    PResult::Done(Value::Int(v),ninp) => {
+                        parts.push(Ratio::from(v));
+                        input2 = ninp;
+                    },
+                    PResult::Done(Value::Real(v),ninp) => {
+                        parts.push(v);
+                        input2 = ninp;
+                    },
+                    PResult::Done(_,_) => return PResult::SyntaxError(ParseError::ExpectedValue,pos.clone()),
+                    err => return err
+                }
+            }
+            let input3 = eat_ws(input2,pos);
+            if input3.len()==0 {
+                return PResult::Incomplete
+            }
+            if input3[0]!=b')' {
+                return PResult::SyntaxError(ParseError::ExpectedClosePar,pos.clone())
+            }
+            pos.col+=1;
+            if parts[1]==Ratio::from(BigInt::from(0)) {
+                return PResult::SyntaxError(ParseError::ExpectedValue,pos.clone())
+            }
+            return PResult::Done(Value::Real(&parts[0]/&parts[1]),&input3[1..])
+        }
+        if input1[0]==b'f' {
+            if input1.len()<3 {
+                return PResult::Incomplete
+            }
+            if input1[1]==b'p' && !is_sym_char(input1[2]) {
+                pos.col+=2;
+                return parse_fp_value(&input1[2..],pos,p)
+            }
+        }
+        if input1[0]!=b'_' {
+            return PResult::SyntaxError(ParseError::ExpectedUnderscore,pos.clone())
+        }
+        pos.col+=1;
+        let input2 = eat_ws(&input1[1..],pos);
+        if input2.len()<3 {
+            return PResult::Incomplete
+        }
+        if input2[0]==b'+' || input2[0]==b'-' || input2[0]==b'N' {
+            return parse_fp_special(input2,pos,p)
+        }
+        if input2[0]!=b'b' || input2[1]!=b'v' {
+            return PResult::SyntaxError(ParseError::ExpectedBV,pos.clone())
+        }
+        pos.col+=2;
+        let mut off = 2;
+        while off < input2.len() {
+            if !(input2[off] as char).is_digit(10) { break }
+            off+=1;
+        }
+        if off==2 {
+            return PResult::Incomplete
+        }
+        match BigUint::parse_bytes(&input2[2..off],10) {
+            None => panic!("Internal error: Cannot parse {:?} to BigUint",&input2[2..off]),
+            Some(v) => {
+                pos.col+=off;
+                let input3 = eat_ws(&input2[off..],pos);
+                off=0;
+                while off<input3.len() {
+                    if !(input3[off] as char).is_digit(10) { break }
+                    off+=1;
+                }
+                if off==0 {
+                    return PResult::Incomplete
+                }
+                match str::from_utf8(&input3[0..off]) {
+                    Err(_) => panic!("Internal error: Cannot parse {:?} into &str",&input3[0..off]),
+                    Ok(sz) => match FromStr::from_str(sz) {
+                        Err(_) => panic!("Internal error: Cannot parse {:?} into usize",sz),
+                        Ok(rsz) => {
+                            pos.col+=off;
+                            let input4 = eat_ws(&input3[off..],pos);
+                            if input4.len()==0 {
+                                return PResult::Incomplete
+                            }
                             if input4[0]==b')' {
                                 pos.col+=1;
                                 PResult::Done(Value::BitVec(rsz,v),
                                               &input4[1..])
                             } else {
-                                PResult::SyntaxError(ParseError::ExpectedClosePar)
+                                PResult::SyntaxError(ParseError::ExpectedClosePar,pos.clone())
                             }
                         }
                     }
                 }
             }
         }
+    } else if input[0]==b'@' || is_sym_char(input[0]) {
+        // Abstract values of uninterpreted sorts (like `@uc_u0_0` or
+        // `u0!val!0`) are only recognized if the sort is known.
+        let mut off = 1;
+        while off<input.len() && is_sym_char(input[off]) {
+            off+=1;
+        }
+        let name = &input[0..off];
+        let is_abstract = name[0]==b'@' || name.windows(5).any(|w| w==b"!val!");
+        match hint {
+            Some(srt) if is_abstract => match p.unbed_sort(srt) {
+                Err(e) => PResult::EmbedError(e),
+                Ok(SortKind::Uninterpreted(_,_)) => match str::from_utf8(name) {
+                    Err(_) => PResult::SyntaxError(ParseError::InvalidUTF8,pos.clone()),
+                    Ok(rname) => match Sort::from_embed(srt,p) {
+                        Err(e) => PResult::EmbedError(e),
+                        Ok(rsrt) => {
+                            pos.col+=off;
+                            PResult::Done(Value::Uninterpreted(rsrt,rname.to_string()),
+                                          &input[off..])
+                        }
+                    }
+                },
+                Ok(_) => PResult::SyntaxError(ParseError::ExpectedValue,pos.clone())
+            },
+            _ => PResult::SyntaxError(ParseError::ExpectedValue,pos.clone())
+        }
     } else {
-        PResult::SyntaxError(ParseError::ExpectedValue)
+        PResult::SyntaxError(ParseError::ExpectedValue,pos.clone())
+    }
+}
+
+/// Parse the rest of an abstract value `(as name sort)` of an uninterpreted
+/// sort, starting after the `as`.
+fn parse_abstract_value<'inp,P : Parser>(input: &'inp[u8],pos: &mut Pos,p: &mut P)
+                                         -> PResult<'inp,Value,P> {
+    let mut off = 0;
+    while off<input.len() && is_sym_char(input[off]) {
+        off+=1;
+    }
+    if off==input.len() {
+        return PResult::Incomplete
+    }
+    if off==0 {
+        return PResult::SyntaxError(ParseError::ExpectedValue,pos.clone())
+    }
+    let name = match str::from_utf8(&input[0..off]) {
+        Err(_) => return PResult::SyntaxError(ParseError::InvalidUTF8,pos.clone()),
+        Ok(name) => name.to_string()
+    };
+    pos.col+=off;
+    let input1 = eat_ws(&input[off..],pos);
+    let (srt,input2) = match parse_sort(input1,pos,p) {
+        PResult::Done(srt,ninp) => (srt,ninp),
+        PResult::Incomplete => return PResult::Incomplete,
+        PResult::EmbedError(e) => return PResult::EmbedError(e),
+        PResult::SyntaxError(e,epos) => return PResult::SyntaxError(e,epos)
+    };
+    match p.unbed_sort(&srt) {
+        Err(e) => return PResult::EmbedError(e),
+        Ok(SortKind::Uninterpreted(_,_)) => {},
+        Ok(_) => return PResult::SyntaxError(ParseError::ExpectedValue,pos.clone())
+    }
+    let input3 = eat_ws(input2,pos);
+    if input3.len()==0 {
+        return PResult::Incomplete
+    }
+    if input3[0]!=b')' {
+        return PResult::SyntaxError(ParseError::ExpectedClosePar,pos.clone())
+    }
+    pos.col+=1;
+    match Sort::from_embed(&srt,p) {
+        Err(e) => PResult::EmbedError(e),
+        Ok(rsrt) => PResult::Done(Value::Uninterpreted(rsrt,name),&input3[1..])
+    }
+}
+
+/// Parse the fields of a datatype value followed by the closing
+/// parenthesis. If `fields` is false, the value is a bare nullary
+/// constructor.
+fn parse_dt_value<'inp,P : Parser>(dt: usize,pars: Vec<P::Sort>,con: usize,
+                                   fields: bool,
+                                   input: &'inp[u8],pos: &mut Pos,p: &mut P)
+                                   -> PResult<'inp,Value,P> {
+    let mut vals = Vec::new();
+    let mut input1 = input;
+    if fields {
+        let def = match p.datatype(dt) {
+            Err(e) => return PResult::EmbedError(e),
+            Ok(def) => def
+        };
+        if con>=def.constructors.len() {
+            return PResult::SyntaxError(ParseError::ExpectedValue,pos.clone())
+        }
+        loop {
+            input1 = eat_ws(input1,pos);
+            if input1.len()==0 {
+                return PResult::Incomplete
+            }
+            if input1[0]==b')' {
+                pos.col+=1;
+                input1 = &input1[1..];
+                break
+            }
+            if vals.len()>=def.constructors[con].len() {
+                return PResult::SyntaxError(ParseError::ExpectedClosePar,pos.clone())
+            }
+            let srt = match p.instantiate_sort(&def.constructors[con][vals.len()],&pars) {
+                Err(e) => return PResult::EmbedError(e),
+                Ok(srt) => srt
+            };
+            match parse_value(input1,pos,p,Some(&srt)) {
+                PResult::Done(v,ninp) => {
+                    vals.push(v);
+                    input1 = ninp;
+                },
+                err => return err
+            }
+        }
+        if vals.len()!=def.constructors[con].len() {
+            return PResult::SyntaxError(ParseError::WrongNumberOfArgs(vals.len(),def.constructors[con].len(),false),pos.clone())
+        }
+    }
+    let srt = match p.embed_sort(SortKind::Datatype(dt,pars)) {
+        Err(e) => return PResult::EmbedError(e),
+        Ok(srt) => srt
+    };
+    match Sort::from_embed(&srt,p) {
+        Err(e) => PResult::EmbedError(e),
+        Ok(rsrt) => PResult::Done(Value::Datatype(rsrt,con,vals),input1)
+    }
+}
+
+/// Parse the rest of a floating point literal `(fp sign exponent significand)`
+fn parse_fp_value<'inp,P : Parser>(input: &'inp[u8],pos: &mut Pos,p: &mut P)
+                                   -> PResult<'inp,Value,P> {
+    let mut parts = Vec::with_capacity(3);
+    let mut input1 = input;
+    for _ in 0..3 {
+        input1 = eat_ws(input1,pos);
+        match parse_value(input1,pos,p,None) {
+            PResult::Done(Value::BitVec(bw,v),ninp) => {
+                parts.push((bw,v));
+                input1 = ninp;
+            },
+            PResult::Done(_,_) => return PResult::SyntaxError(ParseError::ExpectedValue,pos.clone()),
+            err => return err
+        }
+    }
+    let input2 = eat_ws(input1,pos);
+    if input2.len()==0 {
+        return PResult::Incomplete
+    }
+    if input2[0]!=b')' {
+        return PResult::SyntaxError(ParseError::ExpectedClosePar,pos.clone())
+    }
+    pos.col+=1;
+    let (_,ref sig) = parts[0];
+    let (eb,ref exp) = parts[1];
+    let (sb,ref mant) = parts[2];
+    let bits = (sig << (eb+sb)) | (exp << sb) | mant;
+    PResult::Done(Value::Float(eb,sb+1,bits),&input2[1..])
+}
+
+/// Parse the rest of a special floating point constant like `(_ +zero 8 24)`
+fn parse_fp_special<'inp,P : Parser>(input: &'inp[u8],pos: &mut Pos,p: &mut P)
+                                     -> PResult<'inp,Value,P> {
+    let mut off = 0;
+    while off<input.len() && is_sym_char(input[off]) {
+        off+=1;
+    }
+    if off==input.len() {
+        return PResult::Incomplete
+    }
+    let (negative,kind) = match &input[0..off] {
+        b"+zero" => (false,0),
+        b"-zero" => (true,0),
+        b"+oo" => (false,1),
+        b"-oo" => (true,1),
+        b"NaN" => (false,2),
+        _ => return PResult::SyntaxError(ParseError::ExpectedValue,pos.clone())
+    };
+    pos.col+=off;
+    let input1 = eat_ws(&input[off..],pos);
+    let width_pos = pos.clone();
+    let (eb,input2) = match parse_usize(input1,pos,p) {
+        PResult::Done(n,ninp) => (n,ninp),
+        PResult::Incomplete => return PResult::Incomplete,
+        PResult::EmbedError(e) => return PResult::EmbedError(e),
+        PResult::SyntaxError(e,epos) => return PResult::SyntaxError(e,epos)
+    };
+    let input3 = eat_ws(input2,pos);
+    let (sb,input4) = match parse_usize(input3,pos,p) {
+        PResult::Done(n,ninp) => (n,ninp),
+        PResult::Incomplete => return PResult::Incomplete,
+        PResult::EmbedError(e) => return PResult::EmbedError(e),
+        PResult::SyntaxError(e,epos) => return PResult::SyntaxError(e,epos)
+    };
+    let input5 = eat_ws(input4,pos);
+    if input5.len()==0 {
+        return PResult::Incomplete
+    }
+    if input5[0]!=b')' {
+        return PResult::SyntaxError(ParseError::ExpectedClosePar,pos.clone())
+    }
+    if eb<2 || sb<2 {
+        return PResult::SyntaxError(ParseError::ExpectedValue,width_pos)
     }
+    pos.col+=1;
+    let one = BigUint::from(1 as u8);
+    let max_exp = ((&one << eb) - (1 as u8)) << (sb-1);
+    let mut bits = match kind {
+        0 => BigUint::from(0 as u8),
+        1 => max_exp,
+        _ => max_exp | (&one << (sb-2))
+    };
+    if negative {
+        bits |= one << (eb+sb-1);
+    }
+    PResult::Done(Value::Float(eb,sb,bits),&input5[1..])
 }
 
 pub fn parse_info_response_name<'inp,P : Parser>(input: &'inp[u8],pos: &mut Pos,p: &mut P)
@@ -1071,6 +2737,52 @@ pub fn parse_info_response_version<'inp,P : Parser>(input: &'inp[u8],pos: &mut P
     parse_info_response(b"version",input,pos,p)
 }
 
+pub fn parse_info_response_reason_unknown<'inp,P : Parser>(input: &'inp[u8],pos: &mut Pos,p: &mut P)
+                                                           -> PResult<'inp,String,P> {
+    parse_info_response(b"reason-unknown",input,pos,p)
+}
+
+/// Parse the response of `(get-info :all-statistics)`. Both the standard
+/// `(:all-statistics (...))` form and a plain list of attributes are
+/// accepted. Keys are returned without the leading colon.
+pub fn parse_statistics<'inp,P : Parser>(input: &'inp[u8],pos: &mut Pos,p: &mut P)
+                                         -> PResult<'inp,HashMap<String,StatValue>,P> {
+    let (sexpr,rest) = match parse_sexpr(input,pos,p) {
+        PResult::Done(e,ninp) => (e,ninp),
+        PResult::Incomplete => return PResult::Incomplete,
+        PResult::EmbedError(e) => return PResult::EmbedError(e),
+        PResult::SyntaxError(e,epos) => return PResult::SyntaxError(e,epos)
+    };
+    let mut elems = match sexpr {
+        SExpr::List(elems) => elems,
+        _ => return PResult::SyntaxError(ParseError::ExpectedOpenPar,pos.clone())
+    };
+    if elems.len()==2 && elems[0]==SExpr::Atom(String::from(":all-statistics")) {
+        elems = match elems.pop() {
+            Some(SExpr::List(inner)) => inner,
+            _ => return PResult::SyntaxError(ParseError::ExpectedOpenPar,pos.clone())
+        };
+    }
+    let mut stats = HashMap::new();
+    let mut iter = elems.into_iter();
+    while let Some(key) = iter.next() {
+        let name = match key {
+            SExpr::Atom(ref k) if k.starts_with(':') => String::from(&k[1..]),
+            _ => return PResult::SyntaxError(ParseError::ExpectedId,pos.clone())
+        };
+        let val = match iter.next() {
+            None => return PResult::SyntaxError(ParseError::ExpectedValue,pos.clone()),
+            Some(SExpr::Atom(v)) => match f64::from_str(&v) {
+                Ok(n) => StatValue::Number(n),
+                Err(_) => StatValue::String(v)
+            },
+            Some(SExpr::Str(v)) => StatValue::String(v),
+            Some(v) => StatValue::String(format!("{}",v))
+        };
+        stats.insert(name,val);
+    }
+    PResult::Done(stats,rest)
+}
 
 fn parse_info_response<'inp,P : Parser>(info: &'static[u8],input: &'inp[u8],pos: &mut Pos,_: &mut P)
                                         -> PResult<'inp,String,P> {
@@ -1078,7 +2790,7 @@ fn parse_info_response<'inp,P : Parser>(info: &'static[u8],input: &'inp[u8],pos:
         return PResult::Incomplete
     }
     if input[0]!=b'(' {
-        return PResult::SyntaxError(ParseError::ExpectedOpenPar)
+        return PResult::SyntaxError(ParseError::ExpectedOpenPar,pos.clone())
     }
     pos.col+=1;
     let input1 = eat_ws(&input[1..],pos);
@@ -1086,7 +2798,7 @@ fn parse_info_response<'inp,P : Parser>(info: &'static[u8],input: &'inp[u8],pos:
         return PResult::Incomplete
     }
     if &input1[1..info.len()+1]!=info {
-        return PResult::SyntaxError(ParseError::ExpectedLiteral(info))
+        return PResult::SyntaxError(ParseError::ExpectedLiteral(info),pos.clone())
     }
     pos.col+=1+info.len();
     let input2 = eat_ws(&input1[info.len()+1..],pos);
@@ -1094,7 +2806,31 @@ fn parse_info_response<'inp,P : Parser>(info: &'static[u8],input: &'inp[u8],pos:
         return PResult::Incomplete
     }
     if input2[0]!=b'"' {
-        return PResult::SyntaxError(ParseError::ExpectedQuote)
+        // Some infos (like reason-unknown) may also be symbols
+        let mut off = 0;
+        while off<input2.len() && is_sym_char(input2[off]) && input2[off]!=b'"' {
+            off+=1;
+        }
+        if off==input2.len() {
+            return PResult::Incomplete
+        }
+        if off==0 {
+            return PResult::SyntaxError(ParseError::ExpectedQuote,pos.clone())
+        }
+        let resp = match str::from_utf8(&input2[0..off]) {
+            Err(_) => return PResult::SyntaxError(ParseError::InvalidUTF8,pos.clone()),
+            Ok(r) => r
+        };
+        pos.col+=off;
+        let input3 = eat_ws(&input2[off..],pos);
+        if input3.len()==0 {
+            return PResult::Incomplete
+        }
+        if input3[0]!=b')' {
+            return PResult::SyntaxError(ParseError::ExpectedClosePar,pos.clone())
+        }
+        pos.col+=1;
+        return PResult::Done(String::from(resp),&input3[1..])
     }
     let mut off=1;
     // FIXME: Handle escaped chars
@@ -1113,21 +2849,107 @@ fn parse_info_response<'inp,P : Parser>(info: &'static[u8],input: &'inp[u8],pos:
         off+=1;
     }
     let resp = match str::from_utf8(&input2[1..off]) {
-        Err(_) => return PResult::SyntaxError(ParseError::InvalidUTF8),
+        Err(_) => return PResult::SyntaxError(ParseError::InvalidUTF8,pos.clone()),
         Ok(r) => r
     };
-    pos.col+=off+1;
+    advance(&input2[0..off+1],pos);
     let input3 = eat_ws(&input2[off+1..],pos);
     if input3.len()==0 {
         return PResult::Incomplete
     }
     if input3[0]!=b')' {
-        return PResult::SyntaxError(ParseError::ExpectedClosePar)
+        return PResult::SyntaxError(ParseError::ExpectedClosePar,pos.clone())
     }
     pos.col+=1;
     return PResult::Done(String::from(resp),&input3[1..])
 }
 
+/// Parse the response of a `get-option` command.
+pub fn parse_option_value<'inp,P : Parser>(input: &'inp[u8],pos: &mut Pos,p: &mut P)
+                                           -> PResult<'inp,OptionValue,P> {
+    let (sexpr,rest) = match parse_sexpr(input,pos,p) {
+        PResult::Done(e,ninp) => (e,ninp),
+        PResult::Incomplete => return PResult::Incomplete,
+        PResult::EmbedError(e) => return PResult::EmbedError(e),
+        PResult::SyntaxError(e,epos) => return PResult::SyntaxError(e,epos)
+    };
+    let val = match sexpr {
+        SExpr::Atom(ref a) if a=="true" => OptionValue::Bool(true),
+        SExpr::Atom(ref a) if a=="false" => OptionValue::Bool(false),
+        SExpr::Atom(a) => match u64::from_str(&a) {
+            Ok(n) => OptionValue::Numeral(n),
+            Err(_) => match f64::from_str(&a) {
+                Ok(d) => OptionValue::Decimal(d),
+                Err(_) => OptionValue::Symbol(a)
+            }
+        },
+        SExpr::Str(s) => OptionValue::String(s),
+        SExpr::List(_) => return PResult::SyntaxError(ParseError::ExpectedValue,pos.clone())
+    };
+    PResult::Done(val,rest)
+}
+
+/// A solver statistic.
+#[derive(Debug,Clone,PartialEq)]
+pub enum StatValue {
+    Number(f64),
+    String(String)
+}
+
+/// A response which a solver may give to any command.
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub enum GeneralResponse {
+    Success,
+    Unsupported,
+    Error(String)
+}
+
+/// Parse `success`, `unsupported` or `(error "...")`. Returns `None`
+/// without consuming anything if the input is a different response.
+pub fn parse_general_response<'inp,P : Parser>(input: &'inp[u8],pos: &mut Pos,p: &mut P)
+                                               -> PResult<'inp,Option<GeneralResponse>,P> {
+    let input0 = eat_ws(input,pos);
+    if input0.len()==0 {
+        return PResult::Incomplete
+    }
+    for &(kw,ref resp) in [(&b"success"[..],GeneralResponse::Success),
+                           (&b"unsupported"[..],GeneralResponse::Unsupported)].iter() {
+        if input0.len()<=kw.len() {
+            if kw.starts_with(input0) {
+                return PResult::Incomplete
+            }
+        } else if input0.starts_with(kw) && !is_sym_char(input0[kw.len()]) {
+            pos.col+=kw.len();
+            return PResult::Done(Some(resp.clone()),&input0[kw.len()..])
+        }
+    }
+    if input0[0]!=b'(' {
+        return PResult::Done(None,input)
+    }
+    let mut tmp_pos = pos.clone();
+    let input1 = eat_ws(&input0[1..],&mut tmp_pos);
+    if input1.len()<=5 {
+        if b"error".starts_with(input1) {
+            return PResult::Incomplete
+        }
+        return PResult::Done(None,input)
+    }
+    if !input1.starts_with(b"error") || is_sym_char(input1[5]) {
+        return PResult::Done(None,input)
+    }
+    match parse_sexpr(input0,pos,p) {
+        PResult::Done(SExpr::List(elems),ninp) => match elems.get(1) {
+            Some(&SExpr::Str(ref msg)) => PResult::Done(Some(GeneralResponse::Error(msg.clone())),ninp),
+            Some(other) => PResult::Done(Some(GeneralResponse::Error(format!("{}",other))),ninp),
+            None => PResult::Done(Some(GeneralResponse::Error(String::new())),ninp)
+        },
+        PResult::Done(_,_) => unreachable!(),
+        PResult::Incomplete => PResult::Incomplete,
+        PResult::EmbedError(e) => PResult::EmbedError(e),
+        PResult::SyntaxError(e,epos) => PResult::SyntaxError(e,epos)
+    }
+}
+
 pub fn parse_checksat_result<'inp,P : Parser>(input: &'inp[u8],pos: &mut Pos,_: &mut P)
                                               -> PResult<'inp,CheckSatResult,P> {
     if input.len() < 3 {
@@ -1151,54 +2973,1849 @@ pub fn parse_checksat_result<'inp,P : Parser>(input: &'inp[u8],pos: &mut Pos,_:
         pos.col+=7;
         return PResult::Done(CheckSatResult::Unknown,&input[7..])
     }
-    return PResult::SyntaxError(ParseError::ExpectedCheckSatResult)
+    return PResult::SyntaxError(ParseError::ExpectedCheckSatResult,pos.clone())
 }
 
-pub fn parse_get_value_result<'inp,P : Parser>(input: &'inp[u8],pos: &mut Pos,p: &mut P,hint: &P::Sort)
-                                               -> PResult<'inp,Value,P> {
+/// Skip whitespace and comments.
+pub(crate) fn eat_ws_comments<'inp>(inp: &'inp[u8],pos: &mut Pos) -> &'inp[u8] {
+    let mut input = eat_ws(inp,pos);
+    while input.len()>0 && input[0]==b';' {
+        let mut off = 0;
+        while off<input.len() && input[off]!=b'\n' {
+            off+=1;
+        }
+        pos.col+=off;
+        input = eat_ws(&input[off..],pos);
+    }
+    input
+}
+
+/// Skip a complete S-expression.
+fn skip_sexpr<'inp,P : Parser>(input: &'inp[u8],pos: &mut Pos,_: &mut P)
+                               -> PResult<'inp,(),P> {
     if input.len()==0 {
         return PResult::Incomplete
     }
     if input[0]!=b'(' {
-        return PResult::SyntaxError(ParseError::ExpectedOpenPar)
+        let off = match symbol_extent(input) {
+            None => return PResult::Incomplete,
+            Some((_,off)) => off
+        };
+        if off==input.len() {
+            return PResult::Incomplete
+        }
+        if off==0 {
+            return PResult::SyntaxError(ParseError::ExpectedExpr,pos.clone())
+        }
+        advance(&input[0..off],pos);
+        return PResult::Done((),&input[off..])
     }
-    pos.col+=1;
-    let input1=eat_ws(&input[1..],pos);
-    if input1.len()==0 {
+    let mut depth = 0;
+    let mut off = 0;
+    loop {
+        if off>=input.len() {
+            return PResult::Incomplete
+        }
+        match input[off] {
+            b'(' => depth+=1,
+            b')' => {
+                depth-=1;
+                if depth==0 {
+                    pos.col+=1;
+                    return PResult::Done((),&input[off+1..])
+                }
+            },
+            b'\n' => {
+                pos.line+=1;
+                pos.col=0;
+                off+=1;
+                continue
+            },
+            c@b'"' | c@b'|' => {
+                let start = off;
+                off+=1;
+                while off<input.len() && input[off]!=c {
+                    off+=1;
+                }
+                advance(&input[start..off],pos);
+            },
+            _ => {}
+        }
+        off+=1;
+        pos.col+=1;
+    }
+}
+
+/// The definition of a symbol in a model.
+enum ModelEntry<S,E> {
+    Interp(Interpretation<S,E>),
+    /// An array given by `(_ as-array f)`, where `f` is defined elsewhere
+    /// in the model
+    AsArray(Vec<u8>)
+}
+
+/// Parse a single `define-fun` of a model, starting after the
+/// `define-fun` keyword. Besides the definition, the name of the symbol is
+/// returned and the symbol itself if it is known to the parser.
+fn parse_model_entry<'inp,P : Parser>(input: &'inp[u8],pos: &mut Pos,p: &mut P)
+                                      -> PResult<'inp,(Option<Result<P::Var,P::Fun>>,Vec<u8>,
+                                                       ModelEntry<P::Sort,P::Expr>),P> {
+    let input1 = eat_ws(input,pos);
+    let (name,off) = match symbol_extent(input1) {
+        None => return PResult::Incomplete,
+        Some(r) => r
+    };
+    if off==input1.len() {
         return PResult::Incomplete
     }
-    if input1[0]!=b'(' {
-        return PResult::SyntaxError(ParseError::ExpectedOpenPar)
+    if off==0 {
+        return PResult::SyntaxError(ParseError::ExpectedId,pos.clone())
     }
-    pos.col+=1;
-    let input2 = eat_ws(&input1[1..],pos);
-    let (_,input3) = match parse_expr(input2,pos,p,Some(hint)) {
-        PResult::Done(e,ninp) => (e,ninp),
+    let sym = match p.parse_var(name) {
+        Ok(v) => Some(Ok(v)),
+        Err(_) => match p.parse_fun(name) {
+            Ok(f) => Some(Err(f)),
+            Err(_) => None
+        }
+    };
+    advance(&input1[0..off],pos);
+    let input2 = eat_ws(&input1[off..],pos);
+    let mut scope = Scope::new();
+    let (params,input3) = match parse_params(input2,pos,p,&mut scope) {
+        PResult::Done(params,ninp) => (params,ninp),
         PResult::Incomplete => return PResult::Incomplete,
-        PResult::SyntaxError(e) => return PResult::SyntaxError(e),
-        PResult::EmbedError(e) => return PResult::EmbedError(e)
+        PResult::EmbedError(e) => return PResult::EmbedError(e),
+        PResult::SyntaxError(e,epos) => return PResult::SyntaxError(e,epos)
     };
     let input4 = eat_ws(input3,pos);
-    let (val,input5) = match parse_value(input4,pos,p,Some(hint)) {
-        PResult::Done(res,ninp) => (res,ninp),
-        err => return err
+    let (srt,input5) = match parse_sort(input4,pos,p) {
+        PResult::Done(srt,ninp) => (srt,ninp),
+        PResult::Incomplete => return PResult::Incomplete,
+        PResult::EmbedError(e) => return PResult::EmbedError(e),
+        PResult::SyntaxError(e,epos) => return PResult::SyntaxError(e,epos)
     };
     let input6 = eat_ws(input5,pos);
-    if input6.len()==0 {
-        return PResult::Incomplete
-    }
-    if input6[0]!=b')' {
-        return PResult::SyntaxError(ParseError::ExpectedClosePar)
-    }
-    pos.col+=1;
-    let input7 = eat_ws(input6,pos);
-    if input7[0]!=b')' {
-        return PResult::SyntaxError(ParseError::ExpectedClosePar)
-    }
-    pos.col+=1;
-    PResult::Done(val,&input7[1..])
-}
-
+    let is_array = params.len()==0 && match p.unbed_sort(&srt) {
+        Err(e) => return PResult::EmbedError(e),
+        Ok(SortKind::Array(_,_)) => true,
+        Ok(_) => false
+    };
+    let (entry,input7) = if is_array {
+        match parse_as_array(input6,pos) {
+            Some((fun,ninp)) => (ModelEntry::AsArray(fun.to_vec()),ninp),
+            None => match parse_value(input6,pos,p,Some(&srt)) {
+                PResult::Done(v,ninp) => (ModelEntry::Interp(Interpretation::Value(v)),ninp),
+                PResult::Incomplete => return PResult::Incomplete,
+                PResult::EmbedError(e) => return PResult::EmbedError(e),
+                PResult::SyntaxError(e,epos) => return PResult::SyntaxError(e,epos)
+            }
+        }
+    } else {
+        match parse_expr_scoped(input6,pos,p,&scope,Some(&srt)) {
+            PResult::Done(body,ninp) => {
+                let interp = if params.len()==0 {
+                    match p.unbed(&body) {
+                        Err(e) => return PResult::EmbedError(e),
+                        Ok(Expr::Const(v)) => Interpretation::Value(v),
+                        Ok(_) => Interpretation::Fun(params,body)
+                    }
+                } else {
+                    Interpretation::Fun(params,body)
+                };
+                (ModelEntry::Interp(interp),ninp)
+            },
+            PResult::Incomplete => return PResult::Incomplete,
+            PResult::EmbedError(e) => return PResult::EmbedError(e),
+            PResult::SyntaxError(e,epos) => return PResult::SyntaxError(e,epos)
+        }
+    };
+    let input8 = eat_ws(input7,pos);
+    if input8.len()==0 {
+        return PResult::Incomplete
+    }
+    if input8[0]!=b')' {
+        return PResult::SyntaxError(ParseError::ExpectedClosePar,pos.clone())
+    }
+    pos.col+=1;
+    PResult::Done((sym,name.to_vec(),entry),&input8[1..])
+}
+
+/// Recognize an `(_ as-array f)` expression and return the name of the
+/// function. The position is only updated if the expression is found.
+fn parse_as_array<'inp>(input: &'inp[u8],pos: &mut Pos) -> Option<(&'inp[u8],&'inp[u8])> {
+    let mut npos = pos.clone();
+    if input.len()==0 || input[0]!=b'(' {
+        return None
+    }
+    npos.col+=1;
+    let input1 = eat_ws(&input[1..],&mut npos);
+    if input1.len()==0 || input1[0]!=b'_' {
+        return None
+    }
+    npos.col+=1;
+    let input2 = eat_ws(&input1[1..],&mut npos);
+    if starts_with_keyword(input2,b"as-array")!=Some(true) {
+        return None
+    }
+    npos.col+=8;
+    let input3 = eat_ws(&input2[8..],&mut npos);
+    let (name,off) = match symbol_extent(input3) {
+        Some((name,off)) if off>0 => (name,off),
+        _ => return None
+    };
+    advance(&input3[0..off],&mut npos);
+    let input4 = eat_ws(&input3[off..],&mut npos);
+    if input4.len()==0 || input4[0]!=b')' {
+        return None
+    }
+    npos.col+=1;
+    *pos = npos;
+    Some((name,&input4[1..]))
+}
+
+/// Parse the response of a `get-model` command. Both the `(model ...)` form
+/// and a plain list of definitions are accepted. Definitions of symbols
+/// which are unknown to the parser (like auxiliary functions introduced by
+/// the solver) or which cannot be represented are left out.
+pub fn parse_model<'inp,P : Parser>(input: &'inp[u8],pos: &mut Pos,p: &mut P)
+                                    -> PResult<'inp,Model<P::Sort,P::Var,P::Expr,P::Fun>,P> {
+    let input0 = eat_ws_comments(input,pos);
+    if input0.len()==0 {
+        return PResult::Incomplete
+    }
+    if input0[0]!=b'(' {
+        return PResult::SyntaxError(ParseError::ExpectedOpenPar,pos.clone())
+    }
+    pos.col+=1;
+    let mut input1 = eat_ws_comments(&input0[1..],pos);
+    if input1.len()<6 {
+        return PResult::Incomplete
+    }
+    if &input1[0..5]==b"model" && !is_sym_char(input1[5]) {
+        pos.col+=5;
+        input1 = &input1[5..];
+    }
+    let mut model = Model { vars: Vec::new(),
+                            funs: Vec::new() };
+    // Function definitions by name and variables defined by `as-array`,
+    // which can only be resolved once the whole model is known
+    let mut defs: HashMap<Vec<u8>,(Vec<NVar<P::Sort>>,P::Expr)> = HashMap::new();
+    let mut as_arrays = Vec::new();
+    loop {
+        input1 = eat_ws_comments(input1,pos);
+        if input1.len()==0 {
+            return PResult::Incomplete
+        }
+        if input1[0]==b')' {
+            pos.col+=1;
+            for (v,name) in as_arrays.into_iter() {
+                let (params,body) = match defs.get(&name) {
+                    None => continue,
+                    Some(&(ref params,ref body)) => (params,body)
+                };
+                match array_from_fun(p,params,body) {
+                    Err(e) => return PResult::EmbedError(e),
+                    Ok(Some(val)) => model.vars.push((v,Interpretation::Value(val))),
+                    Ok(None) => {}
+                }
+            }
+            return PResult::Done(model,&input1[1..])
+        }
+        let entry_pos = pos.clone();
+        let entry = input1;
+        let mut input2 = &input1[0..0];
+        let mut is_def = false;
+        if input1[0]==b'(' {
+            pos.col+=1;
+            input2 = eat_ws(&input1[1..],pos);
+            let mut off = 0;
+            while off<input2.len() && is_sym_char(input2[off]) {
+                off+=1;
+            }
+            if off==input2.len() {
+                return PResult::Incomplete
+            }
+            is_def = &input2[0..off]==b"define-fun" || &input2[0..off]==b"define-fun-rec";
+            pos.col+=off;
+            input2 = &input2[off..];
+        }
+        if is_def {
+            match parse_model_entry(input2,pos,p) {
+                PResult::Done((sym,name,entry),ninp) => {
+                    match entry {
+                        ModelEntry::Interp(interp) => {
+                            if let Interpretation::Fun(ref params,ref body) = interp {
+                                defs.insert(name,(params.clone(),body.clone()));
+                            }
+                            match sym {
+                                Some(Ok(v)) => model.vars.push((v,interp)),
+                                Some(Err(f)) => model.funs.push((f,interp)),
+                                None => {}
+                            }
+                        },
+                        ModelEntry::AsArray(fun) => if let Some(Ok(v)) = sym {
+                            as_arrays.push((v,fun));
+                        }
+                    }
+                    input1 = ninp;
+                    continue
+                },
+                PResult::Incomplete => return PResult::Incomplete,
+                _ => {}
+            }
+        }
+        *pos = entry_pos;
+        match skip_sexpr(entry,pos,p) {
+            PResult::Done(_,ninp) => { input1 = ninp; },
+            PResult::Incomplete => return PResult::Incomplete,
+            PResult::EmbedError(e) => return PResult::EmbedError(e),
+            PResult::SyntaxError(e,epos) => return PResult::SyntaxError(e,epos)
+        }
+    }
+}
+
+/// Parse an arbitrary S-expression.
+pub fn parse_sexpr<'inp,P : Parser>(input: &'inp[u8],pos: &mut Pos,p: &mut P)
+                                    -> PResult<'inp,SExpr,P> {
+    let input1 = eat_ws_comments(input,pos);
+    if input1.len()==0 {
+        return PResult::Incomplete
+    }
+    match input1[0] {
+        b'(' => {
+            pos.col+=1;
+            let mut elems = Vec::new();
+            let mut input2 = &input1[1..];
+            loop {
+                input2 = eat_ws_comments(input2,pos);
+                if input2.len()==0 {
+                    return PResult::Incomplete
+                }
+                if input2[0]==b')' {
+                    pos.col+=1;
+                    return PResult::Done(SExpr::List(elems),&input2[1..])
+                }
+                match parse_sexpr(input2,pos,p) {
+                    PResult::Done(e,ninp) => {
+                        elems.push(e);
+                        input2 = ninp;
+                    },
+                    err => return err
+                }
+            }
+        },
+        b')' => PResult::SyntaxError(ParseError::ExpectedExpr,pos.clone()),
+        b'"' => {
+            let mut res = Vec::new();
+            let mut off = 1;
+            loop {
+                if off>=input1.len() {
+                    return PResult::Incomplete
+                }
+                if input1[off]==b'"' {
+                    // A doubled quote is an escaped quote
+                    if off+1>=input1.len() {
+                        return PResult::Incomplete
+                    }
+                    if input1[off+1]!=b'"' {
+                        break
+                    }
+                    off+=1;
+                }
+                res.push(input1[off]);
+                off+=1;
+            }
+            match String::from_utf8(res) {
+                Err(_) => PResult::SyntaxError(ParseError::InvalidUTF8,pos.clone()),
+                Ok(str) => {
+                    advance(&input1[0..off+1],pos);
+                    PResult::Done(SExpr::Str(str),&input1[off+1..])
+                }
+            }
+        },
+        b'|' => {
+            let mut off = 1;
+            while off<input1.len() && input1[off]!=b'|' {
+                off+=1;
+            }
+            if off>=input1.len() {
+                return PResult::Incomplete
+            }
+            match str::from_utf8(&input1[0..off+1]) {
+                Err(_) => PResult::SyntaxError(ParseError::InvalidUTF8,pos.clone()),
+                Ok(sym) => {
+                    advance(&input1[0..off+1],pos);
+                    PResult::Done(SExpr::Atom(String::from(sym)),&input1[off+1..])
+                }
+            }
+        },
+        _ => {
+            let mut off = 0;
+            while off<input1.len() && is_sym_char(input1[off]) && input1[off]!=b'"' {
+                off+=1;
+            }
+            if off==input1.len() {
+                return PResult::Incomplete
+            }
+            match str::from_utf8(&input1[0..off]) {
+                Err(_) => PResult::SyntaxError(ParseError::InvalidUTF8,pos.clone()),
+                Ok(sym) => {
+                    pos.col+=off;
+                    PResult::Done(SExpr::Atom(String::from(sym)),&input1[off..])
+                }
+            }
+        }
+    }
+}
+
+/// Parse the response of a `get-unsat-assumptions` command, which is a
+/// list of boolean literals.
+pub fn parse_unsat_assumptions<'inp,P : Parser>(input: &'inp[u8],pos: &mut Pos,p: &mut P)
+                                                -> PResult<'inp,Vec<P::Expr>,P> {
+    if input.len()==0 {
+        return PResult::Incomplete
+    }
+    if input[0]!=b'(' {
+        return PResult::SyntaxError(ParseError::ExpectedOpenPar,pos.clone())
+    }
+    pos.col+=1;
+    let tbool = match p.tp_bool() {
+        Err(e) => return PResult::EmbedError(e),
+        Ok(srt) => srt
+    };
+    let mut lits = Vec::new();
+    let mut input1 = &input[1..];
+    loop {
+        input1 = eat_ws(input1,pos);
+        if input1.len()==0 {
+            return PResult::Incomplete
+        }
+        if input1[0]==b')' {
+            pos.col+=1;
+            return PResult::Done(lits,&input1[1..])
+        }
+        match parse_expr(input1,pos,p,Some(&tbool)) {
+            PResult::Done(e,ninp) => {
+                lits.push(e);
+                input1 = ninp;
+            },
+            PResult::Incomplete => return PResult::Incomplete,
+            PResult::EmbedError(e) => return PResult::EmbedError(e),
+            PResult::SyntaxError(e,epos) => return PResult::SyntaxError(e,epos)
+        }
+    }
+}
+
+/// Parse the response of a `get-interpolants` command, which is a list of
+/// formulas (one for each partition boundary).
+pub fn parse_interpolants<'inp,P : Parser>(input: &'inp[u8],pos: &mut Pos,p: &mut P)
+                                           -> PResult<'inp,Vec<P::Expr>,P> {
+    // Same shape as the response to get-unsat-assumptions
+    parse_unsat_assumptions(input,pos,p)
+}
+
+/// Parse the response of a CVC5 `get-interpol` command, which defines the
+/// interpolant as a nullary function. Returns `None` if the solver failed
+/// to compute an interpolant.
+pub fn parse_interpol<'inp,P : Parser>(input: &'inp[u8],pos: &mut Pos,p: &mut P)
+                                       -> PResult<'inp,Option<P::Expr>,P> {
+    let input0 = eat_ws(input,pos);
+    if input0.len()<5 {
+        return PResult::Incomplete
+    }
+    if &input0[0..4]==b"fail" && !is_sym_char(input0[4]) {
+        pos.col+=4;
+        return PResult::Done(None,&input0[4..])
+    }
+    if input0[0]!=b'(' {
+        return PResult::SyntaxError(ParseError::ExpectedOpenPar,pos.clone())
+    }
+    pos.col+=1;
+    let input1 = eat_ws(&input0[1..],pos);
+    if input1.len()<11 {
+        return PResult::Incomplete
+    }
+    if &input1[0..10]!=b"define-fun" || is_sym_char(input1[10]) {
+        return PResult::SyntaxError(ParseError::ExpectedLiteral(b"define-fun"),pos.clone())
+    }
+    pos.col+=10;
+    let input2 = eat_ws(&input1[10..],pos);
+    let input3 = match skip_sexpr(input2,pos,p) {
+        PResult::Done(_,ninp) => eat_ws(ninp,pos),
+        PResult::Incomplete => return PResult::Incomplete,
+        PResult::EmbedError(e) => return PResult::EmbedError(e),
+        PResult::SyntaxError(e,epos) => return PResult::SyntaxError(e,epos)
+    };
+    if input3.len()<2 {
+        return PResult::Incomplete
+    }
+    if &input3[0..2]!=b"()" {
+        return PResult::SyntaxError(ParseError::ExpectedLiteral(b"()"),pos.clone())
+    }
+    pos.col+=2;
+    let input4 = eat_ws(&input3[2..],pos);
+    let (srt,input5) = match parse_sort(input4,pos,p) {
+        PResult::Done(srt,ninp) => (srt,ninp),
+        PResult::Incomplete => return PResult::Incomplete,
+        PResult::EmbedError(e) => return PResult::EmbedError(e),
+        PResult::SyntaxError(e,epos) => return PResult::SyntaxError(e,epos)
+    };
+    let input6 = eat_ws(input5,pos);
+    let (body,input7) = match parse_expr(input6,pos,p,Some(&srt)) {
+        PResult::Done(e,ninp) => (e,ninp),
+        PResult::Incomplete => return PResult::Incomplete,
+        PResult::EmbedError(e) => return PResult::EmbedError(e),
+        PResult::SyntaxError(e,epos) => return PResult::SyntaxError(e,epos)
+    };
+    let input8 = eat_ws(input7,pos);
+    if input8.len()==0 {
+        return PResult::Incomplete
+    }
+    if input8[0]!=b')' {
+        return PResult::SyntaxError(ParseError::ExpectedClosePar,pos.clone())
+    }
+    pos.col+=1;
+    PResult::Done(Some(body),&input8[1..])
+}
+
+/// Parse the result of applying a tactic (`(goals (goal ...) ...)`). Each
+/// goal is returned as the list of its formulas, goal attributes like
+/// `:precision` are ignored.
+pub fn parse_goals<'inp,P : Parser>(input: &'inp[u8],pos: &mut Pos,p: &mut P)
+                                    -> PResult<'inp,Vec<Vec<P::Expr>>,P> {
+    let input0 = eat_ws(input,pos);
+    if input0.len()==0 {
+        return PResult::Incomplete
+    }
+    if input0[0]!=b'(' {
+        return PResult::SyntaxError(ParseError::ExpectedOpenPar,pos.clone())
+    }
+    pos.col+=1;
+    let input1 = eat_ws(&input0[1..],pos);
+    if input1.len()<6 {
+        return PResult::Incomplete
+    }
+    if &input1[0..5]!=b"goals" || is_sym_char(input1[5]) {
+        return PResult::SyntaxError(ParseError::ExpectedLiteral(b"goals"),pos.clone())
+    }
+    pos.col+=5;
+    let tbool = match p.tp_bool() {
+        Err(e) => return PResult::EmbedError(e),
+        Ok(srt) => srt
+    };
+    let mut goals = Vec::new();
+    let mut input2 = &input1[5..];
+    loop {
+        input2 = eat_ws(input2,pos);
+        if input2.len()==0 {
+            return PResult::Incomplete
+        }
+        if input2[0]==b')' {
+            pos.col+=1;
+            return PResult::Done(goals,&input2[1..])
+        }
+        if input2[0]!=b'(' {
+            return PResult::SyntaxError(ParseError::ExpectedOpenPar,pos.clone())
+        }
+        pos.col+=1;
+        let input3 = eat_ws(&input2[1..],pos);
+        if input3.len()<5 {
+            return PResult::Incomplete
+        }
+        if &input3[0..4]!=b"goal" || is_sym_char(input3[4]) {
+            return PResult::SyntaxError(ParseError::ExpectedLiteral(b"goal"),pos.clone())
+        }
+        pos.col+=4;
+        let mut goal = Vec::new();
+        let mut input4 = &input3[4..];
+        loop {
+            input4 = eat_ws(input4,pos);
+            if input4.len()==0 {
+                return PResult::Incomplete
+            }
+            if input4[0]==b')' {
+                pos.col+=1;
+                input4 = &input4[1..];
+                break
+            }
+            if input4[0]==b':' {
+                // Skip the attribute and its value
+                match skip_sexpr(input4,pos,p) {
+                    PResult::Done(_,ninp) => input4 = eat_ws(ninp,pos),
+                    PResult::Incomplete => return PResult::Incomplete,
+                    PResult::EmbedError(e) => return PResult::EmbedError(e),
+                    PResult::SyntaxError(e,epos) => return PResult::SyntaxError(e,epos)
+                }
+                match skip_sexpr(input4,pos,p) {
+                    PResult::Done(_,ninp) => input4 = ninp,
+                    PResult::Incomplete => return PResult::Incomplete,
+                    PResult::EmbedError(e) => return PResult::EmbedError(e),
+                    PResult::SyntaxError(e,epos) => return PResult::SyntaxError(e,epos)
+                }
+                continue
+            }
+            match parse_expr(input4,pos,p,Some(&tbool)) {
+                PResult::Done(e,ninp) => {
+                    goal.push(e);
+                    input4 = ninp;
+                },
+                PResult::Incomplete => return PResult::Incomplete,
+                PResult::EmbedError(e) => return PResult::EmbedError(e),
+                PResult::SyntaxError(e,epos) => return PResult::SyntaxError(e,epos)
+            }
+        }
+        goals.push(goal);
+        input2 = input4;
+    }
+}
+
+fn is_epsilon(e: &SExpr) -> Option<bool> {
+    match *e {
+        SExpr::Atom(ref a) if a=="epsilon" => Some(true),
+        SExpr::List(ref elems) if elems.len()==2 &&
+            elems[0]==SExpr::Atom(String::from("-")) &&
+            elems[1]==SExpr::Atom(String::from("epsilon")) => Some(false),
+        SExpr::List(ref elems) if elems.len()==3 &&
+            elems[0]==SExpr::Atom(String::from("*")) &&
+            elems[2]==SExpr::Atom(String::from("epsilon")) => match elems[1] {
+                SExpr::List(ref neg) if neg.len()==2 &&
+                    neg[0]==SExpr::Atom(String::from("-")) &&
+                    neg[1]==SExpr::Atom(String::from("1")) => Some(false),
+                SExpr::Atom(ref c) if c=="1" => Some(true),
+                _ => None
+            },
+        _ => None
+    }
+}
+
+fn is_infinity(e: &SExpr) -> Option<bool> {
+    match *e {
+        SExpr::Atom(ref a) if a=="oo" || a=="+oo" => Some(true),
+        SExpr::Atom(ref a) if a=="-oo" => Some(false),
+        SExpr::List(ref elems) if elems.len()==2 &&
+            elems[0]==SExpr::Atom(String::from("-")) &&
+            elems[1]==SExpr::Atom(String::from("oo")) => Some(false),
+        SExpr::List(ref elems) if elems.len()==3 &&
+            elems[0]==SExpr::Atom(String::from("*")) &&
+            elems[2]==SExpr::Atom(String::from("oo")) => match elems[1] {
+                SExpr::List(ref neg) if neg.len()==2 &&
+                    neg[0]==SExpr::Atom(String::from("-")) &&
+                    neg[1]==SExpr::Atom(String::from("1")) => Some(false),
+                _ => None
+            },
+        _ => None
+    }
+}
+
+/// Parse a value that has already been read as an S-expression.
+fn parse_value_sexpr<'inp,P : Parser>(e: &SExpr,pos: &mut Pos,p: &mut P,hint: Option<&P::Sort>)
+                                      -> Result<Value,PResult<'inp,ObjectiveValue,P>> {
+    // The trailing space keeps the value parser from waiting for more input
+    let text = format!("{} ",e);
+    match parse_value(text.as_bytes(),pos,p,hint) {
+        PResult::Done(v,_) => Ok(v),
+        PResult::Incomplete => Err(PResult::SyntaxError(ParseError::ExpectedValue,pos.clone())),
+        PResult::EmbedError(e) => Err(PResult::EmbedError(e)),
+        PResult::SyntaxError(e,epos) => Err(PResult::SyntaxError(e,epos))
+    }
+}
+
+/// Parse the value of an optimization objective, which may be infinite
+/// (`oo`) or contain an infinitesimal (`epsilon`).
+pub fn parse_objective_value<'inp,P : Parser>(input: &'inp[u8],pos: &mut Pos,p: &mut P,
+                                              hint: Option<&P::Sort>)
+                                              -> PResult<'inp,ObjectiveValue,P> {
+    let (e,rest) = match parse_sexpr(input,pos,p) {
+        PResult::Done(e,ninp) => (e,ninp),
+        PResult::Incomplete => return PResult::Incomplete,
+        PResult::EmbedError(e) => return PResult::EmbedError(e),
+        PResult::SyntaxError(e,epos) => return PResult::SyntaxError(e,epos)
+    };
+    if let Some(pos_inf) = is_infinity(&e) {
+        return PResult::Done(ObjectiveValue::Infinite(pos_inf),rest)
+    }
+    let res = match e {
+        SExpr::List(ref elems) if elems.len()==3 &&
+            (elems[0]==SExpr::Atom(String::from("+")) ||
+             elems[0]==SExpr::Atom(String::from("-"))) => {
+                let plus = elems[0]==SExpr::Atom(String::from("+"));
+                match is_epsilon(&elems[2]) {
+                    Some(eps) => match parse_value_sexpr(&elems[1],pos,p,hint) {
+                        Ok(v) => ObjectiveValue::Epsilon(v,eps==plus),
+                        Err(err) => return err
+                    },
+                    None => match parse_value_sexpr(&e,pos,p,hint) {
+                        Ok(v) => ObjectiveValue::Finite(v),
+                        Err(err) => return err
+                    }
+                }
+            },
+        _ => match parse_value_sexpr(&e,pos,p,hint) {
+            Ok(v) => ObjectiveValue::Finite(v),
+            Err(err) => return err
+        }
+    };
+    PResult::Done(res,rest)
+}
+
+/// Parse the response of a `get-objectives` command. The entries are
+/// expected in the order in which the objectives were declared, `hints`
+/// contains their sorts.
+pub fn parse_objectives<'inp,P : Parser>(input: &'inp[u8],pos: &mut Pos,p: &mut P,
+                                         hints: &[P::Sort])
+                                         -> PResult<'inp,Vec<ObjectiveValue>,P> {
+    let input0 = eat_ws(input,pos);
+    if input0.len()==0 {
+        return PResult::Incomplete
+    }
+    if input0[0]!=b'(' {
+        return PResult::SyntaxError(ParseError::ExpectedOpenPar,pos.clone())
+    }
+    pos.col+=1;
+    let input1 = eat_ws(&input0[1..],pos);
+    if input1.len()<11 {
+        return PResult::Incomplete
+    }
+    if &input1[0..10]!=b"objectives" || is_sym_char(input1[10]) {
+        return PResult::SyntaxError(ParseError::ExpectedLiteral(b"objectives"),pos.clone())
+    }
+    pos.col+=10;
+    let mut res = Vec::with_capacity(hints.len());
+    let mut input2 = &input1[10..];
+    loop {
+        input2 = eat_ws(input2,pos);
+        if input2.len()==0 {
+            return PResult::Incomplete
+        }
+        if input2[0]==b')' {
+            pos.col+=1;
+            return PResult::Done(res,&input2[1..])
+        }
+        if input2[0]!=b'(' {
+            return PResult::SyntaxError(ParseError::ExpectedOpenPar,pos.clone())
+        }
+        pos.col+=1;
+        // The objective term itself is not needed
+        let input3 = match skip_sexpr(eat_ws(&input2[1..],pos),pos,p) {
+            PResult::Done(_,ninp) => eat_ws(ninp,pos),
+            PResult::Incomplete => return PResult::Incomplete,
+            PResult::EmbedError(e) => return PResult::EmbedError(e),
+            PResult::SyntaxError(e,epos) => return PResult::SyntaxError(e,epos)
+        };
+        let input4 = match parse_objective_value(input3,pos,p,hints.get(res.len())) {
+            PResult::Done(v,ninp) => {
+                res.push(v);
+                eat_ws(ninp,pos)
+            },
+            PResult::Incomplete => return PResult::Incomplete,
+            PResult::EmbedError(e) => return PResult::EmbedError(e),
+            PResult::SyntaxError(e,epos) => return PResult::SyntaxError(e,epos)
+        };
+        if input4.len()==0 {
+            return PResult::Incomplete
+        }
+        if input4[0]!=b')' {
+            return PResult::SyntaxError(ParseError::ExpectedClosePar,pos.clone())
+        }
+        pos.col+=1;
+        input2 = &input4[1..];
+    }
+}
+
+/// Parse the response of a `get-unsat-core` command, which is a list of
+/// assertion names.
+pub fn parse_unsat_core<'inp,P : Parser>(input: &'inp[u8],pos: &mut Pos,_: &mut P)
+                                         -> PResult<'inp,Vec<String>,P> {
+    if input.len()==0 {
+        return PResult::Incomplete
+    }
+    if input[0]!=b'(' {
+        return PResult::SyntaxError(ParseError::ExpectedOpenPar,pos.clone())
+    }
+    pos.col+=1;
+    let mut names = Vec::new();
+    let mut input1 = &input[1..];
+    loop {
+        input1 = eat_ws(input1,pos);
+        if input1.len()==0 {
+            return PResult::Incomplete
+        }
+        if input1[0]==b')' {
+            pos.col+=1;
+            return PResult::Done(names,&input1[1..])
+        }
+        let (name,off) = match symbol_extent(input1) {
+            None => return PResult::Incomplete,
+            Some(r) => r
+        };
+        if off==input1.len() {
+            return PResult::Incomplete
+        }
+        if off==0 {
+            return PResult::SyntaxError(ParseError::ExpectedId,pos.clone())
+        }
+        match str::from_utf8(name) {
+            Err(_) => return PResult::SyntaxError(ParseError::InvalidUTF8,pos.clone()),
+            Ok(name) => names.push(String::from(name))
+        }
+        advance(&input1[0..off],pos);
+        input1 = &input1[off..];
+    }
+}
+
+pub fn parse_get_value_result<'inp,P : Parser>(input: &'inp[u8],pos: &mut Pos,p: &mut P,hint: &P::Sort)
+                                               -> PResult<'inp,Value,P> {
+    match parse_get_values_result(input,pos,p,&[hint.clone()]) {
+        PResult::Done(mut vals,ninp) => PResult::Done(vals.remove(0),ninp),
+        PResult::Incomplete => PResult::Incomplete,
+        PResult::SyntaxError(e,epos) => PResult::SyntaxError(e,epos),
+        PResult::EmbedError(e) => PResult::EmbedError(e)
+    }
+}
+
+/// Parse the response of a `get-value` command for multiple terms. The
+/// sorts of the terms are used as hints for parsing the values.
+pub fn parse_get_values_result<'inp,P : Parser>(input: &'inp[u8],pos: &mut Pos,p: &mut P,hints: &[P::Sort])
+                                                -> PResult<'inp,Vec<Value>,P> {
+    if input.len()==0 {
+        return PResult::Incomplete
+    }
+    if input[0]!=b'(' {
+        return PResult::SyntaxError(ParseError::ExpectedOpenPar,pos.clone())
+    }
+    pos.col+=1;
+    let mut vals = Vec::with_capacity(hints.len());
+    let mut input1 = &input[1..];
+    for hint in hints.iter() {
+        input1 = eat_ws(input1,pos);
+        if input1.len()==0 {
+            return PResult::Incomplete
+        }
+        if input1[0]!=b'(' {
+            return PResult::SyntaxError(ParseError::ExpectedOpenPar,pos.clone())
+        }
+        pos.col+=1;
+        let input2 = eat_ws(&input1[1..],pos);
+        let (_,input3) = match parse_expr(input2,pos,p,Some(hint)) {
+            PResult::Done(e,ninp) => (e,ninp),
+            PResult::Incomplete => return PResult::Incomplete,
+            PResult::SyntaxError(e,epos) => return PResult::SyntaxError(e,epos),
+            PResult::EmbedError(e) => return PResult::EmbedError(e)
+        };
+        let input4 = eat_ws(input3,pos);
+        let (val,input5) = match parse_value(input4,pos,p,Some(hint)) {
+            PResult::Done(res,ninp) => (res,ninp),
+            PResult::Incomplete => return PResult::Incomplete,
+            PResult::SyntaxError(e,epos) => return PResult::SyntaxError(e,epos),
+            PResult::EmbedError(e) => return PResult::EmbedError(e)
+        };
+        vals.push(val);
+        let input6 = eat_ws(input5,pos);
+        if input6.len()==0 {
+            return PResult::Incomplete
+        }
+        if input6[0]!=b')' {
+            return PResult::SyntaxError(ParseError::ExpectedClosePar,pos.clone())
+        }
+        pos.col+=1;
+        input1 = &input6[1..];
+    }
+    let input7 = eat_ws(input1,pos);
+    if input7.len()==0 {
+        return PResult::Incomplete
+    }
+    if input7[0]!=b')' {
+        return PResult::SyntaxError(ParseError::ExpectedClosePar,pos.clone())
+    }
+    pos.col+=1;
+    PResult::Done(vals,&input7[1..])
+}
+
+impl<S : Display> Display for ParseError<S> {
+    fn fmt(&self,f: &mut Formatter) -> Result<(),fmt::Error> {
+        match *self {
+            ParseError::NumberNotOfSort(ref n,ref srt) => write!(f,"number {} is not of sort {}",n,srt),
+            ParseError::InvalidNumberFormat(c) => write!(f,"invalid number format #{}",c as char),
+            ParseError::ExpectedValue => write!(f,"expected a value"),
+            ParseError::ExpectedBV => write!(f,"expected a bitvector"),
+            ParseError::ExpectedOpenPar => write!(f,"expected '('"),
+            ParseError::ExpectedClosePar => write!(f,"expected ')'"),
+            ParseError::ExpectedUnderscore => write!(f,"expected '_'"),
+            ParseError::TypeMismatch(ref s1,ref s2) => write!(f,"sort mismatch between {} and {}",s1,s2),
+            ParseError::WrongNumberOfArgs(got,exp,at_least)
+                => write!(f,"wrong number of arguments: got {}, expected {}{}",
+                          got,if at_least { "at least " } else { "" },exp),
+            ParseError::ExpectedNumeric(ref srt) => write!(f,"expected a numeric sort, got {}",srt),
+            ParseError::ExpectedBitVec(ref srt) => write!(f,"expected a bitvector sort, got {}",srt),
+            ParseError::ExpectedFloat(ref srt) => write!(f,"expected a floating point sort, got {}",srt),
+            ParseError::ExpectedDatatype(ref srt) => write!(f,"expected a datatype, got {}",srt),
+            ParseError::ExpectedNumeral => write!(f,"expected a numeral"),
+            ParseError::ExpectedId => write!(f,"expected a symbol"),
+            ParseError::ExpectedParFun => write!(f,"expected an indexed function"),
+            ParseError::ExpectedArray => write!(f,"expected an array"),
+            ParseError::ExpectedAs => write!(f,"expected a qualified constructor (as ...)"),
+            ParseError::ExpectedConst => write!(f,"expected a constant"),
+            ParseError::ExpectedSort => write!(f,"expected a sort"),
+            ParseError::ExpectedLitBitVec => write!(f,"expected BitVec"),
+            ParseError::ExpectedArraySort(ref srt) => write!(f,"expected an array sort, got {}",srt),
+            ParseError::UnknownVar => write!(f,"unknown variable"),
+            ParseError::UnknownFun => write!(f,"unknown function"),
+            ParseError::UnknownSort => write!(f,"unknown sort"),
+            ParseError::UnknownAssertion => write!(f,"unknown assertion"),
+            ParseError::ExpectedLiteral(lit) => write!(f,"expected {}",String::from_utf8_lossy(lit)),
+            ParseError::ExpectedQuote => write!(f,"expected a string"),
+            ParseError::InvalidUTF8 => write!(f,"invalid UTF-8"),
+            ParseError::ExpectedCheckSatResult => write!(f,"expected sat, unsat or unknown"),
+            ParseError::ExpectedExpr => write!(f,"expected an expression"),
+            ParseError::UnknownCommand(ref cmd) => write!(f,"unknown command {}",cmd),
+            ParseError::UnsupportedCommand(ref cmd) => write!(f,"unsupported command {}",cmd)
+        }
+    }
+}
+
+/// Shows the error message, followed by the offending line with a caret
+/// under the token:
+///
+/// ```text
+/// 1:19: expected a sort
+/// (declare-fun x () Strng)
+///                   ^~~~~
+/// ```
+impl<S : Display> Display for SyntaxError<S> {
+    fn fmt(&self,f: &mut Formatter) -> Result<(),fmt::Error> {
+        write!(f,"{}:{}: {}\n{}\n",self.pos.line+1,self.pos.col+1,self.error,self.line)?;
+        // Keep tabs so that the caret lines up with the token
+        for c in self.line.chars().take(self.pos.col) {
+            write!(f,"{}",if c=='\t' { '\t' } else { ' ' })?;
+        }
+        write!(f,"^")?;
+        for _ in 1..self.token.len() {
+            write!(f,"~")?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for SExpr {
+    fn fmt(&self,f: &mut Formatter) -> Result<(),fmt::Error> {
+        match *self {
+            SExpr::Atom(ref a) => write!(f,"{}",a),
+            SExpr::Str(ref s) => write!(f,"\"{}\"",s.replace("\"","\"\"")),
+            SExpr::List(ref elems) => {
+                write!(f,"(")?;
+                for (i,e) in elems.iter().enumerate() {
+                    if i>0 {
+                        write!(f," ")?;
+                    }
+                    e.fmt(f)?;
+                }
+                write!(f,")")
+            }
+        }
+    }
+}
+
+/// Parse a symbol.
+fn parse_symbol<'inp,P : Parser>(input: &'inp[u8],pos: &mut Pos,_: &mut P)
+                                 -> PResult<'inp,String,P> {
+    let (name,off) = match symbol_extent(input) {
+        None => return PResult::Incomplete,
+        Some(r) => r
+    };
+    if off==input.len() {
+        return PResult::Incomplete
+    }
+    if off==0 {
+        return PResult::SyntaxError(ParseError::ExpectedId,pos.clone())
+    }
+    match str::from_utf8(name) {
+        Err(_) => PResult::SyntaxError(ParseError::InvalidUTF8,pos.clone()),
+        Ok(name) => {
+            advance(&input[0..off],pos);
+            PResult::Done(String::from(name),&input[off..])
+        }
+    }
+}
+
+/// Parse a closing parenthesis, skipping whitespace and comments before it.
+fn parse_close<'inp,P : Parser>(input: &'inp[u8],pos: &mut Pos,_: &mut P)
+                                -> PResult<'inp,(),P> {
+    let input1 = eat_ws_comments(input,pos);
+    if input1.len()==0 {
+        return PResult::Incomplete
+    }
+    if input1[0]!=b')' {
+        return PResult::SyntaxError(ParseError::ExpectedClosePar,pos.clone())
+    }
+    pos.col+=1;
+    PResult::Done((),&input1[1..])
+}
+
+/// Parse an opening parenthesis, skipping whitespace and comments before it.
+fn parse_open<'inp,P : Parser>(input: &'inp[u8],pos: &mut Pos,_: &mut P)
+                               -> PResult<'inp,(),P> {
+    let input1 = eat_ws_comments(input,pos);
+    if input1.len()==0 {
+        return PResult::Incomplete
+    }
+    if input1[0]!=b'(' {
+        return PResult::SyntaxError(ParseError::ExpectedOpenPar,pos.clone())
+    }
+    pos.col+=1;
+    PResult::Done((),&input1[1..])
+}
+
+/// Parse a parenthesized list of sorts.
+fn parse_sorts<'inp,P : Parser>(input: &'inp[u8],pos: &mut Pos,p: &mut P)
+                                -> PResult<'inp,Vec<P::Sort>,P> {
+    let mut input1 = match parse_open(input,pos,p) {
+        PResult::Done(_,ninp) => ninp,
+        PResult::Incomplete => return PResult::Incomplete,
+        PResult::EmbedError(e) => return PResult::EmbedError(e),
+        PResult::SyntaxError(e,epos) => return PResult::SyntaxError(e,epos)
+    };
+    let mut srts = Vec::new();
+    loop {
+        input1 = eat_ws_comments(input1,pos);
+        if input1.len()==0 {
+            return PResult::Incomplete
+        }
+        if input1[0]==b')' {
+            pos.col+=1;
+            return PResult::Done(srts,&input1[1..])
+        }
+        match parse_sort(input1,pos,p) {
+            PResult::Done(srt,ninp) => {
+                srts.push(srt);
+                input1 = ninp;
+            },
+            PResult::Incomplete => return PResult::Incomplete,
+            PResult::EmbedError(e) => return PResult::EmbedError(e),
+            PResult::SyntaxError(e,epos) => return PResult::SyntaxError(e,epos)
+        }
+    }
+}
+
+/// Parse a parenthesized list of expressions.
+fn parse_exprs<'inp,P : Parser>(input: &'inp[u8],pos: &mut Pos,p: &mut P,
+                                hint: Option<&P::Sort>)
+                                -> PResult<'inp,Vec<P::Expr>,P> {
+    let mut input1 = match parse_open(input,pos,p) {
+        PResult::Done(_,ninp) => ninp,
+        PResult::Incomplete => return PResult::Incomplete,
+        PResult::EmbedError(e) => return PResult::EmbedError(e),
+        PResult::SyntaxError(e,epos) => return PResult::SyntaxError(e,epos)
+    };
+    let mut exprs = Vec::new();
+    loop {
+        input1 = eat_ws_comments(input1,pos);
+        if input1.len()==0 {
+            return PResult::Incomplete
+        }
+        if input1[0]==b')' {
+            pos.col+=1;
+            return PResult::Done(exprs,&input1[1..])
+        }
+        match parse_expr(input1,pos,p,hint) {
+            PResult::Done(e,ninp) => {
+                exprs.push(e);
+                input1 = ninp;
+            },
+            PResult::Incomplete => return PResult::Incomplete,
+            PResult::EmbedError(e) => return PResult::EmbedError(e),
+            PResult::SyntaxError(e,epos) => return PResult::SyntaxError(e,epos)
+        }
+    }
+}
+
+/// Parse the name, parameters and result sort of a function definition.
+/// The parameters are bound in the returned scope.
+fn parse_fun_signature<'inp,P : Parser>(input: &'inp[u8],pos: &mut Pos,p: &mut P)
+                                        -> PResult<'inp,(String,Vec<NVar<P::Sort>>,P::Sort,Scope<P::Sort>),P> {
+    let (name,input1) = match parse_symbol(input,pos,p) {
+        PResult::Done(name,ninp) => (name,ninp),
+        PResult::Incomplete => return PResult::Incomplete,
+        PResult::EmbedError(e) => return PResult::EmbedError(e),
+        PResult::SyntaxError(e,epos) => return PResult::SyntaxError(e,epos)
+    };
+    let mut input2 = match parse_open(input1,pos,p) {
+        PResult::Done(_,ninp) => ninp,
+        PResult::Incomplete => return PResult::Incomplete,
+        PResult::EmbedError(e) => return PResult::EmbedError(e),
+        PResult::SyntaxError(e,epos) => return PResult::SyntaxError(e,epos)
+    };
+    let mut scope = Scope::new();
+    let mut params = Vec::new();
+    loop {
+        input2 = eat_ws_comments(input2,pos);
+        if input2.len()==0 {
+            return PResult::Incomplete
+        }
+        if input2[0]==b')' {
+            pos.col+=1;
+            input2 = &input2[1..];
+            break
+        }
+        if input2[0]!=b'(' {
+            return PResult::SyntaxError(ParseError::ExpectedOpenPar,pos.clone())
+        }
+        pos.col+=1;
+        let input3 = eat_ws(&input2[1..],pos);
+        let (par,input4) = match parse_symbol(input3,pos,p) {
+            PResult::Done(par,ninp) => (par,ninp),
+            PResult::Incomplete => return PResult::Incomplete,
+            PResult::EmbedError(e) => return PResult::EmbedError(e),
+            PResult::SyntaxError(e,epos) => return PResult::SyntaxError(e,epos)
+        };
+        let input5 = eat_ws(input4,pos);
+        let (srt,input6) = match parse_sort(input5,pos,p) {
+            PResult::Done(srt,ninp) => (srt,ninp),
+            PResult::Incomplete => return PResult::Incomplete,
+            PResult::EmbedError(e) => return PResult::EmbedError(e),
+            PResult::SyntaxError(e,epos) => return PResult::SyntaxError(e,epos)
+        };
+        params.push(scope.bind(par.as_bytes(),false,srt));
+        input2 = match parse_close(input6,pos,p) {
+            PResult::Done(_,ninp) => ninp,
+            PResult::Incomplete => return PResult::Incomplete,
+            PResult::EmbedError(e) => return PResult::EmbedError(e),
+            PResult::SyntaxError(e,epos) => return PResult::SyntaxError(e,epos)
+        };
+    }
+    let input7 = eat_ws_comments(input2,pos);
+    match parse_sort(input7,pos,p) {
+        PResult::Done(srt,ninp) => PResult::Done((name,params,srt,scope),ninp),
+        PResult::Incomplete => PResult::Incomplete,
+        PResult::EmbedError(e) => PResult::EmbedError(e),
+        PResult::SyntaxError(e,epos) => PResult::SyntaxError(e,epos)
+    }
+}
+
+/// Parse the rest of a `define-fun` command, starting after the keyword.
+fn parse_define_fun<'inp,P : Parser>(input: &'inp[u8],pos: &mut Pos,p: &mut P)
+                                     -> PResult<'inp,Command<P::Sort,P::Expr>,P> {
+    let ((name,params,srt,scope),input8) = match parse_fun_signature(input,pos,p) {
+        PResult::Done(sig,ninp) => (sig,ninp),
+        PResult::Incomplete => return PResult::Incomplete,
+        PResult::EmbedError(e) => return PResult::EmbedError(e),
+        PResult::SyntaxError(e,epos) => return PResult::SyntaxError(e,epos)
+    };
+    let input9 = eat_ws_comments(input8,pos);
+    match parse_expr_scoped(input9,pos,p,&scope,Some(&srt)) {
+        PResult::Done(body,ninp) => PResult::Done(Command::DefineFun(name,params,srt,body),ninp),
+        PResult::Incomplete => PResult::Incomplete,
+        PResult::EmbedError(e) => PResult::EmbedError(e),
+        PResult::SyntaxError(e,epos) => PResult::SyntaxError(e,epos)
+    }
+}
+
+/// Parse the rest of a `define-fun-rec` command, starting after the
+/// keyword.
+fn parse_define_fun_rec<'inp,P : Parser>(input: &'inp[u8],pos: &mut Pos,p: &mut P)
+                                         -> PResult<'inp,Command<P::Sort,P::Expr>,P> {
+    let ((name,params,srt,scope),input1) = match parse_fun_signature(input,pos,p) {
+        PResult::Done(sig,ninp) => (sig,ninp),
+        PResult::Incomplete => return PResult::Incomplete,
+        PResult::EmbedError(e) => return PResult::EmbedError(e),
+        PResult::SyntaxError(e,epos) => return PResult::SyntaxError(e,epos)
+    };
+    let sig = (name,params,srt);
+    if let Err(e) = p.declare_rec_symbols(&[rec_symbol(&sig)]) {
+        return PResult::EmbedError(e)
+    }
+    let input2 = eat_ws_comments(input1,pos);
+    match parse_expr_scoped(input2,pos,p,&scope,Some(&sig.2)) {
+        PResult::Done(body,ninp) => PResult::Done(Command::DefineFunsRec(vec![sig],vec![body]),ninp),
+        PResult::Incomplete => PResult::Incomplete,
+        PResult::EmbedError(e) => PResult::EmbedError(e),
+        PResult::SyntaxError(e,epos) => PResult::SyntaxError(e,epos)
+    }
+}
+
+/// Parse the rest of a `define-funs-rec` command, starting after the
+/// keyword.
+fn parse_define_funs_rec<'inp,P : Parser>(input: &'inp[u8],pos: &mut Pos,p: &mut P)
+                                          -> PResult<'inp,Command<P::Sort,P::Expr>,P> {
+    let mut input1 = match parse_open(input,pos,p) {
+        PResult::Done(_,ninp) => ninp,
+        PResult::Incomplete => return PResult::Incomplete,
+        PResult::EmbedError(e) => return PResult::EmbedError(e),
+        PResult::SyntaxError(e,epos) => return PResult::SyntaxError(e,epos)
+    };
+    let mut sigs = Vec::new();
+    let mut scopes = Vec::new();
+    loop {
+        input1 = eat_ws_comments(input1,pos);
+        if input1.len()==0 {
+            return PResult::Incomplete
+        }
+        if input1[0]==b')' {
+            pos.col+=1;
+            input1 = &input1[1..];
+            break
+        }
+        let input2 = match parse_open(input1,pos,p) {
+            PResult::Done(_,ninp) => eat_ws(ninp,pos),
+            PResult::Incomplete => return PResult::Incomplete,
+            PResult::EmbedError(e) => return PResult::EmbedError(e),
+            PResult::SyntaxError(e,epos) => return PResult::SyntaxError(e,epos)
+        };
+        let ((name,params,srt,scope),input3) = match parse_fun_signature(input2,pos,p) {
+            PResult::Done(sig,ninp) => (sig,ninp),
+            PResult::Incomplete => return PResult::Incomplete,
+            PResult::EmbedError(e) => return PResult::EmbedError(e),
+            PResult::SyntaxError(e,epos) => return PResult::SyntaxError(e,epos)
+        };
+        sigs.push((name,params,srt));
+        scopes.push(scope);
+        input1 = match parse_close(input3,pos,p) {
+            PResult::Done(_,ninp) => ninp,
+            PResult::Incomplete => return PResult::Incomplete,
+            PResult::EmbedError(e) => return PResult::EmbedError(e),
+            PResult::SyntaxError(e,epos) => return PResult::SyntaxError(e,epos)
+        };
+    }
+    let syms : Vec<_> = sigs.iter().map(rec_symbol).collect();
+    if let Err(e) = p.declare_rec_symbols(&syms) {
+        return PResult::EmbedError(e)
+    }
+    input1 = match parse_open(eat_ws_comments(input1,pos),pos,p) {
+        PResult::Done(_,ninp) => ninp,
+        PResult::Incomplete => return PResult::Incomplete,
+        PResult::EmbedError(e) => return PResult::EmbedError(e),
+        PResult::SyntaxError(e,epos) => return PResult::SyntaxError(e,epos)
+    };
+    let mut bodies = Vec::with_capacity(sigs.len());
+    for (sig,scope) in sigs.iter().zip(scopes.iter()) {
+        input1 = eat_ws_comments(input1,pos);
+        match parse_expr_scoped(input1,pos,p,scope,Some(&sig.2)) {
+            PResult::Done(body,ninp) => {
+                bodies.push(body);
+                input1 = ninp;
+            },
+            PResult::Incomplete => return PResult::Incomplete,
+            PResult::EmbedError(e) => return PResult::EmbedError(e),
+            PResult::SyntaxError(e,epos) => return PResult::SyntaxError(e,epos)
+        }
+    }
+    match parse_close(eat_ws_comments(input1,pos),pos,p) {
+        PResult::Done(_,ninp) => PResult::Done(Command::DefineFunsRec(sigs,bodies),ninp),
+        PResult::Incomplete => PResult::Incomplete,
+        PResult::EmbedError(e) => PResult::EmbedError(e),
+        PResult::SyntaxError(e,epos) => PResult::SyntaxError(e,epos)
+    }
+}
+
+/// The name and signature of a recursive function, as passed to
+/// `Parser::declare_rec_symbols`.
+fn rec_symbol<S : Clone>(sig: &(String,Vec<NVar<S>>,S)) -> (String,Vec<S>,S) {
+    (sig.0.clone(),sig.1.iter().map(|v| v.sort.clone()).collect(),sig.2.clone())
+}
+
+/// Parse a parenthesized list of symbols, like the parameters of a
+/// `define-sort`.
+fn parse_symbols<'inp,P : Parser>(input: &'inp[u8],pos: &mut Pos,p: &mut P)
+                                  -> PResult<'inp,Vec<String>,P> {
+    let mut input1 = match parse_open(input,pos,p) {
+        PResult::Done(_,ninp) => ninp,
+        PResult::Incomplete => return PResult::Incomplete,
+        PResult::EmbedError(e) => return PResult::EmbedError(e),
+        PResult::SyntaxError(e,epos) => return PResult::SyntaxError(e,epos)
+    };
+    let mut syms = Vec::new();
+    loop {
+        input1 = eat_ws_comments(input1,pos);
+        if input1.len()==0 {
+            return PResult::Incomplete
+        }
+        if input1[0]==b')' {
+            pos.col+=1;
+            return PResult::Done(syms,&input1[1..])
+        }
+        match parse_symbol(input1,pos,p) {
+            PResult::Done(sym,ninp) => {
+                syms.push(sym);
+                input1 = ninp;
+            },
+            PResult::Incomplete => return PResult::Incomplete,
+            PResult::EmbedError(e) => return PResult::EmbedError(e),
+            PResult::SyntaxError(e,epos) => return PResult::SyntaxError(e,epos)
+        }
+    }
+}
+
+/// Parse the sort of a datatype field. The sorts declared in the same
+/// command are given by `names` and become recursive references, the
+/// names in `params` are the sort parameters of the datatype.
+fn parse_field_sort<'inp,P : Parser>(input: &'inp[u8],pos: &mut Pos,p: &mut P,
+                                     names: &[String],params: &[String])
+                                     -> PResult<'inp,FieldSort<P::Sort>,P> {
+    if input.len()==0 {
+        return PResult::Incomplete
+    }
+    let applied = input[0]==b'(';
+    let mut npos = pos.clone();
+    let input1 = if applied {
+        npos.col+=1;
+        eat_ws(&input[1..],&mut npos)
+    } else {
+        input
+    };
+    let (name,off) = match symbol_extent(input1) {
+        None => return PResult::Incomplete,
+        Some(r) => r
+    };
+    if off==input1.len() {
+        return PResult::Incomplete
+    }
+    let rec = match names.iter().position(|n| n.as_bytes()==name) {
+        None => return match parse_sort_scoped(input,pos,p,params) {
+            PResult::Done(srt,ninp) => PResult::Done(FieldSort::Sort(srt),ninp),
+            PResult::Incomplete => PResult::Incomplete,
+            PResult::EmbedError(e) => PResult::EmbedError(e),
+            PResult::SyntaxError(e,epos) => PResult::SyntaxError(e,epos)
+        },
+        Some(rec) => rec
+    };
+    advance(&input1[0..off],&mut npos);
+    *pos = npos;
+    if !applied {
+        return PResult::Done(FieldSort::Rec(rec,vec![]),&input1[off..])
+    }
+    // An instance of a parametric datatype of the same declaration
+    let mut args = Vec::new();
+    let mut input2 = eat_ws(&input1[off..],pos);
+    loop {
+        if input2.len()==0 {
+            return PResult::Incomplete
+        }
+        if input2[0]==b')' {
+            pos.col+=1;
+            return PResult::Done(FieldSort::Rec(rec,args),&input2[1..])
+        }
+        match parse_field_sort(input2,pos,p,names,params) {
+            PResult::Done(arg,ninp) => {
+                args.push(arg);
+                input2 = eat_ws(ninp,pos);
+            },
+            PResult::Incomplete => return PResult::Incomplete,
+            PResult::EmbedError(e) => return PResult::EmbedError(e),
+            PResult::SyntaxError(e,epos) => return PResult::SyntaxError(e,epos)
+        }
+    }
+}
+
+/// Parse the constructor list of a datatype declaration, possibly preceded
+/// by `par` and the sort parameters. Returns the parameters and the
+/// constructors.
+fn parse_constructors<'inp,P : Parser>(input: &'inp[u8],pos: &mut Pos,p: &mut P,
+                                       names: &[String])
+                                       -> PResult<'inp,(Vec<String>,Vec<(String,Vec<(String,FieldSort<P::Sort>)>)>),P> {
+    let mut npos = pos.clone();
+    let input1 = match parse_open(input,&mut npos,p) {
+        PResult::Done(_,ninp) => eat_ws_comments(ninp,&mut npos),
+        PResult::Incomplete => return PResult::Incomplete,
+        PResult::EmbedError(e) => return PResult::EmbedError(e),
+        PResult::SyntaxError(e,epos) => return PResult::SyntaxError(e,epos)
+    };
+    match starts_with_keyword(input1,b"par") {
+        None => return PResult::Incomplete,
+        Some(false) => return match parse_constructor_list(input,pos,p,names,&[]) {
+            PResult::Done(cons,ninp) => PResult::Done((vec![],cons),ninp),
+            PResult::Incomplete => PResult::Incomplete,
+            PResult::EmbedError(e) => PResult::EmbedError(e),
+            PResult::SyntaxError(e,epos) => PResult::SyntaxError(e,epos)
+        },
+        Some(true) => {}
+    }
+    *pos = npos;
+    pos.col+=3;
+    let input2 = eat_ws_comments(&input1[3..],pos);
+    let (params,input3) = match parse_symbols(input2,pos,p) {
+        PResult::Done(params,ninp) => (params,ninp),
+        PResult::Incomplete => return PResult::Incomplete,
+        PResult::EmbedError(e) => return PResult::EmbedError(e),
+        PResult::SyntaxError(e,epos) => return PResult::SyntaxError(e,epos)
+    };
+    let input4 = eat_ws_comments(input3,pos);
+    let (cons,input5) = match parse_constructor_list(input4,pos,p,names,&params) {
+        PResult::Done(cons,ninp) => (cons,ninp),
+        PResult::Incomplete => return PResult::Incomplete,
+        PResult::EmbedError(e) => return PResult::EmbedError(e),
+        PResult::SyntaxError(e,epos) => return PResult::SyntaxError(e,epos)
+    };
+    match parse_close(input5,pos,p) {
+        PResult::Done(_,ninp) => PResult::Done((params,cons),ninp),
+        PResult::Incomplete => PResult::Incomplete,
+        PResult::EmbedError(e) => PResult::EmbedError(e),
+        PResult::SyntaxError(e,epos) => PResult::SyntaxError(e,epos)
+    }
+}
+
+/// Parse the parenthesized constructor list of a datatype declaration.
+fn parse_constructor_list<'inp,P : Parser>(input: &'inp[u8],pos: &mut Pos,p: &mut P,
+                                           names: &[String],params: &[String])
+                                           -> PResult<'inp,Vec<(String,Vec<(String,FieldSort<P::Sort>)>)>,P> {
+    let mut input1 = match parse_open(input,pos,p) {
+        PResult::Done(_,ninp) => ninp,
+        PResult::Incomplete => return PResult::Incomplete,
+        PResult::EmbedError(e) => return PResult::EmbedError(e),
+        PResult::SyntaxError(e,epos) => return PResult::SyntaxError(e,epos)
+    };
+    let mut cons = Vec::new();
+    loop {
+        input1 = eat_ws_comments(input1,pos);
+        if input1.len()==0 {
+            return PResult::Incomplete
+        }
+        if input1[0]==b')' {
+            pos.col+=1;
+            return PResult::Done(cons,&input1[1..])
+        }
+        if input1[0]!=b'(' {
+            // A nullary constructor without parentheses
+            match parse_symbol(input1,pos,p) {
+                PResult::Done(con,ninp) => {
+                    cons.push((con,vec![]));
+                    input1 = ninp;
+                    continue
+                },
+                PResult::Incomplete => return PResult::Incomplete,
+                PResult::EmbedError(e) => return PResult::EmbedError(e),
+                PResult::SyntaxError(e,epos) => return PResult::SyntaxError(e,epos)
+            }
+        }
+        pos.col+=1;
+        let input2 = eat_ws(&input1[1..],pos);
+        let (con,mut input3) = match parse_symbol(input2,pos,p) {
+            PResult::Done(con,ninp) => (con,ninp),
+            PResult::Incomplete => return PResult::Incomplete,
+            PResult::EmbedError(e) => return PResult::EmbedError(e),
+            PResult::SyntaxError(e,epos) => return PResult::SyntaxError(e,epos)
+        };
+        let mut fields = Vec::new();
+        loop {
+            input3 = eat_ws_comments(input3,pos);
+            if input3.len()==0 {
+                return PResult::Incomplete
+            }
+            if input3[0]==b')' {
+                pos.col+=1;
+                input3 = &input3[1..];
+                break
+            }
+            if input3[0]!=b'(' {
+                return PResult::SyntaxError(ParseError::ExpectedOpenPar,pos.clone())
+            }
+            pos.col+=1;
+            let input4 = eat_ws(&input3[1..],pos);
+            let (sel,input5) = match parse_symbol(input4,pos,p) {
+                PResult::Done(sel,ninp) => (sel,ninp),
+                PResult::Incomplete => return PResult::Incomplete,
+                PResult::EmbedError(e) => return PResult::EmbedError(e),
+                PResult::SyntaxError(e,epos) => return PResult::SyntaxError(e,epos)
+            };
+            let input6 = eat_ws(input5,pos);
+            let (srt,input7) = match parse_field_sort(input6,pos,p,names,params) {
+                PResult::Done(srt,ninp) => (srt,ninp),
+                PResult::Incomplete => return PResult::Incomplete,
+                PResult::EmbedError(e) => return PResult::EmbedError(e),
+                PResult::SyntaxError(e,epos) => return PResult::SyntaxError(e,epos)
+            };
+            fields.push((sel,srt));
+            input3 = match parse_close(input7,pos,p) {
+                PResult::Done(_,ninp) => ninp,
+                PResult::Incomplete => return PResult::Incomplete,
+                PResult::EmbedError(e) => return PResult::EmbedError(e),
+                PResult::SyntaxError(e,epos) => return PResult::SyntaxError(e,epos)
+            };
+        }
+        cons.push((con,fields));
+        input1 = input3;
+    }
+}
+
+/// Parse the rest of a `declare-datatypes` command, starting after the
+/// keyword.
+fn parse_declare_datatypes<'inp,P : Parser>(input: &'inp[u8],pos: &mut Pos,p: &mut P)
+                                            -> PResult<'inp,Vec<DatatypeDecl<P::Sort>>,P> {
+    let mut input1 = match parse_open(input,pos,p) {
+        PResult::Done(_,ninp) => ninp,
+        PResult::Incomplete => return PResult::Incomplete,
+        PResult::EmbedError(e) => return PResult::EmbedError(e),
+        PResult::SyntaxError(e,epos) => return PResult::SyntaxError(e,epos)
+    };
+    let mut names = Vec::new();
+    let mut arities = Vec::new();
+    loop {
+        input1 = eat_ws_comments(input1,pos);
+        if input1.len()==0 {
+            return PResult::Incomplete
+        }
+        if input1[0]==b')' {
+            pos.col+=1;
+            input1 = &input1[1..];
+            break
+        }
+        if input1[0]!=b'(' {
+            return PResult::SyntaxError(ParseError::ExpectedOpenPar,pos.clone())
+        }
+        pos.col+=1;
+        let input2 = eat_ws(&input1[1..],pos);
+        let (name,input3) = match parse_symbol(input2,pos,p) {
+            PResult::Done(name,ninp) => (name,ninp),
+            PResult::Incomplete => return PResult::Incomplete,
+            PResult::EmbedError(e) => return PResult::EmbedError(e),
+            PResult::SyntaxError(e,epos) => return PResult::SyntaxError(e,epos)
+        };
+        let input4 = eat_ws(input3,pos);
+        let (arity,input5) = match parse_usize(input4,pos,p) {
+            PResult::Done(n,ninp) => (n,ninp),
+            PResult::Incomplete => return PResult::Incomplete,
+            PResult::EmbedError(e) => return PResult::EmbedError(e),
+            PResult::SyntaxError(e,epos) => return PResult::SyntaxError(e,epos)
+        };
+        names.push(name);
+        arities.push(arity);
+        input1 = match parse_close(input5,pos,p) {
+            PResult::Done(_,ninp) => ninp,
+            PResult::Incomplete => return PResult::Incomplete,
+            PResult::EmbedError(e) => return PResult::EmbedError(e),
+            PResult::SyntaxError(e,epos) => return PResult::SyntaxError(e,epos)
+        };
+    }
+    input1 = match parse_open(input1,pos,p) {
+        PResult::Done(_,ninp) => ninp,
+        PResult::Incomplete => return PResult::Incomplete,
+        PResult::EmbedError(e) => return PResult::EmbedError(e),
+        PResult::SyntaxError(e,epos) => return PResult::SyntaxError(e,epos)
+    };
+    let mut decls = Vec::with_capacity(names.len());
+    for (name,&arity) in names.iter().zip(arities.iter()) {
+        input1 = eat_ws_comments(input1,pos);
+        let start = pos.clone();
+        match parse_constructors(input1,pos,p,&names) {
+            PResult::Done((params,cons),ninp) => {
+                if params.len()!=arity {
+                    return PResult::SyntaxError(ParseError::WrongNumberOfArgs(params.len(),arity,false),
+                                                start)
+                }
+                decls.push(DatatypeDecl { name: name.clone(),
+                                          params: params,
+                                          constructors: cons });
+                input1 = ninp;
+            },
+            PResult::Incomplete => return PResult::Incomplete,
+            PResult::EmbedError(e) => return PResult::EmbedError(e),
+            PResult::SyntaxError(e,epos) => return PResult::SyntaxError(e,epos)
+        }
+    }
+    match parse_close(input1,pos,p) {
+        PResult::Done(_,ninp) => PResult::Done(decls,ninp),
+        PResult::Incomplete => PResult::Incomplete,
+        PResult::EmbedError(e) => PResult::EmbedError(e),
+        PResult::SyntaxError(e,epos) => PResult::SyntaxError(e,epos)
+    }
+}
+
+/// Parse the rest of an `assert` command, starting after the keyword. An
+/// assertion annotated with `:named` becomes a named assertion, other
+/// annotations are ignored.
+fn parse_assert<'inp,P : Parser>(input: &'inp[u8],pos: &mut Pos,p: &mut P)
+                                 -> PResult<'inp,Command<P::Sort,P::Expr>,P> {
+    let bool_srt = match p.tp_bool() {
+        Ok(srt) => srt,
+        Err(e) => return PResult::EmbedError(e)
+    };
+    let old_pos = pos.clone();
+    if input.len()>0 && input[0]==b'(' {
+        pos.col+=1;
+        let input1 = eat_ws(&input[1..],pos);
+        if input1.len()<2 {
+            return PResult::Incomplete
+        }
+        if input1[0]==b'!' && !is_sym_char(input1[1]) {
+            pos.col+=1;
+            let input2 = eat_ws(&input1[1..],pos);
+            let (e,mut input3) = match parse_expr(input2,pos,p,Some(&bool_srt)) {
+                PResult::Done(e,ninp) => (e,ninp),
+                PResult::Incomplete => return PResult::Incomplete,
+                PResult::EmbedError(e) => return PResult::EmbedError(e),
+                PResult::SyntaxError(e,epos) => return PResult::SyntaxError(e,epos)
+            };
+            let mut name = None;
+            loop {
+                input3 = eat_ws_comments(input3,pos);
+                if input3.len()==0 {
+                    return PResult::Incomplete
+                }
+                if input3[0]==b')' {
+                    pos.col+=1;
+                    input3 = &input3[1..];
+                    break
+                }
+                let (attr,input4) = match parse_symbol(input3,pos,p) {
+                    PResult::Done(attr,ninp) => (attr,ninp),
+                    PResult::Incomplete => return PResult::Incomplete,
+                    PResult::EmbedError(e) => return PResult::EmbedError(e),
+                    PResult::SyntaxError(e,epos) => return PResult::SyntaxError(e,epos)
+                };
+                let input5 = eat_ws_comments(input4,pos);
+                if attr==":named" {
+                    match parse_symbol(input5,pos,p) {
+                        PResult::Done(n,ninp) => {
+                            name = Some(n);
+                            input3 = ninp;
+                        },
+                        PResult::Incomplete => return PResult::Incomplete,
+                        PResult::EmbedError(e) => return PResult::EmbedError(e),
+                        PResult::SyntaxError(e,epos) => return PResult::SyntaxError(e,epos)
+                    }
+                } else {
+                    match skip_sexpr(input5,pos,p) {
+                        PResult::Done(_,ninp) => { input3 = ninp; },
+                        PResult::Incomplete => return PResult::Incomplete,
+                        PResult::EmbedError(e) => return PResult::EmbedError(e),
+                        PResult::SyntaxError(e,epos) => return PResult::SyntaxError(e,epos)
+                    }
+                }
+            }
+            return match name {
+                Some(n) => PResult::Done(Command::AssertNamed(e,n),input3),
+                None => PResult::Done(Command::Assert(e),input3)
+            }
+        }
+    }
+    *pos = old_pos;
+    match parse_expr(input,pos,p,Some(&bool_srt)) {
+        PResult::Done(e,ninp) => PResult::Done(Command::Assert(e),ninp),
+        PResult::Incomplete => PResult::Incomplete,
+        PResult::EmbedError(e) => PResult::EmbedError(e),
+        PResult::SyntaxError(e,epos) => PResult::SyntaxError(e,epos)
+    }
+}
+
+/// Parse a single command of an SMT-LIB script. Leading whitespace and
+/// comments are skipped. All symbols (including the ones introduced by
+/// declarations) are resolved by the parser, so declarations have to be
+/// made known to it before the commands using them are parsed.
+pub fn parse_command<'inp,P : Parser>(input: &'inp[u8],pos: &mut Pos,p: &mut P)
+                                      -> PResult<'inp,Command<P::Sort,P::Expr>,P> {
+    let input1 = match parse_open(input,pos,p) {
+        PResult::Done(_,ninp) => eat_ws(ninp,pos),
+        PResult::Incomplete => return PResult::Incomplete,
+        PResult::EmbedError(e) => return PResult::EmbedError(e),
+        PResult::SyntaxError(e,epos) => return PResult::SyntaxError(e,epos)
+    };
+    let (name,input2) = match parse_symbol(input1,pos,p) {
+        PResult::Done(name,ninp) => (name,ninp),
+        PResult::Incomplete => return PResult::Incomplete,
+        PResult::EmbedError(e) => return PResult::EmbedError(e),
+        PResult::SyntaxError(e,epos) => return PResult::SyntaxError(e,epos)
+    };
+    let input3 = eat_ws_comments(input2,pos);
+    let res = match &name[..] {
+        "set-logic" => match parse_symbol(input3,pos,p) {
+            PResult::Done(l,ninp) => match Logic::from_str(&l) {
+                Ok(logic) => PResult::Done(Command::SetLogic(logic),ninp),
+                Err(_) => PResult::SyntaxError(ParseError::ExpectedId,pos.clone())
+            },
+            PResult::Incomplete => PResult::Incomplete,
+            PResult::EmbedError(e) => PResult::EmbedError(e),
+            PResult::SyntaxError(e,epos) => PResult::SyntaxError(e,epos)
+        },
+        "set-option" | "set-info" => {
+            let (attr,input4) = match parse_symbol(input3,pos,p) {
+                PResult::Done(attr,ninp) => (attr,ninp),
+                PResult::Incomplete => return PResult::Incomplete,
+                PResult::EmbedError(e) => return PResult::EmbedError(e),
+                PResult::SyntaxError(e,epos) => return PResult::SyntaxError(e,epos)
+            };
+            match parse_option_value(input4,pos,p) {
+                PResult::Done(val,ninp) => PResult::Done(if name=="set-option" {
+                    Command::SetOption(attr,val)
+                } else {
+                    Command::SetInfo(attr,val)
+                },ninp),
+                PResult::Incomplete => PResult::Incomplete,
+                PResult::EmbedError(e) => PResult::EmbedError(e),
+                PResult::SyntaxError(e,epos) => PResult::SyntaxError(e,epos)
+            }
+        },
+        "get-option" | "get-info" => match parse_symbol(input3,pos,p) {
+            PResult::Done(attr,ninp) => PResult::Done(if name=="get-option" {
+                Command::GetOption(attr)
+            } else {
+                Command::GetInfo(attr)
+            },ninp),
+            PResult::Incomplete => PResult::Incomplete,
+            PResult::EmbedError(e) => PResult::EmbedError(e),
+            PResult::SyntaxError(e,epos) => PResult::SyntaxError(e,epos)
+        },
+        "declare-sort" => {
+            let (sym,input4) = match parse_symbol(input3,pos,p) {
+                PResult::Done(sym,ninp) => (sym,ninp),
+                PResult::Incomplete => return PResult::Incomplete,
+                PResult::EmbedError(e) => return PResult::EmbedError(e),
+                PResult::SyntaxError(e,epos) => return PResult::SyntaxError(e,epos)
+            };
+            let input5 = eat_ws(input4,pos);
+            if input5.len()>0 && input5[0]==b')' {
+                // The arity defaults to zero
+                PResult::Done(Command::DeclareSort(sym,0),input5)
+            } else {
+                match parse_usize(input5,pos,p) {
+                    PResult::Done(n,ninp) => PResult::Done(Command::DeclareSort(sym,n),ninp),
+                    PResult::Incomplete => PResult::Incomplete,
+                    PResult::EmbedError(e) => PResult::EmbedError(e),
+                    PResult::SyntaxError(e,epos) => PResult::SyntaxError(e,epos)
+                }
+            }
+        },
+        "declare-fun" | "declare-const" => {
+            let (sym,input4) = match parse_symbol(input3,pos,p) {
+                PResult::Done(sym,ninp) => (sym,ninp),
+                PResult::Incomplete => return PResult::Incomplete,
+                PResult::EmbedError(e) => return PResult::EmbedError(e),
+                PResult::SyntaxError(e,epos) => return PResult::SyntaxError(e,epos)
+            };
+            let (args,input5) = if name=="declare-fun" {
+                match parse_sorts(input4,pos,p) {
+                    PResult::Done(args,ninp) => (args,ninp),
+                    PResult::Incomplete => return PResult::Incomplete,
+                    PResult::EmbedError(e) => return PResult::EmbedError(e),
+                    PResult::SyntaxError(e,epos) => return PResult::SyntaxError(e,epos)
+                }
+            } else {
+                (vec![],input4)
+            };
+            let input6 = eat_ws_comments(input5,pos);
+            match parse_sort(input6,pos,p) {
+                PResult::Done(srt,ninp) => PResult::Done(Command::DeclareFun(sym,args,srt),ninp),
+                PResult::Incomplete => PResult::Incomplete,
+                PResult::EmbedError(e) => PResult::EmbedError(e),
+                PResult::SyntaxError(e,epos) => PResult::SyntaxError(e,epos)
+            }
+        },
+        "define-fun" => parse_define_fun(input3,pos,p),
+        "define-fun-rec" => parse_define_fun_rec(input3,pos,p),
+        "define-funs-rec" => parse_define_funs_rec(input3,pos,p),
+        "define-sort" => {
+            let (sym,input4) = match parse_symbol(input3,pos,p) {
+                PResult::Done(sym,ninp) => (sym,ninp),
+                PResult::Incomplete => return PResult::Incomplete,
+                PResult::EmbedError(e) => return PResult::EmbedError(e),
+                PResult::SyntaxError(e,epos) => return PResult::SyntaxError(e,epos)
+            };
+            let input5 = eat_ws_comments(input4,pos);
+            let (params,input6) = match parse_symbols(input5,pos,p) {
+                PResult::Done(params,ninp) => (params,ninp),
+                PResult::Incomplete => return PResult::Incomplete,
+                PResult::EmbedError(e) => return PResult::EmbedError(e),
+                PResult::SyntaxError(e,epos) => return PResult::SyntaxError(e,epos)
+            };
+            let input7 = eat_ws_comments(input6,pos);
+            match parse_sort_scoped(input7,pos,p,&params) {
+                PResult::Done(srt,ninp) => PResult::Done(Command::DefineSort(sym,params,srt),ninp),
+                PResult::Incomplete => PResult::Incomplete,
+                PResult::EmbedError(e) => PResult::EmbedError(e),
+                PResult::SyntaxError(e,epos) => PResult::SyntaxError(e,epos)
+            }
+        },
+        "declare-datatypes" => match parse_declare_datatypes(input3,pos,p) {
+            PResult::Done(decls,ninp) => PResult::Done(Command::DeclareDatatypes(decls),ninp),
+            PResult::Incomplete => PResult::Incomplete,
+            PResult::EmbedError(e) => PResult::EmbedError(e),
+            PResult::SyntaxError(e,epos) => PResult::SyntaxError(e,epos)
+        },
+        "declare-datatype" => {
+            let (sym,input4) = match parse_symbol(input3,pos,p) {
+                PResult::Done(sym,ninp) => (sym,ninp),
+                PResult::Incomplete => return PResult::Incomplete,
+                PResult::EmbedError(e) => return PResult::EmbedError(e),
+                PResult::SyntaxError(e,epos) => return PResult::SyntaxError(e,epos)
+            };
+            let names = vec![sym];
+            match parse_constructors(input4,pos,p,&names) {
+                PResult::Done((params,cons),ninp) => {
+                    let decl = DatatypeDecl { name: names[0].clone(),
+                                              params: params,
+                                              constructors: cons };
+                    PResult::Done(Command::DeclareDatatypes(vec![decl]),ninp)
+                },
+                PResult::Incomplete => PResult::Incomplete,
+                PResult::EmbedError(e) => PResult::EmbedError(e),
+                PResult::SyntaxError(e,epos) => PResult::SyntaxError(e,epos)
+            }
+        },
+        "push" | "pop" => {
+            let (n,input4) = if input3.len()>0 && input3[0]==b')' {
+                (1,input3)
+            } else {
+                match parse_usize(input3,pos,p) {
+                    PResult::Done(n,ninp) => (n,ninp),
+                    PResult::Incomplete => return PResult::Incomplete,
+                    PResult::EmbedError(e) => return PResult::EmbedError(e),
+                    PResult::SyntaxError(e,epos) => return PResult::SyntaxError(e,epos)
+                }
+            };
+            PResult::Done(if name=="push" {
+                Command::Push(n)
+            } else {
+                Command::Pop(n)
+            },input4)
+        },
+        "assert" => parse_assert(input3,pos,p),
+        "check-sat" => PResult::Done(Command::CheckSat,input3),
+        "check-sat-assuming" => {
+            let bool_srt = match p.tp_bool() {
+                Ok(srt) => srt,
+                Err(e) => return PResult::EmbedError(e)
+            };
+            match parse_exprs(input3,pos,p,Some(&bool_srt)) {
+                PResult::Done(es,ninp) => PResult::Done(Command::CheckSatAssuming(es),ninp),
+                PResult::Incomplete => PResult::Incomplete,
+                PResult::EmbedError(e) => PResult::EmbedError(e),
+                PResult::SyntaxError(e,epos) => PResult::SyntaxError(e,epos)
+            }
+        },
+        "get-value" => match parse_exprs(input3,pos,p,None) {
+            PResult::Done(es,ninp) => PResult::Done(Command::GetValue(es),ninp),
+            PResult::Incomplete => PResult::Incomplete,
+            PResult::EmbedError(e) => PResult::EmbedError(e),
+            PResult::SyntaxError(e,epos) => PResult::SyntaxError(e,epos)
+        },
+        "get-model" => PResult::Done(Command::GetModel,input3),
+        "get-proof" => PResult::Done(Command::GetProof,input3),
+        "get-unsat-core" => PResult::Done(Command::GetUnsatCore,input3),
+        "get-unsat-assumptions" => PResult::Done(Command::GetUnsatAssumptions,input3),
+        "get-assertions" => PResult::Done(Command::GetAssertions,input3),
+        "get-assignment" => PResult::Done(Command::GetAssignment,input3),
+        "reset" => PResult::Done(Command::Reset,input3),
+        "reset-assertions" => PResult::Done(Command::ResetAssertions,input3),
+        "echo" => match parse_sexpr(input3,pos,p) {
+            PResult::Done(SExpr::Str(msg),ninp) => PResult::Done(Command::Echo(msg),ninp),
+            PResult::Done(_,_) => PResult::SyntaxError(ParseError::ExpectedQuote,pos.clone()),
+            PResult::Incomplete => PResult::Incomplete,
+            PResult::EmbedError(e) => PResult::EmbedError(e),
+            PResult::SyntaxError(e,epos) => PResult::SyntaxError(e,epos)
+        },
+        "exit" => PResult::Done(Command::Exit,input3),
+        _ => PResult::SyntaxError(ParseError::UnknownCommand(name.clone()),pos.clone())
+    };
+    match res {
+        PResult::Done(cmd,ninp) => match parse_close(ninp,pos,p) {
+            PResult::Done(_,ninp2) => PResult::Done(cmd,ninp2),
+            PResult::Incomplete => PResult::Incomplete,
+            PResult::EmbedError(e) => PResult::EmbedError(e),
+            PResult::SyntaxError(e,epos) => PResult::SyntaxError(e,epos)
+        },
+        PResult::Incomplete => PResult::Incomplete,
+        PResult::EmbedError(e) => PResult::EmbedError(e),
+        PResult::SyntaxError(e,epos) => PResult::SyntaxError(e,epos)
+    }
+}
+
 #[cfg(test)]
 fn test_parser_() -> Result<(),()> {
     let mut simp = Simple::new();
@@ -1238,3 +4855,224 @@ fn test_parser_() -> Result<(),()> {
 fn test_parser() {
     test_parser_();
 }
+
+#[cfg(test)]
+fn test_parser_float_() -> Result<(),()> {
+    let mut simp = Simple::new();
+    let mut pos = Pos { col: 0, line: 0 };
+
+    let tfloat = simp.tp_float(8,24)?;
+    assert_eq!(parse_sort(b"(_ FloatingPoint 8 24) ",&mut pos,&mut simp),
+               PResult::Done(tfloat.clone(),&b" "[..]));
+    assert_eq!(parse_sort(b"Float32 ",&mut pos,&mut simp),
+               PResult::Done(tfloat.clone(),&b" "[..]));
+    assert_eq!(parse_value(b"(fp #b0 #b10000000 #b00000000000000000000000)",&mut pos,&mut simp,None),
+               PResult::Done(Value::from_f32(2.0),&b""[..]));
+    assert_eq!(parse_value(b"(_ -zero 8 24)",&mut pos,&mut simp,None),
+               PResult::Done(Value::from_f32(-0.0),&b""[..]));
+    assert_eq!(parse_value(b"(_ +oo 11 53)",&mut pos,&mut simp,None),
+               PResult::Done(Value::from_f64(f64::INFINITY),&b""[..]));
+    match parse_value(b"(_ NaN 8 1)",&mut pos,&mut simp,None) {
+        PResult::SyntaxError(ParseError::ExpectedValue,_) => {},
+        _ => return Err(())
+    }
+    match parse_value(b"(_ +oo 8 0)",&mut pos,&mut simp,None) {
+        PResult::SyntaxError(ParseError::ExpectedValue,_) => {},
+        _ => return Err(())
+    }
+    assert_eq!(format!("{}",Value::from_f32(2.0)),
+               "(fp #b0 #b10000000 #b00000000000000000000000)");
+
+    let x = simp.add_var(100,tfloat.clone())?;
+    let y = simp.add_var(101,tfloat)?;
+    let rne = simp.const_rounding_mode(RoundingMode::RNE)?;
+    let sum = simp.fp_add(rne.clone(),x,y)?;
+    assert_eq!(parse_expr(b"(fp.add RNE v100 v101)",&mut pos,&mut simp,None),
+               PResult::Done(sum.clone(),&b""[..]));
+    let conv = simp.to_fp(11,53,Some(rne),sum)?;
+    assert_eq!(parse_expr(b"((_ to_fp 11 53) roundNearestTiesToEven (fp.add RNE v100 v101))",
+                          &mut pos,&mut simp,None),
+               PResult::Done(conv,&b""[..]));
+    Ok(())
+}
+
+#[test]
+fn test_parser_float() {
+    test_parser_float_().unwrap();
+}
+
+#[cfg(test)]
+fn test_parser_datatype_() -> Result<(),()> {
+    let mut simp = Simple::new();
+    let mut pos = Pos { col: 0, line: 0 };
+
+    // (List p0) = nil | cons p0 (List p0)
+    let par = simp.embed_sort(SortKind::Param(0))?;
+    let list_par = simp.tp_datatype(0,vec![par.clone()])?;
+    let list = simp.add_datatype(Datatype { params: 1,
+                                            constructors: vec![vec![],
+                                                               vec![par.clone(),list_par]] });
+    let tint = simp.tp_int()?;
+    let list_int = simp.tp_datatype(list,vec![tint.clone()])?;
+    assert_eq!(parse_sort(b"(d0 Int) ",&mut pos,&mut simp),
+               PResult::Done(list_int.clone(),&b" "[..]));
+    assert_eq!(format!("{}",list_int),"(d0 Int)");
+
+    let nil = Value::Datatype(list_int.clone(),0,vec![]);
+    let one = Value::Datatype(list_int.clone(),1,vec![Value::Int(BigInt::from(1)),nil.clone()]);
+    assert_eq!(parse_value(b"(d0c1 1 (as d0c0 (d0 Int)))",&mut pos,&mut simp,Some(&list_int)),
+               PResult::Done(one.clone(),&b""[..]));
+    assert_eq!(parse_value(b"((as d0c1 (d0 Int)) 1 d0c0)",&mut pos,&mut simp,Some(&list_int)),
+               PResult::Done(one.clone(),&b""[..]));
+    assert_eq!(format!("{}",one),"((as d0c1 (d0 Int)) 1 (as d0c0 (d0 Int)))");
+
+    let x = simp.add_var(100,list_int.clone())?;
+    let head = simp.select_field(1,0,x.clone())?;
+    assert_eq!(parse_expr(b"(d0c1s0 v100)",&mut pos,&mut simp,None),
+               PResult::Done(head.clone(),&b""[..]));
+    let is_cons = simp.is_constructor(1,x.clone())?;
+    assert_eq!(parse_expr(b"((_ is d0c1) v100)",&mut pos,&mut simp,None),
+               PResult::Done(is_cons,&b""[..]));
+    let cons = simp.construct(list,vec![tint],1,vec![head,x])?;
+    assert_eq!(parse_expr(b"((as d0c1 (d0 Int)) (d0c1s0 v100) v100)",&mut pos,&mut simp,None),
+               PResult::Done(cons,&b""[..]));
+
+    // Parametric declarations keep their parameters
+    let decl = b"(declare-datatypes ((Lst 1)) ((par (T) ((nil) (cons (hd T) (tl (Lst T)))))))";
+    match parse_command(decl,&mut pos,&mut simp) {
+        PResult::Done(Command::DeclareDatatypes(decls),_) => {
+            assert_eq!(decls.len(),1);
+            assert_eq!(decls[0].params,vec!["T".to_string()]);
+            let dt = decls[0].datatype();
+            assert_eq!(dt.params,1);
+            assert_eq!(dt.constructors[1],
+                       vec![FieldSort::Sort(par.clone()),
+                            FieldSort::Rec(0,vec![FieldSort::Sort(par.clone())])]);
+        },
+        _ => return Err(())
+    }
+    let bad = b"(declare-datatypes ((Lst 2)) ((par (T) ((nil)))))";
+    match parse_command(bad,&mut pos,&mut simp) {
+        PResult::SyntaxError(ParseError::WrongNumberOfArgs(1,2,false),_) => {},
+        _ => return Err(())
+    }
+    Ok(())
+}
+
+#[test]
+fn test_parser_datatype() {
+    test_parser_datatype_().unwrap();
+}
+
+#[cfg(test)]
+fn test_parser_uninterpreted_() -> Result<(),()> {
+    let mut simp = Simple::new();
+    let mut pos = Pos { col: 0, line: 0 };
+
+    let tint = simp.tp_int()?;
+    let u0 = simp.tp_uninterpreted(0,vec![])?;
+    let u1 = simp.tp_uninterpreted(1,vec![tint])?;
+    assert_eq!(parse_sort(b"u0 ",&mut pos,&mut simp),
+               PResult::Done(u0.clone(),&b" "[..]));
+    assert_eq!(parse_sort(b"(u1 Int) ",&mut pos,&mut simp),
+               PResult::Done(u1.clone(),&b" "[..]));
+    assert_eq!(parse_value(b"(as @uc_u0_0 u0)",&mut pos,&mut simp,Some(&u0)),
+               PResult::Done(Value::Uninterpreted(u0.clone(),"@uc_u0_0".to_string()),&b""[..]));
+    assert_eq!(parse_value(b"u1!val!2)",&mut pos,&mut simp,Some(&u1)),
+               PResult::Done(Value::Uninterpreted(u1.clone(),"u1!val!2".to_string()),&b")"[..]));
+    assert_eq!(format!("{}",Value::Uninterpreted(u1,"u1!val!2".to_string())),
+               "(as u1!val!2 (u1 Int))");
+    Ok(())
+}
+
+#[test]
+fn test_parser_uninterpreted() {
+    test_parser_uninterpreted_().unwrap();
+}
+
+#[cfg(test)]
+fn test_parser_arrays_() -> Result<(),()> {
+    let mut simp : Simple<u64> = Simple::new();
+    let mut pos = Pos { col: 0, line: 0 };
+
+    let tint = simp.tp_int()?;
+    let tbool = simp.tp_bool()?;
+    let arr = simp.tp_array(vec![tint.clone()],tint.clone())?;
+    let arr2 = simp.tp_array(vec![tint.clone(),tbool.clone()],tint.clone())?;
+    let int = |v: i64| Value::Int(BigInt::from(v));
+
+    let stored = Value::array(vec![tint.clone()],tint.clone(),int(0),
+                              vec![(vec![int(1)],int(2)),(vec![int(3)],int(-4))]);
+    let inp = b"(store (store ((as const (Array Int Int)) 0) 1 2) 3 (- 4)))";
+    assert_eq!(parse_value(inp,&mut pos,&mut simp,Some(&arr)),
+               PResult::Done(stored.clone(),&b")"[..]));
+    assert_eq!(format!("{}",stored),
+               "(store (store ((as const (Array Int Int)) 0) 1 2) 3 (- 4))");
+    assert_eq!(stored.sort(&mut simp),Ok(arr.clone()));
+
+    let lambda = b"(lambda ((x Int)) (ite (= x 3) (- 4) (ite (= 1 x) 2 (ite (= x 3) 5 0))))";
+    assert_eq!(parse_value(lambda,&mut pos,&mut simp,Some(&arr)),
+               PResult::Done(stored.clone(),&b""[..]));
+
+    // Multi-index arrays are nested
+    let mut nested = Value::array(vec![tint.clone(),tbool.clone()],tint.clone(),int(5),vec![]);
+    assert_eq!(parse_value(b"((as const (Array Int Bool Int)) 5)",&mut pos,&mut simp,Some(&arr2)),
+               PResult::Done(nested.clone(),&b""[..]));
+    assert_eq!(nested.sort(&mut simp),Ok(arr2.clone()));
+    nested.store(&[int(1),Value::Bool(true)],int(7));
+    assert_eq!(parse_value(b"(store ((as const (Array Int Bool Int)) 5) 1 true 7)",
+                           &mut pos,&mut simp,Some(&arr2)),
+               PResult::Done(nested.clone(),&b""[..]));
+    assert_eq!(format!("{}",nested),
+               "(lambda ((i0 Int) (i1 Bool)) (ite (= i0 1) (ite (= i1 true) 7 5) 5))");
+
+    // Models refer to auxiliary functions via as-array
+    let model = b"(model\n  (define-fun x12 () (Array Int Int)\n    (_ as-array k!0))\n  \
+                  (define-fun k!0 ((x!0 Int)) Int\n    (ite (= x!0 3) (- 4) (ite (= x!0 1) 2 0)))\n  \
+                  (define-fun x13 () (Array Int Int)\n    ((as const (Array Int Int)) 0))\n)";
+    match parse_model(model,&mut pos,&mut simp) {
+        PResult::Done(m,_) => {
+            assert_eq!(m.vars.len(),2);
+            assert_eq!(m.value(&13),Some(&Value::array(vec![tint.clone()],tint.clone(),int(0),vec![])));
+            assert_eq!(m.value(&12),Some(&stored));
+        },
+        _ => return Err(())
+    }
+    Ok(())
+}
+
+#[test]
+fn test_parser_arrays() {
+    test_parser_arrays_().unwrap();
+}
+
+#[cfg(test)]
+fn test_parser_errors_() -> Result<(),()> {
+    let mut simp : Simple<u64> = Simple::new();
+    let mut pos = Pos { col: 0, line: 0 };
+
+    let inp = b"(echo \"a\nb\")\n(x)";
+    assert_eq!(parse_sexpr(inp,&mut pos,&mut simp),
+               PResult::Done(SExpr::List(vec![SExpr::Atom("echo".to_string()),
+                                              SExpr::Str("a\nb".to_string())]),
+                             &b"\n(x)"[..]));
+    assert_eq!(pos,Pos { line: 1, col: 3 });
+
+    let inp = b"(declare-fun x\n\t()\n\tStrng)";
+    pos = Pos { col: 0, line: 0 };
+    match parse_command(inp,&mut pos,&mut simp) {
+        PResult::SyntaxError(err,epos) => {
+            let err = SyntaxError::new(err,epos,inp);
+            assert_eq!(err.pos,Pos { line: 2, col: 1 });
+            assert_eq!(err.token,"Strng");
+            assert_eq!(format!("{}",err),"3:2: expected a sort\n\tStrng)\n\t^~~~~");
+        },
+        _ => return Err(())
+    }
+    Ok(())
+}
+
+#[test]
+fn test_parser_errors() {
+    test_parser_errors_().unwrap();
+}
@@ -0,0 +1,144 @@
+use backend::{InterpolationDialect,QeDialect};
+
+/// Deviations of a solver from the SMT-LIB standard.
+#[derive(PartialEq,Eq,Hash,Clone,Copy,Debug)]
+pub struct Quirks {
+    /// Symbols can only be declared after a `set-logic`
+    pub requires_logic: bool
+}
+
+/// Describes how to start a solver and which parts of the SMT-LIB
+/// language it understands. The responses to `get-value` and `get-model`
+/// need no description: all supported solvers answer `get-value` with a
+/// list of pairs, and models are accepted both as `(model ...)` and as a
+/// bare list of definitions.
+#[derive(PartialEq,Eq,Hash,Clone,Debug)]
+pub struct SolverProfile {
+    pub name: &'static str,
+    pub binary: &'static str,
+    /// Arguments needed to read SMT-LIB commands from stdin incrementally
+    pub args: &'static [&'static str],
+    /// The supported options (`:produce-models`, ...)
+    pub options: &'static [&'static str],
+    pub interpolation: Option<InterpolationDialect>,
+    pub qe: Option<QeDialect>,
+    /// Understands `(simplify ...)`
+    pub simplify: bool,
+    pub quirks: Quirks
+}
+
+impl SolverProfile {
+    pub fn supports_option(&self,opt: &str) -> bool {
+        self.options.iter().any(|o| *o==opt)
+    }
+    /// Find a built-in profile by its (case insensitive) name.
+    pub fn by_name(name: &str) -> Option<&'static SolverProfile> {
+        let lname = name.to_lowercase();
+        for profile in BUILTIN.iter() {
+            if profile.name.to_lowercase()==lname {
+                return Some(*profile)
+            }
+        }
+        None
+    }
+}
+
+pub static Z3: SolverProfile = SolverProfile {
+    name: "Z3",
+    binary: "z3",
+    args: &["-smt2","-in"],
+    options: &[":produce-models",":produce-proofs",":produce-unsat-cores",
+               ":produce-unsat-assumptions",":print-success",":timeout",":rlimit"],
+    interpolation: None,
+    qe: Some(QeDialect::ApplyQe),
+    simplify: true,
+    quirks: Quirks { requires_logic: false }
+};
+
+pub static CVC4: SolverProfile = SolverProfile {
+    name: "CVC4",
+    binary: "cvc4",
+    args: &["--lang=smt2","--incremental"],
+    options: &[":produce-models",":produce-proofs",":produce-unsat-cores",
+               ":produce-unsat-assumptions",":print-success"],
+    interpolation: None,
+    qe: Some(QeDialect::GetQe),
+    simplify: true,
+    quirks: Quirks { requires_logic: false }
+};
+
+pub static CVC5: SolverProfile = SolverProfile {
+    name: "cvc5",
+    binary: "cvc5",
+    args: &["--lang=smt2","--incremental"],
+    options: &[":produce-models",":produce-proofs",":produce-unsat-cores",
+               ":produce-unsat-assumptions",":produce-interpolants",":print-success"],
+    interpolation: Some(InterpolationDialect::GetInterpol),
+    qe: Some(QeDialect::GetQe),
+    simplify: true,
+    quirks: Quirks { requires_logic: false }
+};
+
+pub static YICES2: SolverProfile = SolverProfile {
+    name: "Yices",
+    binary: "yices-smt2",
+    args: &["--incremental","--smt2-model-format"],
+    options: &[":produce-models",":produce-unsat-cores",
+               ":produce-unsat-assumptions",":print-success"],
+    interpolation: None,
+    qe: None,
+    simplify: false,
+    quirks: Quirks { requires_logic: true }
+};
+
+pub static MATHSAT: SolverProfile = SolverProfile {
+    name: "MathSAT5",
+    binary: "mathsat",
+    args: &[],
+    options: &[":produce-models",":produce-proofs",":produce-unsat-cores",
+               ":produce-interpolants",":print-success"],
+    interpolation: Some(InterpolationDialect::GetInterpolants),
+    qe: None,
+    simplify: false,
+    quirks: Quirks { requires_logic: false }
+};
+
+pub static BOOLECTOR: SolverProfile = SolverProfile {
+    name: "Boolector",
+    binary: "boolector",
+    args: &["--smt2","--incremental"],
+    options: &[":produce-models",":produce-unsat-assumptions",":print-success"],
+    interpolation: None,
+    qe: None,
+    simplify: false,
+    quirks: Quirks { requires_logic: false }
+};
+
+pub static BITWUZLA: SolverProfile = SolverProfile {
+    name: "Bitwuzla",
+    binary: "bitwuzla",
+    args: &["--lang","smt2"],
+    options: &[":produce-models",":produce-unsat-cores",
+               ":produce-unsat-assumptions",":print-success"],
+    interpolation: None,
+    qe: None,
+    simplify: false,
+    quirks: Quirks { requires_logic: false }
+};
+
+pub static SMTINTERPOL: SolverProfile = SolverProfile {
+    name: "SMTInterpol",
+    binary: "smtinterpol",
+    args: &["-q"],
+    options: &[":produce-models",":produce-proofs",":produce-unsat-cores",
+               ":produce-unsat-assumptions",":produce-interpolants",":print-success",
+               ":timeout"],
+    interpolation: Some(InterpolationDialect::GetInterpolants),
+    qe: None,
+    simplify: false,
+    quirks: Quirks { requires_logic: true }
+};
+
+/// All built-in profiles.
+pub static BUILTIN: [&'static SolverProfile;8] = [&Z3,&CVC4,&CVC5,&YICES2,&MATHSAT,
+                                                  &BOOLECTOR,&BITWUZLA,&SMTINTERPOL];
@@ -0,0 +1,337 @@
+//! Incremental reading of S-expressions from a solver.
+//!
+//! The `SExprReader` buffers the output of a solver and finds the ends of
+//! complete top-level S-expressions without re-scanning data it has already
+//! seen. Bytes which are not consumed by one response stay in the buffer and
+//! are used for the next one.
+
+use parser::{Parser,SExpr,SyntaxError,PResult,Pos,parse_sexpr};
+use std::io;
+use std::io::Read;
+use std::fmt;
+#[cfg(test)]
+use test::Simple;
+
+/// How many bytes are requested from the underlying reader at once.
+const READ_SIZE: usize = 1024;
+
+#[derive(Clone,Copy,PartialEq,Eq,Debug)]
+enum ScanState {
+    /// Between two tokens
+    Space,
+    /// Inside a comment
+    Comment,
+    /// Inside an atom
+    Atom,
+    /// Inside a string literal
+    Str,
+    /// After a quote inside a string literal, which either ends the string
+    /// or starts an escaped quote
+    StrQuote,
+    /// Inside a `|`-quoted symbol
+    Quoted
+}
+
+pub struct SExprReader {
+    buf: Vec<u8>,
+    /// Start of the data which hasn't been consumed yet
+    start: usize,
+    /// End of the complete top-level expressions found so far
+    complete: usize,
+    /// How far the buffer has been scanned
+    scan: usize,
+    /// Nesting depth of lists at the scan position
+    depth: usize,
+    state: ScanState,
+    /// Whether the last read reached the end of the input
+    closed: bool
+}
+
+#[derive(Debug)]
+pub enum ReadError<S,E> {
+    IO(io::Error),
+    Syntax(SyntaxError<S>),
+    Embed(E)
+}
+
+impl SExprReader {
+    pub fn new() -> Self {
+        SExprReader { buf: Vec::new(),
+                      start: 0,
+                      complete: 0,
+                      scan: 0,
+                      depth: 0,
+                      state: ScanState::Space,
+                      closed: false }
+    }
+    /// Append data to the buffer.
+    pub fn feed(&mut self,data: &[u8]) {
+        self.compact();
+        self.buf.extend_from_slice(data);
+        self.closed = false;
+    }
+    /// Read more data into the buffer. Returns the number of bytes read,
+    /// zero meaning that the end of the input is reached.
+    pub fn fill<R : Read>(&mut self,r: &mut R) -> io::Result<usize> {
+        self.compact();
+        let len = self.buf.len();
+        self.buf.resize(len+READ_SIZE,0);
+        match r.read(&mut self.buf[len..]) {
+            Ok(sz) => {
+                self.buf.truncate(len+sz);
+                self.closed = sz==0;
+                Ok(sz)
+            },
+            Err(e) => {
+                self.buf.truncate(len);
+                Err(e)
+            }
+        }
+    }
+    /// Move the unconsumed data to the front of the buffer.
+    fn compact(&mut self) {
+        if self.start>0 {
+            self.buf.drain(0..self.start);
+            self.complete-=self.start;
+            self.scan-=self.start;
+            self.start = 0;
+        }
+    }
+    /// Scan the buffer for the end of the next complete top-level
+    /// expression. Returns `false` if the buffered data doesn't contain one.
+    pub fn next_item(&mut self) -> bool {
+        while self.scan<self.buf.len() {
+            let c = self.buf[self.scan];
+            match self.state {
+                ScanState::Comment => if c==b'\n' {
+                    self.state = ScanState::Space
+                },
+                ScanState::Str => if c==b'"' {
+                    self.state = ScanState::StrQuote
+                },
+                ScanState::StrQuote => if c==b'"' {
+                    self.state = ScanState::Str
+                } else {
+                    // The string ended before this character
+                    self.state = ScanState::Space;
+                    if self.depth==0 {
+                        self.complete = self.scan;
+                        return true
+                    }
+                    continue
+                },
+                ScanState::Quoted => if c==b'|' {
+                    self.state = ScanState::Space;
+                    if self.depth==0 {
+                        self.scan+=1;
+                        self.complete = self.scan;
+                        return true
+                    }
+                },
+                ScanState::Atom => match c {
+                    b' ' | b'\t' | b'\r' | b'\n' | b'(' | b')' | b'"' => {
+                        self.state = ScanState::Space;
+                        if self.depth==0 {
+                            self.complete = self.scan;
+                            return true
+                        }
+                        continue
+                    },
+                    _ => {}
+                },
+                ScanState::Space => match c {
+                    b' ' | b'\t' | b'\r' | b'\n' => {},
+                    b';' => self.state = ScanState::Comment,
+                    b'"' => self.state = ScanState::Str,
+                    b'|' => self.state = ScanState::Quoted,
+                    b'(' => self.depth+=1,
+                    b')' => {
+                        // A stray closing parenthesis is an item of its own,
+                        // so that parsing it reports the error
+                        if self.depth>0 {
+                            self.depth-=1;
+                        }
+                        if self.depth==0 {
+                            self.scan+=1;
+                            self.complete = self.scan;
+                            return true
+                        }
+                    },
+                    _ => self.state = ScanState::Atom
+                }
+            }
+            self.scan+=1;
+        }
+        // At the end of the input, a trailing atom or string is complete.
+        // It is terminated so that parsers can see where it ends.
+        if self.closed && self.depth==0 &&
+            (self.state==ScanState::Atom || self.state==ScanState::StrQuote) {
+                self.state = ScanState::Space;
+                self.complete = self.scan;
+                self.buf.push(b'\n');
+                return true
+            }
+        false
+    }
+    /// Read from `r` until the next complete top-level expression is
+    /// buffered. Returns `false` if the input ends before that.
+    pub fn read_item<R : Read>(&mut self,r: &mut R) -> io::Result<bool> {
+        while !self.next_item() {
+            if self.closed {
+                self.closed = false;
+                return Ok(false)
+            }
+            self.fill(r)?;
+        }
+        Ok(true)
+    }
+    /// The buffered data which hasn't been consumed yet. It contains all
+    /// complete expressions found by `next_item`, possibly followed by
+    /// incomplete ones.
+    pub fn pending(&self) -> &[u8] {
+        &self.buf[self.start..]
+    }
+    /// Whether complete expressions are buffered which haven't been
+    /// consumed yet.
+    pub fn has_complete(&self) -> bool {
+        self.buf[self.start..self.complete].iter()
+            .any(|c| *c!=b' ' && *c!=b'\t' && *c!=b'\r' && *c!=b'\n')
+    }
+    /// Mark the first `n` bytes of the pending data as consumed.
+    pub fn consume(&mut self,n: usize) {
+        self.start+=n;
+        if self.start>self.complete {
+            self.complete = self.start;
+        }
+        if self.start>self.scan {
+            self.scan = self.start;
+            self.depth = 0;
+            self.state = ScanState::Space;
+        }
+    }
+    /// Mark all complete expressions found so far as consumed, for example
+    /// because a response could not be parsed.
+    pub fn consume_complete(&mut self) {
+        let n = self.complete-self.start;
+        self.consume(n);
+    }
+    /// Consume whitespace and comments at the start of the pending data.
+    pub fn skip_space(&mut self) {
+        let mut off = 0;
+        {
+            let inp = self.pending();
+            while off<inp.len() {
+                match inp[off] {
+                    b' ' | b'\t' | b'\r' | b'\n' => off+=1,
+                    b';' => match inp[off..].iter().position(|c| *c==b'\n') {
+                        Some(end) => off+=end+1,
+                        None => break
+                    },
+                    _ => break
+                }
+            }
+        }
+        self.consume(off);
+    }
+    /// Throw away all buffered data.
+    pub fn clear(&mut self) {
+        self.buf.clear();
+        self.start = 0;
+        self.complete = 0;
+        self.scan = 0;
+        self.depth = 0;
+        self.state = ScanState::Space;
+    }
+    /// Take the next complete expression from the buffer, if there is one.
+    pub fn next_sexpr<P : Parser>(&mut self,p: &mut P)
+                                  -> Result<Option<SExpr>,ReadError<P::Sort,P::Error>> {
+        if !self.has_complete() && !self.next_item() {
+            return Ok(None)
+        }
+        let mut pos = Pos { line: 0, col: 0 };
+        let (res,n) = {
+            let inp = self.pending();
+            match parse_sexpr(inp,&mut pos,p) {
+                PResult::Done(e,ninp) => (Ok(Some(e)),inp.len()-ninp.len()),
+                // Only whitespace and comments are complete
+                PResult::Incomplete => (Ok(None),0),
+                PResult::SyntaxError(err,epos)
+                    => (Err(ReadError::Syntax(SyntaxError::new(err,epos,inp))),0),
+                PResult::EmbedError(err) => (Err(ReadError::Embed(err)),0)
+            }
+        };
+        match res {
+            Ok(Some(_)) => self.consume(n),
+            _ => self.consume_complete()
+        }
+        res
+    }
+    /// Read the next expression from `r`. Returns `None` if the input ends
+    /// before a complete expression.
+    pub fn read_sexpr<R : Read,P : Parser>(&mut self,r: &mut R,p: &mut P)
+                                           -> Result<Option<SExpr>,ReadError<P::Sort,P::Error>> {
+        if !self.has_complete() && !self.read_item(r)? {
+            return Ok(None)
+        }
+        self.next_sexpr(p)
+    }
+}
+
+impl<S,E> From<io::Error> for ReadError<S,E> {
+    fn from(err: io::Error) -> ReadError<S,E> { ReadError::IO(err) }
+}
+
+impl<S,E> From<SyntaxError<S>> for ReadError<S,E> {
+    fn from(err: SyntaxError<S>) -> ReadError<S,E> { ReadError::Syntax(err) }
+}
+
+impl<S : fmt::Display,E : fmt::Debug> fmt::Display for ReadError<S,E> {
+    fn fmt(&self,f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ReadError::IO(ref e) => write!(f,"{}",e),
+            ReadError::Syntax(ref e) => write!(f,"{}",e),
+            ReadError::Embed(ref e) => write!(f,"{:?}",e)
+        }
+    }
+}
+
+#[test]
+fn test_reader_split() {
+    let mut simp : Simple<u64> = Simple::new();
+    let mut rd = SExprReader::new();
+    rd.feed(b"success\n(model (define-fun x () Int");
+    assert!(rd.next_item());
+    rd.consume(8);
+    assert!(!rd.next_item());
+    rd.feed(b" 5)) ; a ) comment\n \"str\"\"ing\" |a )b| (");
+    assert_eq!(rd.next_sexpr(&mut simp).unwrap(),
+               Some(SExpr::List(vec![SExpr::Atom(String::from("model")),
+                                     SExpr::List(vec![SExpr::Atom(String::from("define-fun")),
+                                                      SExpr::Atom(String::from("x")),
+                                                      SExpr::List(vec![]),
+                                                      SExpr::Atom(String::from("Int")),
+                                                      SExpr::Atom(String::from("5"))])])));
+    assert_eq!(rd.next_sexpr(&mut simp).unwrap(),Some(SExpr::Str(String::from("str\"ing"))));
+    assert_eq!(rd.next_sexpr(&mut simp).unwrap(),Some(SExpr::Atom(String::from("|a )b|"))));
+    assert_eq!(rd.next_sexpr(&mut simp).unwrap(),None);
+    // The unfinished list is kept for later
+    assert_eq!(rd.pending(),b" (");
+}
+
+#[test]
+fn test_reader_read() {
+    let mut inp = &b"sat\n(error \"x\")\n)\nunknown"[..];
+    let mut simp : Simple<u64> = Simple::new();
+    let mut rd = SExprReader::new();
+    assert_eq!(rd.read_sexpr(&mut inp,&mut simp).unwrap(),Some(SExpr::Atom(String::from("sat"))));
+    assert_eq!(rd.read_sexpr(&mut inp,&mut simp).unwrap(),
+               Some(SExpr::List(vec![SExpr::Atom(String::from("error")),
+                                     SExpr::Str(String::from("x"))])));
+    match rd.read_sexpr(&mut inp,&mut simp) {
+        Err(ReadError::Syntax(err)) => assert_eq!(err.pos,Pos { line: 1, col: 0 }),
+        _ => panic!("Expected a syntax error")
+    }
+    // A trailing atom is complete at the end of the input
+    assert_eq!(rd.read_sexpr(&mut inp,&mut simp).unwrap(),Some(SExpr::Atom(String::from("unknown"))));
+    assert_eq!(rd.read_sexpr(&mut inp,&mut simp).unwrap(),None);
+}
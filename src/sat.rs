@@ -0,0 +1,573 @@
+use std::ops::Not;
+use std::mem;
+
+/// A propositional literal, i.e. a variable or its negation.
+#[derive(PartialEq,Eq,PartialOrd,Ord,Hash,Clone,Copy,Debug)]
+pub struct Lit(u32);
+
+impl Lit {
+    pub fn new(var: usize,positive: bool) -> Lit {
+        Lit(((var as u32) << 1) | (if positive { 0 } else { 1 }))
+    }
+    pub fn var(&self) -> usize {
+        (self.0 >> 1) as usize
+    }
+    pub fn is_positive(&self) -> bool {
+        self.0 & 1 == 0
+    }
+    fn index(&self) -> usize {
+        self.0 as usize
+    }
+}
+
+impl Not for Lit {
+    type Output = Lit;
+    fn not(self) -> Lit {
+        Lit(self.0 ^ 1)
+    }
+}
+
+#[derive(PartialEq,Eq,Clone,Copy,Debug)]
+enum LBool {
+    True, False, Undef
+}
+
+/// A max-heap of variables ordered by their activity.
+struct VarOrder {
+    heap: Vec<usize>,
+    indices: Vec<Option<usize>>
+}
+
+impl VarOrder {
+    fn new() -> VarOrder {
+        VarOrder { heap: Vec::new(),
+                   indices: Vec::new() }
+    }
+    fn contains(&self,var: usize) -> bool {
+        self.indices[var].is_some()
+    }
+    fn insert(&mut self,var: usize,act: &[f64]) {
+        while self.indices.len()<=var {
+            self.indices.push(None);
+        }
+        if self.contains(var) {
+            return
+        }
+        let pos = self.heap.len();
+        self.heap.push(var);
+        self.indices[var] = Some(pos);
+        self.up(pos,act);
+    }
+    /// Restore the heap property after the activity of a variable increased.
+    fn bumped(&mut self,var: usize,act: &[f64]) {
+        if let Some(pos) = self.indices[var] {
+            self.up(pos,act);
+        }
+    }
+    fn pop(&mut self,act: &[f64]) -> Option<usize> {
+        if self.heap.len()==0 {
+            return None
+        }
+        let top = self.heap.swap_remove(0);
+        self.indices[top] = None;
+        if self.heap.len()>0 {
+            self.indices[self.heap[0]] = Some(0);
+            self.down(0,act);
+        }
+        Some(top)
+    }
+    fn up(&mut self,mut pos: usize,act: &[f64]) {
+        let var = self.heap[pos];
+        while pos>0 {
+            let parent = (pos-1)/2;
+            if act[self.heap[parent]]>=act[var] {
+                break
+            }
+            self.heap[pos] = self.heap[parent];
+            self.indices[self.heap[pos]] = Some(pos);
+            pos = parent;
+        }
+        self.heap[pos] = var;
+        self.indices[var] = Some(pos);
+    }
+    fn down(&mut self,mut pos: usize,act: &[f64]) {
+        let var = self.heap[pos];
+        loop {
+            let left = 2*pos+1;
+            if left>=self.heap.len() {
+                break
+            }
+            let right = left+1;
+            let child = if right<self.heap.len() && act[self.heap[right]]>act[self.heap[left]] {
+                right
+            } else {
+                left
+            };
+            if act[self.heap[child]]<=act[var] {
+                break
+            }
+            self.heap[pos] = self.heap[child];
+            self.indices[self.heap[pos]] = Some(pos);
+            pos = child;
+        }
+        self.heap[pos] = var;
+        self.indices[var] = Some(pos);
+    }
+}
+
+/// An incremental CDCL SAT solver with two watched literals, conflict
+/// analysis using the first unique implication point, activity based
+/// branching with phase saving and Luby restarts. Clauses can be added
+/// between calls to `solve`, which can be given a set of assumptions.
+pub struct Solver {
+    clauses: Vec<Vec<Lit>>,
+    /// For every literal, the clauses in which its negation is watched
+    watches: Vec<Vec<usize>>,
+    assigns: Vec<LBool>,
+    level: Vec<usize>,
+    reason: Vec<Option<usize>>,
+    trail: Vec<Lit>,
+    trail_lim: Vec<usize>,
+    qhead: usize,
+    activity: Vec<f64>,
+    var_inc: f64,
+    order: VarOrder,
+    phase: Vec<bool>,
+    seen: Vec<bool>,
+    /// False once the clauses are unsatisfiable without any assumptions
+    ok: bool,
+    model: Vec<bool>,
+    failed: Vec<Lit>,
+    decisions: u64,
+    conflicts: u64,
+    propagations: u64
+}
+
+const VAR_DECAY: f64 = 0.95;
+const RESTART_BASE: u64 = 100;
+
+/// The i-th element of the Luby sequence (1,1,2,1,1,2,4,...).
+fn luby(mut i: u64) -> u64 {
+    let mut size = 1;
+    let mut seq = 0;
+    while size<i+1 {
+        seq+=1;
+        size = 2*size+1;
+    }
+    while size-1!=i {
+        size = (size-1)/2;
+        seq-=1;
+        i = i % size;
+    }
+    1 << seq
+}
+
+impl Solver {
+    pub fn new() -> Solver {
+        Solver { clauses: Vec::new(),
+                 watches: Vec::new(),
+                 assigns: Vec::new(),
+                 level: Vec::new(),
+                 reason: Vec::new(),
+                 trail: Vec::new(),
+                 trail_lim: Vec::new(),
+                 qhead: 0,
+                 activity: Vec::new(),
+                 var_inc: 1.0,
+                 order: VarOrder::new(),
+                 phase: Vec::new(),
+                 seen: Vec::new(),
+                 ok: true,
+                 model: Vec::new(),
+                 failed: Vec::new(),
+                 decisions: 0,
+                 conflicts: 0,
+                 propagations: 0 }
+    }
+    pub fn new_var(&mut self) -> usize {
+        let var = self.assigns.len();
+        self.assigns.push(LBool::Undef);
+        self.level.push(0);
+        self.reason.push(None);
+        self.activity.push(0.0);
+        self.phase.push(false);
+        self.seen.push(false);
+        self.watches.push(Vec::new());
+        self.watches.push(Vec::new());
+        self.order.insert(var,&self.activity);
+        var
+    }
+    pub fn num_vars(&self) -> usize {
+        self.assigns.len()
+    }
+    pub fn num_clauses(&self) -> usize {
+        self.clauses.len()
+    }
+    pub fn decisions(&self) -> u64 {
+        self.decisions
+    }
+    pub fn conflicts(&self) -> u64 {
+        self.conflicts
+    }
+    pub fn propagations(&self) -> u64 {
+        self.propagations
+    }
+    fn value(&self,lit: Lit) -> LBool {
+        match self.assigns[lit.var()] {
+            LBool::Undef => LBool::Undef,
+            LBool::True => if lit.is_positive() { LBool::True } else { LBool::False },
+            LBool::False => if lit.is_positive() { LBool::False } else { LBool::True }
+        }
+    }
+    fn decision_level(&self) -> usize {
+        self.trail_lim.len()
+    }
+    fn enqueue(&mut self,lit: Lit,reason: Option<usize>) {
+        let var = lit.var();
+        self.assigns[var] = if lit.is_positive() { LBool::True } else { LBool::False };
+        self.level[var] = self.decision_level();
+        self.reason[var] = reason;
+        self.trail.push(lit);
+    }
+    fn attach(&mut self,clause: Vec<Lit>) -> usize {
+        let idx = self.clauses.len();
+        self.watches[(!clause[0]).index()].push(idx);
+        self.watches[(!clause[1]).index()].push(idx);
+        self.clauses.push(clause);
+        idx
+    }
+    /// Add a clause. Returns false if the clauses became unsatisfiable.
+    pub fn add_clause(&mut self,lits: &[Lit]) -> bool {
+        if !self.ok {
+            return false
+        }
+        debug_assert_eq!(self.decision_level(),0);
+        let mut clause = lits.to_vec();
+        clause.sort();
+        clause.dedup();
+        let mut simplified = Vec::with_capacity(clause.len());
+        for (i,&lit) in clause.iter().enumerate() {
+            if i>0 && clause[i-1]==!lit {
+                // Tautology
+                return true
+            }
+            match self.value(lit) {
+                LBool::True => return true,
+                LBool::False => {},
+                LBool::Undef => simplified.push(lit)
+            }
+        }
+        match simplified.len() {
+            0 => { self.ok = false; },
+            1 => {
+                self.enqueue(simplified[0],None);
+                self.ok = self.propagate().is_none();
+            },
+            _ => { self.attach(simplified); }
+        }
+        self.ok
+    }
+    /// Propagate all enqueued literals, returning a conflicting clause.
+    fn propagate(&mut self) -> Option<usize> {
+        while self.qhead<self.trail.len() {
+            let p = self.trail[self.qhead];
+            self.qhead+=1;
+            self.propagations+=1;
+            let false_lit = !p;
+            let mut ws = mem::replace(&mut self.watches[p.index()],Vec::new());
+            let mut i = 0;
+            let mut j = 0;
+            let mut conflict = None;
+            while i<ws.len() {
+                let ci = ws[i];
+                i+=1;
+                if self.clauses[ci][0]==false_lit {
+                    self.clauses[ci].swap(0,1);
+                }
+                let first = self.clauses[ci][0];
+                if self.value(first)==LBool::True {
+                    ws[j] = ci;
+                    j+=1;
+                    continue
+                }
+                let mut found = false;
+                for k in 2..self.clauses[ci].len() {
+                    let lit = self.clauses[ci][k];
+                    if self.value(lit)!=LBool::False {
+                        self.clauses[ci].swap(1,k);
+                        self.watches[(!lit).index()].push(ci);
+                        found = true;
+                        break
+                    }
+                }
+                if found {
+                    continue
+                }
+                ws[j] = ci;
+                j+=1;
+                if self.value(first)==LBool::False {
+                    conflict = Some(ci);
+                    self.qhead = self.trail.len();
+                    while i<ws.len() {
+                        ws[j] = ws[i];
+                        j+=1;
+                        i+=1;
+                    }
+                } else {
+                    self.enqueue(first,Some(ci));
+                }
+            }
+            ws.truncate(j);
+            self.watches[p.index()] = ws;
+            if conflict.is_some() {
+                return conflict
+            }
+        }
+        None
+    }
+    fn bump(&mut self,var: usize) {
+        self.activity[var]+=self.var_inc;
+        if self.activity[var]>1e100 {
+            for act in self.activity.iter_mut() {
+                *act*=1e-100;
+            }
+            self.var_inc*=1e-100;
+        }
+        self.order.bumped(var,&self.activity);
+    }
+    /// Derive a clause from a conflict, returning it with the level to
+    /// backtrack to. The first literal of the clause is the asserting one.
+    fn analyze(&mut self,mut conflict: usize) -> (Vec<Lit>,usize) {
+        let mut learnt = vec![Lit(0)];
+        let mut open = 0;
+        let mut p : Option<Lit> = None;
+        let mut index = self.trail.len();
+        loop {
+            let start = if p.is_none() { 0 } else { 1 };
+            for k in start..self.clauses[conflict].len() {
+                let q = self.clauses[conflict][k];
+                let var = q.var();
+                if !self.seen[var] && self.level[var]>0 {
+                    self.bump(var);
+                    self.seen[var] = true;
+                    if self.level[var]>=self.decision_level() {
+                        open+=1;
+                    } else {
+                        learnt.push(q);
+                    }
+                }
+            }
+            loop {
+                index-=1;
+                if self.seen[self.trail[index].var()] {
+                    break
+                }
+            }
+            let lit = self.trail[index];
+            p = Some(lit);
+            self.seen[lit.var()] = false;
+            open-=1;
+            if open==0 {
+                break
+            }
+            conflict = self.reason[lit.var()].expect("Implied literal without reason");
+        }
+        learnt[0] = !p.unwrap();
+        for lit in learnt[1..].iter() {
+            self.seen[lit.var()] = false;
+        }
+        let mut bt_level = 0;
+        if learnt.len()>1 {
+            let mut max = 1;
+            for k in 2..learnt.len() {
+                if self.level[learnt[k].var()]>self.level[learnt[max].var()] {
+                    max = k;
+                }
+            }
+            learnt.swap(1,max);
+            bt_level = self.level[learnt[1].var()];
+        }
+        (learnt,bt_level)
+    }
+    /// Compute the assumptions responsible for the given assumption being
+    /// false.
+    fn analyze_final(&mut self,failed: Lit) {
+        self.failed = vec![failed];
+        if self.decision_level()==0 {
+            return
+        }
+        self.seen[failed.var()] = true;
+        for i in (self.trail_lim[0]..self.trail.len()).rev() {
+            let var = self.trail[i].var();
+            if self.seen[var] {
+                match self.reason[var] {
+                    None => if self.level[var]>0 {
+                        self.failed.push(self.trail[i]);
+                    },
+                    Some(ci) => for k in 1..self.clauses[ci].len() {
+                        let v = self.clauses[ci][k].var();
+                        if self.level[v]>0 {
+                            self.seen[v] = true;
+                        }
+                    }
+                }
+                self.seen[var] = false;
+            }
+        }
+        self.seen[failed.var()] = false;
+    }
+    fn cancel_until(&mut self,level: usize) {
+        if self.decision_level()<=level {
+            return
+        }
+        for i in (self.trail_lim[level]..self.trail.len()).rev() {
+            let lit = self.trail[i];
+            let var = lit.var();
+            self.assigns[var] = LBool::Undef;
+            self.reason[var] = None;
+            self.phase[var] = lit.is_positive();
+            self.order.insert(var,&self.activity);
+        }
+        self.trail.truncate(self.trail_lim[level]);
+        self.trail_lim.truncate(level);
+        self.qhead = self.trail.len();
+    }
+    fn pick_branch(&mut self) -> Option<Lit> {
+        while let Some(var) = self.order.pop(&self.activity) {
+            if self.assigns[var]==LBool::Undef {
+                return Some(Lit::new(var,self.phase[var]))
+            }
+        }
+        None
+    }
+    /// Search for a model until the given number of conflicts is reached.
+    fn search(&mut self,budget: u64,assumptions: &[Lit]) -> Option<bool> {
+        let mut conflicts = 0;
+        loop {
+            if let Some(conflict) = self.propagate() {
+                self.conflicts+=1;
+                conflicts+=1;
+                if self.decision_level()==0 {
+                    self.ok = false;
+                    self.failed.clear();
+                    return Some(false)
+                }
+                let (learnt,bt_level) = self.analyze(conflict);
+                self.cancel_until(bt_level);
+                if learnt.len()==1 {
+                    self.enqueue(learnt[0],None);
+                } else {
+                    let lit = learnt[0];
+                    let ci = self.attach(learnt);
+                    self.enqueue(lit,Some(ci));
+                }
+                self.var_inc/=VAR_DECAY;
+            } else {
+                if conflicts>=budget {
+                    self.cancel_until(0);
+                    return None
+                }
+                let mut next = None;
+                while self.decision_level()<assumptions.len() {
+                    let p = assumptions[self.decision_level()];
+                    match self.value(p) {
+                        LBool::True => self.trail_lim.push(self.trail.len()),
+                        LBool::False => {
+                            self.analyze_final(p);
+                            return Some(false)
+                        },
+                        LBool::Undef => {
+                            next = Some(p);
+                            break
+                        }
+                    }
+                }
+                let lit = match next {
+                    Some(lit) => lit,
+                    None => match self.pick_branch() {
+                        Some(lit) => {
+                            self.decisions+=1;
+                            lit
+                        },
+                        None => {
+                            self.model = self.assigns.iter().map(|v| *v==LBool::True).collect();
+                            return Some(true)
+                        }
+                    }
+                };
+                self.trail_lim.push(self.trail.len());
+                self.enqueue(lit,None);
+            }
+        }
+    }
+    /// Check whether the clauses together with the assumptions are
+    /// satisfiable.
+    pub fn solve(&mut self,assumptions: &[Lit]) -> bool {
+        self.model.clear();
+        self.failed.clear();
+        if !self.ok {
+            return false
+        }
+        let mut restarts = 0;
+        loop {
+            let budget = luby(restarts)*RESTART_BASE;
+            restarts+=1;
+            if let Some(res) = self.search(budget,assumptions) {
+                self.cancel_until(0);
+                return res
+            }
+        }
+    }
+    /// The value of a literal in the model found by the last `solve`, if
+    /// its variable existed back then.
+    pub fn model_value(&self,lit: Lit) -> Option<bool> {
+        if lit.var()<self.model.len() {
+            Some(self.model[lit.var()]==lit.is_positive())
+        } else {
+            None
+        }
+    }
+    /// The subset of assumptions of the last unsuccessful `solve` which
+    /// made it unsatisfiable.
+    pub fn failed_assumptions(&self) -> &[Lit] {
+        &self.failed
+    }
+}
+
+#[test]
+fn test_sat_pigeonhole() {
+    // Four pigeons do not fit into three holes
+    let mut solver = Solver::new();
+    let p : Vec<Vec<Lit>> = (0..4).map(|_| (0..3).map(|_| Lit::new(solver.new_var(),true))
+                                       .collect()).collect();
+    for pigeon in p.iter() {
+        assert!(solver.add_clause(pigeon));
+    }
+    for h in 0..3 {
+        for i in 0..4 {
+            for j in i+1..4 {
+                solver.add_clause(&[!p[i][h],!p[j][h]]);
+            }
+        }
+    }
+    assert!(!solver.solve(&[]));
+}
+
+#[test]
+fn test_sat_assumptions() {
+    let mut solver = Solver::new();
+    let a = Lit::new(solver.new_var(),true);
+    let b = Lit::new(solver.new_var(),true);
+    let c = Lit::new(solver.new_var(),true);
+    solver.add_clause(&[!a,b]);
+    solver.add_clause(&[!b,c]);
+    assert!(solver.solve(&[a]));
+    assert_eq!(solver.model_value(c),Some(true));
+    assert!(!solver.solve(&[c,a,!b]));
+    let mut failed = solver.failed_assumptions().to_vec();
+    failed.sort();
+    assert_eq!(failed,vec![a,!b]);
+    // The clauses alone are still satisfiable
+    assert!(solver.solve(&[!c]));
+    assert_eq!(solver.model_value(a),Some(false));
+}
@@ -0,0 +1,980 @@
+use expr::{Expr,Function,NVar};
+use types::{Sort,SortKind,Value,Datatype,FieldSort,RoundingMode};
+use embed::Embed;
+use backend::{Backend,Pipe,PipeSort,PipeVar,PipeExpr,PipeFun,PipeError,AssertionId};
+use model::Model;
+use options::{Logic,OptionValue};
+use parser::{Parser,CheckSatResult,StatValue,SExpr,Command,SyntaxError,PResult,Pos,
+             parse_command,eat_ws_comments};
+use std::io::{Write,Empty,empty};
+use std::collections::HashMap;
+use std::rc::Rc;
+use num_bigint::{BigInt,BigUint};
+use num_rational::Ratio;
+#[cfg(test)]
+use expr::OrdOp;
+#[cfg(test)]
+use bitblast::BitBlaster;
+#[cfg(test)]
+use std::str;
+
+/// A backend which records all commands as an SMT-LIB script instead of
+/// running a solver, for example to produce benchmark files. Commands which
+/// expect an answer are written as well, but answered with stubs: checks
+/// return the configured answer (`Unknown` by default), values are the
+/// default values of their sorts and models and cores are empty.
+pub struct ScriptWriter<W : Write> {
+    pipe: Pipe<Empty,W>,
+    answer: CheckSatResult,
+    annotate: bool,
+    options: HashMap<String,OptionValue>
+}
+
+fn status(res: &CheckSatResult) -> &'static str {
+    match *res {
+        CheckSatResult::Sat => "sat",
+        CheckSatResult::Unsat => "unsat",
+        CheckSatResult::Unknown => "unknown"
+    }
+}
+
+impl<W : Write> ScriptWriter<W> {
+    pub fn new(outp: W) -> Self {
+        ScriptWriter { pipe: Pipe::new(empty(),outp),
+                       answer: CheckSatResult::Unknown,
+                       annotate: false,
+                       options: HashMap::new() }
+    }
+    /// Set the result returned by the following checks.
+    pub fn set_answer(&mut self,answer: CheckSatResult) {
+        self.answer = answer;
+    }
+    /// Precede every check by `(set-info :status ...)` with the configured
+    /// answer, as expected in benchmark files.
+    pub fn annotate_status(&mut self,annotate: bool) {
+        self.annotate = annotate;
+    }
+    /// Terminate the script with `(exit)` and return the output.
+    pub fn finish(mut self) -> Result<W,PipeError> {
+        write!(self.pipe.writer,"(exit)\n")?;
+        self.pipe.writer.flush()?;
+        Ok(self.pipe.writer)
+    }
+    fn check(&mut self) -> Result<CheckSatResult,PipeError> {
+        self.pipe.writer.flush()?;
+        Ok(match self.answer {
+            CheckSatResult::Sat => CheckSatResult::Sat,
+            CheckSatResult::Unsat => CheckSatResult::Unsat,
+            CheckSatResult::Unknown => CheckSatResult::Unknown
+        })
+    }
+    fn write_status(&mut self) -> Result<(),PipeError> {
+        if self.annotate {
+            write!(self.pipe.writer,"(set-info :status {})\n",status(&self.answer))?;
+        }
+        Ok(())
+    }
+    /// A value of the given sort to answer `get-value` with.
+    fn default_value(&mut self,srt: &PipeSort) -> Result<Value,PipeError> {
+        match self.unbed_sort(srt)? {
+            SortKind::Bool => Ok(Value::Bool(false)),
+            SortKind::Int => Ok(Value::Int(BigInt::from(0))),
+            SortKind::Real => Ok(Value::Real(Ratio::from_integer(BigInt::from(0)))),
+            SortKind::BitVec(bw) => Ok(Value::BitVec(bw,BigUint::from(0u32))),
+            SortKind::FloatingPoint(eb,sb) => Ok(Value::Float(eb,sb,BigUint::from(0u32))),
+            SortKind::RoundingMode => Ok(Value::RoundingMode(RoundingMode::RNE)),
+            _ => Err(PipeError::Unsupported(String::from("get-value")))
+        }
+    }
+}
+
+impl<W : Write> Embed for ScriptWriter<W> {
+    type Sort = PipeSort;
+    type Var = PipeVar;
+    type Expr = PipeExpr;
+    type Fun = PipeFun;
+    type Error = PipeError;
+    fn embed_sort(&mut self,tp: SortKind<PipeSort>) -> Result<PipeSort,PipeError> {
+        self.pipe.embed_sort(tp)
+    }
+    fn unbed_sort(&mut self,tp: &PipeSort) -> Result<SortKind<PipeSort>,PipeError> {
+        self.pipe.unbed_sort(tp)
+    }
+    fn embed(&mut self,e: Expr<PipeSort,PipeVar,PipeExpr,PipeFun>) -> Result<PipeExpr,PipeError> {
+        self.pipe.embed(e)
+    }
+    fn unbed(&mut self,e: &PipeExpr) -> Result<Expr<PipeSort,PipeVar,PipeExpr,PipeFun>,PipeError> {
+        self.pipe.unbed(e)
+    }
+    fn type_of(&mut self,e: &PipeExpr) -> Result<PipeSort,PipeError> {
+        self.pipe.type_of(e)
+    }
+    fn type_of_var(&mut self,v: &PipeVar) -> Result<PipeSort,PipeError> {
+        self.pipe.type_of_var(v)
+    }
+    fn type_of_fun(&mut self,f: &PipeFun) -> Result<PipeSort,PipeError> {
+        self.pipe.type_of_fun(f)
+    }
+    fn arity(&mut self,f: &PipeFun) -> Result<usize,PipeError> {
+        self.pipe.arity(f)
+    }
+    fn type_of_arg(&mut self,f: &PipeFun,arg: usize) -> Result<PipeSort,PipeError> {
+        self.pipe.type_of_arg(f,arg)
+    }
+    fn datatype(&mut self,dt: usize) -> Result<Datatype<PipeSort>,PipeError> {
+        self.pipe.datatype(dt)
+    }
+}
+
+impl<W : Write> Backend for ScriptWriter<W> {
+    fn unsupported(what: &str) -> PipeError {
+        PipeError::Unsupported(String::from(what))
+    }
+    fn enable_models(&mut self) -> Result<(),PipeError> {
+        self.set_option(":produce-models",OptionValue::Bool(true))
+    }
+    fn enable_proofs(&mut self) -> Result<(),PipeError> {
+        self.set_option(":produce-proofs",OptionValue::Bool(true))
+    }
+    fn enable_unsat_cores(&mut self) -> Result<(),PipeError> {
+        self.set_option(":produce-unsat-cores",OptionValue::Bool(true))
+    }
+    fn enable_interpolants(&mut self) -> Result<(),PipeError> {
+        self.set_option(":produce-interpolants",OptionValue::Bool(true))
+    }
+    fn set_logic(&mut self,logic: Logic) -> Result<(),PipeError> {
+        self.pipe.set_logic(logic)
+    }
+    fn set_option(&mut self,name: &str,value: OptionValue) -> Result<(),PipeError> {
+        // Nobody would acknowledge the commands
+        if name==":print-success" {
+            return Err(PipeError::Unsupported(String::from(name)))
+        }
+        self.pipe.set_option(name,value.clone())?;
+        self.options.insert(String::from(name),value);
+        Ok(())
+    }
+    fn get_option(&mut self,name: &str) -> Result<OptionValue,PipeError> {
+        match self.options.get(name) {
+            Some(value) => Ok(value.clone()),
+            None => Err(PipeError::Unsupported(String::from(name)))
+        }
+    }
+    fn set_info(&mut self,name: &str,value: OptionValue) -> Result<(),PipeError> {
+        self.pipe.set_info(name,value)
+    }
+    fn solver_name(&mut self) -> Result<String,PipeError> {
+        Ok(String::from("smtrs-script"))
+    }
+    fn solver_version(&mut self) -> Result<String,PipeError> {
+        Ok(String::from(env!("CARGO_PKG_VERSION")))
+    }
+    fn reason_unknown(&mut self) -> Result<String,PipeError> {
+        Ok(String::from("incomplete"))
+    }
+    fn statistics(&mut self) -> Result<HashMap<String,StatValue>,PipeError> {
+        Ok(HashMap::new())
+    }
+    fn comment(&mut self,comment: &str) -> Result<(),PipeError> {
+        self.pipe.comment(comment)
+    }
+    fn push(&mut self) -> Result<(),PipeError> {
+        self.pipe.push()
+    }
+    fn pop(&mut self) -> Result<(),PipeError> {
+        self.pipe.pop()
+    }
+    fn declare_var(&mut self,tp: PipeSort,name: Option<String>) -> Result<PipeVar,PipeError> {
+        self.pipe.declare_var(tp,name)
+    }
+    fn define_var(&mut self,e: PipeExpr) -> Result<PipeVar,PipeError> {
+        self.pipe.define_var(e)
+    }
+    fn assert(&mut self,expr: PipeExpr) -> Result<(),PipeError> {
+        self.pipe.assert(expr)
+    }
+    fn assert_named(&mut self,expr: PipeExpr) -> Result<AssertionId,PipeError> {
+        self.pipe.assert_named(expr)
+    }
+    fn get_unsat_core(&mut self) -> Result<Vec<AssertionId>,PipeError> {
+        write!(self.pipe.writer,"(get-unsat-core)\n")?;
+        Ok(Vec::new())
+    }
+    fn check_sat(&mut self) -> Result<CheckSatResult,PipeError> {
+        self.write_status()?;
+        write!(self.pipe.writer,"(check-sat)\n")?;
+        self.check()
+    }
+    fn check_sat_assuming(&mut self,assumptions: &[PipeExpr])
+                          -> Result<CheckSatResult,PipeError> {
+        self.write_status()?;
+        write!(self.pipe.writer,"(check-sat-assuming (")?;
+        for (i,e) in assumptions.iter().enumerate() {
+            if i>0 {
+                write!(self.pipe.writer," ")?;
+            }
+            self.pipe.fmt_expr(e)?;
+        }
+        write!(self.pipe.writer,"))\n")?;
+        self.check()
+    }
+    fn get_unsat_assumptions(&mut self) -> Result<Vec<PipeExpr>,PipeError> {
+        write!(self.pipe.writer,"(get-unsat-assumptions)\n")?;
+        Ok(Vec::new())
+    }
+    fn get_proof(&mut self) -> Result<SExpr,PipeError> {
+        Err(PipeError::Unsupported(String::from("get-proof")))
+    }
+    fn get_interpolant(&mut self,_: &[PipeExpr],_: &[PipeExpr])
+                       -> Result<Option<PipeExpr>,PipeError> {
+        Err(PipeError::Unsupported(String::from("get-interpolants")))
+    }
+    fn eliminate_quantifiers(&mut self,_: PipeExpr) -> Result<PipeExpr,PipeError> {
+        Err(PipeError::Unsupported(String::from("qe")))
+    }
+    fn solver_simplify(&mut self,_: PipeExpr) -> Result<PipeExpr,PipeError> {
+        Err(PipeError::Unsupported(String::from("simplify")))
+    }
+    fn get_value(&mut self,expr: PipeExpr) -> Result<Value,PipeError> {
+        let mut vals = self.get_values(vec![expr])?;
+        Ok(vals.pop().unwrap())
+    }
+    fn get_values(&mut self,exprs: Vec<PipeExpr>) -> Result<Vec<Value>,PipeError> {
+        if exprs.len()==0 {
+            return Ok(vec![])
+        }
+        let mut res = Vec::with_capacity(exprs.len());
+        write!(self.pipe.writer,"(get-value (")?;
+        for (i,e) in exprs.iter().enumerate() {
+            if i>0 {
+                write!(self.pipe.writer," ")?;
+            }
+            self.pipe.fmt_expr(e)?;
+            let srt = self.type_of(e)?;
+            res.push(self.default_value(&srt)?);
+        }
+        write!(self.pipe.writer,"))\n")?;
+        Ok(res)
+    }
+    fn get_model(&mut self) -> Result<Model<PipeSort,PipeVar,PipeExpr,PipeFun>,PipeError> {
+        write!(self.pipe.writer,"(get-model)\n")?;
+        Ok(Model::new())
+    }
+    fn declare_datatypes(&mut self,dts: Vec<Datatype<FieldSort<PipeSort>>>)
+                         -> Result<usize,PipeError> {
+        self.pipe.declare_datatypes(dts)
+    }
+    fn declare_sort(&mut self,arity: usize) -> Result<usize,PipeError> {
+        self.pipe.declare_sort(arity)
+    }
+    fn define_sort(&mut self,params: usize,body: PipeSort) -> Result<usize,PipeError> {
+        self.pipe.define_sort(params,body)
+    }
+    fn defined_sort(&mut self,id: usize,args: Vec<PipeSort>) -> Result<PipeSort,PipeError> {
+        self.pipe.defined_sort(id,args)
+    }
+    fn declare_fun(&mut self,args: Vec<PipeSort>,ret: PipeSort,name: Option<String>)
+                   -> Result<PipeFun,PipeError> {
+        self.pipe.declare_fun(args,ret,name)
+    }
+    fn define_fun(&mut self,params: Vec<NVar<PipeSort>>,body: PipeExpr)
+                  -> Result<PipeFun,PipeError> {
+        self.pipe.define_fun(params,body)
+    }
+    fn declare_funs_rec(&mut self,sigs: &[(Vec<NVar<PipeSort>>,PipeSort)])
+                        -> Result<Vec<PipeFun>,PipeError> {
+        self.pipe.declare_funs_rec(sigs)
+    }
+    fn emit_funs_rec(&mut self,funs: &[PipeFun],sigs: &[(Vec<NVar<PipeSort>>,PipeSort)],
+                     exprs: Vec<PipeExpr>) -> Result<(),PipeError> {
+        self.pipe.emit_funs_rec(funs,sigs,exprs)
+    }
+}
+
+/// An expression of a parsed script.
+#[derive(Debug,Clone,PartialEq,Eq,Hash)]
+pub struct ScriptExpr(Rc<Expr<Sort,ScriptVar,ScriptExpr,ScriptFun>>);
+
+/// A constant declared or defined in a script, numbered in order of
+/// declaration.
+#[derive(Debug,Clone,Copy,PartialEq,Eq,Hash)]
+pub struct ScriptVar(pub usize);
+
+/// A function declared or defined in a script, numbered in order of
+/// declaration.
+#[derive(Debug,Clone,Copy,PartialEq,Eq,Hash)]
+pub struct ScriptFun(pub usize);
+
+#[derive(Debug)]
+pub enum ScriptError {
+    Syntax(SyntaxError<Sort>),
+    /// The script ends in the middle of a command
+    Incomplete,
+    UnknownSymbol(String),
+    UnknownDatatype(usize)
+}
+
+#[derive(Clone,Copy)]
+enum Symbol {
+    Var(usize),
+    Fun(usize),
+    Constructor(usize,usize),
+    Selector(usize,usize,usize)
+}
+
+/// The symbols declared by the commands of a script, used to embed the
+/// expressions of the script. Symbols can be shadowed by later
+/// declarations of the same name, but are never removed.
+pub struct Script {
+    vars: Vec<Sort>,
+    funs: Vec<(Vec<Sort>,Sort)>,
+    datatypes: Vec<Datatype<Sort>>,
+    sorts: usize,
+    symbols: HashMap<Vec<u8>,Symbol>,
+    sort_names: HashMap<Vec<u8>,SortKind<Sort>>,
+    aliases: HashMap<Vec<u8>,(usize,Sort)> // number of parameters, definition
+}
+
+impl Script {
+    pub fn new() -> Self {
+        Script { vars: Vec::new(),
+                 funs: Vec::new(),
+                 datatypes: Vec::new(),
+                 sorts: 0,
+                 symbols: HashMap::new(),
+                 sort_names: HashMap::new(),
+                 aliases: HashMap::new() }
+    }
+    /// Make the symbols declared by a command known, so that the following
+    /// commands can use them.
+    pub fn declare(&mut self,cmd: &Command<Sort,ScriptExpr>) -> Result<(),ScriptError> {
+        match *cmd {
+            Command::DeclareSort(ref name,_) => {
+                self.sort_names.insert(name.as_bytes().to_vec(),
+                                       SortKind::Uninterpreted(self.sorts,vec![]));
+                self.sorts+=1;
+            },
+            Command::DeclareFun(ref name,ref args,ref ret)
+                => self.declare_symbol(name,args.clone(),ret.clone()),
+            Command::DefineFun(ref name,ref params,ref ret,_) => {
+                let args = params.iter().map(|p| p.sort.clone()).collect();
+                self.declare_symbol(name,args,ret.clone())
+            },
+            Command::DefineSort(ref name,ref params,ref body) => {
+                self.aliases.insert(name.as_bytes().to_vec(),(params.len(),body.clone()));
+            },
+            Command::DeclareDatatypes(ref decls) => {
+                let base = self.datatypes.len();
+                for (i,decl) in decls.iter().enumerate() {
+                    self.sort_names.insert(decl.name.as_bytes().to_vec(),
+                                           SortKind::Datatype(base+i,vec![]));
+                }
+                for (i,decl) in decls.iter().enumerate() {
+                    let mut cons = Vec::with_capacity(decl.constructors.len());
+                    for (c,&(ref con,ref fields)) in decl.constructors.iter().enumerate() {
+                        self.symbols.insert(con.as_bytes().to_vec(),
+                                            Symbol::Constructor(base+i,c));
+                        let mut nfields = Vec::with_capacity(fields.len());
+                        for (f,&(ref sel,ref srt)) in fields.iter().enumerate() {
+                            self.symbols.insert(sel.as_bytes().to_vec(),
+                                                Symbol::Selector(base+i,c,f));
+                            nfields.push(srt.resolve(base,self)?);
+                        }
+                        cons.push(nfields);
+                    }
+                    self.datatypes.push(Datatype { params: decl.params.len(),
+                                                   constructors: cons });
+                }
+            },
+            _ => {}
+        }
+        Ok(())
+    }
+    fn declare_symbol(&mut self,name: &str,args: Vec<Sort>,ret: Sort) {
+        let sym = if args.len()==0 {
+            self.vars.push(ret);
+            Symbol::Var(self.vars.len()-1)
+        } else {
+            self.funs.push((args,ret));
+            Symbol::Fun(self.funs.len()-1)
+        };
+        self.symbols.insert(name.as_bytes().to_vec(),sym);
+    }
+}
+
+impl Embed for Script {
+    type Sort = Sort;
+    type Var = ScriptVar;
+    type Expr = ScriptExpr;
+    type Fun = ScriptFun;
+    type Error = ScriptError;
+    fn embed_sort(&mut self,tp: SortKind<Sort>) -> Result<Sort,ScriptError> {
+        Ok(Sort::from_kind(tp))
+    }
+    fn unbed_sort(&mut self,tp: &Sort) -> Result<SortKind<Sort>,ScriptError> {
+        Ok(tp.kind())
+    }
+    fn embed(&mut self,e: Expr<Sort,ScriptVar,ScriptExpr,ScriptFun>)
+             -> Result<ScriptExpr,ScriptError> {
+        Ok(ScriptExpr(Rc::new(e)))
+    }
+    fn unbed(&mut self,e: &ScriptExpr)
+             -> Result<Expr<Sort,ScriptVar,ScriptExpr,ScriptFun>,ScriptError> {
+        Ok((*e.0).clone())
+    }
+    fn type_of_var(&mut self,var: &ScriptVar) -> Result<Sort,ScriptError> {
+        Ok(self.vars[var.0].clone())
+    }
+    fn type_of_fun(&mut self,fun: &ScriptFun) -> Result<Sort,ScriptError> {
+        Ok(self.funs[fun.0].1.clone())
+    }
+    fn arity(&mut self,fun: &ScriptFun) -> Result<usize,ScriptError> {
+        Ok(self.funs[fun.0].0.len())
+    }
+    fn type_of_arg(&mut self,fun: &ScriptFun,p: usize) -> Result<Sort,ScriptError> {
+        Ok(self.funs[fun.0].0[p].clone())
+    }
+    fn datatype(&mut self,dt: usize) -> Result<Datatype<Sort>,ScriptError> {
+        match self.datatypes.get(dt) {
+            Some(def) => Ok(def.clone()),
+            None => Err(ScriptError::UnknownDatatype(dt))
+        }
+    }
+}
+
+impl Parser for Script {
+    fn parse_var(&mut self,name: &[u8]) -> Result<ScriptVar,ScriptError> {
+        match self.symbols.get(name) {
+            Some(&Symbol::Var(v)) => Ok(ScriptVar(v)),
+            _ => Err(ScriptError::UnknownSymbol(String::from_utf8_lossy(name).into_owned()))
+        }
+    }
+    fn parse_fun(&mut self,name: &[u8]) -> Result<ScriptFun,ScriptError> {
+        match self.symbols.get(name) {
+            Some(&Symbol::Fun(f)) => Ok(ScriptFun(f)),
+            _ => Err(ScriptError::UnknownSymbol(String::from_utf8_lossy(name).into_owned()))
+        }
+    }
+    fn parse_sort_name(&mut self,name: &[u8]) -> Option<SortKind<Sort>> {
+        self.sort_names.get(name).cloned()
+    }
+    fn parse_sort_alias(&mut self,name: &[u8]) -> Option<(usize,Sort)> {
+        self.aliases.get(name).cloned()
+    }
+    fn declare_rec_symbols(&mut self,sigs: &[(String,Vec<Sort>,Sort)]) -> Result<(),ScriptError> {
+        // Recursive functions are always functions, even without arguments,
+        // since the backend defines them that way
+        for &(ref name,ref args,ref ret) in sigs.iter() {
+            self.funs.push((args.clone(),ret.clone()));
+            self.symbols.insert(name.as_bytes().to_vec(),Symbol::Fun(self.funs.len()-1));
+        }
+        Ok(())
+    }
+    fn parse_dt_symbol(&mut self,name: &[u8]) -> Option<(usize,usize,Option<usize>)> {
+        match self.symbols.get(name) {
+            Some(&Symbol::Constructor(dt,con)) => Some((dt,con,None)),
+            Some(&Symbol::Selector(dt,con,field)) => Some((dt,con,Some(field))),
+            _ => None
+        }
+    }
+}
+
+/// Parse a complete SMT-LIB script. The declarations of the script are
+/// collected in the returned `Script`, which embeds the expressions of the
+/// commands.
+pub fn parse_script(input: &[u8]) -> Result<(Script,Vec<Command<Sort,ScriptExpr>>),ScriptError> {
+    let mut script = Script::new();
+    let mut cmds = Vec::new();
+    let mut pos = Pos { line: 0, col: 0 };
+    let mut inp = input;
+    loop {
+        inp = eat_ws_comments(inp,&mut pos);
+        if inp.len()==0 {
+            return Ok((script,cmds))
+        }
+        match parse_command(inp,&mut pos,&mut script) {
+            PResult::Done(cmd,ninp) => {
+                script.declare(&cmd)?;
+                cmds.push(cmd);
+                inp = ninp;
+            },
+            PResult::Incomplete => return Err(ScriptError::Incomplete),
+            PResult::EmbedError(e) => return Err(e),
+            PResult::SyntaxError(e,epos) => return Err(ScriptError::Syntax(SyntaxError::new(e,epos,input)))
+        }
+    }
+}
+
+/// The answer of a backend to a command of a replayed script.
+#[derive(Debug)]
+pub enum Response<S,V,E,F> {
+    CheckSat(CheckSatResult),
+    Values(Vec<Value>),
+    Model(Model<S,V,E,F>),
+    /// The names of the assertions in the unsat core
+    UnsatCore(Vec<String>),
+    UnsatAssumptions(Vec<E>),
+    Proof(SExpr),
+    Option(OptionValue),
+    /// The answer to `get-info` for `:name`, `:version` or `:reason-unknown`
+    Info(String),
+    Statistics(HashMap<String,StatValue>),
+    Echo(String)
+}
+
+#[derive(Debug)]
+pub enum ReplayError<E> {
+    Backend(E),
+    /// The command has no counterpart in the backend interface
+    Unsupported(String)
+}
+
+impl<E> From<E> for ReplayError<E> {
+    fn from(e: E) -> Self {
+        ReplayError::Backend(e)
+    }
+}
+
+/// The backend symbols corresponding to the declarations of a script.
+struct Mapping<B : Backend> {
+    vars: Vec<B::Var>,
+    funs: Vec<B::Fun>,
+    datatypes: Vec<usize>,
+    sorts: Vec<usize>
+}
+
+impl<B : Backend> Mapping<B> {
+    fn remap_sort(&self,srt: &Sort) -> Sort {
+        Sort::from_kind(match srt.kind() {
+            SortKind::Array(idx,el) => {
+                let nidx = idx.iter().map(|i| self.remap_sort(i)).collect();
+                SortKind::Array(nidx,self.remap_sort(&el))
+            },
+            SortKind::Datatype(dt,args) => {
+                let nargs = args.iter().map(|arg| self.remap_sort(arg)).collect();
+                SortKind::Datatype(self.datatypes[dt],nargs)
+            },
+            SortKind::Uninterpreted(id,args) => {
+                let nargs = args.iter().map(|arg| self.remap_sort(arg)).collect();
+                SortKind::Uninterpreted(self.sorts[id],nargs)
+            },
+            kind => kind
+        })
+    }
+    fn sort(&self,srt: &Sort,b: &mut B) -> Result<B::Sort,B::Error> {
+        self.remap_sort(srt).embed(b)
+    }
+    fn field_sort(&self,srt: &FieldSort<Sort>,b: &mut B) -> Result<FieldSort<B::Sort>,B::Error> {
+        match *srt {
+            FieldSort::Sort(ref srt) => Ok(FieldSort::Sort(self.sort(srt,b)?)),
+            FieldSort::Rec(n,ref args) => {
+                let mut nargs = Vec::with_capacity(args.len());
+                for arg in args.iter() {
+                    nargs.push(self.field_sort(arg,b)?);
+                }
+                Ok(FieldSort::Rec(n,nargs))
+            }
+        }
+    }
+    fn value(&self,val: &Value) -> Value {
+        match *val {
+            Value::Datatype(ref srt,con,ref fields)
+                => Value::Datatype(self.remap_sort(srt),con,
+                                   fields.iter().map(|f| self.value(f)).collect()),
+            Value::Uninterpreted(ref srt,ref name)
+                => Value::Uninterpreted(self.remap_sort(srt),name.clone()),
+            Value::Array { ref index, ref element, ref default, ref entries }
+                => Value::Array { index: index.iter().map(|i| self.remap_sort(i)).collect(),
+                                  element: self.remap_sort(element),
+                                  default: Box::new(self.value(default)),
+                                  entries: entries.iter()
+                                  .map(|&(ref i,ref v)| (self.value(i),self.value(v)))
+                                  .collect() },
+            ref v => v.clone()
+        }
+    }
+    fn nvar(&self,var: &NVar<Sort>,b: &mut B) -> Result<NVar<B::Sort>,B::Error> {
+        Ok(NVar { id: var.id,
+                  sort: self.sort(&var.sort,b)? })
+    }
+    fn function(&self,fun: &Function<Sort,ScriptFun>,b: &mut B)
+                -> Result<Function<B::Sort,B::Fun>,B::Error> {
+        let nfun = fun.map(&mut |srt| self.sort(srt,b),
+                           &mut |f| Ok(self.funs[f.0].clone()))?;
+        Ok(match nfun {
+            Function::Constructor(dt,pars,con)
+                => Function::Constructor(self.datatypes[dt],pars,con),
+            Function::Selector(dt,pars,con,field)
+                => Function::Selector(self.datatypes[dt],pars,con,field),
+            Function::Tester(dt,pars,con)
+                => Function::Tester(self.datatypes[dt],pars,con),
+            f => f
+        })
+    }
+    fn expr(&self,e: &ScriptExpr,b: &mut B) -> Result<B::Expr,B::Error> {
+        let ne = match *e.0 {
+            Expr::Var(ref v) => Expr::Var(self.vars[v.0].clone()),
+            Expr::QVar(ref v) => Expr::QVar(self.nvar(v,b)?),
+            Expr::LVar(ref v) => Expr::LVar(self.nvar(v,b)?),
+            Expr::Const(ref v) => Expr::Const(self.value(v)),
+            Expr::App(ref fun,ref args) => {
+                let nfun = self.function(fun,b)?;
+                let nargs = self.exprs(args,b)?;
+                Expr::App(nfun,nargs)
+            },
+            Expr::AsArray(ref fun) => Expr::AsArray(self.function(fun,b)?),
+            Expr::Exists(ref vars,ref body) | Expr::Forall(ref vars,ref body) => {
+                let mut nvars = Vec::with_capacity(vars.len());
+                for var in vars.iter() {
+                    nvars.push(self.nvar(var,b)?);
+                }
+                let nbody = self.expr(body,b)?;
+                if let Expr::Exists(_,_) = *e.0 {
+                    Expr::Exists(nvars,nbody)
+                } else {
+                    Expr::Forall(nvars,nbody)
+                }
+            },
+            Expr::Let(ref binds,ref body) => {
+                let mut nbinds = Vec::with_capacity(binds.len());
+                for &(ref var,ref def) in binds.iter() {
+                    nbinds.push((self.nvar(var,b)?,self.expr(def,b)?));
+                }
+                Expr::Let(nbinds,self.expr(body,b)?)
+            }
+        };
+        b.embed(ne)
+    }
+    fn exprs(&self,es: &[ScriptExpr],b: &mut B) -> Result<Vec<B::Expr>,B::Error> {
+        let mut res = Vec::with_capacity(es.len());
+        for e in es.iter() {
+            res.push(self.expr(e,b)?);
+        }
+        Ok(res)
+    }
+}
+
+/// Replays the commands of a parsed script against a backend. The commands
+/// have to be run in the order of the script, starting with the first one.
+pub struct Replay<'a,B : Backend + 'a> {
+    backend: &'a mut B,
+    map: Mapping<B>,
+    named: HashMap<AssertionId,String>
+}
+
+impl<'a,B : Backend> Replay<'a,B> {
+    pub fn new(backend: &'a mut B) -> Self {
+        Replay { backend: backend,
+                 map: Mapping { vars: Vec::new(),
+                                funs: Vec::new(),
+                                datatypes: Vec::new(),
+                                sorts: Vec::new() },
+                 named: HashMap::new() }
+    }
+    /// Run a single command, returning the answer of the backend if the
+    /// command has one.
+    pub fn run(&mut self,cmd: &Command<Sort,ScriptExpr>)
+               -> Result<Option<Response<B::Sort,B::Var,B::Expr,B::Fun>>,
+                         ReplayError<B::Error>> {
+        let b = &mut *self.backend;
+        let map = &mut self.map;
+        match *cmd {
+            Command::SetLogic(ref logic) => b.set_logic(logic.clone())?,
+            Command::SetOption(ref name,ref val) => b.set_option(name,val.clone())?,
+            Command::GetOption(ref name) => return Ok(Some(Response::Option(b.get_option(name)?))),
+            Command::SetInfo(ref name,ref val) => b.set_info(name,val.clone())?,
+            Command::GetInfo(ref name) => return Ok(Some(match &name[..] {
+                ":name" => Response::Info(b.solver_name()?),
+                ":version" => Response::Info(b.solver_version()?),
+                ":reason-unknown" => Response::Info(b.reason_unknown()?),
+                ":all-statistics" => Response::Statistics(b.statistics()?),
+                _ => return Err(ReplayError::Unsupported(format!("get-info {}",name)))
+            })),
+            Command::DeclareSort(_,arity) => {
+                let id = b.declare_sort(arity)?;
+                map.sorts.push(id);
+            },
+            Command::DeclareFun(ref name,ref args,ref ret) => {
+                let nret = map.sort(ret,b)?;
+                if args.len()==0 {
+                    let var = b.declare_var(nret,Some(name.clone()))?;
+                    map.vars.push(var);
+                } else {
+                    let mut nargs = Vec::with_capacity(args.len());
+                    for arg in args.iter() {
+                        nargs.push(map.sort(arg,b)?);
+                    }
+                    let fun = b.declare_fun(nargs,nret,Some(name.clone()))?;
+                    map.funs.push(fun);
+                }
+            },
+            Command::DefineFun(_,ref params,_,ref body) => {
+                let nbody = map.expr(body,b)?;
+                if params.len()==0 {
+                    let var = b.define_var(nbody)?;
+                    map.vars.push(var);
+                } else {
+                    let mut nparams = Vec::with_capacity(params.len());
+                    for par in params.iter() {
+                        nparams.push(map.nvar(par,b)?);
+                    }
+                    let fun = b.define_fun(nparams,nbody)?;
+                    map.funs.push(fun);
+                }
+            },
+            Command::DefineFunsRec(ref sigs,ref bodies) => {
+                let mut nsigs = Vec::with_capacity(sigs.len());
+                for &(_,ref params,ref ret) in sigs.iter() {
+                    let mut nparams = Vec::with_capacity(params.len());
+                    for par in params.iter() {
+                        nparams.push(map.nvar(par,b)?);
+                    }
+                    nsigs.push((nparams,map.sort(ret,b)?));
+                }
+                let funs = b.declare_funs_rec(&nsigs)?;
+                // The bodies refer to the functions being defined
+                map.funs.extend(funs.iter().cloned());
+                let nbodies = map.exprs(bodies,b)?;
+                b.emit_funs_rec(&funs,&nsigs,nbodies)?;
+            },
+            Command::DefineSort(_,ref params,ref body) => {
+                // Aliases are expanded by the parser, the backend only
+                // learns about the definition
+                let nbody = map.sort(body,b)?;
+                b.define_sort(params.len(),nbody)?;
+            },
+            Command::DeclareDatatypes(ref decls) => {
+                let mut dts = Vec::with_capacity(decls.len());
+                for decl in decls.iter() {
+                    let dt = decl.datatype();
+                    let mut cons = Vec::with_capacity(dt.constructors.len());
+                    for fields in dt.constructors.iter() {
+                        let mut nfields = Vec::with_capacity(fields.len());
+                        for field in fields.iter() {
+                            nfields.push(map.field_sort(field,b)?);
+                        }
+                        cons.push(nfields);
+                    }
+                    dts.push(Datatype { params: dt.params,
+                                        constructors: cons });
+                }
+                let base = b.declare_datatypes(dts)?;
+                for i in 0..decls.len() {
+                    map.datatypes.push(base+i);
+                }
+            },
+            Command::Push(n) => for _ in 0..n {
+                b.push()?;
+            },
+            Command::Pop(n) => for _ in 0..n {
+                b.pop()?;
+            },
+            Command::Assert(ref e) => {
+                let ne = map.expr(e,b)?;
+                b.assert(ne)?;
+            },
+            Command::AssertNamed(ref e,ref name) => {
+                let ne = map.expr(e,b)?;
+                let id = b.assert_named(ne)?;
+                self.named.insert(id,name.clone());
+            },
+            Command::CheckSat => return Ok(Some(Response::CheckSat(b.check_sat()?))),
+            Command::CheckSatAssuming(ref es) => {
+                let nes = map.exprs(es,b)?;
+                return Ok(Some(Response::CheckSat(b.check_sat_assuming(&nes)?)))
+            },
+            Command::GetValue(ref es) => {
+                let nes = map.exprs(es,b)?;
+                return Ok(Some(Response::Values(b.get_values(nes)?)))
+            },
+            Command::GetModel => return Ok(Some(Response::Model(b.get_model()?))),
+            Command::GetProof => return Ok(Some(Response::Proof(b.get_proof()?))),
+            Command::GetUnsatCore => {
+                let core = b.get_unsat_core()?;
+                let mut names = Vec::with_capacity(core.len());
+                for id in core.iter() {
+                    if let Some(name) = self.named.get(id) {
+                        names.push(name.clone());
+                    }
+                }
+                return Ok(Some(Response::UnsatCore(names)))
+            },
+            Command::GetUnsatAssumptions
+                => return Ok(Some(Response::UnsatAssumptions(b.get_unsat_assumptions()?))),
+            Command::GetAssertions => return Err(ReplayError::Unsupported(String::from("get-assertions"))),
+            Command::GetAssignment => return Err(ReplayError::Unsupported(String::from("get-assignment"))),
+            Command::Reset => return Err(ReplayError::Unsupported(String::from("reset"))),
+            Command::ResetAssertions
+                => return Err(ReplayError::Unsupported(String::from("reset-assertions"))),
+            Command::Echo(ref msg) => return Ok(Some(Response::Echo(msg.clone()))),
+            Command::Exit => {}
+        }
+        Ok(None)
+    }
+    /// Run the commands up to the first `exit` and collect the answers.
+    pub fn run_all(&mut self,cmds: &[Command<Sort,ScriptExpr>])
+                   -> Result<Vec<Response<B::Sort,B::Var,B::Expr,B::Fun>>,
+                             ReplayError<B::Error>> {
+        let mut res = Vec::new();
+        for cmd in cmds.iter() {
+            if let Command::Exit = *cmd {
+                break
+            }
+            if let Some(resp) = self.run(cmd)? {
+                res.push(resp);
+            }
+        }
+        Ok(res)
+    }
+}
+
+#[test]
+fn test_script_writer() {
+    let mut script = ScriptWriter::new(Vec::new());
+    script.set_logic(Logic::QF_LIA).unwrap();
+    let tint = script.tp_int().unwrap();
+    let x = script.declare_var(tint,Some("x".to_string())).unwrap();
+    let x = script.embed(Expr::Var(x)).unwrap();
+    let one = script.const_int(BigInt::from(1)).unwrap();
+    let gt = script.embed(Expr::App(Function::OrdInt(OrdOp::Gt),vec![x.clone(),one])).unwrap();
+    script.assert(gt).unwrap();
+    script.set_answer(CheckSatResult::Sat);
+    script.annotate_status(true);
+    assert_eq!(script.check_sat().unwrap(),CheckSatResult::Sat);
+    assert_eq!(script.get_value(x).unwrap(),Value::Int(BigInt::from(0)));
+    let out = script.finish().unwrap();
+    assert_eq!(str::from_utf8(&out).unwrap(),
+               "(set-logic QF_LIA)\n\
+                (declare-fun x () Int)\n\
+                (assert (> x 1))\n\
+                (set-info :status sat)\n\
+                (check-sat)\n\
+                (get-value (x))\n\
+                (exit)\n");
+}
+
+#[test]
+fn test_script_replay() {
+    let (_,cmds) = parse_script(b"; a small benchmark\n\
+                                  (set-logic QF_BV)\n\
+                                  (set-info :status sat)\n\
+                                  (declare-fun x () (_ BitVec 8))\n\
+                                  (declare-const y (_ BitVec 8))\n\
+                                  (define-fun lt ((p (_ BitVec 8)) (q (_ BitVec 8))) Bool\n\
+                                    (bvult p q))\n\
+                                  (define-fun five () (_ BitVec 8) #x05)\n\
+                                  (assert (lt x y))\n\
+                                  (assert (! (= x five) :named fix_x))\n\
+                                  (push 1)\n\
+                                  (assert (! (= y #x03) :named small_y))\n\
+                                  (check-sat)\n\
+                                  (get-unsat-core)\n\
+                                  (pop 1)\n\
+                                  (check-sat) ; now satisfiable\n\
+                                  (get-value (x (bvadd x #x01)))\n\
+                                  (echo \"done\")\n\
+                                  (exit)\n\
+                                  (check-sat)\n").unwrap();
+    assert_eq!(cmds.len(),18);
+    assert_eq!(cmds[0],Command::SetLogic(Logic::QF_BV));
+    match cmds[7] {
+        Command::AssertNamed(_,ref name) => assert_eq!(name,"fix_x"),
+        ref cmd => panic!("Unexpected command {:?}",cmd)
+    }
+    let mut b = BitBlaster::new();
+    let resps = Replay::new(&mut b).run_all(&cmds).unwrap();
+    assert_eq!(resps.len(),5);
+    match resps[0] {
+        Response::CheckSat(ref res) => assert_eq!(*res,CheckSatResult::Unsat),
+        ref r => panic!("Unexpected response {:?}",r)
+    }
+    match resps[1] {
+        Response::UnsatCore(ref core) => {
+            let mut core = core.clone();
+            core.sort();
+            assert_eq!(core,vec!["fix_x".to_string(),"small_y".to_string()]);
+        },
+        ref r => panic!("Unexpected response {:?}",r)
+    }
+    match resps[2] {
+        Response::CheckSat(ref res) => assert_eq!(*res,CheckSatResult::Sat),
+        ref r => panic!("Unexpected response {:?}",r)
+    }
+    match resps[3] {
+        Response::Values(ref vals) => assert_eq!(*vals,vec![Value::BitVec(8,BigUint::from(5u32)),
+                                                             Value::BitVec(8,BigUint::from(6u32))]),
+        ref r => panic!("Unexpected response {:?}",r)
+    }
+    match resps[4] {
+        Response::Echo(ref msg) => assert_eq!(msg,"done"),
+        ref r => panic!("Unexpected response {:?}",r)
+    }
+}
+
+#[test]
+fn test_script_datatypes() {
+    let (_,cmds) = parse_script(b"(declare-sort U 0)\n\
+                                  (declare-datatypes ((List 0)) (((nil) (cons (head Int) (tail List)))))\n\
+                                  (declare-fun u () U)\n\
+                                  (declare-fun f (U) List)\n\
+                                  (declare-const l List)\n\
+                                  (assert (= l (cons 1 nil)))\n\
+                                  (assert (= (head (f u)) (head (tail l))))\n\
+                                  (check-sat-assuming (((_ is nil) (f u))))\n").unwrap();
+    let mut pipe = Pipe::new(&b"unsat\n"[..],Vec::new());
+    let resps = Replay::new(&mut pipe).run_all(&cmds).unwrap();
+    match resps[0] {
+        Response::CheckSat(ref res) => assert_eq!(*res,CheckSatResult::Unsat),
+        ref r => panic!("Unexpected response {:?}",r)
+    }
+    assert_eq!(str::from_utf8(&pipe.writer).unwrap(),
+               "(declare-sort u0 0)\n\
+                (declare-datatypes ((d0 0)) (((d0c0)(d0c1 (d0c1s0 Int) (d0c1s1 d0)))))\n\
+                (declare-fun u () u0)\n\
+                (declare-fun f (u0) d0)\n\
+                (declare-fun l () d0)\n\
+                (assert (= l (d0c1 1 d0c0)))\n\
+                (assert (= (d0c1s0 (f u)) (d0c1s0 (d0c1s1 l))))\n\
+                (check-sat-assuming (((_ is d0c0) (f u))))\n");
+}
+
+#[test]
+fn test_script_definitions() {
+    let (_,cmds) = parse_script(b"(define-sort Word () (_ BitVec 16))\n\
+                                  (define-sort Mem (A) (Array A Word))\n\
+                                  (declare-datatypes ((Lst 1)) ((par (T) ((nil) (cons (hd T) (tl (Lst T)))))))\n\
+                                  (declare-const m (Mem Int))\n\
+                                  (declare-const l (Lst Word))\n\
+                                  (define-fun-rec len ((x (Lst Word))) Int\n\
+                                    (ite ((_ is nil) x) 0 (+ 1 (len (tl x)))))\n\
+                                  (define-funs-rec ((ev ((n Int)) Bool) (od ((n Int)) Bool))\n\
+                                    ((ite (= n 0) true (od (- n 1))) (ite (= n 0) false (ev (- n 1)))))\n\
+                                  (assert (= (hd l) #x0001))\n\
+                                  (assert (ev (len l)))\n\
+                                  (check-sat)\n\
+                                  (reset)\n").unwrap();
+    assert_eq!(cmds.len(),11);
+    let mut pipe = Pipe::new(&b"sat\n"[..],Vec::new());
+    {
+        let mut replay = Replay::new(&mut pipe);
+        let resps = replay.run_all(&cmds[0..10]).unwrap();
+        match resps[0] {
+            Response::CheckSat(ref res) => assert_eq!(*res,CheckSatResult::Sat),
+            ref r => panic!("Unexpected response {:?}",r)
+        }
+        match replay.run(&cmds[10]) {
+            Err(ReplayError::Unsupported(ref cmd)) => assert_eq!(cmd,"reset"),
+            r => panic!("Unexpected result {:?}",r)
+        }
+    }
+    assert_eq!(str::from_utf8(&pipe.writer).unwrap(),
+               "(declare-datatypes ((d0 1)) ((par (p0) ((d0c0)(d0c1 (d0c1s0 p0) (d0c1s1 (d0 p0)))))))\n\
+                (declare-fun m () (Array Int (_ BitVec 16)))\n\
+                (declare-fun l () (d0 (_ BitVec 16)))\n\
+                (define-fun-rec f0 ((qv0 (d0 (_ BitVec 16)))) Int (ite ((_ is d0c0) qv0) 0 (+ 1 (f0 (d0c1s1 qv0)))))\n\
+                (define-funs-rec ((f1 ((qv0 Int)) Bool)(f2 ((qv0 Int)) Bool)) \
+                ((ite (= qv0 0) true (f2 (- qv0 1))) (ite (= qv0 0) false (f1 (- qv0 1)))))\n\
+                (assert (= (d0c1s0 l) #x0001))\n\
+                (assert (f1 (f0 l)))\n\
+                (check-sat)\n");
+}
@@ -1,8 +1,12 @@
 use embed::Embed;
-use backend::Backend;
-use expr::{Expr,Function};
-use types::{SortKind,Value};
-use parser::CheckSatResult;
+use backend::{Backend,Optimizer,AssertionId,ObjectiveId};
+use expr::{Expr,Function,NVar};
+use types::{SortKind,Value,Datatype,FieldSort,ObjectiveValue};
+use parser::{CheckSatResult,SExpr,StatValue};
+use model::Model;
+use options::{Logic,OptionValue};
+use num_bigint::BigUint;
+use std::collections::HashMap;
 
 pub struct Simplify<B>(B);
 
@@ -187,9 +191,16 @@ impl<B : Embed> Embed for Simplify<B> {
                    -> Result<Self::Sort,Self::Error> {
         self.0.type_of_arg(f,arg)
     }
+    fn datatype(&mut self,dt: usize)
+                -> Result<Datatype<Self::Sort>,Self::Error> {
+        self.0.datatype(dt)
+    }
 }
 
 impl<B : Backend> Backend for Simplify<B> {
+    fn unsupported(what: &str) -> Self::Error {
+        B::unsupported(what)
+    }
     fn enable_models(&mut self) -> Result<(),Self::Error> {
         self.0.enable_models()
     }
@@ -202,12 +213,30 @@ impl<B : Backend> Backend for Simplify<B> {
     fn enable_interpolants(&mut self) -> Result<(),Self::Error> {
         self.0.enable_interpolants()
     }
+    fn set_logic(&mut self,logic: Logic) -> Result<(),Self::Error> {
+        self.0.set_logic(logic)
+    }
+    fn set_option(&mut self,name: &str,value: OptionValue) -> Result<(),Self::Error> {
+        self.0.set_option(name,value)
+    }
+    fn get_option(&mut self,name: &str) -> Result<OptionValue,Self::Error> {
+        self.0.get_option(name)
+    }
+    fn set_info(&mut self,name: &str,value: OptionValue) -> Result<(),Self::Error> {
+        self.0.set_info(name,value)
+    }
     fn solver_name(&mut self) -> Result<String,Self::Error> {
         self.0.solver_name()
     }
     fn solver_version(&mut self) -> Result<String,Self::Error> {
         self.0.solver_version()
     }
+    fn reason_unknown(&mut self) -> Result<String,Self::Error> {
+        self.0.reason_unknown()
+    }
+    fn statistics(&mut self) -> Result<HashMap<String,StatValue>,Self::Error> {
+        self.0.statistics()
+    }
     fn comment(&mut self,comment: &str) -> Result<(),Self::Error> {
         self.0.comment(comment)
     }
@@ -226,10 +255,91 @@ impl<B : Backend> Backend for Simplify<B> {
     fn assert(&mut self,e: Self::Expr) -> Result<(),Self::Error> {
         self.0.assert(e)
     }
+    fn assert_named(&mut self,e: Self::Expr) -> Result<AssertionId,Self::Error> {
+        // Named assertions are always passed on, even if they are trivial,
+        // since they have to be able to show up in unsat cores.
+        self.0.assert_named(e)
+    }
+    fn get_unsat_core(&mut self) -> Result<Vec<AssertionId>,Self::Error> {
+        self.0.get_unsat_core()
+    }
     fn check_sat(&mut self) -> Result<CheckSatResult,Self::Error> {
         self.0.check_sat()
     }
+    fn check_sat_assuming(&mut self,assumptions: &[Self::Expr]) -> Result<CheckSatResult,Self::Error> {
+        self.0.check_sat_assuming(assumptions)
+    }
+    fn get_unsat_assumptions(&mut self) -> Result<Vec<Self::Expr>,Self::Error> {
+        self.0.get_unsat_assumptions()
+    }
+    fn get_proof(&mut self) -> Result<SExpr,Self::Error> {
+        self.0.get_proof()
+    }
+    fn get_interpolant(&mut self,a: &[Self::Expr],b: &[Self::Expr])
+                       -> Result<Option<Self::Expr>,Self::Error> {
+        self.0.get_interpolant(a,b)
+    }
+    fn eliminate_quantifiers(&mut self,e: Self::Expr) -> Result<Self::Expr,Self::Error> {
+        self.0.eliminate_quantifiers(e)
+    }
+    fn solver_simplify(&mut self,e: Self::Expr) -> Result<Self::Expr,Self::Error> {
+        self.0.solver_simplify(e)
+    }
     fn get_value(&mut self,e: Self::Expr) -> Result<Value,Self::Error> {
         self.0.get_value(e)
     }
+    fn get_values(&mut self,es: Vec<Self::Expr>) -> Result<Vec<Value>,Self::Error> {
+        self.0.get_values(es)
+    }
+    fn get_model(&mut self)
+                 -> Result<Model<Self::Sort,Self::Var,Self::Expr,Self::Fun>,Self::Error> {
+        self.0.get_model()
+    }
+    fn declare_datatypes(&mut self,dts: Vec<Datatype<FieldSort<Self::Sort>>>)
+                         -> Result<usize,Self::Error> {
+        self.0.declare_datatypes(dts)
+    }
+    fn declare_fun(&mut self,args: Vec<Self::Sort>,ret: Self::Sort,name: Option<String>)
+                   -> Result<Self::Fun,Self::Error> {
+        self.0.declare_fun(args,ret,name)
+    }
+    fn define_fun(&mut self,params: Vec<NVar<Self::Sort>>,body: Self::Expr)
+                  -> Result<Self::Fun,Self::Error> {
+        self.0.define_fun(params,body)
+    }
+    fn declare_funs_rec(&mut self,sigs: &[(Vec<NVar<Self::Sort>>,Self::Sort)])
+                        -> Result<Vec<Self::Fun>,Self::Error> {
+        self.0.declare_funs_rec(sigs)
+    }
+    fn emit_funs_rec(&mut self,funs: &[Self::Fun],sigs: &[(Vec<NVar<Self::Sort>>,Self::Sort)],
+                     bodies: Vec<Self::Expr>) -> Result<(),Self::Error> {
+        self.0.emit_funs_rec(funs,sigs,bodies)
+    }
+    fn declare_sort(&mut self,arity: usize) -> Result<usize,Self::Error> {
+        self.0.declare_sort(arity)
+    }
+    fn define_sort(&mut self,params: usize,body: Self::Sort)
+                   -> Result<usize,Self::Error> {
+        self.0.define_sort(params,body)
+    }
+    fn defined_sort(&mut self,id: usize,args: Vec<Self::Sort>)
+                    -> Result<Self::Sort,Self::Error> {
+        self.0.defined_sort(id,args)
+    }
+}
+
+impl<B : Optimizer> Optimizer for Simplify<B> {
+    fn minimize(&mut self,e: Self::Expr) -> Result<ObjectiveId,Self::Error> {
+        self.0.minimize(e)
+    }
+    fn maximize(&mut self,e: Self::Expr) -> Result<ObjectiveId,Self::Error> {
+        self.0.maximize(e)
+    }
+    fn assert_soft(&mut self,e: Self::Expr,weight: BigUint,group: Option<&str>)
+                   -> Result<ObjectiveId,Self::Error> {
+        self.0.assert_soft(e,weight,group)
+    }
+    fn get_objectives(&mut self) -> Result<Vec<ObjectiveValue>,Self::Error> {
+        self.0.get_objectives()
+    }
 }
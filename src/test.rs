@@ -1,30 +1,40 @@
-use expr::{Expr};
-use types::{Sort,SortKind};
+use expr::{Expr,Function};
+use types::{Sort,SortKind,Datatype,Value};
 use embed::Embed;
-use parser::Parser;
+use parser::{Parser,CheckSatResult};
+use backend::Backend;
+use options::Logic;
 use composite::Transformation;
 use std::collections::HashMap;
 use std::hash::Hash;
 use std::str;
 use std::fmt::Debug;
 use std::clone::Clone;
+use num_bigint::BigInt;
+use num_rational::Ratio;
 
 #[derive(Debug,PartialEq,Eq,Clone,Hash)]
 pub struct SimpleExpr<V>(Expr<Sort,V,Box<SimpleExpr<V>>,()>);
 
 #[derive(Debug,PartialEq,Eq,Clone)]
 pub struct Simple<V : Eq + Hash + Debug> {
-    vars: HashMap<V,Sort>
+    vars: HashMap<V,Sort>,
+    datatypes: Vec<Datatype<Sort>>
 }
 
 impl<V : Eq + Hash + Debug + Clone> Simple<V> {
     pub fn new() -> Simple<V> {
-        Simple { vars: HashMap::new() }
+        Simple { vars: HashMap::new(),
+                 datatypes: Vec::new() }
     }
     pub fn add_var(&mut self,v: V,s: Sort) -> Result<Box<SimpleExpr<V>>,()> {
         self.vars.insert(v.clone(),s);
         self.embed(Expr::Var(v))
     }
+    pub fn add_datatype(&mut self,dt: Datatype<Sort>) -> usize {
+        self.datatypes.push(dt);
+        self.datatypes.len()-1
+    }
 }
 
 impl<V : Clone + Eq + Hash + Debug> Embed for Simple<V> {
@@ -70,6 +80,9 @@ impl<V : Clone + Eq + Hash + Debug> Embed for Simple<V> {
         let SimpleExpr(ref re) = **e;
         re.sort(self)
     }
+    fn datatype(&mut self,dt: usize) -> Result<Datatype<Sort>,()> {
+        Ok(self.datatypes[dt].clone())
+    }
 }
 
 impl Parser for Simple<u64> {
@@ -91,6 +104,58 @@ impl Parser for Simple<u64> {
     }
 }
 
+/// A backend implementing only the required methods
+impl Backend for Simple<usize> {
+    fn unsupported(_: &str) -> () {
+        ()
+    }
+    fn enable_models(&mut self) -> Result<(),()> {
+        Ok(())
+    }
+    fn enable_proofs(&mut self) -> Result<(),()> {
+        Err(())
+    }
+    fn enable_unsat_cores(&mut self) -> Result<(),()> {
+        Err(())
+    }
+    fn enable_interpolants(&mut self) -> Result<(),()> {
+        Err(())
+    }
+    fn solver_name(&mut self) -> Result<String,()> {
+        Ok(String::from("simple"))
+    }
+    fn solver_version(&mut self) -> Result<String,()> {
+        Ok(String::from("0"))
+    }
+    fn comment(&mut self,_: &str) -> Result<(),()> {
+        Ok(())
+    }
+    fn push(&mut self) -> Result<(),()> {
+        Ok(())
+    }
+    fn pop(&mut self) -> Result<(),()> {
+        Ok(())
+    }
+    fn declare_var(&mut self,srt: Sort,_: Option<String>) -> Result<usize,()> {
+        let var = self.vars.len();
+        self.vars.insert(var,srt);
+        Ok(var)
+    }
+    fn define_var(&mut self,e: Box<SimpleExpr<usize>>) -> Result<usize,()> {
+        let srt = self.type_of(&e)?;
+        self.declare_var(srt,None)
+    }
+    fn assert(&mut self,_: Box<SimpleExpr<usize>>) -> Result<(),()> {
+        Ok(())
+    }
+    fn check_sat(&mut self) -> Result<CheckSatResult,()> {
+        Ok(CheckSatResult::Unknown)
+    }
+    fn get_value(&mut self,_: Box<SimpleExpr<usize>>) -> Result<Value,()> {
+        Err(())
+    }
+}
+
 /// Transformation tests
 #[test]
 fn test_transformation() {
@@ -105,3 +170,42 @@ fn test_transformation() {
     let res = tr1.get_all(&ivec,&mut em).unwrap();
     assert_eq!(res,vec![ctrue,cfalse]);
 }
+
+/// The array is the first argument of a select
+#[test]
+fn test_embed_select() {
+    let mut em : Simple<usize> = Simple::new();
+    let tint = em.tp_int().unwrap();
+    let tbool = em.tp_bool().unwrap();
+    let tarr = em.tp_array(vec![tint.clone()],tbool.clone()).unwrap();
+    let arr = em.add_var(0,tarr).unwrap();
+    let idx = em.const_int(BigInt::from(3)).unwrap();
+    let sel = em.select(arr.clone(),vec![idx.clone()]).unwrap();
+    assert_eq!(em.unbed(&sel).unwrap(),
+               Expr::App(Function::Select(vec![tint],tbool.clone()),vec![arr,idx]));
+    assert_eq!(em.type_of(&sel),Ok(tbool));
+}
+
+/// Negative numbers are printed as SMT-LIB terms
+#[test]
+fn test_value_display() {
+    assert_eq!(format!("{}",Value::Int(BigInt::from(5))),"5");
+    assert_eq!(format!("{}",Value::Int(BigInt::from(-5))),"(- 5)");
+    assert_eq!(format!("{}",Value::Real(Ratio::new(BigInt::from(3),BigInt::from(2)))),"(/ 3 2)");
+    assert_eq!(format!("{}",Value::Real(Ratio::new(BigInt::from(-3),BigInt::from(2)))),
+               "(- (/ 3 2))");
+}
+
+/// The optional commands of a backend default to unsupported
+#[test]
+fn test_backend_defaults() {
+    let mut b : Simple<usize> = Simple::new();
+    let tbool = b.tp_bool().unwrap();
+    let x = b.declare(tbool.clone()).unwrap();
+    assert_eq!(b.set_logic(Logic::QF_UF),Err(()));
+    assert_eq!(b.check_sat_assuming(&[x]),Err(()));
+    assert_eq!(b.declare_fun(vec![],tbool,None),Err(()));
+    assert_eq!(b.get_unsat_core(),Err(()));
+    assert!(b.get_model().is_err());
+    assert_eq!(b.check_sat(),Ok(CheckSatResult::Unknown));
+}
@@ -3,6 +3,7 @@ extern crate num_rational;
 
 use self::num_bigint::{BigInt,BigUint,ToBigInt};
 use self::num_rational::Ratio;
+use num_traits::Signed;
 use embed::Embed;
 use std::fmt::{Display,Formatter,Error};
 use std::ops::Shl;
@@ -13,7 +14,12 @@ pub enum SortKind<T> {
     Int,
     Real,
     BitVec(usize),
-    Array(Vec<T>,T)
+    Array(Vec<T>,T),
+    FloatingPoint(usize,usize), // exponent bits, significand bits
+    RoundingMode,
+    Datatype(usize,Vec<T>), // datatype id, parameters
+    Param(usize), // sort parameter of a datatype or sort definition
+    Uninterpreted(usize,Vec<T>) // declared sort, arguments
 }
 
 #[derive(Clone,Debug,PartialEq,Eq,PartialOrd,Ord,Hash)]
@@ -21,12 +27,77 @@ pub enum Value {
     Bool(bool),
     Int(BigInt),
     Real(Ratio<BigInt>),
-    BitVec(usize,BigUint)
+    BitVec(usize,BigUint),
+    Float(usize,usize,BigUint), // exponent bits, significand bits, IEEE bit pattern
+    RoundingMode(RoundingMode),
+    Datatype(Sort,usize,Vec<Value>), // datatype sort, constructor, fields
+    Uninterpreted(Sort,String), // uninterpreted sort, abstract value
+    /// An array mapping every index not listed in `entries` to `default`.
+    /// The entries are sorted by their index. Arrays with several index
+    /// sorts are nested: The entries are indexed by the first index sort
+    /// and hold arrays over the remaining ones.
+    Array { index: Vec<Sort>, element: Sort, default: Box<Value>, entries: Vec<(Value,Value)> }
+}
+
+/// The optimal value of an optimization objective.
+#[derive(Clone,Debug,PartialEq,Eq,PartialOrd,Ord,Hash)]
+pub enum ObjectiveValue {
+    Finite(Value),
+    /// The objective is unbounded, `true` for positive infinity (`oo`)
+    Infinite(bool),
+    /// The bound is only approached: `true` for value plus `epsilon`,
+    /// `false` for value minus `epsilon`
+    Epsilon(Value,bool)
+}
+
+#[derive(Clone,Copy,Debug,PartialEq,Eq,PartialOrd,Ord,Hash)]
+pub enum RoundingMode {
+    RNE, // round nearest, ties to even
+    RNA, // round nearest, ties away from zero
+    RTP, // round toward positive
+    RTN, // round toward negative
+    RTZ  // round toward zero
 }
 
 #[derive(Clone,Debug,PartialEq,Eq,PartialOrd,Ord,Hash)]
 pub struct Sort(SortKind<Box<Sort>>);
 
+/// An algebraic datatype with `params` sort parameters. Every constructor
+/// is given by the sorts of its fields, which can refer to the parameters
+/// via `SortKind::Param`.
+#[derive(Clone,Debug,PartialEq,Eq,Hash)]
+pub struct Datatype<T> {
+    pub params: usize,
+    pub constructors: Vec<Vec<T>>
+}
+
+/// The sort of a field in a datatype declaration. Since datatypes can be
+/// mutually recursive, a field can refer to the n-th datatype of the
+/// declaration it is part of.
+#[derive(Clone,Debug,PartialEq,Eq,Hash)]
+pub enum FieldSort<T> {
+    Sort(T),
+    Rec(usize,Vec<FieldSort<T>>)
+}
+
+impl<T : Clone> FieldSort<T> {
+    /// Turn the field sort into a proper sort, given the id of the first
+    /// datatype in the declaration.
+    pub fn resolve<Em : Embed<Sort=T>>(&self,base: usize,em: &mut Em)
+                                       -> Result<T,Em::Error> {
+        match *self {
+            FieldSort::Sort(ref srt) => Ok(srt.clone()),
+            FieldSort::Rec(n,ref args) => {
+                let mut nargs = Vec::with_capacity(args.len());
+                for arg in args.iter() {
+                    nargs.push(arg.resolve(base,em)?);
+                }
+                em.tp_datatype(base+n,nargs)
+            }
+        }
+    }
+}
+
 /// Get the signed value from a bitvector
 pub fn bv_signed_value(bw: usize,val: &BigUint) -> BigInt {
     let limit = BigUint::from(1 as u8).shl(bw-1);
@@ -68,8 +139,103 @@ impl Value {
             Value::Bool(_) => em.tp_bool(),
             Value::Int(_) => em.tp_int(),
             Value::Real(_) => em.tp_real(),
-            Value::BitVec(sz,_) => em.tp_bitvec(sz)
+            Value::BitVec(sz,_) => em.tp_bitvec(sz),
+            Value::Float(eb,sb,_) => em.tp_float(eb,sb),
+            Value::RoundingMode(_) => em.tp_rounding_mode(),
+            Value::Datatype(ref srt,_,_) => srt.embed(em),
+            Value::Uninterpreted(ref srt,_) => srt.embed(em),
+            Value::Array { ref index, ref element, .. } => {
+                let mut nidx = Vec::with_capacity(index.len());
+                for i in index.iter() {
+                    nidx.push(i.embed(em)?);
+                }
+                let nel = element.embed(em)?;
+                em.embed_sort(SortKind::Array(nidx,nel))
+            }
+        }
+    }
+    /// Build an array value from its index and element sorts, a default
+    /// value and entries which list a value for every index sort. Like a
+    /// chain of `store`s, later entries override earlier ones for the same
+    /// indices.
+    pub fn array(index: Vec<Sort>,element: Sort,default: Value,
+                 entries: Vec<(Vec<Value>,Value)>) -> Value {
+        let mut res = Value::const_array(&index,&element,default);
+        for (idx,val) in entries.into_iter() {
+            res.store(&idx,val);
         }
+        res
+    }
+    /// An array mapping all indices to the same value.
+    fn const_array(index: &[Sort],element: &Sort,default: Value) -> Value {
+        let inner = if index.len()>1 {
+            Value::const_array(&index[1..],element,default)
+        } else {
+            default
+        };
+        Value::Array { index: index.to_vec(),
+                       element: element.clone(),
+                       default: Box::new(inner),
+                       entries: Vec::new() }
+    }
+    /// Look up the element of an array value at the given indices, like the
+    /// `select` function.
+    ///
+    /// # Panics
+    ///
+    /// If the value isn't an array with as many index sorts as indices are
+    /// given.
+    pub fn select(&self,idx: &[Value]) -> &Value {
+        match *self {
+            Value::Array { ref index, ref default, ref entries, .. } => {
+                assert_eq!(index.len(),idx.len());
+                let el = match entries.binary_search_by(|&(ref k,_)| k.cmp(&idx[0])) {
+                    Ok(i) => &entries[i].1,
+                    Err(_) => &**default
+                };
+                if idx.len()==1 {
+                    el
+                } else {
+                    el.select(&idx[1..])
+                }
+            },
+            _ => panic!("Cannot select from non-array value {}",self)
+        }
+    }
+    /// Update an array value at the given indices, like the `store` function.
+    ///
+    /// # Panics
+    ///
+    /// If the value isn't an array with as many index sorts as indices are
+    /// given.
+    pub fn store(&mut self,idx: &[Value],val: Value) {
+        match *self {
+            Value::Array { ref index, ref default, ref mut entries, .. } => {
+                assert_eq!(index.len(),idx.len());
+                // Entries are kept sorted by their index
+                let i = match entries.binary_search_by(|&(ref k,_)| k.cmp(&idx[0])) {
+                    Ok(i) => i,
+                    Err(i) => {
+                        entries.insert(i,(idx[0].clone(),(**default).clone()));
+                        i
+                    }
+                };
+                if idx.len()==1 {
+                    entries[i].1 = val
+                } else {
+                    entries[i].1.store(&idx[1..],val)
+                }
+            },
+            _ => panic!("Cannot store into non-array value {}",self)
+        }
+    }
+    /// Create a single precision floating point value
+    pub fn from_f32(v: f32) -> Value {
+        Value::Float(8,24,BigUint::from(v.to_bits()))
+    }
+    /// Create a double precision floating point value
+    pub fn from_f64(v: f64) -> Value {
+        Value::Float(11,53,BigUint::from(v.to_bits()))
     }
 }
 
@@ -86,6 +252,23 @@ impl<T> SortKind<T> {
                     narr.push(f(e))
                 }
                 SortKind::Array(narr,f(el))
+            },
+            SortKind::FloatingPoint(eb,sb) => SortKind::FloatingPoint(eb,sb),
+            SortKind::RoundingMode => SortKind::RoundingMode,
+            SortKind::Datatype(id,ref args) => {
+                let mut nargs = Vec::with_capacity(args.len());
+                for arg in args.iter() {
+                    nargs.push(f(arg))
+                }
+                SortKind::Datatype(id,nargs)
+            },
+            SortKind::Param(n) => SortKind::Param(n),
+            SortKind::Uninterpreted(id,ref args) => {
+                let mut nargs = Vec::with_capacity(args.len());
+                for arg in args.iter() {
+                    nargs.push(f(arg))
+                }
+                SortKind::Uninterpreted(id,nargs)
             }
         }
     }
@@ -101,6 +284,23 @@ impl<T> SortKind<T> {
                     narr.push(f(e))
                 }
                 SortKind::Array(narr,f(el))
+            },
+            SortKind::FloatingPoint(eb,sb) => SortKind::FloatingPoint(eb,sb),
+            SortKind::RoundingMode => SortKind::RoundingMode,
+            SortKind::Datatype(id,mut args) => {
+                let mut nargs = Vec::with_capacity(args.len());
+                for arg in args.drain(0..) {
+                    nargs.push(f(arg))
+                }
+                SortKind::Datatype(id,nargs)
+            },
+            SortKind::Param(n) => SortKind::Param(n),
+            SortKind::Uninterpreted(id,mut args) => {
+                let mut nargs = Vec::with_capacity(args.len());
+                for arg in args.drain(0..) {
+                    nargs.push(f(arg))
+                }
+                SortKind::Uninterpreted(id,nargs)
             }
         }
     }
@@ -119,27 +319,153 @@ impl<T : Display> Display for SortKind<T> {
                     write!(f,"{} ",i)?;
                 }
                 write!(f,"{})",el)
+            },
+            SortKind::FloatingPoint(eb,sb) => write!(f,"(_ FloatingPoint {} {})",eb,sb),
+            SortKind::RoundingMode => write!(f,"RoundingMode"),
+            SortKind::Datatype(id,ref args) => if args.len()==0 {
+                write!(f,"d{}",id)
+            } else {
+                write!(f,"(d{}",id)?;
+                for arg in args.iter() {
+                    write!(f," {}",arg)?;
+                }
+                write!(f,")")
+            },
+            SortKind::Param(n) => write!(f,"p{}",n),
+            SortKind::Uninterpreted(id,ref args) => if args.len()==0 {
+                write!(f,"u{}",id)
+            } else {
+                write!(f,"(u{}",id)?;
+                for arg in args.iter() {
+                    write!(f," {}",arg)?;
+                }
+                write!(f,")")
             }
         }
     }
 }
 
+impl Display for RoundingMode {
+    fn fmt(&self, f: &mut Formatter) -> Result<(),Error> {
+        match *self {
+            RoundingMode::RNE => f.write_str("RNE"),
+            RoundingMode::RNA => f.write_str("RNA"),
+            RoundingMode::RTP => f.write_str("RTP"),
+            RoundingMode::RTN => f.write_str("RTN"),
+            RoundingMode::RTZ => f.write_str("RTZ")
+        }
+    }
+}
+
 impl Display for Value {
     fn fmt(&self, f: &mut Formatter) -> Result<(),Error> {
         match *self {
             Value::Bool(true) => f.write_str("true"),
             Value::Bool(false) => f.write_str("false"),
-            Value::Int(ref v) => v.fmt(f),
-            Value::Real(ref rv) => write!(f,"(/ {} {})",rv.numer(),rv.denom()),
+            Value::Int(ref v) => if v.is_negative() {
+                write!(f,"(- {})",-v)
+            } else {
+                v.fmt(f)
+            },
+            Value::Real(ref rv) => if rv.numer().is_negative() {
+                write!(f,"(- (/ {} {}))",-rv.numer(),rv.denom())
+            } else {
+                write!(f,"(/ {} {})",rv.numer(),rv.denom())
+            },
             Value::BitVec(sz,ref v) => if sz%4==0 {
                 write!(f,"#x{0:01$X}",v,sz/4)
             } else {
                 write!(f,"#b{0:01$b}",v,sz)
+            },
+            Value::Float(eb,sb,ref v) => {
+                let sig_mask = (BigUint::from(1 as u8) << (sb-1)) - (1 as u8);
+                let exp_mask = (BigUint::from(1 as u8) << eb) - (1 as u8);
+                let sign = v >> (eb+sb-1);
+                let exp = (v >> (sb-1)) & exp_mask;
+                let sig = v & sig_mask;
+                write!(f,"(fp #b{} #b{1:02$b} #b{3:04$b})",sign,exp,eb,sig,sb-1)
+            },
+            Value::RoundingMode(rm) => rm.fmt(f),
+            Value::Datatype(ref srt,con,ref fields) => {
+                let (id,parametric) = match srt.0 {
+                    SortKind::Datatype(id,ref args) => (id,args.len()>0),
+                    _ => panic!("Datatype value with non-datatype sort {}",srt)
+                };
+                if fields.len()>0 {
+                    write!(f,"(")?;
+                }
+                if parametric {
+                    write!(f,"(as d{}c{} {})",id,con,srt)?;
+                } else {
+                    write!(f,"d{}c{}",id,con)?;
+                }
+                if fields.len()>0 {
+                    for field in fields.iter() {
+                        write!(f," {}",field)?;
+                    }
+                    write!(f,")")?;
+                }
+                Ok(())
+            },
+            Value::Uninterpreted(ref srt,ref name) => write!(f,"(as {} {})",name,srt),
+            Value::Array { ref index, ref element, ref default, ref entries } => if index.len()==1 {
+                for _ in entries.iter() {
+                    write!(f,"(store ")?;
+                }
+                write!(f,"((as const (Array {} {})) {})",index[0],element,default)?;
+                for &(ref idx,ref val) in entries.iter() {
+                    write!(f," {} {})",idx,val)?;
+                }
+                Ok(())
+            } else {
+                // Nested entries can't be expressed by store chains
+                write!(f,"(lambda (")?;
+                for (i,srt) in index.iter().enumerate() {
+                    if i>0 {
+                        write!(f," ")?;
+                    }
+                    write!(f,"(i{} {})",i,srt)?;
+                }
+                write!(f,") ")?;
+                fmt_array_body(self,0,index.len(),f)?;
+                write!(f,")")
             }
         }
     }
 }
 
+/// Print the body of a lambda expression for a nested array value, in which
+/// the indices are called `i0`, `i1`, ...
+fn fmt_array_body(val: &Value,depth: usize,indices: usize,f: &mut Formatter) -> Result<(),Error> {
+    match *val {
+        Value::Array { ref default, ref entries, .. } if depth<indices => {
+            for &(ref idx,ref el) in entries.iter() {
+                write!(f,"(ite (= i{} {}) ",depth,idx)?;
+                fmt_array_body(el,depth+1,indices,f)?;
+                write!(f," ")?;
+            }
+            fmt_array_body(default,depth+1,indices,f)?;
+            for _ in entries.iter() {
+                write!(f,")")?;
+            }
+            Ok(())
+        },
+        _ => val.fmt(f)
+    }
+}
+
+impl Display for ObjectiveValue {
+    fn fmt(&self, f: &mut Formatter) -> Result<(),Error> {
+        match *self {
+            ObjectiveValue::Finite(ref v) => v.fmt(f),
+            ObjectiveValue::Infinite(true) => f.write_str("oo"),
+            ObjectiveValue::Infinite(false) => f.write_str("(- oo)"),
+            ObjectiveValue::Epsilon(ref v,true) => write!(f,"(+ {} epsilon)",v),
+            ObjectiveValue::Epsilon(ref v,false) => write!(f,"(- {} epsilon)",v)
+        }
+    }
+}
+
 impl Sort {
     pub fn embed<Em : Embed>(&self,em: &mut Em)
                              -> Result<Em::Sort,Em::Error> {
@@ -156,6 +482,25 @@ impl Sort {
                 }
                 let nel = el.embed(em)?;
                 em.embed_sort(SortKind::Array(nidx,nel))
+            },
+            SortKind::FloatingPoint(eb,sb) => em.embed_sort(SortKind::FloatingPoint(eb,sb)),
+            SortKind::RoundingMode => em.embed_sort(SortKind::RoundingMode),
+            SortKind::Datatype(id,ref args) => {
+                let mut nargs = Vec::with_capacity(args.len());
+                for arg in args.iter() {
+                    let narg = arg.embed(em)?;
+                    nargs.push(narg)
+                }
+                em.embed_sort(SortKind::Datatype(id,nargs))
+            },
+            SortKind::Param(n) => em.embed_sort(SortKind::Param(n)),
+            SortKind::Uninterpreted(id,ref args) => {
+                let mut nargs = Vec::with_capacity(args.len());
+                for arg in args.iter() {
+                    let narg = arg.embed(em)?;
+                    nargs.push(narg)
+                }
+                em.embed_sort(SortKind::Uninterpreted(id,nargs))
             }
         }
     }
@@ -173,6 +518,25 @@ impl Sort {
                 }
                 let nel = Sort::from_embed(&el,em)?;
                 Ok(Sort(SortKind::Array(nidx,Box::new(nel))))
+            },
+            SortKind::FloatingPoint(eb,sb) => Ok(Sort(SortKind::FloatingPoint(eb,sb))),
+            SortKind::RoundingMode => Ok(Sort(SortKind::RoundingMode)),
+            SortKind::Datatype(id,args) => {
+                let mut nargs = Vec::with_capacity(args.len());
+                for arg in args.iter() {
+                    let narg = Sort::from_embed(arg,em)?;
+                    nargs.push(Box::new(narg));
+                }
+                Ok(Sort(SortKind::Datatype(id,nargs)))
+            },
+            SortKind::Param(n) => Ok(Sort(SortKind::Param(n))),
+            SortKind::Uninterpreted(id,args) => {
+                let mut nargs = Vec::with_capacity(args.len());
+                for arg in args.iter() {
+                    let narg = Sort::from_embed(arg,em)?;
+                    nargs.push(Box::new(narg));
+                }
+                Ok(Sort(SortKind::Uninterpreted(id,nargs)))
             }
         }
     }
@@ -0,0 +1,1600 @@
+use expr::{Expr,Function,NVar,ArithOp,OrdOp,BVOp,FPOp};
+use types::{SortKind,Value,Datatype,FieldSort,RoundingMode};
+use embed::Embed;
+use backend::{Backend,AssertionId};
+use model::Model;
+use options::{Logic,OptionValue};
+use parser::*;
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::ffi::{CStr,CString};
+use std::os::raw::c_uint;
+use std::ptr;
+use std::str;
+use std::str::FromStr;
+use num_bigint::BigUint;
+#[cfg(feature="test-z3-native")]
+use num_bigint::BigInt;
+use self::ffi::*;
+
+/// A backend which runs Z3 in-process using its C API. Expressions are
+/// translated to Z3 terms as soon as they are embedded, so no textual
+/// round-trip is needed to build and assert them. Symbols are named like
+/// the ones of a `Pipe`.
+pub struct Z3 {
+    ctx: Z3_context,
+    solver: Z3_solver,
+    /// The parameters set so far, which are re-applied when the solver is
+    /// replaced by `set_logic`
+    params: Z3_params,
+    proofs: bool,
+    sorts: Vec<(SortKind<Z3Sort>,Option<Z3_sort>)>,
+    sort_map: HashMap<SortKind<Z3Sort>,Z3Sort>,
+    exprs: Vec<(Expr<Z3Sort,Z3Var,Z3Expr,Z3Fun>,Z3_ast)>,
+    expr_map: HashMap<Expr<Z3Sort,Z3Var,Z3Expr,Z3Fun>,Z3Expr>,
+    /// Maps Z3 term ids back to expressions
+    asts: HashMap<c_uint,Z3Expr>,
+    vars: Vec<(Z3Sort,Z3_ast)>,
+    named_vars: HashMap<String,usize>,
+    funs: Vec<(Vec<Z3Sort>,Z3Sort,FunImpl)>,
+    named_funs: HashMap<String,usize>,
+    datatypes: Vec<Z3Datatype>,
+    declared_sorts: Vec<Z3_sort>,
+    defined_sorts: Vec<(usize,Z3Sort)>,
+    /// Maps the ids of the literals tracking named assertions to the assertions
+    assertions: HashMap<c_uint,usize>,
+    named_assertions: usize,
+    options: HashMap<String,OptionValue>,
+    logic: Option<Logic>,
+    declared: bool
+}
+
+enum FunImpl {
+    Declared(Z3_func_decl),
+    /// A function defined by `define_fun`, which is expanded when applied
+    Defined(Vec<Z3_ast>,Z3_ast)
+}
+
+struct Z3Datatype {
+    def: Datatype<Z3Sort>,
+    sort: Z3_sort,
+    /// Constructor, tester and selectors of every constructor
+    constructors: Vec<(Z3_func_decl,Z3_func_decl,Vec<Z3_func_decl>)>
+}
+
+#[derive(PartialEq,Eq,PartialOrd,Ord,Hash,Clone,Copy,Debug)]
+pub struct Z3Sort(usize);
+#[derive(PartialEq,Eq,PartialOrd,Ord,Hash,Clone,Copy,Debug)]
+pub struct Z3Var(usize);
+#[derive(PartialEq,Eq,PartialOrd,Ord,Hash,Clone,Copy,Debug)]
+pub struct Z3Expr(usize);
+pub type Z3Fun = usize;
+
+#[derive(Debug)]
+pub enum Z3Error {
+    Parse(ParseError<Z3Sort>),
+    /// A string returned by Z3 which could not be parsed
+    Syntax(SyntaxError<Z3Sort>),
+    /// A feature which cannot be mapped to Z3's API
+    Unsupported(String),
+    /// An error reported by Z3
+    Solver(String),
+    /// The logic was set twice or after declarations
+    MisplacedSetLogic
+}
+
+const Z3_VAR_NAME: &'static str = "v";
+const Z3_FUN_NAME: &'static str = "f";
+const Z3_ASSERTION_NAME: &'static str = "a";
+
+/// Call a Z3 API function and check whether it reported an error.
+macro_rules! z3 {
+    ($slf:expr,$f:ident($($arg:expr),*)) => {{
+        let ctx = $slf.ctx;
+        let res = unsafe { $f(ctx $(,$arg)*) };
+        $slf.check().map(|_| res)
+    }}
+}
+
+fn from_c_str(s: Z3_string) -> String {
+    unsafe { CStr::from_ptr(s) }.to_string_lossy().into_owned()
+}
+
+fn check_sat_result(res: Z3_lbool) -> CheckSatResult {
+    match res {
+        1 => CheckSatResult::Sat,
+        -1 => CheckSatResult::Unsat,
+        _ => CheckSatResult::Unknown
+    }
+}
+
+impl Z3 {
+    pub fn new() -> Result<Z3,Z3Error> {
+        Z3::with_params(&[])
+    }
+    /// Create a context with the given global parameters, like
+    /// `("proof","true")`, which is required for `get_proof`.
+    pub fn with_params(params: &[(&str,&str)]) -> Result<Z3,Z3Error> {
+        let mut proofs = false;
+        let ctx = unsafe {
+            let cfg = Z3_mk_config();
+            for &(name,value) in params.iter() {
+                if name=="proof" {
+                    proofs = value=="true";
+                }
+                let cname = CString::new(name).expect("Parameter name contains NUL");
+                let cvalue = CString::new(value).expect("Parameter value contains NUL");
+                Z3_set_param_value(cfg,cname.as_ptr(),cvalue.as_ptr());
+            }
+            let ctx = Z3_mk_context_rc(cfg);
+            Z3_del_config(cfg);
+            if ctx.is_null() {
+                return Err(Z3Error::Solver(String::from("Cannot create context")))
+            }
+            // Report errors via Z3_get_error_code instead of aborting
+            Z3_set_error_handler(ctx,None);
+            // Print models in SMT-LIB syntax, so that they can be parsed
+            Z3_set_ast_print_mode(ctx,Z3_PRINT_SMTLIB2_COMPLIANT);
+            ctx
+        };
+        let mut z3 = Z3 { ctx: ctx,
+                          solver: ptr::null_mut(),
+                          params: ptr::null_mut(),
+                          proofs: proofs,
+                          sorts: Vec::new(),
+                          sort_map: HashMap::new(),
+                          exprs: Vec::new(),
+                          expr_map: HashMap::new(),
+                          asts: HashMap::new(),
+                          vars: Vec::new(),
+                          named_vars: HashMap::new(),
+                          funs: Vec::new(),
+                          named_funs: HashMap::new(),
+                          datatypes: Vec::new(),
+                          declared_sorts: Vec::new(),
+                          defined_sorts: Vec::new(),
+                          assertions: HashMap::new(),
+                          named_assertions: 0,
+                          options: HashMap::new(),
+                          logic: None,
+                          declared: false };
+        z3.params = z3!(z3,Z3_mk_params())?;
+        unsafe { Z3_params_inc_ref(ctx,z3.params) };
+        z3.solver = z3!(z3,Z3_mk_solver())?;
+        unsafe { Z3_solver_inc_ref(ctx,z3.solver) };
+        Ok(z3)
+    }
+    fn check(&self) -> Result<(),Z3Error> {
+        let code = unsafe { Z3_get_error_code(self.ctx) };
+        if code==0 {
+            Ok(())
+        } else {
+            let msg = unsafe { Z3_get_error_msg(self.ctx,code) };
+            Err(Z3Error::Solver(from_c_str(msg)))
+        }
+    }
+    /// Keep a term alive until the context is deleted or it is released.
+    fn keep(&self,ast: Z3_ast) -> Z3_ast {
+        unsafe { Z3_inc_ref(self.ctx,ast) };
+        ast
+    }
+    fn release(&self,ast: Z3_ast) {
+        unsafe { Z3_dec_ref(self.ctx,ast) };
+    }
+    fn symbol(&self,name: &str) -> Z3_symbol {
+        let cname = CString::new(name).expect("Symbol contains NUL");
+        unsafe { Z3_mk_string_symbol(self.ctx,cname.as_ptr()) }
+    }
+    fn ast_to_string(&self,ast: Z3_ast) -> Result<String,Z3Error> {
+        let s = z3!(self,Z3_ast_to_string(ast))?;
+        Ok(from_c_str(s))
+    }
+    fn parse<T,F>(&mut self,s: String,parse: F) -> Result<T,Z3Error>
+        where F : for<'inp> Fn(&'inp[u8],&mut Pos,&mut Z3) -> PResult<'inp,T,Z3> {
+        let mut inp = s.into_bytes();
+        inp.push(b'\n');
+        let mut pos = Pos { line: 0, col: 0 };
+        match parse(&inp,&mut pos,self) {
+            PResult::Done(res,_) => Ok(res),
+            PResult::Incomplete => Err(Z3Error::Parse(ParseError::ExpectedExpr)),
+            PResult::SyntaxError(err,epos) => Err(Z3Error::Syntax(SyntaxError::new(err,epos,&inp))),
+            PResult::EmbedError(err) => Err(err)
+        }
+    }
+    /// Must be called before declaring a symbol or asserting.
+    fn declaration(&mut self) {
+        self.declared = true;
+    }
+    fn raw_sort(&self,srt: &Z3Sort) -> Result<Z3_sort,Z3Error> {
+        match self.sorts[srt.0] {
+            (_,Some(raw)) => Ok(raw),
+            (ref kind,None) => Err(Z3Error::Unsupported(format!("sort {:?}",kind)))
+        }
+    }
+    fn raw_sort_kind(&mut self,kind: SortKind<Z3Sort>) -> Result<Z3_sort,Z3Error> {
+        let srt = self.embed_sort(kind)?;
+        self.raw_sort(&srt)
+    }
+    fn raw_expr(&self,e: &Z3Expr) -> Z3_ast {
+        self.exprs[e.0].1
+    }
+    /// The constant representing a quantified or let-bound variable.
+    fn bound_var(&mut self,prefix: &str,var: &NVar<Z3Sort>) -> Result<Z3_ast,Z3Error> {
+        let srt = self.raw_sort(&var.sort)?;
+        let sym = self.symbol(&format!("{}{}",prefix,var.id));
+        let c = z3!(self,Z3_mk_const(sym,srt))?;
+        Ok(self.keep(c))
+    }
+    fn set_param(&self,params: Z3_params,name: &str,value: &OptionValue) -> Result<(),Z3Error> {
+        let key = self.symbol(name);
+        match *value {
+            OptionValue::Bool(b) => z3!(self,Z3_params_set_bool(params,key,b)),
+            OptionValue::Numeral(n) => if n > c_uint::max_value() as u64 {
+                Err(Z3Error::Unsupported(format!("{} {}",name,n)))
+            } else {
+                z3!(self,Z3_params_set_uint(params,key,n as c_uint))
+            },
+            OptionValue::Decimal(d) => z3!(self,Z3_params_set_double(params,key,d)),
+            OptionValue::String(ref s) | OptionValue::Symbol(ref s) => {
+                let val = self.symbol(s);
+                z3!(self,Z3_params_set_symbol(params,key,val))
+            }
+        }
+    }
+    /// Set a solver parameter (like `unsat_core`).
+    fn solver_param(&mut self,name: &str,value: &OptionValue) -> Result<(),Z3Error> {
+        // Set the parameter on its own first, so that an invalid parameter
+        // does not end up in the persistent parameter set
+        let params = z3!(self,Z3_mk_params())?;
+        unsafe { Z3_params_inc_ref(self.ctx,params) };
+        let res = self.set_param(params,name,value)
+            .and_then(|_| z3!(self,Z3_solver_set_params(self.solver,params)));
+        unsafe { Z3_params_dec_ref(self.ctx,params) };
+        res?;
+        self.set_param(self.params,name,value)
+    }
+    fn model(&self) -> Result<Z3_model,Z3Error> {
+        let model = z3!(self,Z3_solver_get_model(self.solver))?;
+        unsafe { Z3_model_inc_ref(self.ctx,model) };
+        Ok(model)
+    }
+    /// Translate the solver's answer back, using the sort as hint.
+    fn from_ast(&mut self,ast: Z3_ast,hint: &Z3Sort) -> Result<Z3Expr,Z3Error> {
+        let s = self.ast_to_string(ast)?;
+        let hint = *hint;
+        self.parse(s,|inp,pos,p| parse_expr(inp,pos,p,Some(&hint)))
+    }
+    fn mk_numeral(&mut self,num: &str,kind: SortKind<Z3Sort>) -> Result<Z3_ast,Z3Error> {
+        let srt = self.raw_sort_kind(kind)?;
+        let cnum = CString::new(num).expect("Numeral contains NUL");
+        let res = z3!(self,Z3_mk_numeral(cnum.as_ptr(),srt))?;
+        Ok(self.keep(res))
+    }
+    fn mk_value(&mut self,val: &Value) -> Result<Z3_ast,Z3Error> {
+        let res = match *val {
+            Value::Bool(true) => z3!(self,Z3_mk_true())?,
+            Value::Bool(false) => z3!(self,Z3_mk_false())?,
+            Value::Int(ref v) => return self.mk_numeral(&v.to_string(),SortKind::Int),
+            Value::Real(ref v) => return self.mk_numeral(&format!("{}/{}",v.numer(),v.denom()),
+                                                         SortKind::Real),
+            Value::BitVec(bw,ref v) => return self.mk_numeral(&v.to_string(),SortKind::BitVec(bw)),
+            Value::Float(eb,sb,ref v) => {
+                let sig_mask = (BigUint::from(1 as u8) << (sb-1)) - (1 as u8);
+                let exp_mask = (BigUint::from(1 as u8) << eb) - (1 as u8);
+                let sign = self.mk_numeral(&(v >> (eb+sb-1)).to_string(),SortKind::BitVec(1))?;
+                let exp = self.mk_numeral(&((v >> (sb-1)) & exp_mask).to_string(),
+                                          SortKind::BitVec(eb))?;
+                let sig = self.mk_numeral(&(v & sig_mask).to_string(),SortKind::BitVec(sb-1))?;
+                let res = z3!(self,Z3_mk_fpa_fp(sign,exp,sig))?;
+                self.keep(res);
+                for part in [sign,exp,sig].iter() {
+                    self.release(*part);
+                }
+                return Ok(res)
+            },
+            Value::RoundingMode(rm) => match rm {
+                RoundingMode::RNE => z3!(self,Z3_mk_fpa_rne())?,
+                RoundingMode::RNA => z3!(self,Z3_mk_fpa_rna())?,
+                RoundingMode::RTP => z3!(self,Z3_mk_fpa_rtp())?,
+                RoundingMode::RTN => z3!(self,Z3_mk_fpa_rtn())?,
+                RoundingMode::RTZ => z3!(self,Z3_mk_fpa_rtz())?
+            },
+            Value::Datatype(ref srt,con,ref fields) => {
+                let dt = match srt.kind() {
+                    SortKind::Datatype(dt,ref args) if args.len()==0 => dt,
+                    _ => return Err(Z3Error::Unsupported(format!("value {}",val)))
+                };
+                let mut args = Vec::with_capacity(fields.len());
+                for field in fields.iter() {
+                    args.push(self.mk_value(field)?);
+                }
+                let decl = self.datatypes[dt].constructors[con].0;
+                let res = z3!(self,Z3_mk_app(decl,args.len() as c_uint,args.as_ptr()))?;
+                self.keep(res);
+                for arg in args.into_iter() {
+                    self.release(arg);
+                }
+                return Ok(res)
+            },
+            Value::Uninterpreted(_,_) => return Err(Z3Error::Unsupported(format!("value {}",val))),
+            Value::Array { ref index, ref default, ref entries, .. } => {
+                if index.len()!=1 {
+                    return Err(Z3Error::Unsupported(String::from("multi-dimensional arrays")))
+                }
+                let idx = index[0].embed(self)?;
+                let srt = self.raw_sort(&idx)?;
+                let def = self.mk_value(default)?;
+                let mut res = z3!(self,Z3_mk_const_array(srt,def))?;
+                self.keep(res);
+                self.release(def);
+                for &(ref i,ref v) in entries.iter() {
+                    let ri = self.mk_value(i)?;
+                    let rv = self.mk_value(v)?;
+                    let nres = z3!(self,Z3_mk_store(res,ri,rv))?;
+                    self.keep(nres);
+                    for part in [res,ri,rv].iter() {
+                        self.release(*part);
+                    }
+                    res = nres;
+                }
+                return Ok(res)
+            }
+        };
+        Ok(self.keep(res))
+    }
+    /// Apply a binary operation to all arguments from left to right.
+    fn fold(&mut self,args: &[Z3_ast],
+            op: unsafe extern "C" fn(Z3_context,Z3_ast,Z3_ast) -> Z3_ast)
+            -> Result<Z3_ast,Z3Error> {
+        let mut acc = self.keep(args[0]);
+        for arg in args[1..].iter() {
+            let res = z3!(self,op(acc,*arg))?;
+            self.keep(res);
+            self.release(acc);
+            acc = res;
+        }
+        Ok(acc)
+    }
+    /// Apply a binary operation to all arguments from right to left.
+    fn fold_right(&mut self,args: &[Z3_ast],
+                  op: unsafe extern "C" fn(Z3_context,Z3_ast,Z3_ast) -> Z3_ast)
+                  -> Result<Z3_ast,Z3Error> {
+        let mut acc = self.keep(args[args.len()-1]);
+        for arg in args[0..args.len()-1].iter().rev() {
+            let res = z3!(self,op(*arg,acc))?;
+            self.keep(res);
+            self.release(acc);
+            acc = res;
+        }
+        Ok(acc)
+    }
+    fn mk_app(&mut self,fun: &Function<Z3Sort,Z3Fun>,args: &[Z3_ast]) -> Result<Z3_ast,Z3Error> {
+        let n = args.len() as c_uint;
+        let p = args.as_ptr();
+        let res = match *fun {
+            Function::Fun(f) => match self.funs[f].2 {
+                FunImpl::Declared(decl) => z3!(self,Z3_mk_app(decl,n,p))?,
+                FunImpl::Defined(ref params,body)
+                    => z3!(self,Z3_substitute(body,n,params.as_ptr(),p))?
+            },
+            Function::Eq(_,_) => if args.len()==2 {
+                z3!(self,Z3_mk_eq(args[0],args[1]))?
+            } else if args.len()<2 {
+                z3!(self,Z3_mk_true())?
+            } else {
+                let mut eqs = Vec::with_capacity(args.len()-1);
+                for i in 1..args.len() {
+                    let eq = z3!(self,Z3_mk_eq(args[i-1],args[i]))?;
+                    eqs.push(self.keep(eq));
+                }
+                let res = z3!(self,Z3_mk_and(eqs.len() as c_uint,eqs.as_ptr()))?;
+                self.keep(res);
+                for eq in eqs.into_iter() {
+                    self.release(eq);
+                }
+                return Ok(res)
+            },
+            Function::Distinct(_,_) => z3!(self,Z3_mk_distinct(n,p))?,
+            Function::Map(_,_) => return Err(Z3Error::Unsupported(String::from("map"))),
+            Function::OrdInt(op) | Function::OrdReal(op) => match op {
+                OrdOp::Ge => z3!(self,Z3_mk_ge(args[0],args[1]))?,
+                OrdOp::Gt => z3!(self,Z3_mk_gt(args[0],args[1]))?,
+                OrdOp::Le => z3!(self,Z3_mk_le(args[0],args[1]))?,
+                OrdOp::Lt => z3!(self,Z3_mk_lt(args[0],args[1]))?
+            },
+            Function::ArithInt(op,_) | Function::ArithReal(op,_) => match op {
+                ArithOp::Add => z3!(self,Z3_mk_add(n,p))?,
+                ArithOp::Sub => if args.len()==1 {
+                    z3!(self,Z3_mk_unary_minus(args[0]))?
+                } else {
+                    z3!(self,Z3_mk_sub(n,p))?
+                },
+                ArithOp::Mult => z3!(self,Z3_mk_mul(n,p))?
+            },
+            Function::Div | Function::Divide => z3!(self,Z3_mk_div(args[0],args[1]))?,
+            Function::Mod => z3!(self,Z3_mk_mod(args[0],args[1]))?,
+            Function::Rem => z3!(self,Z3_mk_rem(args[0],args[1]))?,
+            Function::Exp => z3!(self,Z3_mk_power(args[0],args[1]))?,
+            Function::AbsInt | Function::AbsReal => {
+                let zero = if *fun==Function::AbsInt {
+                    self.mk_numeral("0",SortKind::Int)?
+                } else {
+                    self.mk_numeral("0",SortKind::Real)?
+                };
+                let pos = z3!(self,Z3_mk_ge(args[0],zero))?;
+                self.keep(pos);
+                let neg = z3!(self,Z3_mk_unary_minus(args[0]))?;
+                self.keep(neg);
+                let res = z3!(self,Z3_mk_ite(pos,args[0],neg))?;
+                self.keep(res);
+                for tmp in [zero,pos,neg].iter() {
+                    self.release(*tmp);
+                }
+                return Ok(res)
+            },
+            Function::Not => z3!(self,Z3_mk_not(args[0]))?,
+            Function::And(_) => if args.len()==0 {
+                z3!(self,Z3_mk_true())?
+            } else {
+                z3!(self,Z3_mk_and(n,p))?
+            },
+            Function::Or(_) => if args.len()==0 {
+                z3!(self,Z3_mk_false())?
+            } else {
+                z3!(self,Z3_mk_or(n,p))?
+            },
+            Function::XOr(_) => if args.len()==0 {
+                z3!(self,Z3_mk_false())?
+            } else {
+                return self.fold(args,Z3_mk_xor)
+            },
+            Function::Implies(_) => return self.fold_right(args,Z3_mk_implies),
+            Function::AtLeast(k,_) => z3!(self,Z3_mk_atleast(n,p,k as c_uint))?,
+            Function::AtMost(k,_) => z3!(self,Z3_mk_atmost(n,p,k as c_uint))?,
+            Function::ToReal => z3!(self,Z3_mk_int2real(args[0]))?,
+            Function::ToInt => z3!(self,Z3_mk_real2int(args[0]))?,
+            Function::ITE(_) => z3!(self,Z3_mk_ite(args[0],args[1],args[2]))?,
+            Function::BV(_,op) => match op {
+                BVOp::Ord(signed,op) => match (signed,op) {
+                    (true,OrdOp::Ge) => z3!(self,Z3_mk_bvsge(args[0],args[1]))?,
+                    (true,OrdOp::Gt) => z3!(self,Z3_mk_bvsgt(args[0],args[1]))?,
+                    (true,OrdOp::Le) => z3!(self,Z3_mk_bvsle(args[0],args[1]))?,
+                    (true,OrdOp::Lt) => z3!(self,Z3_mk_bvslt(args[0],args[1]))?,
+                    (false,OrdOp::Ge) => z3!(self,Z3_mk_bvuge(args[0],args[1]))?,
+                    (false,OrdOp::Gt) => z3!(self,Z3_mk_bvugt(args[0],args[1]))?,
+                    (false,OrdOp::Le) => z3!(self,Z3_mk_bvule(args[0],args[1]))?,
+                    (false,OrdOp::Lt) => z3!(self,Z3_mk_bvult(args[0],args[1]))?
+                },
+                BVOp::Arith(ArithOp::Add) => return self.fold(args,Z3_mk_bvadd),
+                BVOp::Arith(ArithOp::Sub) => if args.len()==1 {
+                    z3!(self,Z3_mk_bvneg(args[0]))?
+                } else {
+                    return self.fold(args,Z3_mk_bvsub)
+                },
+                BVOp::Arith(ArithOp::Mult) => return self.fold(args,Z3_mk_bvmul),
+                BVOp::Rem(true) => z3!(self,Z3_mk_bvsrem(args[0],args[1]))?,
+                BVOp::Rem(false) => z3!(self,Z3_mk_bvurem(args[0],args[1]))?,
+                BVOp::Div(true) => z3!(self,Z3_mk_bvsdiv(args[0],args[1]))?,
+                BVOp::Div(false) => z3!(self,Z3_mk_bvudiv(args[0],args[1]))?,
+                BVOp::SHL => z3!(self,Z3_mk_bvshl(args[0],args[1]))?,
+                BVOp::LSHR => z3!(self,Z3_mk_bvlshr(args[0],args[1]))?,
+                BVOp::ASHR => z3!(self,Z3_mk_bvashr(args[0],args[1]))?,
+                BVOp::XOr => return self.fold(args,Z3_mk_bvxor),
+                BVOp::And => return self.fold(args,Z3_mk_bvand),
+                BVOp::Or => return self.fold(args,Z3_mk_bvor),
+                BVOp::Not => z3!(self,Z3_mk_bvnot(args[0]))?,
+                BVOp::Neg => z3!(self,Z3_mk_bvneg(args[0]))?,
+                BVOp::Extract(start,len)
+                    => z3!(self,Z3_mk_extract((start+len-1) as c_uint,start as c_uint,args[0]))?,
+                BVOp::Concat => return self.fold(args,Z3_mk_concat)
+            },
+            Function::FP(eb,sb,op) => match op {
+                FPOp::Abs => z3!(self,Z3_mk_fpa_abs(args[0]))?,
+                FPOp::Neg => z3!(self,Z3_mk_fpa_neg(args[0]))?,
+                FPOp::Arith(ArithOp::Add) => z3!(self,Z3_mk_fpa_add(args[0],args[1],args[2]))?,
+                FPOp::Arith(ArithOp::Sub) => z3!(self,Z3_mk_fpa_sub(args[0],args[1],args[2]))?,
+                FPOp::Arith(ArithOp::Mult) => z3!(self,Z3_mk_fpa_mul(args[0],args[1],args[2]))?,
+                FPOp::Div => z3!(self,Z3_mk_fpa_div(args[0],args[1],args[2]))?,
+                FPOp::FMA => z3!(self,Z3_mk_fpa_fma(args[0],args[1],args[2],args[3]))?,
+                FPOp::Sqrt => z3!(self,Z3_mk_fpa_sqrt(args[0],args[1]))?,
+                FPOp::Rem => z3!(self,Z3_mk_fpa_rem(args[0],args[1]))?,
+                FPOp::RoundToIntegral => z3!(self,Z3_mk_fpa_round_to_integral(args[0],args[1]))?,
+                FPOp::Min => z3!(self,Z3_mk_fpa_min(args[0],args[1]))?,
+                FPOp::Max => z3!(self,Z3_mk_fpa_max(args[0],args[1]))?,
+                FPOp::Ord(OrdOp::Ge) => z3!(self,Z3_mk_fpa_geq(args[0],args[1]))?,
+                FPOp::Ord(OrdOp::Gt) => z3!(self,Z3_mk_fpa_gt(args[0],args[1]))?,
+                FPOp::Ord(OrdOp::Le) => z3!(self,Z3_mk_fpa_leq(args[0],args[1]))?,
+                FPOp::Ord(OrdOp::Lt) => z3!(self,Z3_mk_fpa_lt(args[0],args[1]))?,
+                FPOp::Eq => z3!(self,Z3_mk_fpa_eq(args[0],args[1]))?,
+                FPOp::IsNormal => z3!(self,Z3_mk_fpa_is_normal(args[0]))?,
+                FPOp::IsSubnormal => z3!(self,Z3_mk_fpa_is_subnormal(args[0]))?,
+                FPOp::IsZero => z3!(self,Z3_mk_fpa_is_zero(args[0]))?,
+                FPOp::IsInfinite => z3!(self,Z3_mk_fpa_is_infinite(args[0]))?,
+                FPOp::IsNaN => z3!(self,Z3_mk_fpa_is_nan(args[0]))?,
+                FPOp::IsNegative => z3!(self,Z3_mk_fpa_is_negative(args[0]))?,
+                FPOp::IsPositive => z3!(self,Z3_mk_fpa_is_positive(args[0]))?,
+                FPOp::ToUBV(sz) => z3!(self,Z3_mk_fpa_to_ubv(args[0],args[1],sz as c_uint))?,
+                FPOp::ToSBV(sz) => z3!(self,Z3_mk_fpa_to_sbv(args[0],args[1],sz as c_uint))?,
+                FPOp::ToReal => z3!(self,Z3_mk_fpa_to_real(args[0]))?,
+                FPOp::FromBV => {
+                    let srt = self.raw_sort_kind(SortKind::FloatingPoint(eb,sb))?;
+                    z3!(self,Z3_mk_fpa_to_fp_bv(args[0],srt))?
+                },
+                FPOp::FromFP(_,_) => {
+                    let srt = self.raw_sort_kind(SortKind::FloatingPoint(eb,sb))?;
+                    z3!(self,Z3_mk_fpa_to_fp_float(args[0],args[1],srt))?
+                },
+                FPOp::FromReal => {
+                    let srt = self.raw_sort_kind(SortKind::FloatingPoint(eb,sb))?;
+                    z3!(self,Z3_mk_fpa_to_fp_real(args[0],args[1],srt))?
+                },
+                FPOp::FromSBV(_) => {
+                    let srt = self.raw_sort_kind(SortKind::FloatingPoint(eb,sb))?;
+                    z3!(self,Z3_mk_fpa_to_fp_signed(args[0],args[1],srt))?
+                },
+                FPOp::FromUBV(_) => {
+                    let srt = self.raw_sort_kind(SortKind::FloatingPoint(eb,sb))?;
+                    z3!(self,Z3_mk_fpa_to_fp_unsigned(args[0],args[1],srt))?
+                }
+            },
+            Function::Select(ref idx,_) | Function::Store(ref idx,_) if idx.len()!=1
+                => return Err(Z3Error::Unsupported(String::from("multi-dimensional arrays"))),
+            Function::Select(_,_) => z3!(self,Z3_mk_select(args[0],args[1]))?,
+            Function::Store(_,_) => z3!(self,Z3_mk_store(args[0],args[1],args[2]))?,
+            Function::ConstArray(ref idx,_) => {
+                if idx.len()!=1 {
+                    return Err(Z3Error::Unsupported(String::from("multi-dimensional arrays")))
+                }
+                let srt = self.raw_sort(&idx[0])?;
+                z3!(self,Z3_mk_const_array(srt,args[0]))?
+            },
+            Function::Constructor(dt,_,con) => {
+                let decl = self.datatypes[dt].constructors[con].0;
+                z3!(self,Z3_mk_app(decl,n,p))?
+            },
+            Function::Selector(dt,_,con,field) => {
+                let decl = self.datatypes[dt].constructors[con].2[field];
+                z3!(self,Z3_mk_app(decl,n,p))?
+            },
+            Function::Tester(dt,_,con) => {
+                let decl = self.datatypes[dt].constructors[con].1;
+                z3!(self,Z3_mk_app(decl,n,p))?
+            }
+        };
+        Ok(self.keep(res))
+    }
+    fn mk_expr(&mut self,e: &Expr<Z3Sort,Z3Var,Z3Expr,Z3Fun>) -> Result<Z3_ast,Z3Error> {
+        match *e {
+            Expr::Var(ref v) => Ok(self.keep(self.vars[v.0].1)),
+            Expr::QVar(ref v) => self.bound_var("qv",v),
+            Expr::LVar(ref v) => self.bound_var("lv",v),
+            Expr::Const(ref c) => self.mk_value(c),
+            Expr::App(ref fun,ref args) => {
+                let raw : Vec<Z3_ast> = args.iter().map(|arg| self.raw_expr(arg)).collect();
+                self.mk_app(fun,&raw)
+            },
+            Expr::AsArray(Function::Fun(f)) => match self.funs[f].2 {
+                FunImpl::Declared(decl) => {
+                    let res = z3!(self,Z3_mk_as_array(decl))?;
+                    Ok(self.keep(res))
+                },
+                FunImpl::Defined(_,_)
+                    => Err(Z3Error::Unsupported(String::from("as-array of a defined function")))
+            },
+            Expr::AsArray(_) => Err(Z3Error::Unsupported(String::from("as-array"))),
+            Expr::Exists(ref vars,ref body) | Expr::Forall(ref vars,ref body) => {
+                let mut bound = Vec::with_capacity(vars.len());
+                for var in vars.iter() {
+                    bound.push(self.bound_var("qv",var)?);
+                }
+                let rbody = self.raw_expr(body);
+                let n = bound.len() as c_uint;
+                let res = match *e {
+                    Expr::Exists(_,_)
+                        => z3!(self,Z3_mk_exists_const(0,n,bound.as_ptr(),0,ptr::null(),rbody))?,
+                    _ => z3!(self,Z3_mk_forall_const(0,n,bound.as_ptr(),0,ptr::null(),rbody))?
+                };
+                self.keep(res);
+                for var in bound.into_iter() {
+                    self.release(var);
+                }
+                Ok(res)
+            },
+            Expr::Let(ref binds,ref body) => {
+                let mut from = Vec::with_capacity(binds.len());
+                let mut to = Vec::with_capacity(binds.len());
+                for &(ref var,ref bind) in binds.iter() {
+                    from.push(self.bound_var("lv",var)?);
+                    to.push(self.raw_expr(bind));
+                }
+                let rbody = self.raw_expr(body);
+                let res = z3!(self,Z3_substitute(rbody,from.len() as c_uint,
+                                                 from.as_ptr(),to.as_ptr()))?;
+                self.keep(res);
+                for var in from.into_iter() {
+                    self.release(var);
+                }
+                Ok(res)
+            }
+        }
+    }
+}
+
+impl Drop for Z3 {
+    fn drop(&mut self) {
+        unsafe {
+            Z3_solver_dec_ref(self.ctx,self.solver);
+            Z3_params_dec_ref(self.ctx,self.params);
+            Z3_del_context(self.ctx);
+        }
+    }
+}
+
+impl Embed for Z3 {
+    type Sort = Z3Sort;
+    type Var = Z3Var;
+    type Expr = Z3Expr;
+    type Fun = Z3Fun;
+    type Error = Z3Error;
+    fn embed_sort(&mut self,tp: SortKind<Z3Sort>) -> Result<Z3Sort,Z3Error> {
+        if let Some(srt) = self.sort_map.get(&tp) {
+            return Ok(*srt)
+        }
+        // Sorts which cannot be represented in Z3 are only rejected once
+        // they are used
+        let raw = match tp {
+            SortKind::Bool => Some(z3!(self,Z3_mk_bool_sort())?),
+            SortKind::Int => Some(z3!(self,Z3_mk_int_sort())?),
+            SortKind::Real => Some(z3!(self,Z3_mk_real_sort())?),
+            SortKind::BitVec(bw) => Some(z3!(self,Z3_mk_bv_sort(bw as c_uint))?),
+            SortKind::Array(ref idx,ref el) => if idx.len()==1 {
+                match (self.sorts[idx[0].0].1,self.sorts[el.0].1) {
+                    (Some(ridx),Some(rel)) => Some(z3!(self,Z3_mk_array_sort(ridx,rel))?),
+                    _ => None
+                }
+            } else {
+                None
+            },
+            SortKind::FloatingPoint(eb,sb)
+                => Some(z3!(self,Z3_mk_fpa_sort(eb as c_uint,sb as c_uint))?),
+            SortKind::RoundingMode => Some(z3!(self,Z3_mk_fpa_rounding_mode_sort())?),
+            SortKind::Datatype(dt,ref pars) => if pars.len()==0 && dt<self.datatypes.len() {
+                Some(self.datatypes[dt].sort)
+            } else {
+                None
+            },
+            SortKind::Uninterpreted(id,ref args) => if args.len()==0 {
+                Some(self.declared_sorts[id])
+            } else {
+                None
+            },
+            SortKind::Param(_) => None
+        };
+        if let Some(raw) = raw {
+            self.keep(raw);
+        }
+        let srt = Z3Sort(self.sorts.len());
+        self.sorts.push((tp.clone(),raw));
+        self.sort_map.insert(tp,srt);
+        Ok(srt)
+    }
+    fn unbed_sort(&mut self,tp: &Z3Sort) -> Result<SortKind<Z3Sort>,Z3Error> {
+        Ok(self.sorts[tp.0].0.clone())
+    }
+    fn embed(&mut self,e: Expr<Z3Sort,Z3Var,Z3Expr,Z3Fun>) -> Result<Z3Expr,Z3Error> {
+        if let Some(re) = self.expr_map.get(&e) {
+            return Ok(*re)
+        }
+        let ast = self.mk_expr(&e)?;
+        let id = z3!(self,Z3_get_ast_id(ast))?;
+        let re = Z3Expr(self.exprs.len());
+        self.exprs.push((e.clone(),ast));
+        self.expr_map.insert(e,re);
+        self.asts.entry(id).or_insert(re);
+        Ok(re)
+    }
+    fn unbed(&mut self,e: &Z3Expr) -> Result<Expr<Z3Sort,Z3Var,Z3Expr,Z3Fun>,Z3Error> {
+        Ok(self.exprs[e.0].0.clone())
+    }
+    fn type_of_var(&mut self,v: &Z3Var) -> Result<Z3Sort,Z3Error> {
+        Ok(self.vars[v.0].0)
+    }
+    fn type_of_fun(&mut self,f: &Z3Fun) -> Result<Z3Sort,Z3Error> {
+        Ok(self.funs[*f].1)
+    }
+    fn arity(&mut self,f: &Z3Fun) -> Result<usize,Z3Error> {
+        Ok(self.funs[*f].0.len())
+    }
+    fn type_of_arg(&mut self,f: &Z3Fun,arg: usize) -> Result<Z3Sort,Z3Error> {
+        Ok(self.funs[*f].0[arg])
+    }
+    fn datatype(&mut self,dt: usize) -> Result<Datatype<Z3Sort>,Z3Error> {
+        Ok(self.datatypes[dt].def.clone())
+    }
+}
+
+impl Backend for Z3 {
+    fn unsupported(what: &str) -> Z3Error {
+        Z3Error::Unsupported(String::from(what))
+    }
+    fn enable_models(&mut self) -> Result<(),Z3Error> {
+        self.solver_param("model",&OptionValue::Bool(true))
+    }
+    fn enable_proofs(&mut self) -> Result<(),Z3Error> {
+        if self.proofs {
+            Ok(())
+        } else {
+            // Proof generation can only be enabled for a whole context
+            Err(Z3Error::Unsupported(String::from(":produce-proofs")))
+        }
+    }
+    fn enable_unsat_cores(&mut self) -> Result<(),Z3Error> {
+        self.solver_param("unsat_core",&OptionValue::Bool(true))
+    }
+    fn enable_interpolants(&mut self) -> Result<(),Z3Error> {
+        Err(Z3Error::Unsupported(String::from(":produce-interpolants")))
+    }
+    fn set_logic(&mut self,logic: Logic) -> Result<(),Z3Error> {
+        if self.declared || self.logic.is_some() {
+            return Err(Z3Error::MisplacedSetLogic)
+        }
+        let sym = self.symbol(&format!("{}",logic));
+        let solver = z3!(self,Z3_mk_solver_for_logic(sym))?;
+        unsafe {
+            Z3_solver_inc_ref(self.ctx,solver);
+            Z3_solver_dec_ref(self.ctx,self.solver);
+        }
+        self.solver = solver;
+        z3!(self,Z3_solver_set_params(self.solver,self.params))?;
+        self.logic = Some(logic);
+        Ok(())
+    }
+    fn set_option(&mut self,name: &str,value: OptionValue) -> Result<(),Z3Error> {
+        match name {
+            ":produce-models" => self.solver_param("model",&value)?,
+            ":produce-unsat-cores" => self.solver_param("unsat_core",&value)?,
+            ":produce-proofs" => if value==OptionValue::Bool(true) {
+                self.enable_proofs()?
+            },
+            // Without a textual interface, these have no effect
+            ":print-success" | ":produce-unsat-assumptions" => {},
+            _ => {
+                let param = if name.starts_with(':') { &name[1..] } else { name };
+                self.solver_param(&param.replace("-","_"),&value)?
+            }
+        }
+        self.options.insert(String::from(name),value);
+        Ok(())
+    }
+    fn get_option(&mut self,name: &str) -> Result<OptionValue,Z3Error> {
+        match self.options.get(name) {
+            Some(value) => Ok(value.clone()),
+            None => Err(Z3Error::Unsupported(String::from(name)))
+        }
+    }
+    fn set_info(&mut self,_: &str,_: OptionValue) -> Result<(),Z3Error> {
+        Ok(())
+    }
+    fn solver_name(&mut self) -> Result<String,Z3Error> {
+        Ok(String::from("Z3"))
+    }
+    fn solver_version(&mut self) -> Result<String,Z3Error> {
+        let (mut major,mut minor,mut build,mut rev) = (0,0,0,0);
+        unsafe { Z3_get_version(&mut major,&mut minor,&mut build,&mut rev) };
+        Ok(format!("{}.{}.{}",major,minor,build))
+    }
+    fn reason_unknown(&mut self) -> Result<String,Z3Error> {
+        let reason = z3!(self,Z3_solver_get_reason_unknown(self.solver))?;
+        Ok(from_c_str(reason))
+    }
+    fn statistics(&mut self) -> Result<HashMap<String,StatValue>,Z3Error> {
+        let stats = z3!(self,Z3_solver_get_statistics(self.solver))?;
+        unsafe { Z3_stats_inc_ref(self.ctx,stats) };
+        let sz = unsafe { Z3_stats_size(self.ctx,stats) };
+        let mut res = HashMap::with_capacity(sz as usize);
+        for i in 0..sz {
+            // Use the same keys as the textual interface
+            let key = from_c_str(unsafe { Z3_stats_get_key(self.ctx,stats,i) }).replace(" ","-");
+            let value = unsafe {
+                if Z3_stats_is_uint(self.ctx,stats,i) {
+                    Z3_stats_get_uint_value(self.ctx,stats,i) as f64
+                } else {
+                    Z3_stats_get_double_value(self.ctx,stats,i)
+                }
+            };
+            res.insert(key,StatValue::Number(value));
+        }
+        unsafe { Z3_stats_dec_ref(self.ctx,stats) };
+        Ok(res)
+    }
+    fn comment(&mut self,_: &str) -> Result<(),Z3Error> {
+        Ok(())
+    }
+    fn push(&mut self) -> Result<(),Z3Error> {
+        z3!(self,Z3_solver_push(self.solver))
+    }
+    fn pop(&mut self) -> Result<(),Z3Error> {
+        z3!(self,Z3_solver_pop(self.solver,1))
+    }
+    fn declare_var(&mut self,tp: Z3Sort,name: Option<String>) -> Result<Z3Var,Z3Error> {
+        self.declaration();
+        let vid = self.vars.len();
+        let srt = self.raw_sort(&tp)?;
+        let sym = match name {
+            None => self.symbol(&format!("{}{}",Z3_VAR_NAME,vid)),
+            Some(ref name) => self.symbol(name)
+        };
+        let c = z3!(self,Z3_mk_const(sym,srt))?;
+        if let Some(name) = name {
+            match self.named_vars.entry(name) {
+                Entry::Occupied(_) => panic!("Cannot declare two variables with the same name"),
+                Entry::Vacant(v) => { v.insert(vid); }
+            }
+        }
+        self.vars.push((tp,self.keep(c)));
+        Ok(Z3Var(vid))
+    }
+    fn define_var(&mut self,e: Z3Expr) -> Result<Z3Var,Z3Error> {
+        self.declaration();
+        let vid = self.vars.len();
+        let tp = self.type_of(&e)?;
+        // The variable simply stands for the term
+        let ast = self.keep(self.raw_expr(&e));
+        self.vars.push((tp,ast));
+        Ok(Z3Var(vid))
+    }
+    fn assert(&mut self,expr: Z3Expr) -> Result<(),Z3Error> {
+        self.declaration();
+        z3!(self,Z3_solver_assert(self.solver,self.raw_expr(&expr)))
+    }
+    fn assert_named(&mut self,expr: Z3Expr) -> Result<AssertionId,Z3Error> {
+        self.declaration();
+        let aid = self.named_assertions;
+        let srt = self.raw_sort_kind(SortKind::Bool)?;
+        let sym = self.symbol(&format!("{}{}",Z3_ASSERTION_NAME,aid));
+        let lit = z3!(self,Z3_mk_const(sym,srt))?;
+        self.keep(lit);
+        z3!(self,Z3_solver_assert_and_track(self.solver,self.raw_expr(&expr),lit))?;
+        let id = z3!(self,Z3_get_ast_id(lit))?;
+        self.assertions.insert(id,aid);
+        self.named_assertions+=1;
+        Ok(AssertionId(aid))
+    }
+    fn get_unsat_core(&mut self) -> Result<Vec<AssertionId>,Z3Error> {
+        let core = z3!(self,Z3_solver_get_unsat_core(self.solver))?;
+        let mut res = Vec::new();
+        unsafe {
+            Z3_ast_vector_inc_ref(self.ctx,core);
+            for i in 0..Z3_ast_vector_size(self.ctx,core) {
+                let lit = Z3_ast_vector_get(self.ctx,core,i);
+                // Skip the assumptions of check_sat_assuming
+                if let Some(aid) = self.assertions.get(&Z3_get_ast_id(self.ctx,lit)) {
+                    res.push(AssertionId(*aid));
+                }
+            }
+            Z3_ast_vector_dec_ref(self.ctx,core);
+        }
+        Ok(res)
+    }
+    fn check_sat(&mut self) -> Result<CheckSatResult,Z3Error> {
+        let res = z3!(self,Z3_solver_check(self.solver))?;
+        Ok(check_sat_result(res))
+    }
+    fn check_sat_assuming(&mut self,assumptions: &[Z3Expr]) -> Result<CheckSatResult,Z3Error> {
+        let raw : Vec<Z3_ast> = assumptions.iter().map(|e| self.raw_expr(e)).collect();
+        let res = z3!(self,Z3_solver_check_assumptions(self.solver,raw.len() as c_uint,
+                                                      raw.as_ptr()))?;
+        Ok(check_sat_result(res))
+    }
+    fn get_unsat_assumptions(&mut self) -> Result<Vec<Z3Expr>,Z3Error> {
+        let core = z3!(self,Z3_solver_get_unsat_core(self.solver))?;
+        let mut res = Vec::new();
+        unsafe {
+            Z3_ast_vector_inc_ref(self.ctx,core);
+            for i in 0..Z3_ast_vector_size(self.ctx,core) {
+                let lit = Z3_ast_vector_get(self.ctx,core,i);
+                // Skip the literals tracking named assertions
+                if let Some(e) = self.asts.get(&Z3_get_ast_id(self.ctx,lit)) {
+                    res.push(*e);
+                }
+            }
+            Z3_ast_vector_dec_ref(self.ctx,core);
+        }
+        Ok(res)
+    }
+    fn get_proof(&mut self) -> Result<SExpr,Z3Error> {
+        let proof = z3!(self,Z3_solver_get_proof(self.solver))?;
+        self.keep(proof);
+        let s = self.ast_to_string(proof);
+        self.release(proof);
+        self.parse(s?,parse_sexpr)
+    }
+    fn get_interpolant(&mut self,_: &[Z3Expr],_: &[Z3Expr])
+                       -> Result<Option<Z3Expr>,Z3Error> {
+        Err(Z3Error::Unsupported(String::from("get-interpolants")))
+    }
+    fn eliminate_quantifiers(&mut self,expr: Z3Expr) -> Result<Z3Expr,Z3Error> {
+        let cname = CString::new("qe").unwrap();
+        let tactic = z3!(self,Z3_mk_tactic(cname.as_ptr()))?;
+        unsafe { Z3_tactic_inc_ref(self.ctx,tactic) };
+        let goal = z3!(self,Z3_mk_goal(false,false,false))?;
+        unsafe { Z3_goal_inc_ref(self.ctx,goal) };
+        let applied = z3!(self,Z3_goal_assert(goal,self.raw_expr(&expr)))
+            .and_then(|_| z3!(self,Z3_tactic_apply(tactic,goal)));
+        unsafe {
+            Z3_goal_dec_ref(self.ctx,goal);
+            Z3_tactic_dec_ref(self.ctx,tactic);
+        }
+        let result = applied?;
+        unsafe { Z3_apply_result_inc_ref(self.ctx,result) };
+        let tbool = self.tp_bool()?;
+        let mut goals = Vec::new();
+        let ngoals = unsafe { Z3_apply_result_get_num_subgoals(self.ctx,result) };
+        for i in 0..ngoals {
+            let subgoal = unsafe { Z3_apply_result_get_subgoal(self.ctx,result,i) };
+            unsafe { Z3_goal_inc_ref(self.ctx,subgoal) };
+            let mut strs = Vec::new();
+            for j in 0..unsafe { Z3_goal_size(self.ctx,subgoal) } {
+                let f = unsafe { Z3_goal_formula(self.ctx,subgoal,j) };
+                strs.push(self.ast_to_string(f));
+            }
+            unsafe { Z3_goal_dec_ref(self.ctx,subgoal) };
+            goals.push(strs);
+        }
+        unsafe { Z3_apply_result_dec_ref(self.ctx,result) };
+        // The result is the disjunction of the resulting goals
+        let mut disj = Vec::with_capacity(goals.len());
+        for goal in goals.into_iter() {
+            let mut conj = Vec::with_capacity(goal.len());
+            for s in goal.into_iter() {
+                conj.push(self.parse(s?,|inp,pos,p| parse_expr(inp,pos,p,Some(&tbool)))?);
+            }
+            let e = match conj.len() {
+                0 => self.const_bool(true)?,
+                1 => conj.into_iter().next().unwrap(),
+                _ => self.and(conj)?
+            };
+            disj.push(e);
+        }
+        match disj.len() {
+            0 => self.const_bool(false),
+            1 => Ok(disj.into_iter().next().unwrap()),
+            _ => self.or(disj)
+        }
+    }
+    fn solver_simplify(&mut self,expr: Z3Expr) -> Result<Z3Expr,Z3Error> {
+        let simp = z3!(self,Z3_simplify(self.raw_expr(&expr)))?;
+        self.keep(simp);
+        let hint = self.type_of(&expr)?;
+        let res = self.from_ast(simp,&hint);
+        self.release(simp);
+        res
+    }
+    fn get_value(&mut self,expr: Z3Expr) -> Result<Value,Z3Error> {
+        let model = self.model()?;
+        let mut val = ptr::null_mut();
+        let ok = z3!(self,Z3_model_eval(model,self.raw_expr(&expr),true,&mut val));
+        unsafe { Z3_model_dec_ref(self.ctx,model) };
+        if !ok? {
+            return Err(Z3Error::Solver(String::from("Cannot evaluate expression in model")))
+        }
+        self.keep(val);
+        let s = self.ast_to_string(val);
+        self.release(val);
+        let hint = self.type_of(&expr)?;
+        self.parse(s?,|inp,pos,p| parse_value(inp,pos,p,Some(&hint)))
+    }
+    fn get_model(&mut self) -> Result<Model<Z3Sort,Z3Var,Z3Expr,Z3Fun>,Z3Error> {
+        let model = self.model()?;
+        let s = z3!(self,Z3_model_to_string(model)).map(from_c_str);
+        unsafe { Z3_model_dec_ref(self.ctx,model) };
+        self.parse(format!("({})",s?),parse_model)
+    }
+    fn declare_datatypes(&mut self,dts: Vec<Datatype<FieldSort<Z3Sort>>>)
+                         -> Result<usize,Z3Error> {
+        self.declaration();
+        let base = self.datatypes.len();
+        let mut names = Vec::with_capacity(dts.len());
+        let mut lists = Vec::with_capacity(dts.len());
+        let mut cons = Vec::with_capacity(dts.len());
+        for (i,dt) in dts.iter().enumerate() {
+            if dt.params>0 {
+                return Err(Z3Error::Unsupported(String::from("parametric datatypes")))
+            }
+            names.push(self.symbol(&format!("d{}",base+i)));
+            let mut dt_cons = Vec::with_capacity(dt.constructors.len());
+            for (c,con) in dt.constructors.iter().enumerate() {
+                let mut field_names = Vec::with_capacity(con.len());
+                let mut sorts = Vec::with_capacity(con.len());
+                let mut refs = Vec::with_capacity(con.len());
+                for (f,field) in con.iter().enumerate() {
+                    field_names.push(self.symbol(&format!("d{}c{}s{}",base+i,c,f)));
+                    match *field {
+                        FieldSort::Sort(ref srt) => {
+                            sorts.push(self.raw_sort(srt)?);
+                            refs.push(0);
+                        },
+                        FieldSort::Rec(n,ref args) => {
+                            if args.len()>0 {
+                                return Err(Z3Error::Unsupported(String::from("parametric datatypes")))
+                            }
+                            sorts.push(ptr::null_mut());
+                            refs.push(n as c_uint);
+                        }
+                    }
+                }
+                let name = self.symbol(&format!("d{}c{}",base+i,c));
+                let tester = self.symbol(&format!("is-d{}c{}",base+i,c));
+                let rcon = z3!(self,Z3_mk_constructor(name,tester,con.len() as c_uint,
+                                                      field_names.as_ptr(),sorts.as_ptr(),
+                                                      refs.as_mut_ptr()))?;
+                dt_cons.push(rcon);
+            }
+            let list = z3!(self,Z3_mk_constructor_list(dt_cons.len() as c_uint,dt_cons.as_ptr()))?;
+            lists.push(list);
+            cons.push(dt_cons);
+        }
+        let mut sorts = vec![ptr::null_mut(); dts.len()];
+        let declared = z3!(self,Z3_mk_datatypes(dts.len() as c_uint,names.as_ptr(),
+                                                sorts.as_mut_ptr(),lists.as_mut_ptr()));
+        if declared.is_ok() {
+            for (i,dt_cons) in cons.iter().enumerate() {
+                let mut decls = Vec::with_capacity(dt_cons.len());
+                for (c,rcon) in dt_cons.iter().enumerate() {
+                    let nfields = dts[i].constructors[c].len();
+                    let mut con = ptr::null_mut();
+                    let mut tester = ptr::null_mut();
+                    let mut sels = vec![ptr::null_mut(); nfields];
+                    unsafe {
+                        Z3_query_constructor(self.ctx,*rcon,nfields as c_uint,
+                                             &mut con,&mut tester,sels.as_mut_ptr());
+                    }
+                    self.keep(con);
+                    self.keep(tester);
+                    for sel in sels.iter() {
+                        self.keep(*sel);
+                    }
+                    decls.push((con,tester,sels));
+                }
+                self.datatypes.push(Z3Datatype { def: Datatype { params: 0,
+                                                                  constructors: Vec::new() },
+                                                 sort: self.keep(sorts[i]),
+                                                 constructors: decls });
+            }
+        }
+        unsafe {
+            for list in lists.into_iter() {
+                Z3_del_constructor_list(self.ctx,list);
+            }
+            for rcon in cons.into_iter().flat_map(|c| c.into_iter()) {
+                Z3_del_constructor(self.ctx,rcon);
+            }
+        }
+        declared?;
+        // The datatypes have to be registered before the fields can be
+        // resolved
+        for (i,dt) in dts.iter().enumerate() {
+            let mut ncons = Vec::with_capacity(dt.constructors.len());
+            for con in dt.constructors.iter() {
+                let mut fields = Vec::with_capacity(con.len());
+                for field in con.iter() {
+                    fields.push(field.resolve(base,self)?);
+                }
+                ncons.push(fields);
+            }
+            self.datatypes[base+i].def.constructors = ncons;
+        }
+        Ok(base)
+    }
+    fn declare_sort(&mut self,arity: usize) -> Result<usize,Z3Error> {
+        if arity>0 {
+            return Err(Z3Error::Unsupported(String::from("sorts with arguments")))
+        }
+        self.declaration();
+        let id = self.declared_sorts.len();
+        let sym = self.symbol(&format!("u{}",id));
+        let srt = z3!(self,Z3_mk_uninterpreted_sort(sym))?;
+        self.declared_sorts.push(self.keep(srt));
+        Ok(id)
+    }
+    fn define_sort(&mut self,params: usize,body: Z3Sort) -> Result<usize,Z3Error> {
+        let id = self.defined_sorts.len();
+        self.defined_sorts.push((params,body));
+        Ok(id)
+    }
+    fn defined_sort(&mut self,id: usize,args: Vec<Z3Sort>) -> Result<Z3Sort,Z3Error> {
+        let (params,body) = self.defined_sorts[id];
+        debug_assert_eq!(params,args.len());
+        self.instantiate_sort(&body,&args)
+    }
+    fn declare_fun(&mut self,args: Vec<Z3Sort>,ret: Z3Sort,name: Option<String>)
+                   -> Result<Z3Fun,Z3Error> {
+        self.declaration();
+        let fid = self.funs.len();
+        let mut rargs = Vec::with_capacity(args.len());
+        for arg in args.iter() {
+            rargs.push(self.raw_sort(arg)?);
+        }
+        let rret = self.raw_sort(&ret)?;
+        let sym = match name {
+            None => self.symbol(&format!("{}{}",Z3_FUN_NAME,fid)),
+            Some(ref name) => self.symbol(name)
+        };
+        let decl = z3!(self,Z3_mk_func_decl(sym,rargs.len() as c_uint,rargs.as_ptr(),rret))?;
+        if let Some(name) = name {
+            match self.named_funs.entry(name) {
+                Entry::Occupied(_) => panic!("Cannot declare two functions with the same name"),
+                Entry::Vacant(v) => { v.insert(fid); }
+            }
+        }
+        self.funs.push((args,ret,FunImpl::Declared(self.keep(decl))));
+        Ok(fid)
+    }
+    fn define_fun(&mut self,params: Vec<NVar<Z3Sort>>,body: Z3Expr) -> Result<Z3Fun,Z3Error> {
+        self.declaration();
+        let fid = self.funs.len();
+        let ret = self.type_of(&body)?;
+        let mut rparams = Vec::with_capacity(params.len());
+        for par in params.iter() {
+            rparams.push(self.bound_var("qv",par)?);
+        }
+        let rbody = self.keep(self.raw_expr(&body));
+        self.funs.push((params.into_iter().map(|p| p.sort).collect(),ret,
+                        FunImpl::Defined(rparams,rbody)));
+        Ok(fid)
+    }
+    fn declare_funs_rec(&mut self,sigs: &[(Vec<NVar<Z3Sort>>,Z3Sort)])
+                        -> Result<Vec<Z3Fun>,Z3Error> {
+        let mut funs = Vec::with_capacity(sigs.len());
+        for &(ref params,ref ret) in sigs.iter() {
+            let fid = self.funs.len();
+            let mut rargs = Vec::with_capacity(params.len());
+            for par in params.iter() {
+                rargs.push(self.raw_sort(&par.sort)?);
+            }
+            let rret = self.raw_sort(ret)?;
+            let sym = self.symbol(&format!("{}{}",Z3_FUN_NAME,fid));
+            let decl = z3!(self,Z3_mk_rec_func_decl(sym,rargs.len() as c_uint,
+                                                    rargs.as_ptr(),rret))?;
+            let args = params.iter().map(|p| p.sort).collect();
+            self.funs.push((args,*ret,FunImpl::Declared(self.keep(decl))));
+            funs.push(fid);
+        }
+        Ok(funs)
+    }
+    fn emit_funs_rec(&mut self,funs: &[Z3Fun],sigs: &[(Vec<NVar<Z3Sort>>,Z3Sort)],
+                     exprs: Vec<Z3Expr>) -> Result<(),Z3Error> {
+        debug_assert_eq!(exprs.len(),funs.len());
+        self.declaration();
+        for ((fid,&(ref params,_)),body) in funs.iter().zip(sigs.iter()).zip(exprs.iter()) {
+            let decl = match self.funs[*fid].2 {
+                FunImpl::Declared(decl) => decl,
+                FunImpl::Defined(_,_) => panic!("Function is not declared recursive")
+            };
+            let mut rparams = Vec::with_capacity(params.len());
+            for par in params.iter() {
+                rparams.push(self.bound_var("qv",par)?);
+            }
+            let res = z3!(self,Z3_add_rec_def(decl,rparams.len() as c_uint,
+                                              rparams.as_mut_ptr(),self.raw_expr(body)));
+            for par in rparams.into_iter() {
+                self.release(par);
+            }
+            res?;
+        }
+        Ok(())
+    }
+}
+
+impl Parser for Z3 {
+    fn parse_var(&mut self,inp: &[u8]) -> Result<Z3Var,Z3Error> {
+        let pref = Z3_VAR_NAME.len();
+        match str::from_utf8(inp) {
+            Err(_) => Err(Z3Error::Parse(ParseError::UnknownVar)),
+            Ok(nstr) => match self.named_vars.get(nstr) {
+                Some(n) => Ok(Z3Var(*n)),
+                None => if nstr.len() <= pref || &nstr[0..pref] != Z3_VAR_NAME {
+                    Err(Z3Error::Parse(ParseError::UnknownVar))
+                } else {
+                    match FromStr::from_str(&nstr[pref..]) {
+                        Ok(n) if n<self.vars.len() => Ok(Z3Var(n)),
+                        _ => Err(Z3Error::Parse(ParseError::UnknownVar))
+                    }
+                }
+            }
+        }
+    }
+    fn parse_fun(&mut self,inp: &[u8]) -> Result<Z3Fun,Z3Error> {
+        let pref = Z3_FUN_NAME.len();
+        match str::from_utf8(inp) {
+            Err(_) => Err(Z3Error::Parse(ParseError::UnknownFun)),
+            Ok(nstr) => match self.named_funs.get(nstr) {
+                Some(n) => Ok(*n),
+                None => if nstr.len() <= pref || &nstr[0..pref] != Z3_FUN_NAME {
+                    Err(Z3Error::Parse(ParseError::UnknownFun))
+                } else {
+                    match FromStr::from_str(&nstr[pref..]) {
+                        Ok(n) if n<self.funs.len() => Ok(n),
+                        _ => Err(Z3Error::Parse(ParseError::UnknownFun))
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[allow(non_camel_case_types)]
+mod ffi {
+    use std::os::raw::{c_char,c_int,c_uint,c_void};
+
+    pub type Z3_config = *mut c_void;
+    pub type Z3_context = *mut c_void;
+    pub type Z3_symbol = *mut c_void;
+    pub type Z3_ast = *mut c_void;
+    pub type Z3_sort = *mut c_void;
+    pub type Z3_func_decl = *mut c_void;
+    pub type Z3_constructor = *mut c_void;
+    pub type Z3_constructor_list = *mut c_void;
+    pub type Z3_solver = *mut c_void;
+    pub type Z3_params = *mut c_void;
+    pub type Z3_model = *mut c_void;
+    pub type Z3_ast_vector = *mut c_void;
+    pub type Z3_stats = *mut c_void;
+    pub type Z3_tactic = *mut c_void;
+    pub type Z3_goal = *mut c_void;
+    pub type Z3_apply_result = *mut c_void;
+    pub type Z3_string = *const c_char;
+    pub type Z3_lbool = c_int;
+    pub type Z3_error_code = c_int;
+    pub type Z3_error_handler = extern "C" fn(Z3_context,Z3_error_code);
+    pub type Z3_ast_print_mode = c_int;
+
+    pub const Z3_PRINT_SMTLIB2_COMPLIANT: Z3_ast_print_mode = 2;
+
+    #[link(name="z3")]
+    extern "C" {
+        pub fn Z3_mk_config() -> Z3_config;
+        pub fn Z3_del_config(c: Z3_config);
+        pub fn Z3_set_param_value(c: Z3_config,id: Z3_string,value: Z3_string);
+        pub fn Z3_mk_context_rc(c: Z3_config) -> Z3_context;
+        pub fn Z3_del_context(c: Z3_context);
+        pub fn Z3_set_error_handler(c: Z3_context,h: Option<Z3_error_handler>);
+        pub fn Z3_get_error_code(c: Z3_context) -> Z3_error_code;
+        pub fn Z3_get_error_msg(c: Z3_context,err: Z3_error_code) -> Z3_string;
+        pub fn Z3_set_ast_print_mode(c: Z3_context,mode: Z3_ast_print_mode);
+        pub fn Z3_get_version(major: *mut c_uint,minor: *mut c_uint,
+                              build: *mut c_uint,rev: *mut c_uint);
+        pub fn Z3_inc_ref(c: Z3_context,a: Z3_ast);
+        pub fn Z3_dec_ref(c: Z3_context,a: Z3_ast);
+        pub fn Z3_mk_string_symbol(c: Z3_context,s: Z3_string) -> Z3_symbol;
+        pub fn Z3_ast_to_string(c: Z3_context,a: Z3_ast) -> Z3_string;
+        pub fn Z3_get_ast_id(c: Z3_context,a: Z3_ast) -> c_uint;
+
+        pub fn Z3_mk_bool_sort(c: Z3_context) -> Z3_sort;
+        pub fn Z3_mk_int_sort(c: Z3_context) -> Z3_sort;
+        pub fn Z3_mk_real_sort(c: Z3_context) -> Z3_sort;
+        pub fn Z3_mk_bv_sort(c: Z3_context,sz: c_uint) -> Z3_sort;
+        pub fn Z3_mk_array_sort(c: Z3_context,domain: Z3_sort,range: Z3_sort) -> Z3_sort;
+        pub fn Z3_mk_fpa_sort(c: Z3_context,ebits: c_uint,sbits: c_uint) -> Z3_sort;
+        pub fn Z3_mk_fpa_rounding_mode_sort(c: Z3_context) -> Z3_sort;
+        pub fn Z3_mk_uninterpreted_sort(c: Z3_context,s: Z3_symbol) -> Z3_sort;
+        pub fn Z3_mk_constructor(c: Z3_context,name: Z3_symbol,recognizer: Z3_symbol,
+                                 num_fields: c_uint,field_names: *const Z3_symbol,
+                                 sorts: *const Z3_sort,sort_refs: *mut c_uint) -> Z3_constructor;
+        pub fn Z3_del_constructor(c: Z3_context,constr: Z3_constructor);
+        pub fn Z3_mk_constructor_list(c: Z3_context,num: c_uint,
+                                      constructors: *const Z3_constructor) -> Z3_constructor_list;
+        pub fn Z3_del_constructor_list(c: Z3_context,clist: Z3_constructor_list);
+        pub fn Z3_mk_datatypes(c: Z3_context,num: c_uint,names: *const Z3_symbol,
+                               sorts: *mut Z3_sort,lists: *mut Z3_constructor_list);
+        pub fn Z3_query_constructor(c: Z3_context,constr: Z3_constructor,num_fields: c_uint,
+                                    constructor: *mut Z3_func_decl,tester: *mut Z3_func_decl,
+                                    accessors: *mut Z3_func_decl);
+
+        pub fn Z3_mk_func_decl(c: Z3_context,s: Z3_symbol,domain_size: c_uint,
+                               domain: *const Z3_sort,range: Z3_sort) -> Z3_func_decl;
+        pub fn Z3_mk_rec_func_decl(c: Z3_context,s: Z3_symbol,domain_size: c_uint,
+                                   domain: *const Z3_sort,range: Z3_sort) -> Z3_func_decl;
+        pub fn Z3_add_rec_def(c: Z3_context,f: Z3_func_decl,n: c_uint,
+                              args: *mut Z3_ast,body: Z3_ast);
+        pub fn Z3_mk_app(c: Z3_context,d: Z3_func_decl,n: c_uint,args: *const Z3_ast) -> Z3_ast;
+        pub fn Z3_mk_const(c: Z3_context,s: Z3_symbol,ty: Z3_sort) -> Z3_ast;
+        pub fn Z3_mk_numeral(c: Z3_context,numeral: Z3_string,ty: Z3_sort) -> Z3_ast;
+        pub fn Z3_mk_as_array(c: Z3_context,f: Z3_func_decl) -> Z3_ast;
+        pub fn Z3_mk_forall_const(c: Z3_context,weight: c_uint,num_bound: c_uint,
+                                  bound: *const Z3_ast,num_patterns: c_uint,
+                                  patterns: *const c_void,body: Z3_ast) -> Z3_ast;
+        pub fn Z3_mk_exists_const(c: Z3_context,weight: c_uint,num_bound: c_uint,
+                                  bound: *const Z3_ast,num_patterns: c_uint,
+                                  patterns: *const c_void,body: Z3_ast) -> Z3_ast;
+        pub fn Z3_substitute(c: Z3_context,a: Z3_ast,n: c_uint,
+                             from: *const Z3_ast,to: *const Z3_ast) -> Z3_ast;
+        pub fn Z3_simplify(c: Z3_context,a: Z3_ast) -> Z3_ast;
+
+        pub fn Z3_mk_true(c: Z3_context) -> Z3_ast;
+        pub fn Z3_mk_false(c: Z3_context) -> Z3_ast;
+        pub fn Z3_mk_eq(c: Z3_context,l: Z3_ast,r: Z3_ast) -> Z3_ast;
+        pub fn Z3_mk_distinct(c: Z3_context,n: c_uint,args: *const Z3_ast) -> Z3_ast;
+        pub fn Z3_mk_not(c: Z3_context,a: Z3_ast) -> Z3_ast;
+        pub fn Z3_mk_ite(c: Z3_context,t1: Z3_ast,t2: Z3_ast,t3: Z3_ast) -> Z3_ast;
+        pub fn Z3_mk_implies(c: Z3_context,t1: Z3_ast,t2: Z3_ast) -> Z3_ast;
+        pub fn Z3_mk_xor(c: Z3_context,t1: Z3_ast,t2: Z3_ast) -> Z3_ast;
+        pub fn Z3_mk_and(c: Z3_context,n: c_uint,args: *const Z3_ast) -> Z3_ast;
+        pub fn Z3_mk_or(c: Z3_context,n: c_uint,args: *const Z3_ast) -> Z3_ast;
+        pub fn Z3_mk_atleast(c: Z3_context,n: c_uint,args: *const Z3_ast,k: c_uint) -> Z3_ast;
+        pub fn Z3_mk_atmost(c: Z3_context,n: c_uint,args: *const Z3_ast,k: c_uint) -> Z3_ast;
+
+        pub fn Z3_mk_add(c: Z3_context,n: c_uint,args: *const Z3_ast) -> Z3_ast;
+        pub fn Z3_mk_mul(c: Z3_context,n: c_uint,args: *const Z3_ast) -> Z3_ast;
+        pub fn Z3_mk_sub(c: Z3_context,n: c_uint,args: *const Z3_ast) -> Z3_ast;
+        pub fn Z3_mk_unary_minus(c: Z3_context,a: Z3_ast) -> Z3_ast;
+        pub fn Z3_mk_div(c: Z3_context,a1: Z3_ast,a2: Z3_ast) -> Z3_ast;
+        pub fn Z3_mk_mod(c: Z3_context,a1: Z3_ast,a2: Z3_ast) -> Z3_ast;
+        pub fn Z3_mk_rem(c: Z3_context,a1: Z3_ast,a2: Z3_ast) -> Z3_ast;
+        pub fn Z3_mk_power(c: Z3_context,a1: Z3_ast,a2: Z3_ast) -> Z3_ast;
+        pub fn Z3_mk_lt(c: Z3_context,t1: Z3_ast,t2: Z3_ast) -> Z3_ast;
+        pub fn Z3_mk_le(c: Z3_context,t1: Z3_ast,t2: Z3_ast) -> Z3_ast;
+        pub fn Z3_mk_gt(c: Z3_context,t1: Z3_ast,t2: Z3_ast) -> Z3_ast;
+        pub fn Z3_mk_ge(c: Z3_context,t1: Z3_ast,t2: Z3_ast) -> Z3_ast;
+        pub fn Z3_mk_int2real(c: Z3_context,t: Z3_ast) -> Z3_ast;
+        pub fn Z3_mk_real2int(c: Z3_context,t: Z3_ast) -> Z3_ast;
+
+        pub fn Z3_mk_bvnot(c: Z3_context,t: Z3_ast) -> Z3_ast;
+        pub fn Z3_mk_bvneg(c: Z3_context,t: Z3_ast) -> Z3_ast;
+        pub fn Z3_mk_bvand(c: Z3_context,t1: Z3_ast,t2: Z3_ast) -> Z3_ast;
+        pub fn Z3_mk_bvor(c: Z3_context,t1: Z3_ast,t2: Z3_ast) -> Z3_ast;
+        pub fn Z3_mk_bvxor(c: Z3_context,t1: Z3_ast,t2: Z3_ast) -> Z3_ast;
+        pub fn Z3_mk_bvadd(c: Z3_context,t1: Z3_ast,t2: Z3_ast) -> Z3_ast;
+        pub fn Z3_mk_bvsub(c: Z3_context,t1: Z3_ast,t2: Z3_ast) -> Z3_ast;
+        pub fn Z3_mk_bvmul(c: Z3_context,t1: Z3_ast,t2: Z3_ast) -> Z3_ast;
+        pub fn Z3_mk_bvudiv(c: Z3_context,t1: Z3_ast,t2: Z3_ast) -> Z3_ast;
+        pub fn Z3_mk_bvsdiv(c: Z3_context,t1: Z3_ast,t2: Z3_ast) -> Z3_ast;
+        pub fn Z3_mk_bvurem(c: Z3_context,t1: Z3_ast,t2: Z3_ast) -> Z3_ast;
+        pub fn Z3_mk_bvsrem(c: Z3_context,t1: Z3_ast,t2: Z3_ast) -> Z3_ast;
+        pub fn Z3_mk_bvult(c: Z3_context,t1: Z3_ast,t2: Z3_ast) -> Z3_ast;
+        pub fn Z3_mk_bvslt(c: Z3_context,t1: Z3_ast,t2: Z3_ast) -> Z3_ast;
+        pub fn Z3_mk_bvule(c: Z3_context,t1: Z3_ast,t2: Z3_ast) -> Z3_ast;
+        pub fn Z3_mk_bvsle(c: Z3_context,t1: Z3_ast,t2: Z3_ast) -> Z3_ast;
+        pub fn Z3_mk_bvuge(c: Z3_context,t1: Z3_ast,t2: Z3_ast) -> Z3_ast;
+        pub fn Z3_mk_bvsge(c: Z3_context,t1: Z3_ast,t2: Z3_ast) -> Z3_ast;
+        pub fn Z3_mk_bvugt(c: Z3_context,t1: Z3_ast,t2: Z3_ast) -> Z3_ast;
+        pub fn Z3_mk_bvsgt(c: Z3_context,t1: Z3_ast,t2: Z3_ast) -> Z3_ast;
+        pub fn Z3_mk_bvshl(c: Z3_context,t1: Z3_ast,t2: Z3_ast) -> Z3_ast;
+        pub fn Z3_mk_bvlshr(c: Z3_context,t1: Z3_ast,t2: Z3_ast) -> Z3_ast;
+        pub fn Z3_mk_bvashr(c: Z3_context,t1: Z3_ast,t2: Z3_ast) -> Z3_ast;
+        pub fn Z3_mk_concat(c: Z3_context,t1: Z3_ast,t2: Z3_ast) -> Z3_ast;
+        pub fn Z3_mk_extract(c: Z3_context,high: c_uint,low: c_uint,t: Z3_ast) -> Z3_ast;
+
+        pub fn Z3_mk_fpa_rne(c: Z3_context) -> Z3_ast;
+        pub fn Z3_mk_fpa_rna(c: Z3_context) -> Z3_ast;
+        pub fn Z3_mk_fpa_rtp(c: Z3_context) -> Z3_ast;
+        pub fn Z3_mk_fpa_rtn(c: Z3_context) -> Z3_ast;
+        pub fn Z3_mk_fpa_rtz(c: Z3_context) -> Z3_ast;
+        pub fn Z3_mk_fpa_fp(c: Z3_context,sgn: Z3_ast,exp: Z3_ast,sig: Z3_ast) -> Z3_ast;
+        pub fn Z3_mk_fpa_abs(c: Z3_context,t: Z3_ast) -> Z3_ast;
+        pub fn Z3_mk_fpa_neg(c: Z3_context,t: Z3_ast) -> Z3_ast;
+        pub fn Z3_mk_fpa_add(c: Z3_context,rm: Z3_ast,t1: Z3_ast,t2: Z3_ast) -> Z3_ast;
+        pub fn Z3_mk_fpa_sub(c: Z3_context,rm: Z3_ast,t1: Z3_ast,t2: Z3_ast) -> Z3_ast;
+        pub fn Z3_mk_fpa_mul(c: Z3_context,rm: Z3_ast,t1: Z3_ast,t2: Z3_ast) -> Z3_ast;
+        pub fn Z3_mk_fpa_div(c: Z3_context,rm: Z3_ast,t1: Z3_ast,t2: Z3_ast) -> Z3_ast;
+        pub fn Z3_mk_fpa_fma(c: Z3_context,rm: Z3_ast,t1: Z3_ast,t2: Z3_ast,t3: Z3_ast) -> Z3_ast;
+        pub fn Z3_mk_fpa_sqrt(c: Z3_context,rm: Z3_ast,t: Z3_ast) -> Z3_ast;
+        pub fn Z3_mk_fpa_rem(c: Z3_context,t1: Z3_ast,t2: Z3_ast) -> Z3_ast;
+        pub fn Z3_mk_fpa_round_to_integral(c: Z3_context,rm: Z3_ast,t: Z3_ast) -> Z3_ast;
+        pub fn Z3_mk_fpa_min(c: Z3_context,t1: Z3_ast,t2: Z3_ast) -> Z3_ast;
+        pub fn Z3_mk_fpa_max(c: Z3_context,t1: Z3_ast,t2: Z3_ast) -> Z3_ast;
+        pub fn Z3_mk_fpa_leq(c: Z3_context,t1: Z3_ast,t2: Z3_ast) -> Z3_ast;
+        pub fn Z3_mk_fpa_lt(c: Z3_context,t1: Z3_ast,t2: Z3_ast) -> Z3_ast;
+        pub fn Z3_mk_fpa_geq(c: Z3_context,t1: Z3_ast,t2: Z3_ast) -> Z3_ast;
+        pub fn Z3_mk_fpa_gt(c: Z3_context,t1: Z3_ast,t2: Z3_ast) -> Z3_ast;
+        pub fn Z3_mk_fpa_eq(c: Z3_context,t1: Z3_ast,t2: Z3_ast) -> Z3_ast;
+        pub fn Z3_mk_fpa_is_normal(c: Z3_context,t: Z3_ast) -> Z3_ast;
+        pub fn Z3_mk_fpa_is_subnormal(c: Z3_context,t: Z3_ast) -> Z3_ast;
+        pub fn Z3_mk_fpa_is_zero(c: Z3_context,t: Z3_ast) -> Z3_ast;
+        pub fn Z3_mk_fpa_is_infinite(c: Z3_context,t: Z3_ast) -> Z3_ast;
+        pub fn Z3_mk_fpa_is_nan(c: Z3_context,t: Z3_ast) -> Z3_ast;
+        pub fn Z3_mk_fpa_is_negative(c: Z3_context,t: Z3_ast) -> Z3_ast;
+        pub fn Z3_mk_fpa_is_positive(c: Z3_context,t: Z3_ast) -> Z3_ast;
+        pub fn Z3_mk_fpa_to_fp_bv(c: Z3_context,bv: Z3_ast,s: Z3_sort) -> Z3_ast;
+        pub fn Z3_mk_fpa_to_fp_float(c: Z3_context,rm: Z3_ast,t: Z3_ast,s: Z3_sort) -> Z3_ast;
+        pub fn Z3_mk_fpa_to_fp_real(c: Z3_context,rm: Z3_ast,t: Z3_ast,s: Z3_sort) -> Z3_ast;
+        pub fn Z3_mk_fpa_to_fp_signed(c: Z3_context,rm: Z3_ast,t: Z3_ast,s: Z3_sort) -> Z3_ast;
+        pub fn Z3_mk_fpa_to_fp_unsigned(c: Z3_context,rm: Z3_ast,t: Z3_ast,s: Z3_sort) -> Z3_ast;
+        pub fn Z3_mk_fpa_to_ubv(c: Z3_context,rm: Z3_ast,t: Z3_ast,sz: c_uint) -> Z3_ast;
+        pub fn Z3_mk_fpa_to_sbv(c: Z3_context,rm: Z3_ast,t: Z3_ast,sz: c_uint) -> Z3_ast;
+        pub fn Z3_mk_fpa_to_real(c: Z3_context,t: Z3_ast) -> Z3_ast;
+
+        pub fn Z3_mk_select(c: Z3_context,a: Z3_ast,i: Z3_ast) -> Z3_ast;
+        pub fn Z3_mk_store(c: Z3_context,a: Z3_ast,i: Z3_ast,v: Z3_ast) -> Z3_ast;
+        pub fn Z3_mk_const_array(c: Z3_context,domain: Z3_sort,v: Z3_ast) -> Z3_ast;
+
+        pub fn Z3_mk_params(c: Z3_context) -> Z3_params;
+        pub fn Z3_params_inc_ref(c: Z3_context,p: Z3_params);
+        pub fn Z3_params_dec_ref(c: Z3_context,p: Z3_params);
+        pub fn Z3_params_set_bool(c: Z3_context,p: Z3_params,k: Z3_symbol,v: bool);
+        pub fn Z3_params_set_uint(c: Z3_context,p: Z3_params,k: Z3_symbol,v: c_uint);
+        pub fn Z3_params_set_double(c: Z3_context,p: Z3_params,k: Z3_symbol,v: f64);
+        pub fn Z3_params_set_symbol(c: Z3_context,p: Z3_params,k: Z3_symbol,v: Z3_symbol);
+
+        pub fn Z3_mk_solver(c: Z3_context) -> Z3_solver;
+        pub fn Z3_mk_solver_for_logic(c: Z3_context,logic: Z3_symbol) -> Z3_solver;
+        pub fn Z3_solver_inc_ref(c: Z3_context,s: Z3_solver);
+        pub fn Z3_solver_dec_ref(c: Z3_context,s: Z3_solver);
+        pub fn Z3_solver_set_params(c: Z3_context,s: Z3_solver,p: Z3_params);
+        pub fn Z3_solver_push(c: Z3_context,s: Z3_solver);
+        pub fn Z3_solver_pop(c: Z3_context,s: Z3_solver,n: c_uint);
+        pub fn Z3_solver_assert(c: Z3_context,s: Z3_solver,a: Z3_ast);
+        pub fn Z3_solver_assert_and_track(c: Z3_context,s: Z3_solver,a: Z3_ast,p: Z3_ast);
+        pub fn Z3_solver_check(c: Z3_context,s: Z3_solver) -> Z3_lbool;
+        pub fn Z3_solver_check_assumptions(c: Z3_context,s: Z3_solver,n: c_uint,
+                                           assumptions: *const Z3_ast) -> Z3_lbool;
+        pub fn Z3_solver_get_unsat_core(c: Z3_context,s: Z3_solver) -> Z3_ast_vector;
+        pub fn Z3_solver_get_model(c: Z3_context,s: Z3_solver) -> Z3_model;
+        pub fn Z3_solver_get_proof(c: Z3_context,s: Z3_solver) -> Z3_ast;
+        pub fn Z3_solver_get_reason_unknown(c: Z3_context,s: Z3_solver) -> Z3_string;
+        pub fn Z3_solver_get_statistics(c: Z3_context,s: Z3_solver) -> Z3_stats;
+
+        pub fn Z3_ast_vector_inc_ref(c: Z3_context,v: Z3_ast_vector);
+        pub fn Z3_ast_vector_dec_ref(c: Z3_context,v: Z3_ast_vector);
+        pub fn Z3_ast_vector_size(c: Z3_context,v: Z3_ast_vector) -> c_uint;
+        pub fn Z3_ast_vector_get(c: Z3_context,v: Z3_ast_vector,i: c_uint) -> Z3_ast;
+
+        pub fn Z3_model_inc_ref(c: Z3_context,m: Z3_model);
+        pub fn Z3_model_dec_ref(c: Z3_context,m: Z3_model);
+        pub fn Z3_model_eval(c: Z3_context,m: Z3_model,t: Z3_ast,completion: bool,
+                             v: *mut Z3_ast) -> bool;
+        pub fn Z3_model_to_string(c: Z3_context,m: Z3_model) -> Z3_string;
+
+        pub fn Z3_stats_inc_ref(c: Z3_context,s: Z3_stats);
+        pub fn Z3_stats_dec_ref(c: Z3_context,s: Z3_stats);
+        pub fn Z3_stats_size(c: Z3_context,s: Z3_stats) -> c_uint;
+        pub fn Z3_stats_get_key(c: Z3_context,s: Z3_stats,i: c_uint) -> Z3_string;
+        pub fn Z3_stats_is_uint(c: Z3_context,s: Z3_stats,i: c_uint) -> bool;
+        pub fn Z3_stats_get_uint_value(c: Z3_context,s: Z3_stats,i: c_uint) -> c_uint;
+        pub fn Z3_stats_get_double_value(c: Z3_context,s: Z3_stats,i: c_uint) -> f64;
+
+        pub fn Z3_mk_tactic(c: Z3_context,name: Z3_string) -> Z3_tactic;
+        pub fn Z3_tactic_inc_ref(c: Z3_context,t: Z3_tactic);
+        pub fn Z3_tactic_dec_ref(c: Z3_context,t: Z3_tactic);
+        pub fn Z3_tactic_apply(c: Z3_context,t: Z3_tactic,g: Z3_goal) -> Z3_apply_result;
+        pub fn Z3_mk_goal(c: Z3_context,models: bool,unsat_cores: bool,proofs: bool) -> Z3_goal;
+        pub fn Z3_goal_inc_ref(c: Z3_context,g: Z3_goal);
+        pub fn Z3_goal_dec_ref(c: Z3_context,g: Z3_goal);
+        pub fn Z3_goal_assert(c: Z3_context,g: Z3_goal,a: Z3_ast);
+        pub fn Z3_goal_size(c: Z3_context,g: Z3_goal) -> c_uint;
+        pub fn Z3_goal_formula(c: Z3_context,g: Z3_goal,i: c_uint) -> Z3_ast;
+        pub fn Z3_apply_result_inc_ref(c: Z3_context,r: Z3_apply_result);
+        pub fn Z3_apply_result_dec_ref(c: Z3_context,r: Z3_apply_result);
+        pub fn Z3_apply_result_get_num_subgoals(c: Z3_context,r: Z3_apply_result) -> c_uint;
+        pub fn Z3_apply_result_get_subgoal(c: Z3_context,r: Z3_apply_result,i: c_uint) -> Z3_goal;
+    }
+}
+
+#[cfg(feature="test-z3-native")]
+#[test]
+fn test_z3_native() {
+    let mut solver = Z3::new().expect("Cannot create Z3 context");
+    solver.enable_models().expect("Cannot enable models");
+    assert_eq!(solver.solver_name().unwrap(),"Z3".to_string());
+    let tint = solver.tp_int().expect("Cannot create inttype");
+    let v1 = solver.declare(tint).expect("Cannot declare var");
+    let v2 = solver.declare(tint).expect("Cannot declare var");
+    let eq = solver.eq(v1,v2).expect("Cannot create = expr");
+    solver.assert(eq).expect("Cannot assert");
+    let five = solver.const_int(BigInt::from(5)).unwrap();
+    let gt = solver.embed(Expr::App(Function::OrdInt(OrdOp::Gt),vec![v1,five])).unwrap();
+    solver.assert(gt).unwrap();
+    assert_eq!(solver.check_sat().expect("Cannot checksat"),CheckSatResult::Sat);
+    let rv1 = solver.get_value(v1).expect("Cannot get-value");
+    let rv2 = solver.get_value(v2).expect("Cannot get-value");
+    assert_eq!(rv1,rv2);
+    match rv1 {
+        Value::Int(ref v) => assert!(*v > BigInt::from(5)),
+        _ => panic!("Expected an integer value")
+    }
+    let model = solver.get_model().expect("Cannot get model");
+    assert_eq!(model.value(&Z3Var(0)),Some(&rv1));
+    let neq = solver.not(eq).expect("Cannot create not expr");
+    solver.assert(neq).expect("Cannot assert");
+    assert_eq!(solver.check_sat().expect("Cannot checksat"),CheckSatResult::Unsat);
+}
+
+#[cfg(feature="test-z3-native")]
+#[test]
+fn test_z3_native_cores() {
+    let mut solver = Z3::new().unwrap();
+    solver.enable_unsat_cores().unwrap();
+    let tbv = solver.tp_bitvec(8).unwrap();
+    let x = solver.declare(tbv).unwrap();
+    let one = solver.const_bitvec(8,BigUint::from(1 as u8)).unwrap();
+    let sum = solver.bvadd(x,one).unwrap();
+    let lt = solver.bvult(sum,x).unwrap();
+    let a0 = solver.assert_named(lt).unwrap();
+    let zero = solver.const_bitvec(8,BigUint::from(0 as u8)).unwrap();
+    let ne = solver.eq(x,zero).unwrap();
+    let a1 = solver.assert_named(ne).unwrap();
+    let tbool = solver.tp_bool().unwrap();
+    let p = solver.declare(tbool).unwrap();
+    let np = solver.not(p).unwrap();
+    solver.assert(np).unwrap();
+    assert_eq!(solver.check_sat_assuming(&[p]).unwrap(),CheckSatResult::Unsat);
+    assert_eq!(solver.get_unsat_assumptions().unwrap(),vec![p]);
+    assert_eq!(solver.check_sat().unwrap(),CheckSatResult::Unsat);
+    let mut core = solver.get_unsat_core().unwrap();
+    core.sort();
+    assert_eq!(core,vec![a0,a1]);
+}
+
+#[cfg(feature="test-z3-native")]
+#[test]
+fn test_z3_native_defs() {
+    let mut solver = Z3::new().unwrap();
+    let tint = solver.tp_int().unwrap();
+    // A list of integers
+    let list = solver.declare_datatype(Datatype { params: 0,
+                                                  constructors: vec![vec![],
+                                                                     vec![FieldSort::Sort(tint),
+                                                                          FieldSort::Rec(0,vec![])]] })
+        .unwrap();
+    let tlist = solver.tp_datatype(list,vec![]).unwrap();
+    let l = solver.declare(tlist).unwrap();
+    let is_cons = solver.is_constructor(1,l).unwrap();
+    solver.assert(is_cons).unwrap();
+    let head = solver.select_field(1,0,l).unwrap();
+    let x = NVar { id: 0, sort: tint };
+    let qx = solver.embed(Expr::QVar(x.clone())).unwrap();
+    let body = solver.add_int(vec![qx,qx]).unwrap();
+    let double = solver.define_fun(vec![x.clone()],body).unwrap();
+    let dhead = solver.app(double,vec![head]).unwrap();
+    let six = solver.const_int(BigInt::from(6)).unwrap();
+    let eq = solver.eq(dhead,six).unwrap();
+    solver.assert(eq).unwrap();
+    assert_eq!(solver.check_sat().unwrap(),CheckSatResult::Sat);
+    assert_eq!(solver.get_value(head).unwrap(),Value::Int(BigInt::from(3)));
+    let three = solver.const_int(BigInt::from(3)).unwrap();
+    let sum = solver.add_int(vec![three,three]).unwrap();
+    assert_eq!(solver.solver_simplify(sum).unwrap(),six);
+    // exists x. x+x = v0  <=>  v0 mod 2 = 0 (or an equivalent formula)
+    let v = solver.declare(tint).unwrap();
+    let ex_body = solver.eq(body,v).unwrap();
+    let ex = solver.embed(Expr::Exists(vec![x],ex_body)).unwrap();
+    let qf = solver.eliminate_quantifiers(ex).unwrap();
+    solver.push().unwrap();
+    let seven = solver.const_int(BigInt::from(7)).unwrap();
+    let is_seven = solver.eq(v,seven).unwrap();
+    solver.assert(is_seven).unwrap();
+    solver.assert(qf).unwrap();
+    assert_eq!(solver.check_sat().unwrap(),CheckSatResult::Unsat);
+    solver.pop().unwrap();
+}
+
+#[cfg(feature="test-z3-native")]
+#[test]
+fn test_z3_native_arrays() {
+    let mut solver = Z3::new().unwrap();
+    solver.enable_models().unwrap();
+    let tint = solver.tp_int().unwrap();
+    let tarr = solver.tp_array(vec![tint],tint).unwrap();
+    let a = solver.declare(tarr).unwrap();
+    for &(i,v) in [(1,2),(3,4)].iter() {
+        let ri = solver.const_int(BigInt::from(i)).unwrap();
+        let rv = solver.const_int(BigInt::from(v)).unwrap();
+        let sel = solver.select(a,vec![ri]).unwrap();
+        let eq = solver.eq(sel,rv).unwrap();
+        solver.assert(eq).unwrap();
+    }
+    assert_eq!(solver.check_sat().unwrap(),CheckSatResult::Sat);
+    let val = solver.get_value(a).unwrap();
+    for &(i,v) in [(1,2),(3,4)].iter() {
+        assert_eq!(val.select(&[Value::Int(BigInt::from(i))]),&Value::Int(BigInt::from(v)));
+    }
+    assert_eq!(val.sort(&mut solver).unwrap(),tarr);
+    let model = solver.get_model().unwrap();
+    assert_eq!(model.value(&Z3Var(0)),Some(&val));
+    // Array values can be used in expressions
+    let c = solver.embed(Expr::Const(val)).unwrap();
+    let eq = solver.eq(a,c).unwrap();
+    let neq = solver.not(eq).unwrap();
+    solver.assert(neq).unwrap();
+    assert_eq!(solver.check_sat().unwrap(),CheckSatResult::Sat);
+}